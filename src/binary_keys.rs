@@ -0,0 +1,132 @@
+//! Keys of the config's `binaries` table, which name a binary or match several
+//!
+//! A key with `*` (any run of characters) or `?` (any single character) is a glob, like
+//! `demo_*`, and a key between slashes, like `/bench_\d+/`, is a regex that has to match
+//! the whole binary name. An entry named exactly after a binary always wins over
+//! patterns; among patterns, the one with the longest literal prefix wins, then the one
+//! defined first in the file.
+
+use anyhow::Result;
+use regex::Regex;
+
+/// A key of the `binaries` table
+pub enum BinaryKey {
+    /// The name of a single binary
+    Name(String),
+    /// A glob or regex, with the number of characters every match starts with
+    Pattern { regex: Regex, literal_prefix: usize },
+}
+
+impl BinaryKey {
+    /// Reads `key` as a name, glob or regex
+    pub fn parse(key: &str) -> Result<Self> {
+        if let Some(pattern) = key
+            .strip_prefix('/')
+            .and_then(|rest| rest.strip_suffix('/'))
+        {
+            let pattern = pattern.strip_prefix('^').unwrap_or(pattern);
+            let pattern = pattern.strip_suffix('$').unwrap_or(pattern);
+            let regex = Regex::new(&format!("^(?:{})$", pattern))
+                .map_err(|e| anyhow::anyhow!("Invalid regex in binaries key '{}': {}", key, e))?;
+            let literal = pattern
+                .chars()
+                .take_while(|c| !r"\.+*?()|[]{}^$".contains(*c))
+                .count();
+            // A quantifier that allows zero repeats makes the character before it optional,
+            // and an alternative of the whole pattern needn't start with the prefix at all
+            let literal_prefix = match pattern.chars().nth(literal) {
+                _ if has_top_level_alternation(pattern) => 0,
+                Some('?' | '*' | '{') => literal.saturating_sub(1),
+                _ => literal,
+            };
+            return Ok(BinaryKey::Pattern {
+                regex,
+                literal_prefix,
+            });
+        }
+        if key.contains(['*', '?']) {
+            let pattern: String = key
+                .chars()
+                .map(|c| match c {
+                    '*' => ".*".to_string(),
+                    '?' => ".".to_string(),
+                    c => regex::escape(&c.to_string()),
+                })
+                .collect();
+            let regex = Regex::new(&format!("^{}$", pattern))?;
+            let literal_prefix = key.chars().take_while(|c| !matches!(c, '*' | '?')).count();
+            return Ok(BinaryKey::Pattern {
+                regex,
+                literal_prefix,
+            });
+        }
+        Ok(BinaryKey::Name(key.to_string()))
+    }
+
+    /// Whether the entry applies to the binary `name`
+    pub fn matches(&self, name: &str) -> bool {
+        match self {
+            BinaryKey::Name(key) => key == name,
+            BinaryKey::Pattern { regex, .. } => regex.is_match(name),
+        }
+    }
+}
+
+/// Whether `pattern` has a `|` outside of groups and character classes
+fn has_top_level_alternation(pattern: &str) -> bool {
+    let mut depth = 0usize;
+    let mut in_class = false;
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            ']' if in_class => in_class = false,
+            _ if in_class => {}
+            '[' => in_class = true,
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            '|' if depth == 0 => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// The entries of a binary, best first: its exact entry, then the matching patterns by
+/// the length of their literal prefix and then in the order they were defined. Keys
+/// that don't parse are left out; they are reported when the config is loaded.
+pub fn matching_keys<'a>(keys: impl Iterator<Item = &'a str>, name: &str) -> Vec<&'a str> {
+    let mut matches: Vec<(usize, &str)> = keys
+        .filter_map(|key| {
+            let parsed = BinaryKey::parse(key).ok()?;
+            let priority = match parsed {
+                BinaryKey::Name(_) => usize::MAX,
+                BinaryKey::Pattern { literal_prefix, .. } => literal_prefix,
+            };
+            parsed.matches(name).then_some((priority, key))
+        })
+        .collect();
+    // A stable sort keeps the order of definition among equally long prefixes
+    matches.sort_by_key(|(literal_prefix, _)| std::cmp::Reverse(*literal_prefix));
+    matches.into_iter().map(|(_, key)| key).collect()
+}
+
+/// Whether the pattern keys `first` and `second` match the same binary with the same
+/// priority, so only their order in the file decides between them
+pub fn ambiguous(first: &str, second: &str) -> bool {
+    match (BinaryKey::parse(first), BinaryKey::parse(second)) {
+        (
+            Ok(BinaryKey::Pattern {
+                literal_prefix: first,
+                ..
+            }),
+            Ok(BinaryKey::Pattern {
+                literal_prefix: second,
+                ..
+            }),
+        ) => first == second,
+        _ => false,
+    }
+}
@@ -0,0 +1,587 @@
+//! Frame acquisition and GIF (or APNG) encoding
+//!
+//! Frames come from a [`FrameSource`], so the capture pipeline can run against the
+//! live mGBA window or against generated test-pattern frames. Native window capture
+//! backends are feature-gated and chosen with `--capture-backend`. A backend is opened
+//! once per run as a [`WindowCapture`], and captures whichever window the
+//! [`WindowQuery`] of each binary finds.
+
+#[cfg(all(feature = "coregraphics", target_os = "macos"))]
+mod coregraphics;
+#[cfg(all(feature = "dxgi", target_os = "windows"))]
+mod dxgi;
+#[cfg(all(feature = "portal", target_os = "linux"))]
+mod portal;
+#[cfg(all(feature = "x11-shm", target_os = "linux"))]
+mod x11_shm;
+
+use crate::window::{find_mgba_window, WindowQuery};
+use anyhow::Result;
+use clap::ValueEnum;
+use gif::{AnyExtension, Encoder, Frame, Repeat};
+use image::{ImageBuffer, RgbImage, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::BufWriter;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+/// GBA screen resolution, used for synthetic frames
+pub const GBA_SCREEN_SIZE: (u32, u32) = (240, 160);
+
+/// Produces the frames that make up a GIF
+pub trait FrameSource: Send + Sync {
+    /// Captures the current frame as RGBA
+    fn capture(&self) -> Result<RgbaImage>;
+
+    /// Reports the size of the frames this source is expected to produce
+    fn dimensions(&self) -> Result<(u32, u32)>;
+}
+
+/// Grabs the contents of the window a [`WindowQuery`] finds
+pub trait WindowCapture: Send + Sync {
+    /// Captures the window's current contents as RGBA
+    fn capture(&self, query: &WindowQuery) -> Result<RgbaImage>;
+
+    /// Reports the size of the window's frames
+    fn dimensions(&self, query: &WindowQuery) -> Result<(u32, u32)> {
+        let window = find_mgba_window(query)?;
+        Ok((window.width(), window.height()))
+    }
+}
+
+/// Screen-capture backend used to grab frames from the mGBA window
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CaptureBackend {
+    /// Best backend available on this platform, falling back to xcap
+    Auto,
+    /// Cross-platform capture through the xcap crate
+    Xcap,
+    /// X11 MIT-SHM window capture (Linux, `x11-shm` feature)
+    X11Shm,
+    /// DXGI desktop duplication (Windows, `dxgi` feature)
+    Dxgi,
+    /// CoreGraphics window capture (macOS, `coregraphics` feature)
+    Coregraphics,
+    /// xdg-desktop-portal screenshots for Wayland sessions (Linux, `portal` feature)
+    Portal,
+}
+
+impl CaptureBackend {
+    /// Name as accepted by `--capture-backend`
+    pub fn name(self) -> &'static str {
+        match self {
+            CaptureBackend::Auto => "auto",
+            CaptureBackend::Xcap => "xcap",
+            CaptureBackend::X11Shm => "x11-shm",
+            CaptureBackend::Dxgi => "dxgi",
+            CaptureBackend::Coregraphics => "coregraphics",
+            CaptureBackend::Portal => "portal",
+        }
+    }
+
+    /// Platform the backend is available on, and whether that is the current one
+    fn platform(self) -> (&'static str, bool) {
+        match self {
+            CaptureBackend::X11Shm | CaptureBackend::Portal => ("Linux", cfg!(target_os = "linux")),
+            CaptureBackend::Dxgi => ("Windows", cfg!(target_os = "windows")),
+            CaptureBackend::Coregraphics => ("macOS", cfg!(target_os = "macos")),
+            CaptureBackend::Auto | CaptureBackend::Xcap => ("any platform", true),
+        }
+    }
+
+    /// Native backend preferred by `auto` on this platform
+    fn native() -> Option<Self> {
+        if cfg!(target_os = "linux") {
+            Some(CaptureBackend::X11Shm)
+        } else if cfg!(target_os = "windows") {
+            Some(CaptureBackend::Dxgi)
+        } else if cfg!(target_os = "macos") {
+            Some(CaptureBackend::Coregraphics)
+        } else {
+            None
+        }
+    }
+}
+
+/// Opens a capture backend, resolving `auto` to the best one available. Returns the
+/// backend actually used alongside it.
+pub fn open_window_capture(
+    backend: CaptureBackend,
+) -> Result<(CaptureBackend, Arc<dyn WindowCapture>)> {
+    match backend {
+        CaptureBackend::Auto => {
+            if let Some(native) = CaptureBackend::native() {
+                match open_native_backend(native) {
+                    Ok(Some(source)) => return Ok((native, source)),
+                    Ok(None) => {}
+                    Err(e) => println!(
+                        "Note: {} capture unavailable ({}), using xcap",
+                        native.name(),
+                        e
+                    ),
+                }
+            }
+            Ok((CaptureBackend::Xcap, Arc::new(XcapCapture)))
+        }
+        CaptureBackend::Xcap => Ok((CaptureBackend::Xcap, Arc::new(XcapCapture))),
+        native => match open_native_backend(native) {
+            Ok(Some(source)) => Ok((native, source)),
+            Ok(None) => Err(backend_not_compiled(native)),
+            Err(e) => Err(anyhow::anyhow!(
+                "Capture backend '{}' is not supported here: {}. Try --capture-backend xcap",
+                native.name(),
+                e
+            )),
+        },
+    }
+}
+
+/// Opens a native backend, or returns `None` if it isn't compiled into this build
+fn open_native_backend(backend: CaptureBackend) -> Result<Option<Arc<dyn WindowCapture>>> {
+    match backend {
+        #[cfg(all(feature = "x11-shm", target_os = "linux"))]
+        CaptureBackend::X11Shm => Ok(Some(Arc::new(x11_shm::X11ShmCapture::connect()?))),
+        #[cfg(all(feature = "dxgi", target_os = "windows"))]
+        CaptureBackend::Dxgi => Ok(Some(Arc::new(dxgi::DxgiCapture::new()?))),
+        #[cfg(all(feature = "coregraphics", target_os = "macos"))]
+        CaptureBackend::Coregraphics => Ok(Some(Arc::new(coregraphics::CoreGraphicsCapture))),
+        #[cfg(all(feature = "portal", target_os = "linux"))]
+        CaptureBackend::Portal => Ok(Some(Arc::new(portal::PortalCapture::new()?))),
+        _ => Ok(None),
+    }
+}
+
+/// Explains how to get a backend that isn't part of this build
+fn backend_not_compiled(backend: CaptureBackend) -> anyhow::Error {
+    let (platform, on_platform) = backend.platform();
+    if on_platform {
+        anyhow::anyhow!(
+            "Capture backend '{}' is not compiled in. Reinstall with: cargo install --git https://github.com/zpg6/agbrs-capture --features {}",
+            backend.name(),
+            backend.name()
+        )
+    } else {
+        anyhow::anyhow!(
+            "Capture backend '{}' is only available on {}",
+            backend.name(),
+            platform
+        )
+    }
+}
+
+/// Captures the mGBA window through xcap
+struct XcapCapture;
+
+impl WindowCapture for XcapCapture {
+    fn capture(&self, query: &WindowQuery) -> Result<RgbaImage> {
+        let image = find_mgba_window(query)?.capture_image()?;
+        ImageBuffer::from_raw(image.width(), image.height(), image.into_raw())
+            .ok_or_else(|| anyhow::anyhow!("Failed to convert frame to RgbaImage"))
+    }
+}
+
+/// Frames of the mGBA window of one binary, captured by the run's backend
+pub struct WindowFrameSource {
+    capture: Arc<dyn WindowCapture>,
+    query: WindowQuery,
+}
+
+impl WindowFrameSource {
+    pub fn new(capture: Arc<dyn WindowCapture>, query: WindowQuery) -> Self {
+        Self { capture, query }
+    }
+}
+
+impl FrameSource for WindowFrameSource {
+    fn capture(&self) -> Result<RgbaImage> {
+        self.capture.capture(&self.query)
+    }
+
+    fn dimensions(&self) -> Result<(u32, u32)> {
+        self.capture.dimensions(&self.query)
+    }
+}
+
+/// Generates deterministic test-pattern frames: a gradient background with a square
+/// that moves a few pixels on every capture. Lets the capture and encode pipeline run
+/// without a display server or emulator.
+pub struct SyntheticFrameSource {
+    width: u32,
+    height: u32,
+    next_frame: AtomicU32,
+}
+
+impl SyntheticFrameSource {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            next_frame: AtomicU32::new(0),
+        }
+    }
+}
+
+impl FrameSource for SyntheticFrameSource {
+    fn capture(&self) -> Result<RgbaImage> {
+        let index = self.next_frame.fetch_add(1, Ordering::Relaxed);
+        let (width, height) = (self.width, self.height);
+        let square_size = 16.min(width).min(height);
+        let square_x = (index * 4) % (width - square_size + 1);
+        let square_y = (height - square_size) / 2;
+
+        Ok(ImageBuffer::from_fn(width, height, |x, y| {
+            let in_square = (square_x..square_x + square_size).contains(&x)
+                && (square_y..square_y + square_size).contains(&y);
+            if in_square {
+                Rgba([255, 255, 255, 255])
+            } else {
+                Rgba([
+                    (x * 255 / width) as u8,
+                    (y * 255 / height) as u8,
+                    (index * 8 % 256) as u8,
+                    255,
+                ])
+            }
+        }))
+    }
+
+    fn dimensions(&self) -> Result<(u32, u32)> {
+        Ok((self.width, self.height))
+    }
+}
+
+/// Fraction of pixels that differ noticeably between two frames; frames of different
+/// sizes differ entirely
+pub fn changed_pixel_fraction(a: &RgbaImage, b: &RgbaImage) -> f32 {
+    if a.dimensions() != b.dimensions() {
+        return 1.0;
+    }
+    let total = a.width() as usize * a.height() as usize;
+    if total == 0 {
+        return 0.0;
+    }
+    // Small per-channel differences come from scaling and color conversion, not the game
+    let changed = a
+        .pixels()
+        .zip(b.pixels())
+        .filter(|(a, b)| (0..3).any(|channel| a[channel].abs_diff(b[channel]) > 8))
+        .count();
+    changed as f32 / total as f32
+}
+
+/// Heuristic for agb's panic screen when the panic message isn't in the emulator log:
+/// two identical frames (the panic screen no longer updates) dominated by red
+pub fn looks_like_panic_screen(previous: &RgbaImage, last: &RgbaImage) -> bool {
+    if previous.as_raw() != last.as_raw() || last.width() == 0 || last.height() == 0 {
+        return false;
+    }
+
+    let red_pixels = last
+        .pixels()
+        .filter(|pixel| pixel[0] >= 128 && pixel[1] < 64 && pixel[2] < 64)
+        .count();
+    red_pixels * 2 > (last.width() * last.height()) as usize
+}
+
+/// File format of a capture, set with `--format` or a binary's `output.format`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// Animated GIF, with colors reduced to a 256-color palette per frame
+    #[default]
+    Gif,
+    /// Animated PNG, with the frames' colors kept exactly
+    Apng,
+}
+
+impl OutputFormat {
+    /// Name as accepted by `--format`, for messages
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputFormat::Gif => "gif",
+            OutputFormat::Apng => "apng",
+        }
+    }
+
+    /// Extension of the written file
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Gif => "gif",
+            OutputFormat::Apng => "png",
+        }
+    }
+}
+
+/// How many times an animation plays, set with `--loop` or a binary's `loop`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Loop {
+    /// Plays over and over
+    #[default]
+    Infinite,
+    /// Plays this many times (1 or more), then stops on its last frame
+    Plays(u16),
+}
+
+impl Loop {
+    /// Parses `infinite` or a number of plays
+    pub fn parse(text: &str) -> Result<Self> {
+        let text = text.trim();
+        if text.eq_ignore_ascii_case("infinite") {
+            return Ok(Loop::Infinite);
+        }
+        let plays: i64 = text.parse().map_err(|_| {
+            anyhow::anyhow!(
+                "Invalid loop '{}' (expected \"infinite\" or how many times to play, like 1)",
+                text
+            )
+        })?;
+        Loop::plays(plays)
+    }
+
+    /// Plays `plays` times, which must be 1 or more
+    pub fn plays(plays: i64) -> Result<Self> {
+        match u16::try_from(plays) {
+            Ok(plays) if plays > 0 => Ok(Loop::Plays(plays)),
+            _ if plays < 1 => Err(anyhow::anyhow!(
+                "Invalid loop {}: it must be \"infinite\" or a number of plays, 1 or more. \
+                 A GIF stores the plays after the first (its NETSCAPE count), where 0 means \
+                 forever and some viewers play the count one time too few, so write \
+                 \"infinite\" to loop forever",
+                plays
+            )),
+            _ => Err(anyhow::anyhow!(
+                "Invalid loop {}: at most {} plays can be stored; write \"infinite\" for more",
+                plays,
+                u16::MAX
+            )),
+        }
+    }
+
+    /// The GIF repeat count: the plays after the first. `Finite(0)` leaves out the
+    /// NETSCAPE extension, which every viewer plays once.
+    fn gif_repeat(self) -> Repeat {
+        match self {
+            Loop::Infinite => Repeat::Infinite,
+            Loop::Plays(plays) => Repeat::Finite(plays - 1),
+        }
+    }
+
+    /// The APNG play count, where 0 is forever
+    fn apng_plays(self) -> u32 {
+        match self {
+            Loop::Infinite => 0,
+            Loop::Plays(plays) => plays.into(),
+        }
+    }
+
+    /// How the animation plays, as printed in the summary
+    pub fn describe(self) -> String {
+        match self {
+            Loop::Infinite => "loops forever".to_string(),
+            Loop::Plays(1) => "plays once".to_string(),
+            Loop::Plays(plays) => format!("plays {} times", plays),
+        }
+    }
+}
+
+impl std::fmt::Display for Loop {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Loop::Infinite => write!(f, "infinite"),
+            Loop::Plays(plays) => write!(f, "{}", plays),
+        }
+    }
+}
+
+/// `loop` in the config: `"infinite"` or a number of plays
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum LoopSetting {
+    Plays(i64),
+    Text(String),
+}
+
+impl LoopSetting {
+    /// The setting as a [`Loop`], or why it isn't one
+    pub fn resolve(&self) -> Result<Loop> {
+        match self {
+            LoopSetting::Plays(plays) => Loop::plays(*plays),
+            LoopSetting::Text(text) => Loop::parse(text),
+        }
+    }
+}
+
+/// Writes the frames of a capture to a file in one of the [`OutputFormat`]s
+pub enum AnimationEncoder {
+    Gif(Encoder<File>),
+    Apng(png::Writer<BufWriter<File>>),
+}
+
+impl AnimationEncoder {
+    /// Creates `path` for `frame_count` frames that play as `loop_count` says, with
+    /// `comment` stored in the file (a GIF comment or a PNG text chunk)
+    pub fn create(
+        format: OutputFormat,
+        path: &str,
+        (width, height): (u32, u32),
+        frame_count: u32,
+        loop_count: Loop,
+        comment: Option<&str>,
+    ) -> Result<Self> {
+        let file = File::create(path)?;
+        match format {
+            OutputFormat::Gif => {
+                let mut encoder = Encoder::new(file, width as u16, height as u16, &[])?;
+                encoder.set_repeat(loop_count.gif_repeat())?;
+                if let Some(comment) = comment {
+                    encoder.write_raw_extension(AnyExtension(0xFE), &[comment.as_bytes()])?;
+                }
+                Ok(AnimationEncoder::Gif(encoder))
+            }
+            OutputFormat::Apng => {
+                let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+                encoder.set_color(png::ColorType::Rgba);
+                encoder.set_depth(png::BitDepth::Eight);
+                encoder.set_animated(frame_count, loop_count.apng_plays())?;
+                if let Some(comment) = comment {
+                    encoder.add_text_chunk("Comment".to_string(), comment.to_string())?;
+                }
+                Ok(AnimationEncoder::Apng(encoder.write_header()?))
+            }
+        }
+    }
+
+    /// Adds a frame shown for `frame_delay_ms`
+    pub fn add_frame(&mut self, rgba_image: RgbaImage, frame_delay_ms: u64) -> Result<()> {
+        match self {
+            AnimationEncoder::Gif(encoder) => add_frame_to_gif(encoder, rgba_image, frame_delay_ms),
+            AnimationEncoder::Apng(writer) => {
+                writer.set_frame_delay(frame_delay_ms.min(u16::MAX as u64) as u16, 1000)?;
+                writer.write_image_data(rgba_image.as_raw())?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Completes the file
+    pub fn finish(self) -> Result<()> {
+        match self {
+            AnimationEncoder::Gif(_) => Ok(()),
+            AnimationEncoder::Apng(writer) => Ok(writer.finish()?),
+        }
+    }
+}
+
+/// Converts RGBA image to GIF frame and adds to encoder with configurable timing
+fn add_frame_to_gif(
+    encoder: &mut Encoder<File>,
+    rgba_image: RgbaImage,
+    frame_delay_ms: u64,
+) -> Result<()> {
+    // Convert RGBA to RGB (GIF doesn't support alpha channel)
+    let rgb_image: RgbImage =
+        ImageBuffer::from_fn(rgba_image.width(), rgba_image.height(), |x, y| {
+            let rgba_pixel = rgba_image.get_pixel(x, y);
+            image::Rgb([rgba_pixel[0], rgba_pixel[1], rgba_pixel[2]])
+        });
+
+    let mut frame = Frame::from_rgb(
+        rgb_image.width() as u16,
+        rgb_image.height() as u16,
+        rgb_image.as_raw(),
+    );
+    frame.delay = (frame_delay_ms / 10) as u16; // Convert ms to centiseconds
+
+    encoder.write_frame(&frame)?;
+    Ok(())
+}
+
+/// Nominal GBA refresh rate (16.78 MHz clock / 280896 cycles per frame)
+pub const GBA_FPS: f32 = 59.7275;
+
+/// Shortest frame delay GIF viewers honour; anything lower is slowed down to 100ms
+const MIN_GIF_FRAME_DELAY_MS: u64 = 20;
+
+/// Emulation statistics shown in the mGBA window title
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TitleStats {
+    /// Emulated frames per second, e.g. "(59.7 fps)"
+    pub fps: Option<f32>,
+    /// Emulated frame counter, e.g. "frame 1234"
+    pub frame: Option<u64>,
+}
+
+/// Parses the fps display and frame counter from an mGBA window title.
+///
+/// mGBA-Qt shows e.g. "mGBA - Game (59.7 fps) - 0.10.3" and mGBA-SDL "mGBA - Game
+/// (60 fps)"; fields that aren't present (paused, older builds, counter disabled) are `None`.
+pub fn parse_title_stats(title: &str) -> TitleStats {
+    let tokens: Vec<&str> = title
+        .split(|c: char| !(c.is_ascii_alphanumeric() || c == '.'))
+        .filter(|token| !token.is_empty())
+        .collect();
+    let mut stats = TitleStats::default();
+
+    for (index, token) in tokens.iter().enumerate() {
+        let lower = token.to_ascii_lowercase();
+        let previous = index.checked_sub(1).map(|i| tokens[i]);
+        let next = tokens.get(index + 1).copied();
+
+        if lower == "fps" {
+            stats.fps = stats.fps.or(previous.and_then(|value| value.parse().ok()));
+        } else if let Some(value) = lower.strip_suffix("fps") {
+            stats.fps = stats.fps.or(value.parse().ok());
+        } else if lower == "frame" {
+            stats.frame = stats.frame.or(next.and_then(|value| value.parse().ok()));
+        } else if lower == "frames" {
+            stats.frame = stats
+                .frame
+                .or(previous.and_then(|value| value.parse().ok()));
+        }
+    }
+
+    stats
+}
+
+/// Turns title samples, taken at the given milliseconds since capture start, into
+/// emulation speed relative to real time. The frame counter is preferred over the fps
+/// display, which mGBA averages over the last second.
+pub fn emulation_speeds(samples: &[(u64, TitleStats)]) -> Vec<(u64, f32)> {
+    let mut speeds = Vec::new();
+
+    for (index, (time_ms, stats)) in samples.iter().enumerate() {
+        let from_counter = index.checked_sub(1).and_then(|i| {
+            let (previous_ms, previous) = samples[i];
+            let frames = stats.frame?.checked_sub(previous.frame?)?;
+            let elapsed_ms = time_ms.checked_sub(previous_ms).filter(|&ms| ms > 0)?;
+            Some(frames as f32 / GBA_FPS * 1000.0 / elapsed_ms as f32)
+        });
+
+        if let Some(speed) = from_counter.or(stats.fps.map(|fps| fps / GBA_FPS)) {
+            speeds.push((*time_ms, speed));
+        }
+    }
+
+    speeds
+}
+
+/// Scales each frame's delay by the emulation speed while it was on screen, so the GIF
+/// plays back in emulated time rather than wall-clock time
+pub fn emulated_frame_delays(
+    speeds: &[(u64, f32)],
+    frame_count: u32,
+    frame_delay_ms: u64,
+) -> Vec<u64> {
+    (0..frame_count as u64)
+        .map(|index| {
+            let frame_end_ms = (index + 1) * frame_delay_ms;
+            let speed = speeds
+                .iter()
+                .find(|(time_ms, _)| *time_ms >= frame_end_ms)
+                .or(speeds.last())
+                .map_or(1.0, |(_, speed)| *speed);
+            ((frame_delay_ms as f32 * speed).round() as u64).max(MIN_GIF_FRAME_DELAY_MS)
+        })
+        .collect()
+}
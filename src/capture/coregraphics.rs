@@ -0,0 +1,64 @@
+//! CoreGraphics capture of the mGBA window on macOS
+//!
+//! Grabs the window by id with `CGWindowListCreateImage`, skipping the full window
+//! enumeration xcap performs on every frame.
+
+use super::WindowCapture;
+use crate::window::{find_mgba_window, WindowQuery};
+use anyhow::Result;
+use core_graphics::display::CGRect;
+use core_graphics::geometry::{CGPoint, CGSize};
+use core_graphics::image::CGImage;
+use core_graphics::window::{
+    create_image, kCGWindowImageBoundsIgnoreFraming, kCGWindowListOptionIncludingWindow,
+};
+use image::RgbaImage;
+
+/// Captures the mGBA window through CoreGraphics
+pub struct CoreGraphicsCapture;
+
+/// Captures a single window by id, or `None` if it no longer exists
+fn capture_window(window: u32) -> Option<CGImage> {
+    // A null rect asks for the window's own bounds
+    let bounds = CGRect::new(
+        &CGPoint::new(f64::INFINITY, f64::INFINITY),
+        &CGSize::new(0.0, 0.0),
+    );
+    create_image(
+        bounds,
+        kCGWindowListOptionIncludingWindow,
+        window,
+        kCGWindowImageBoundsIgnoreFraming,
+    )
+}
+
+/// Converts a BGRA CGImage (rows may be padded) into a tightly packed RGBA image
+fn to_rgba(image: &CGImage) -> Result<RgbaImage> {
+    let width = image.width();
+    let height = image.height();
+    let data = image.data();
+    let mut rgba = Vec::with_capacity(width * height * 4);
+
+    for row in data.bytes().chunks_exact(image.bytes_per_row()) {
+        for pixel in row[..width * 4].chunks_exact(4) {
+            rgba.extend_from_slice(&[pixel[2], pixel[1], pixel[0], 255]);
+        }
+    }
+
+    RgbaImage::from_raw(width as u32, height as u32, rgba)
+        .ok_or_else(|| anyhow::anyhow!("Failed to convert frame to RgbaImage"))
+}
+
+impl WindowCapture for CoreGraphicsCapture {
+    fn capture(&self, query: &WindowQuery) -> Result<RgbaImage> {
+        if let Some(image) = query.chosen_window().and_then(capture_window) {
+            return to_rgba(&image);
+        }
+
+        // The chosen window is gone (e.g. a new mGBA launch), so look it up again
+        let window = find_mgba_window(query)?.id();
+        let image = capture_window(window)
+            .ok_or_else(|| anyhow::anyhow!("CoreGraphics could not capture window {}", window))?;
+        to_rgba(&image)
+    }
+}
@@ -0,0 +1,217 @@
+//! DXGI desktop duplication capture of the mGBA window on Windows
+//!
+//! Duplicates the output the window is on and crops the window rectangle out of it.
+//! When the desktop has not changed since the previous frame the last image is reused,
+//! instead of returning the blank frames GDI capture sometimes produces.
+
+use super::WindowCapture;
+use crate::window::{find_mgba_window, WindowQuery};
+use anyhow::Result;
+use image::RgbaImage;
+use std::sync::Mutex;
+use windows::core::Interface;
+use windows::Win32::Foundation::{HMODULE, RECT};
+use windows::Win32::Graphics::Direct3D::D3D_DRIVER_TYPE_HARDWARE;
+use windows::Win32::Graphics::Direct3D11::{
+    D3D11CreateDevice, ID3D11Device, ID3D11DeviceContext, ID3D11Texture2D, D3D11_CPU_ACCESS_READ,
+    D3D11_CREATE_DEVICE_BGRA_SUPPORT, D3D11_MAPPED_SUBRESOURCE, D3D11_MAP_READ, D3D11_SDK_VERSION,
+    D3D11_TEXTURE2D_DESC, D3D11_USAGE_STAGING,
+};
+use windows::Win32::Graphics::Dxgi::{
+    IDXGIDevice, IDXGIOutput1, IDXGIOutputDuplication, IDXGIResource, DXGI_ERROR_ACCESS_LOST,
+    DXGI_ERROR_WAIT_TIMEOUT, DXGI_OUTDUPL_FRAME_INFO, DXGI_OUTPUT_DESC,
+};
+
+/// How long to wait for a new desktop frame before reusing the previous one
+const ACQUIRE_TIMEOUT_MS: u32 = 50;
+
+/// A duplicated output and the most recent BGRA copy of its contents
+struct Duplication {
+    device: ID3D11Device,
+    context: ID3D11DeviceContext,
+    duplication: IDXGIOutputDuplication,
+    /// Desktop coordinates of the duplicated output
+    bounds: RECT,
+    /// Last desktop image as (bgra, width, height)
+    last_frame: Option<(Vec<u8>, u32, u32)>,
+}
+
+// COM objects are only used while holding the capture's mutex
+unsafe impl Send for Duplication {}
+
+impl Duplication {
+    /// Duplicates the output that contains the given desktop point
+    fn for_point(x: i32, y: i32) -> Result<Self> {
+        // SAFETY: straightforward D3D11/DXGI calls with owned out-parameters
+        unsafe {
+            let mut device = None;
+            let mut context = None;
+            D3D11CreateDevice(
+                None,
+                D3D_DRIVER_TYPE_HARDWARE,
+                HMODULE::default(),
+                D3D11_CREATE_DEVICE_BGRA_SUPPORT,
+                None,
+                D3D11_SDK_VERSION,
+                Some(&mut device),
+                None,
+                Some(&mut context),
+            )?;
+            let device: ID3D11Device =
+                device.ok_or_else(|| anyhow::anyhow!("D3D11 returned no device"))?;
+            let context: ID3D11DeviceContext =
+                context.ok_or_else(|| anyhow::anyhow!("D3D11 returned no device context"))?;
+
+            let adapter = device.cast::<IDXGIDevice>()?.GetAdapter()?;
+            let mut index = 0;
+            while let Ok(output) = adapter.EnumOutputs(index) {
+                let mut desc = DXGI_OUTPUT_DESC::default();
+                output.GetDesc(&mut desc)?;
+                let bounds = desc.DesktopCoordinates;
+                if (bounds.left..bounds.right).contains(&x)
+                    && (bounds.top..bounds.bottom).contains(&y)
+                {
+                    let duplication = output.cast::<IDXGIOutput1>()?.DuplicateOutput(&device)?;
+                    return Ok(Self {
+                        device,
+                        context,
+                        duplication,
+                        bounds,
+                        last_frame: None,
+                    });
+                }
+                index += 1;
+            }
+        }
+
+        Err(anyhow::anyhow!(
+            "no display output contains the mGBA window at ({}, {})",
+            x,
+            y
+        ))
+    }
+
+    /// Copies the next desktop frame into `last_frame`, keeping the previous one when
+    /// nothing changed within the timeout
+    fn refresh(&mut self) -> Result<()> {
+        // SAFETY: the acquired frame is released before returning and the staging
+        // texture is unmapped after copying
+        unsafe {
+            let mut info = DXGI_OUTDUPL_FRAME_INFO::default();
+            let mut resource: Option<IDXGIResource> = None;
+            match self
+                .duplication
+                .AcquireNextFrame(ACQUIRE_TIMEOUT_MS, &mut info, &mut resource)
+            {
+                Ok(()) => {}
+                Err(e) if e.code() == DXGI_ERROR_WAIT_TIMEOUT && self.last_frame.is_some() => {
+                    return Ok(())
+                }
+                Err(e) => return Err(e.into()),
+            }
+
+            let copied = self.copy_frame(resource);
+            let _ = self.duplication.ReleaseFrame();
+            self.last_frame = Some(copied?);
+        }
+        Ok(())
+    }
+
+    unsafe fn copy_frame(&self, resource: Option<IDXGIResource>) -> Result<(Vec<u8>, u32, u32)> {
+        let texture: ID3D11Texture2D = resource
+            .ok_or_else(|| anyhow::anyhow!("DXGI returned no desktop resource"))?
+            .cast()?;
+
+        let mut desc = D3D11_TEXTURE2D_DESC::default();
+        texture.GetDesc(&mut desc);
+        desc.Usage = D3D11_USAGE_STAGING;
+        desc.BindFlags = 0;
+        desc.CPUAccessFlags = D3D11_CPU_ACCESS_READ.0 as u32;
+        desc.MiscFlags = 0;
+
+        let mut staging = None;
+        self.device
+            .CreateTexture2D(&desc, None, Some(&mut staging))?;
+        let staging =
+            staging.ok_or_else(|| anyhow::anyhow!("D3D11 returned no staging texture"))?;
+        self.context.CopyResource(&staging, &texture);
+
+        let mut mapped = D3D11_MAPPED_SUBRESOURCE::default();
+        self.context
+            .Map(&staging, 0, D3D11_MAP_READ, 0, Some(&mut mapped))?;
+
+        let (width, height) = (desc.Width, desc.Height);
+        let row_bytes = width as usize * 4;
+        let mut bgra = Vec::with_capacity(row_bytes * height as usize);
+        for row in 0..height as usize {
+            let start = (mapped.pData as *const u8).add(row * mapped.RowPitch as usize);
+            bgra.extend_from_slice(std::slice::from_raw_parts(start, row_bytes));
+        }
+        self.context.Unmap(&staging, 0);
+
+        Ok((bgra, width, height))
+    }
+}
+
+/// Captures the mGBA window through DXGI desktop duplication
+pub struct DxgiCapture {
+    duplication: Mutex<Option<Duplication>>,
+}
+
+impl DxgiCapture {
+    /// Checks that desktop duplication is available on the primary output
+    pub fn new() -> Result<Self> {
+        let probe = Duplication::for_point(0, 0)?;
+        Ok(Self {
+            duplication: Mutex::new(Some(probe)),
+        })
+    }
+}
+
+impl WindowCapture for DxgiCapture {
+    fn capture(&self, query: &WindowQuery) -> Result<RgbaImage> {
+        let window = find_mgba_window(query)?;
+        let (left, top) = (window.x(), window.y());
+        let (width, height) = (window.width(), window.height());
+
+        let mut guard = self.duplication.lock().unwrap();
+
+        // Re-duplicate when the window moved to another output
+        let on_output = guard.as_ref().is_some_and(|duplication| {
+            (duplication.bounds.left..duplication.bounds.right).contains(&left)
+                && (duplication.bounds.top..duplication.bounds.bottom).contains(&top)
+        });
+        if !on_output {
+            *guard = Some(Duplication::for_point(left, top)?);
+        }
+        let duplication = guard.as_mut().unwrap();
+
+        if let Err(e) = duplication.refresh() {
+            // Access is lost on mode changes and secure desktop switches; start over next frame
+            let lost = e
+                .downcast_ref::<windows::core::Error>()
+                .is_some_and(|e| e.code() == DXGI_ERROR_ACCESS_LOST);
+            if lost {
+                *guard = None;
+            }
+            return Err(e);
+        }
+
+        let (bgra, desktop_width, desktop_height) = duplication.last_frame.as_ref().unwrap();
+        let origin_x = (left - duplication.bounds.left).max(0) as u32;
+        let origin_y = (top - duplication.bounds.top).max(0) as u32;
+        let width = width.min(desktop_width.saturating_sub(origin_x));
+        let height = height.min(desktop_height.saturating_sub(origin_y));
+
+        let mut rgba = Vec::with_capacity((width * height * 4) as usize);
+        for y in origin_y..origin_y + height {
+            let start = ((y * desktop_width + origin_x) * 4) as usize;
+            for pixel in bgra[start..start + (width * 4) as usize].chunks_exact(4) {
+                rgba.extend_from_slice(&[pixel[2], pixel[1], pixel[0], 255]);
+            }
+        }
+
+        RgbaImage::from_raw(width, height, rgba)
+            .ok_or_else(|| anyhow::anyhow!("Failed to convert frame to RgbaImage"))
+    }
+}
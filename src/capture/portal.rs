@@ -0,0 +1,86 @@
+//! xdg-desktop-portal screenshot capture for Wayland sessions
+//!
+//! Wayland compositors don't let clients read other windows' contents, so frames are
+//! requested from the desktop portal's Screenshot interface instead. The portal only
+//! takes whole-screen screenshots and hands them back as image files, so each frame is
+//! read from disk and cropped to the mGBA window, whose position is known while mGBA runs
+//! under XWayland. Every request is a D-Bus round trip plus a PNG encode and decode, which
+//! limits this backend to a few frames per second.
+
+use super::WindowCapture;
+use crate::window::{find_mgba_window, WindowQuery};
+use anyhow::Result;
+use ashpd::desktop::screenshot::Screenshot;
+use image::RgbaImage;
+use tokio::runtime::Runtime;
+
+/// Captures the mGBA window through the Screenshot portal
+pub struct PortalCapture {
+    /// Runs portal requests, which are async, from the synchronous `capture`
+    runtime: Runtime,
+}
+
+impl PortalCapture {
+    /// Checks that the Screenshot portal answers without asking the user each time
+    pub fn new() -> Result<Self> {
+        let source = Self {
+            runtime: tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()?,
+        };
+        source.screenshot()?;
+        Ok(source)
+    }
+
+    /// Takes a non-interactive whole-screen screenshot
+    fn screenshot(&self) -> Result<RgbaImage> {
+        // Frame capture runs inside the main runtime, which can't block on another one
+        let uri = std::thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    self.runtime.block_on(async {
+                        let response = Screenshot::request()
+                            .interactive(false)
+                            .modal(false)
+                            .send()
+                            .await?
+                            .response()?;
+                        Ok::<_, ashpd::Error>(response.uri().clone())
+                    })
+                })
+                .join()
+                .map_err(|_| anyhow::anyhow!("Screenshot portal request panicked"))
+        })?
+        .map_err(|e| anyhow::anyhow!("Screenshot portal request failed: {}", e))?;
+
+        let path = uri
+            .to_file_path()
+            .map_err(|_| anyhow::anyhow!("Screenshot portal returned a non-file URI: {}", uri))?;
+        let image = image::open(&path);
+        // The portal saves every screenshot (often to ~/Pictures); don't leave them behind
+        let _ = std::fs::remove_file(&path);
+        Ok(image?.to_rgba8())
+    }
+}
+
+impl WindowCapture for PortalCapture {
+    fn capture(&self, query: &WindowQuery) -> Result<RgbaImage> {
+        let screen = self.screenshot()?;
+        let window = find_mgba_window(query)?;
+
+        let x = (window.x().max(0) as u32).min(screen.width());
+        let y = (window.y().max(0) as u32).min(screen.height());
+        let width = window.width().min(screen.width() - x);
+        let height = window.height().min(screen.height() - y);
+        if width == 0 || height == 0 {
+            return Err(anyhow::anyhow!(
+                "mGBA window at ({}, {}) is outside the {}x{} screenshot",
+                window.x(),
+                window.y(),
+                screen.width(),
+                screen.height()
+            ));
+        }
+        Ok(image::imageops::crop_imm(&screen, x, y, width, height).to_image())
+    }
+}
@@ -0,0 +1,193 @@
+//! MIT-SHM capture of the mGBA window on X11
+//!
+//! The X server writes the window contents straight into a shared memory segment,
+//! which avoids copying every frame through the socket.
+
+use super::WindowCapture;
+use crate::window::{find_mgba_window, WindowQuery};
+use anyhow::Result;
+use image::RgbaImage;
+use std::sync::Mutex;
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::protocol::shm::{self, ConnectionExt as _};
+use x11rb::protocol::xproto::{ConnectionExt as _, ImageFormat, ImageOrder};
+use x11rb::rust_connection::RustConnection;
+
+/// A SysV shared memory segment attached both locally and on the X server
+struct Segment {
+    seg: shm::Seg,
+    addr: *mut u8,
+    size: usize,
+}
+
+// The segment is only accessed while holding the capture's mutex
+unsafe impl Send for Segment {}
+
+impl Segment {
+    fn create(conn: &RustConnection, size: usize) -> Result<Self> {
+        // SAFETY: plain SysV shm calls; every failure path detaches what was attached
+        unsafe {
+            let shmid = libc::shmget(libc::IPC_PRIVATE, size, libc::IPC_CREAT | 0o600);
+            if shmid < 0 {
+                return Err(anyhow::anyhow!(
+                    "shmget failed: {}",
+                    std::io::Error::last_os_error()
+                ));
+            }
+
+            let addr = libc::shmat(shmid, std::ptr::null(), 0);
+            if addr as isize == -1 {
+                let error = std::io::Error::last_os_error();
+                libc::shmctl(shmid, libc::IPC_RMID, std::ptr::null_mut());
+                return Err(anyhow::anyhow!("shmat failed: {}", error));
+            }
+
+            let seg = conn.generate_id()?;
+            let attached = conn
+                .shm_attach(seg, shmid as u32, false)
+                .map_err(anyhow::Error::from)
+                .and_then(|cookie| cookie.check().map_err(anyhow::Error::from));
+
+            // The segment stays alive until both sides detach
+            libc::shmctl(shmid, libc::IPC_RMID, std::ptr::null_mut());
+
+            if let Err(e) = attached {
+                libc::shmdt(addr);
+                return Err(anyhow::anyhow!("X server could not attach segment: {}", e));
+            }
+
+            Ok(Self {
+                seg,
+                addr: addr as *mut u8,
+                size,
+            })
+        }
+    }
+
+    fn release(self, conn: &RustConnection) {
+        let _ = conn.shm_detach(self.seg);
+        let _ = conn.flush();
+        // SAFETY: addr came from shmat and is not used after this point
+        unsafe {
+            libc::shmdt(self.addr as *const libc::c_void);
+        }
+    }
+}
+
+/// Captures the mGBA window through the X11 MIT-SHM extension
+pub struct X11ShmCapture {
+    conn: RustConnection,
+    /// Shared memory the frames are written to, grown for larger windows
+    segment: Mutex<Option<Segment>>,
+}
+
+impl X11ShmCapture {
+    /// Connects to the X server named by `DISPLAY` and checks for MIT-SHM support
+    pub fn connect() -> Result<Self> {
+        let (conn, _) = x11rb::connect(None)
+            .map_err(|e| anyhow::anyhow!("cannot connect to the X server: {}", e))?;
+        if conn
+            .extension_information(shm::X11_EXTENSION_NAME)?
+            .is_none()
+        {
+            return Err(anyhow::anyhow!(
+                "the X server does not support the MIT-SHM extension"
+            ));
+        }
+
+        Ok(Self {
+            conn,
+            segment: Mutex::new(None),
+        })
+    }
+
+    fn capture_window(&self, segment: &mut Option<Segment>, window: u32) -> Result<RgbaImage> {
+        let geometry = self.conn.get_geometry(window)?.reply()?;
+        let (width, height) = (geometry.width as u32, geometry.height as u32);
+
+        let setup = self.conn.setup();
+        let bits_per_pixel = setup
+            .pixmap_formats
+            .iter()
+            .find(|format| format.depth == geometry.depth)
+            .map(|format| format.bits_per_pixel)
+            .unwrap_or(0);
+        if bits_per_pixel != 32 {
+            return Err(anyhow::anyhow!(
+                "unsupported window depth {} ({} bits per pixel)",
+                geometry.depth,
+                bits_per_pixel
+            ));
+        }
+
+        let size = (width * height * 4) as usize;
+        if segment.as_ref().is_none_or(|segment| segment.size < size) {
+            if let Some(old) = segment.take() {
+                old.release(&self.conn);
+            }
+            *segment = Some(Segment::create(&self.conn, size)?);
+        }
+        let segment = segment.as_ref().unwrap();
+
+        self.conn
+            .shm_get_image(
+                window,
+                0,
+                0,
+                width as u16,
+                height as u16,
+                !0,
+                ImageFormat::Z_PIXMAP.into(),
+                segment.seg,
+                0,
+            )?
+            .reply()?;
+
+        // SAFETY: the server has finished writing `size` bytes into the segment
+        let data = unsafe { std::slice::from_raw_parts(segment.addr, size) };
+        let lsb_first = setup.image_byte_order == ImageOrder::LSB_FIRST;
+
+        // 32bpp Z-pixmaps are BGRX on little-endian servers and XRGB on big-endian ones
+        let mut rgba = Vec::with_capacity(size);
+        for pixel in data.chunks_exact(4) {
+            if lsb_first {
+                rgba.extend_from_slice(&[pixel[2], pixel[1], pixel[0], 255]);
+            } else {
+                rgba.extend_from_slice(&[pixel[1], pixel[2], pixel[3], 255]);
+            }
+        }
+
+        RgbaImage::from_raw(width, height, rgba)
+            .ok_or_else(|| anyhow::anyhow!("Failed to convert frame to RgbaImage"))
+    }
+}
+
+impl WindowCapture for X11ShmCapture {
+    fn capture(&self, query: &WindowQuery) -> Result<RgbaImage> {
+        let mut segment = self.segment.lock().unwrap();
+
+        if let Some(window) = query.chosen_window() {
+            if let Ok(image) = self.capture_window(&mut segment, window) {
+                return Ok(image);
+            }
+        }
+
+        // The chosen window is gone (e.g. a new mGBA launch), so look it up again
+        let window = find_mgba_window(query)?.id();
+        self.capture_window(&mut segment, window)
+    }
+
+    fn dimensions(&self, query: &WindowQuery) -> Result<(u32, u32)> {
+        let window = find_mgba_window(query)?.id();
+        let geometry = self.conn.get_geometry(window)?.reply()?;
+        Ok((geometry.width as u32, geometry.height as u32))
+    }
+}
+
+impl Drop for X11ShmCapture {
+    fn drop(&mut self) {
+        if let Some(segment) = self.segment.get_mut().unwrap().take() {
+            segment.release(&self.conn);
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! The project's cargo configuration
+//!
+//! agb projects build for `thumbv4t-none-eabi` and set the emulator as the cargo runner
+//! for that target in `.cargo/config.toml`, so `cargo run` starts mGBA. Reading the
+//! runner tells the tool which emulator frontend to expect before anything is built.
+
+use crate::window::Emulator;
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+/// Target triple of the GBA
+const GBA_TARGET: &str = "thumbv4t-none-eabi";
+
+/// The runner configured for the GBA target
+pub struct Runner {
+    /// Program as written in the config, with any arguments stripped
+    pub program: String,
+}
+
+impl Runner {
+    /// The emulator frontend the runner starts, or `None` for wrapper scripts and other
+    /// programs that aren't recognisably mGBA
+    pub fn emulator(&self) -> Option<Emulator> {
+        program_emulator(&self.program)
+    }
+
+    /// Finds the runner program, either as a path relative to the project or on `PATH`
+    pub fn locate(&self, project_dir: &Path) -> Option<PathBuf> {
+        locate_program(&self.program, project_dir)
+    }
+}
+
+/// The emulator frontend an executable is, judged by its name (`mgba-qt.AppImage` is
+/// mGBA-Qt), or `None` if it isn't recognisably mGBA
+pub fn program_emulator(program: &str) -> Option<Emulator> {
+    let name = Path::new(program)
+        .file_stem()?
+        .to_string_lossy()
+        .to_lowercase();
+    match name.as_str() {
+        "mgba-qt" => Some(Emulator::MgbaQt),
+        "mgba-sdl" => Some(Emulator::MgbaSdl),
+        // Linux packages name the SDL frontend plain `mgba`; elsewhere that is mGBA-Qt
+        "mgba" if cfg!(target_os = "linux") => Some(Emulator::MgbaSdl),
+        "mgba" => Some(Emulator::MgbaQt),
+        _ => None,
+    }
+}
+
+/// Finds a program, either as a path relative to the project or on `PATH`
+pub fn locate_program(program: &str, project_dir: &Path) -> Option<PathBuf> {
+    let program = Path::new(program);
+    if program.components().count() > 1 {
+        let path = project_dir.join(program);
+        return path.is_file().then_some(path);
+    }
+
+    let extensions: &[&str] = if cfg!(target_os = "windows") {
+        &["", "exe", "bat", "cmd"]
+    } else {
+        &[""]
+    };
+    std::env::split_paths(&std::env::var_os("PATH")?).find_map(|dir| {
+        extensions
+            .iter()
+            .map(|extension| {
+                // Appended rather than replaced, as names can contain dots of their own,
+                // such as `mgba-qt.AppImage`
+                let mut name = program.as_os_str().to_owned();
+                if !extension.is_empty() {
+                    name.push(".");
+                    name.push(extension);
+                }
+                dir.join(name)
+            })
+            .find(|path| path.is_file())
+    })
+}
+
+/// The parsed `.cargo/config.toml` of a project
+pub struct CargoConfig {
+    config: toml::Table,
+}
+
+impl CargoConfig {
+    /// Reads the project's `.cargo/config.toml` (or the older `.cargo/config`), returning
+    /// `None` if there is none
+    pub fn load(project_dir: &Path) -> Result<Option<Self>> {
+        let Some(path) = ["config.toml", "config"]
+            .iter()
+            .map(|name| project_dir.join(".cargo").join(name))
+            .find(|path| path.is_file())
+        else {
+            return Ok(None);
+        };
+
+        let contents = std::fs::read_to_string(&path)?;
+        let config = contents
+            .parse::<toml::Table>()
+            .map_err(|e| anyhow::anyhow!("Failed to parse {}: {}", path.display(), e))?;
+        Ok(Some(Self { config }))
+    }
+
+    /// The default build target (`build.target`)
+    fn build_target(&self) -> Option<&str> {
+        self.config.get("build")?.get("target")?.as_str()
+    }
+
+    /// Whether the project builds for the GBA
+    pub fn targets_gba(&self) -> bool {
+        self.build_target()
+            .is_some_and(|target| target.starts_with(GBA_TARGET))
+            || self
+                .config
+                .get("target")
+                .and_then(|targets| targets.get(GBA_TARGET))
+                .is_some()
+    }
+
+    /// The runner for the active target: `build.target` when it is set, otherwise the
+    /// GBA target
+    pub fn runner(&self) -> Option<Runner> {
+        let target = self.build_target().unwrap_or(GBA_TARGET);
+        let runner = self.config.get("target")?.get(target)?.get("runner")?;
+
+        // Either a command line string or an array of program and arguments
+        let program = match runner {
+            toml::Value::String(command) => command.split_whitespace().next()?.to_string(),
+            toml::Value::Array(parts) => parts.first()?.as_str()?.to_string(),
+            _ => return None,
+        };
+        Some(Runner { program })
+    }
+}
@@ -0,0 +1,78 @@
+//! The project's `Cargo.toml`, and the cargo features each binary is built with
+//!
+//! A binary with `required-features` in its `[[bin]]` table is skipped by cargo unless
+//! those features are enabled, so `cargo build --bin <name>` fails for it. Reading them
+//! lets such binaries build without any configuration.
+
+use anyhow::Result;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// The features a binary is built and run with
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Features {
+    /// Features enabled with `--features`
+    pub features: Vec<String>,
+    /// Enable every feature (`--all-features`)
+    pub all_features: bool,
+    /// Leave out the default features (`--no-default-features`)
+    pub no_default_features: bool,
+}
+
+impl Features {
+    /// The cargo arguments that select these features
+    pub fn arguments(&self) -> Vec<String> {
+        let mut arguments = Vec::new();
+        if self.all_features {
+            arguments.push("--all-features".to_string());
+        }
+        if self.no_default_features {
+            arguments.push("--no-default-features".to_string());
+        }
+        if !self.features.is_empty() && !self.all_features {
+            arguments.push("--features".to_string());
+            arguments.push(self.features.join(","));
+        }
+        arguments
+    }
+
+    /// Adds `features` that aren't enabled yet, keeping the order they are given in
+    pub fn require(&mut self, features: &[String]) {
+        for feature in features {
+            if !self.features.contains(feature) {
+                self.features.push(feature.clone());
+            }
+        }
+    }
+}
+
+/// The `required-features` of each binary in the project's `Cargo.toml` that declares
+/// any, by binary name. A project without a readable manifest has none.
+pub fn required_features(project_dir: &Path) -> Result<HashMap<String, Vec<String>>> {
+    let path = project_dir.join("Cargo.toml");
+    let Ok(contents) = std::fs::read_to_string(&path) else {
+        return Ok(HashMap::new());
+    };
+    let manifest = contents
+        .parse::<toml::Table>()
+        .map_err(|e| anyhow::anyhow!("Failed to parse {}: {}", path.display(), e))?;
+
+    let bins = manifest
+        .get("bin")
+        .and_then(toml::Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    Ok(bins
+        .iter()
+        .filter_map(|bin| {
+            let name = bin.get("name")?.as_str()?;
+            let features: Vec<String> = bin
+                .get("required-features")?
+                .as_array()?
+                .iter()
+                .filter_map(|feature| feature.as_str().map(str::to_string))
+                .collect();
+            (!features.is_empty()).then(|| (name.to_string(), features))
+        })
+        .collect())
+}
@@ -0,0 +1,637 @@
+//! The project's capture configuration file, `capture.toml` or `capture.json`
+//!
+//! Both hold the same structure; TOML reads better with long sequences, which can be
+//! written as multi-line strings. A project has one or the other, never both. Commands
+//! that write settings back, like `record` and `calibrate`, edit the file in its own
+//! format and keep the rest of it as it is, comments included, and create
+//! `capture.toml` in projects that have neither. `--config` names a file to use instead,
+//! and a user-level `config.toml` or `config.json` in the platform's config directory
+//! provides settings for every project.
+//!
+//! The top-level `version` says which version of the format a file is written in, 1 when
+//! it is missing. Files of an older version are brought up to date in memory by the
+//! [`MIGRATIONS`] since, and `--migrate-config` writes the result back.
+//!
+//! A top-level `extends` names another config file, relative to the one that has it,
+//! which is read first; the file is then merged over it table by table.
+
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::de::{DeserializeOwned, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::path::{Path, PathBuf};
+
+pub const TOML_NAME: &str = "capture.toml";
+pub const JSON_NAME: &str = "capture.json";
+
+/// Version of the config format this build reads and writes
+pub const CONFIG_VERSION: u64 = 1;
+
+/// A change to the config format, which turns a config of the version before it into
+/// one of `version` with the same meaning
+pub struct Migration {
+    pub version: u64,
+    pub description: &'static str,
+    pub migrate: fn(&mut serde_json::Value),
+}
+
+/// Every change to the config format, oldest first. Version 1 is the format of all
+/// files written before `version` existed, so there are none yet.
+const MIGRATIONS: &[Migration] = &[];
+
+/// A version of the config format, with the migrations that lead up to it
+pub struct Schema {
+    pub version: u64,
+    pub migrations: &'static [Migration],
+}
+
+/// The config format this build reads and writes
+pub const SCHEMA: Schema = Schema {
+    version: CONFIG_VERSION,
+    migrations: MIGRATIONS,
+};
+
+/// How a config file is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ConfigFormat {
+    Toml,
+    Json,
+}
+
+/// A value written into the config by [`ConfigFile::set`]
+pub enum ConfigValue<'a> {
+    Integer(u64),
+    Float(f64),
+    String(&'a str),
+}
+
+impl ConfigValue<'_> {
+    /// The value as it reads back from the file
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            ConfigValue::Integer(number) => (*number).into(),
+            ConfigValue::Float(number) => (*number).into(),
+            ConfigValue::String(text) => (*text).into(),
+        }
+    }
+
+    fn to_toml(&self) -> toml_edit::Item {
+        match self {
+            ConfigValue::Integer(number) => toml_edit::value(*number as i64),
+            ConfigValue::Float(number) => toml_edit::value(*number),
+            ConfigValue::String(text) => toml_edit::value(*text),
+        }
+    }
+}
+
+/// A config file, which may not exist yet
+#[derive(Debug, Clone)]
+pub struct ConfigFile {
+    pub path: PathBuf,
+    pub format: ConfigFormat,
+    /// How messages refer to the file
+    name: String,
+}
+
+impl ConfigFile {
+    /// The config file in `dir` under either name, if there is one. Having both is an
+    /// error, so neither silently goes unused.
+    fn find_in(dir: &Path, toml_name: &str, json_name: &str) -> Result<Option<Self>> {
+        let toml_path = dir.join(toml_name);
+        let json_path = dir.join(json_name);
+        let (path, format, name) = match (toml_path.exists(), json_path.exists()) {
+            (true, true) => {
+                return Err(anyhow::anyhow!(
+                    "{} has both {} and {}; keep one of them",
+                    dir.display(),
+                    toml_name,
+                    json_name
+                ))
+            }
+            (true, false) => (toml_path, ConfigFormat::Toml, toml_name),
+            (false, true) => (json_path, ConfigFormat::Json, json_name),
+            (false, false) => return Ok(None),
+        };
+        Ok(Some(Self {
+            path,
+            format,
+            name: name.to_string(),
+        }))
+    }
+
+    /// The file at `path`, given with `--config`; its extension gives the format
+    fn at(path: &Path) -> Result<Self> {
+        let format = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => ConfigFormat::Toml,
+            Some("json") => ConfigFormat::Json,
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Can't tell the format of {} (expected a .toml or .json file)",
+                    path.display()
+                ))
+            }
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            format,
+            name: path.display().to_string(),
+        })
+    }
+
+    /// The project's config file: the one given with `--config`, which has to exist, or
+    /// else the one in `project_dir` if there is one
+    pub fn find(project_dir: &Path, explicit: Option<&Path>) -> Result<Option<Self>> {
+        match explicit {
+            Some(path) if !path.exists() => Err(anyhow::anyhow!(
+                "Config file {} does not exist",
+                path.display()
+            )),
+            Some(path) => Self::at(path).map(Some),
+            None => Self::find_in(project_dir, TOML_NAME, JSON_NAME),
+        }
+    }
+
+    /// The file settings are written to: the one given with `--config`, the project's
+    /// existing one, or a new `capture.toml`
+    pub fn for_writing(project_dir: &Path, explicit: Option<&Path>) -> Result<Self> {
+        if let Some(path) = explicit {
+            return Self::at(path);
+        }
+        Ok(Self::find(project_dir, None)?
+            .unwrap_or_else(|| Self::in_project(project_dir, ConfigFormat::Toml)))
+    }
+
+    /// The project's config file in `format`, whether or not it exists
+    pub fn in_project(project_dir: &Path, format: ConfigFormat) -> Self {
+        let name = match format {
+            ConfigFormat::Toml => TOML_NAME,
+            ConfigFormat::Json => JSON_NAME,
+        };
+        Self {
+            path: project_dir.join(name),
+            format,
+            name: name.to_string(),
+        }
+    }
+
+    /// The user-level config, `config.toml` or `config.json` in `agbrs-capture` under the
+    /// platform's config directory, if there is one
+    pub fn user() -> Result<Option<Self>> {
+        match user_config_dir() {
+            Some(dir) => Self::find_in(&dir, "config.toml", "config.json").map(|file| {
+                file.map(|file| Self {
+                    name: file.path.display().to_string(),
+                    ..file
+                })
+            }),
+            None => Ok(None),
+        }
+    }
+
+    /// File name, or the path of a file outside the project, for messages
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn read(&self) -> Result<String> {
+        std::fs::read_to_string(&self.path)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", self.name(), e))
+    }
+
+    /// Reads the file into `T`, migrated to [`CONFIG_VERSION`] first if it is older and
+    /// merged over the files it extends. Errors name the file and, unless it was migrated,
+    /// the line and column; in a merged config, of the file that sets the wrong value.
+    pub fn parse<T: DeserializeOwned>(&self) -> Result<T> {
+        self.parse_announcing(true)
+    }
+
+    /// Like [`Self::parse`], without printing the migrations and files extended
+    pub fn parse_quietly<T: DeserializeOwned>(&self) -> Result<T> {
+        self.parse_announcing(false)
+    }
+
+    fn parse_announcing<T: DeserializeOwned>(&self, announce: bool) -> Result<T> {
+        let mut files = Vec::new();
+        let (config, changed) = self.resolve(announce, &mut Vec::new(), &mut files)?;
+        if changed {
+            // The merged value has no positions, but the file that set the wrong value
+            // fails the same way on its own text
+            return serde_json::from_value(config).map_err(|e| {
+                let message = e.to_string();
+                files
+                    .iter()
+                    .find_map(|file| file.locate_error::<T>(&message))
+                    .unwrap_or_else(|| anyhow::anyhow!("Failed to parse {}: {}", self.name(), e))
+            });
+        }
+        let content = self.read()?;
+        match self.format {
+            ConfigFormat::Toml => toml::from_str(&content)
+                .map_err(|e| anyhow::anyhow!("Failed to parse {}: {}", self.name(), e)),
+            ConfigFormat::Json => serde_json::from_str(&content)
+                .map_err(|e| anyhow::anyhow!("Failed to parse {}: {}", self.name(), e)),
+        }
+    }
+
+    /// The error of reading the file's own text into `T`, with its line and column, if it
+    /// is the error `message` of the merged config
+    fn locate_error<T: DeserializeOwned>(&self, message: &str) -> Option<anyhow::Error> {
+        let content = self.read().ok()?;
+        let error = match self.format {
+            ConfigFormat::Toml => {
+                let error = toml::from_str::<T>(&content).err()?;
+                (error.message() == message).then(|| error.to_string())?
+            }
+            ConfigFormat::Json => {
+                let error = serde_json::from_str::<T>(&content).err()?.to_string();
+                error.starts_with(message).then_some(error)?
+            }
+        };
+        Some(anyhow::anyhow!(
+            "Failed to parse {}: {}",
+            self.name(),
+            error
+        ))
+    }
+
+    /// The file as a generic value, migrated and merged over the file its `extends` names,
+    /// and whether that made it differ from what the file says. `chain` holds the files
+    /// that extend this one, to catch cycles, and `files` gets each file read that needed
+    /// no migration, whose text says where a value is.
+    fn resolve(
+        &self,
+        announce: bool,
+        chain: &mut Vec<PathBuf>,
+        files: &mut Vec<Self>,
+    ) -> Result<(serde_json::Value, bool)> {
+        let mut config = self.read_value()?;
+        let migrated = self
+            .migrate_value(&mut config, &SCHEMA, announce)?
+            .is_some();
+        if !migrated {
+            files.push(self.clone());
+        }
+        if migrated && announce {
+            println!(
+                "Note: {} was migrated in memory; --migrate-config updates the file",
+                self.name()
+            );
+        }
+        let Some(extends) = config
+            .as_object_mut()
+            .and_then(|object| object.remove("extends"))
+        else {
+            return Ok((config, migrated));
+        };
+        let extends = extends.as_str().ok_or_else(|| {
+            anyhow::anyhow!(
+                "extends in {} must be the path of another config file",
+                self.name()
+            )
+        })?;
+
+        let own_path = self.path.canonicalize()?;
+        if chain.contains(&own_path) {
+            let cycle: Vec<String> = chain
+                .iter()
+                .chain([&own_path])
+                .map(|path| path.display().to_string())
+                .collect();
+            return Err(anyhow::anyhow!(
+                "Config files extend each other in a cycle: {}",
+                cycle.join(" -> ")
+            ));
+        }
+        chain.push(own_path);
+
+        // Relative to the extending file, wherever the tool runs from
+        let base_path = self.path.parent().unwrap_or(Path::new("")).join(extends);
+        if !base_path.exists() {
+            return Err(anyhow::anyhow!(
+                "{} extends {}, which does not exist",
+                self.name(),
+                base_path.display()
+            ));
+        }
+        let base = Self::at(&base_path)?;
+        if announce {
+            println!("{} extends {}", self.name(), base.name());
+        }
+        let (mut merged, _) = base.resolve(announce, chain, files)?;
+        merge_config(&mut merged, config);
+        Ok((merged, true))
+    }
+
+    /// The file as a generic value, whatever its format
+    fn read_value(&self) -> Result<serde_json::Value> {
+        let content = self.read()?;
+        match self.format {
+            ConfigFormat::Toml => toml::from_str(&content)
+                .map_err(|e| anyhow::anyhow!("Failed to parse {}: {}", self.name(), e)),
+            ConfigFormat::Json => serde_json::from_str(&content)
+                .map_err(|e| anyhow::anyhow!("Failed to parse {}: {}", self.name(), e)),
+        }
+    }
+
+    /// Runs the migrations of `schema` that `config` is missing, describing each if
+    /// `announce` is set, and returns the version it had, or `None` when it is current.
+    /// A version newer than `schema` is an error.
+    fn migrate_value(
+        &self,
+        config: &mut serde_json::Value,
+        schema: &Schema,
+        announce: bool,
+    ) -> Result<Option<u64>> {
+        let version = match config.get("version") {
+            None => 1,
+            Some(version) => version
+                .as_u64()
+                .filter(|version| *version >= 1)
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Invalid version {} in {} (expected a whole number from 1)",
+                        version,
+                        self.name()
+                    )
+                })?,
+        };
+        if version > schema.version {
+            return Err(anyhow::anyhow!(
+                "{} is written for version {} of the config format, but this agbrs-capture only understands up to version {}; please upgrade agbrs-capture",
+                self.name(),
+                version,
+                schema.version
+            ));
+        }
+        let pending: Vec<&Migration> = schema
+            .migrations
+            .iter()
+            .filter(|migration| migration.version > version)
+            .collect();
+        if pending.is_empty() {
+            return Ok(None);
+        }
+        for migration in pending {
+            if announce {
+                println!(
+                    "Note: migrating {} to version {}: {}",
+                    self.name(),
+                    migration.version,
+                    migration.description
+                );
+            }
+            (migration.migrate)(config);
+        }
+        if let Some(object) = config.as_object_mut() {
+            object.insert("version".to_string(), schema.version.into());
+        }
+        Ok(Some(version))
+    }
+
+    /// Rewrites the file in the current version of the format and returns the version it
+    /// had. A file that only lacks `version` keeps its contents and comments and gets
+    /// the field; a migrated one is written anew.
+    pub fn migrate(&self) -> Result<u64> {
+        self.migrate_to(&SCHEMA)
+    }
+
+    /// Like [`Self::migrate`], to the version of `schema`
+    pub fn migrate_to(&self, schema: &Schema) -> Result<u64> {
+        let mut config = self.read_value()?;
+        match self.migrate_value(&mut config, schema, true)? {
+            Some(version) => {
+                let content = match self.format {
+                    ConfigFormat::Toml => toml::to_string_pretty(&config)?,
+                    ConfigFormat::Json => serde_json::to_string_pretty(&config)? + "\n",
+                };
+                std::fs::write(&self.path, content)
+                    .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", self.name(), e))?;
+                Ok(version)
+            }
+            None => {
+                let version = config
+                    .get("version")
+                    .and_then(serde_json::Value::as_u64)
+                    .unwrap_or(1);
+                self.set(&["version"], ConfigValue::Integer(schema.version))?;
+                Ok(version)
+            }
+        }
+    }
+
+    /// Paths of the keys in the file that `parsed`, read from it with [`Self::parse`],
+    /// has no field for, like `binaries.demo.durring_capture`. Serde drops such keys
+    /// silently, so they are found by comparing the file with `parsed` written back out.
+    pub fn unknown_keys<T: Serialize>(&self, parsed: &T) -> Result<Vec<String>> {
+        let (raw, _) = self.resolve(false, &mut Vec::new(), &mut Vec::new())?;
+        let known = serde_json::to_value(parsed)?;
+        let mut unknown = Vec::new();
+        collect_unknown_keys(&raw, &known, "", &mut unknown);
+        Ok(unknown)
+    }
+
+    /// The value the file itself has at `keys`, not counting the files it extends, or
+    /// `None` when the file or the key doesn't exist
+    pub fn get(&self, keys: &[&str]) -> Result<Option<serde_json::Value>> {
+        if !self.path.exists() {
+            return Ok(None);
+        }
+        let mut value = &self.read_value()?;
+        for key in keys {
+            match value.get(key) {
+                Some(inner) => value = inner,
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(value.clone()))
+    }
+
+    /// Sets the value at `keys`, like `["settings", "input_latency_ms"]`, creating the
+    /// tables on the way and the file itself if needed
+    pub fn set(&self, keys: &[&str], value: ConfigValue) -> Result<()> {
+        self.set_all(&[(keys.to_vec(), value)])
+    }
+
+    /// Sets several values at once, like [`Self::set`], in a single write
+    pub fn set_all(&self, values: &[(Vec<&str>, ConfigValue)]) -> Result<()> {
+        let exists = self.path.exists();
+        let no_key = || anyhow::anyhow!("No key to set in {}", self.name());
+        let content = match self.format {
+            ConfigFormat::Toml => {
+                let mut document = match exists {
+                    true => self
+                        .read()?
+                        .parse::<toml_edit::DocumentMut>()
+                        .map_err(|e| anyhow::anyhow!("Failed to parse {}: {}", self.name(), e))?,
+                    false => toml_edit::DocumentMut::new(),
+                };
+                for (keys, value) in values {
+                    let (last, tables) = keys.split_last().ok_or_else(no_key)?;
+                    let mut table: &mut dyn toml_edit::TableLike = document.as_table_mut();
+                    for (depth, key) in tables.iter().enumerate() {
+                        table = table
+                            .entry(key)
+                            .or_insert(toml_edit::table())
+                            .as_table_like_mut()
+                            .ok_or_else(|| {
+                                anyhow::anyhow!(
+                                    "{} in {} is not a table",
+                                    tables[..=depth].join("."),
+                                    self.name()
+                                )
+                            })?;
+                    }
+                    table.insert(last, value.to_toml());
+                }
+                document.to_string()
+            }
+            ConfigFormat::Json => {
+                let mut config = match exists {
+                    true => serde_json::from_str(&self.read()?)
+                        .map_err(|e| anyhow::anyhow!("Failed to parse {}: {}", self.name(), e))?,
+                    false => serde_json::json!({}),
+                };
+                for (keys, value) in values {
+                    let (last, tables) = keys.split_last().ok_or_else(no_key)?;
+                    let mut object = config.as_object_mut().ok_or_else(|| {
+                        anyhow::anyhow!("{} does not contain a JSON object", self.name())
+                    })?;
+                    for (depth, key) in tables.iter().enumerate() {
+                        object = object
+                            .entry(*key)
+                            .or_insert_with(|| serde_json::json!({}))
+                            .as_object_mut()
+                            .ok_or_else(|| {
+                                anyhow::anyhow!(
+                                    "{} in {} is not an object",
+                                    tables[..=depth].join("."),
+                                    self.name()
+                                )
+                            })?;
+                    }
+                    object.insert(last.to_string(), value.to_json());
+                }
+                serde_json::to_string_pretty(&config)? + "\n"
+            }
+        };
+        std::fs::write(&self.path, content)
+            .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", self.name(), e))
+    }
+}
+
+/// Merges `over` into `base`: tables key by key, recursively, and anything else replaced.
+/// Arrays are replaced too, unless `over` has a `"..."` element, which stands for the
+/// elements of the array in `base`.
+pub fn merge_config(base: &mut serde_json::Value, over: serde_json::Value) {
+    use serde_json::Value;
+    let is_marker = |item: &Value| item.as_str() == Some("...");
+    match (base, over) {
+        (Value::Object(base), Value::Object(over)) => {
+            for (key, value) in over {
+                match base.get_mut(&key) {
+                    Some(base_value) => merge_config(base_value, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, Value::Array(items)) if items.iter().any(is_marker) => {
+            let mut inherited = match base.take() {
+                Value::Array(base_items) => base_items,
+                _ => Vec::new(),
+            };
+            let mut merged = Vec::new();
+            for item in items {
+                if is_marker(&item) {
+                    merged.append(&mut inherited);
+                } else {
+                    merged.push(item);
+                }
+            }
+            *base = Value::Array(merged);
+        }
+        (base, over) => *base = over,
+    }
+}
+
+/// Adds the keys of `raw` that `known` lacks to `unknown`, recursively. Keys set to null
+/// are `None` fields, which aren't written back.
+fn collect_unknown_keys(
+    raw: &serde_json::Value,
+    known: &serde_json::Value,
+    path: &str,
+    unknown: &mut Vec<String>,
+) {
+    match (raw, known) {
+        (serde_json::Value::Object(raw), serde_json::Value::Object(known)) => {
+            for (key, value) in raw {
+                let key_path = match path {
+                    "" => key.clone(),
+                    _ => format!("{}.{}", path, key),
+                };
+                match known.get(key) {
+                    Some(known) => collect_unknown_keys(value, known, &key_path, unknown),
+                    None if !value.is_null() => unknown.push(key_path),
+                    None => {}
+                }
+            }
+        }
+        (serde_json::Value::Array(raw), serde_json::Value::Array(known)) => {
+            for (index, (raw, known)) in raw.iter().zip(known).enumerate() {
+                collect_unknown_keys(raw, known, &format!("{}[{}]", path, index), unknown);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Names of the fields of the struct `T`, as its `Deserialize` implementation expects
+/// them; empty for anything else
+pub fn field_names<'de, T: Deserialize<'de>>() -> &'static [&'static str] {
+    let mut fields: &'static [&'static str] = &[];
+    let _ = T::deserialize(FieldNames(&mut fields));
+    fields
+}
+
+/// A deserializer that only records the field names a struct asks for
+struct FieldNames<'a>(&'a mut &'static [&'static str]);
+
+impl<'de> Deserializer<'de> for FieldNames<'_> {
+    type Error = serde::de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(serde::de::Error::custom("not a struct"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        *self.0 = fields;
+        Err(serde::de::Error::custom("field names recorded"))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes
+        byte_buf option unit unit_struct newtype_struct seq tuple tuple_struct map enum
+        identifier ignored_any
+    }
+}
+
+/// Where the user-level config lives on this platform
+fn user_config_dir() -> Option<PathBuf> {
+    let dir = if cfg!(target_os = "windows") {
+        PathBuf::from(std::env::var_os("APPDATA")?)
+    } else if cfg!(target_os = "macos") {
+        PathBuf::from(std::env::var_os("HOME")?).join("Library/Application Support")
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|config_home| !config_home.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?
+    };
+    Some(dir.join("agbrs-capture"))
+}
@@ -0,0 +1,147 @@
+//! Output of the emulator process
+//!
+//! The emulator's stdout and stderr are recorded line by line, so captures can start and
+//! stop on markers the ROM prints to the mGBA log, and written to a per-binary run log
+//! file. With `--inherit-child-output` they are passed through to the terminal as well.
+
+use anyhow::Result;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::process::Child;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+
+/// How often the recorded output is checked while waiting for a marker
+const MARKER_POLL_INTERVAL_MS: u64 = 20;
+
+/// Start of the message Rust prints for a panic, which agb forwards to the mGBA log
+const PANIC_MARKER: &str = "panicked at";
+
+/// Lines printed by every emulator process launched during this run
+#[derive(Clone, Default)]
+pub struct EmulatorLog {
+    lines: Arc<Mutex<Vec<String>>>,
+    /// Run log of the binary being captured, which receives new lines
+    file: Arc<Mutex<Option<File>>>,
+    /// Echo lines to the terminal
+    echo: bool,
+}
+
+impl EmulatorLog {
+    pub fn new(echo: bool) -> Self {
+        Self {
+            echo,
+            ..Self::default()
+        }
+    }
+
+    /// Writes lines printed from now on to a new log file at `path`, replacing the
+    /// previous one. Lines already recorded stay in the earlier file.
+    pub fn write_to(&self, path: &Path) -> Result<()> {
+        let file = File::create(path)
+            .map_err(|e| anyhow::anyhow!("Failed to create run log {}: {}", path.display(), e))?;
+        *self.file.lock().unwrap() = Some(file);
+        Ok(())
+    }
+
+    /// Takes over the piped stdout and stderr of a child. Returns the threads reading
+    /// them, which finish once the child has closed both.
+    pub fn attach(&self, child: &mut Child) -> Vec<JoinHandle<()>> {
+        let mut readers = Vec::new();
+        if let Some(stdout) = child.stdout.take() {
+            readers.push(self.tee(stdout, false));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            readers.push(self.tee(stderr, true));
+        }
+        readers
+    }
+
+    fn tee(&self, stream: impl Read + Send + 'static, to_stderr: bool) -> JoinHandle<()> {
+        let lines = self.lines.clone();
+        let file = self.file.clone();
+        let echo = self.echo;
+        thread::spawn(move || {
+            for line in BufReader::new(stream).lines().map_while(|line| line.ok()) {
+                if echo && to_stderr {
+                    let _ = writeln!(std::io::stderr(), "{}", line);
+                } else if echo {
+                    let _ = writeln!(std::io::stdout(), "{}", line);
+                }
+                if let Some(file) = file.lock().unwrap().as_mut() {
+                    let _ = writeln!(file, "{}", line);
+                }
+                lines.lock().unwrap().push(line);
+            }
+        })
+    }
+
+    /// Number of lines recorded so far, for use as the start of a marker search
+    pub fn line_count(&self) -> usize {
+        self.lines.lock().unwrap().len()
+    }
+
+    /// The last `count` lines recorded at or after `from`
+    pub fn tail(&self, from: usize, count: usize) -> Vec<String> {
+        let lines = self.lines.lock().unwrap();
+        let from = from.min(lines.len()).max(lines.len().saturating_sub(count));
+        lines[from..].to_vec()
+    }
+
+    /// Finds the first line at or after `from` that contains `marker`, returning the
+    /// position just after it
+    pub fn find(&self, marker: &str, from: usize) -> Option<usize> {
+        let lines = self.lines.lock().unwrap();
+        lines
+            .iter()
+            .enumerate()
+            .skip(from)
+            .find(|(_, line)| line.contains(marker))
+            .map(|(index, _)| index + 1)
+    }
+
+    /// Waits until a line containing `marker` appears at or after `from`. Returns the
+    /// position just after it, or `None` on timeout or shutdown.
+    pub async fn wait_for(
+        &self,
+        marker: &str,
+        from: usize,
+        timeout: Duration,
+        shutdown: &AtomicBool,
+    ) -> Option<usize> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(position) = self.find(marker, from) {
+                return Some(position);
+            }
+            if Instant::now() >= deadline || shutdown.load(Ordering::Relaxed) {
+                return None;
+            }
+            sleep(Duration::from_millis(MARKER_POLL_INTERVAL_MS)).await;
+        }
+    }
+
+    /// Returns the panic message logged at or after `from`: the "panicked at" line and,
+    /// for current Rust versions which put it on its own line, the message after it
+    pub fn panic_message(&self, from: usize) -> Option<String> {
+        let lines = self.lines.lock().unwrap();
+        let index = lines
+            .iter()
+            .skip(from)
+            .position(|line| line.contains(PANIC_MARKER))?
+            + from;
+
+        let mut message = lines[index].trim().to_string();
+        if message.trim_end().ends_with(':') {
+            if let Some(next) = lines.get(index + 1) {
+                message.push(' ');
+                message.push_str(next.trim());
+            }
+        }
+        Some(message)
+    }
+}
@@ -0,0 +1,53 @@
+//! Environment variables set for a binary's build and run
+//!
+//! Values can refer to the tool's own environment as `${NAME}`, so a path or secret
+//! doesn't have to be written into the config file. `$$` is a literal `$`.
+
+use anyhow::Result;
+
+/// `value` with each `${NAME}` replaced by that variable of the tool's environment
+pub fn interpolate(value: &str) -> Result<String> {
+    let mut interpolated = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(index) = rest.find('$') {
+        interpolated.push_str(&rest[..index]);
+        let after = &rest[index + 1..];
+        if let Some(after) = after.strip_prefix('$') {
+            interpolated.push('$');
+            rest = after;
+        } else if let Some(reference) = after.strip_prefix('{') {
+            let end = reference
+                .find('}')
+                .ok_or_else(|| anyhow::anyhow!("'${{' without a closing '}}' in '{}'", value))?;
+            let name = &reference[..end];
+            if name.is_empty() {
+                return Err(anyhow::anyhow!("empty '${{}}' in '{}'", value));
+            }
+            let variable = std::env::var(name).map_err(|_| {
+                anyhow::anyhow!(
+                    "environment variable {} is not set (referred to as ${{{}}}; write $$ for a literal $)",
+                    name,
+                    name
+                )
+            })?;
+            interpolated.push_str(&variable);
+            rest = &reference[end + 1..];
+        } else {
+            interpolated.push('$');
+            rest = after;
+        }
+    }
+    interpolated.push_str(rest);
+    Ok(interpolated)
+}
+
+/// Why `name` can't be the name of an environment variable, if it can't
+pub fn invalid_name(name: &str) -> Option<&'static str> {
+    if name.is_empty() {
+        Some("the name is empty")
+    } else if name.contains(['=', '\0']) {
+        Some("names can't contain '=' or NUL")
+    } else {
+        None
+    }
+}
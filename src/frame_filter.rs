@@ -0,0 +1,191 @@
+//! An external command run on every captured frame (`frame_filter_cmd`)
+//!
+//! Each frame is written to the command's stdin as a PNG, and the PNG it writes to its
+//! stdout replaces the frame, which must keep its size. Frames are filtered after the
+//! capture and before they are encoded, a few at a time. The command is started once
+//! per frame, so even a quick filter adds up over a long capture. Like the hooks, each
+//! run of the command is stopped after `hook_timeout` and on Ctrl+C, failing the binary.
+
+use crate::hooks::HookCommand;
+use crate::wait_time;
+use anyhow::Result;
+use image::{ImageFormat, ImageOutputFormat, RgbaImage};
+use std::collections::BTreeMap;
+use std::io::{Cursor, Read, Write};
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Most frames filtered at once
+const MAX_PARALLEL: usize = 8;
+
+/// Frame count from which the cost of the filter is warned about
+const MANY_FRAMES: usize = 300;
+
+/// How often a running command is checked on
+const POLL_INTERVAL_MS: u64 = 20;
+
+/// The command frames are filtered with, and where it runs
+#[derive(Debug, Clone)]
+pub struct FrameFilter {
+    pub command: HookCommand,
+    pub project_dir: PathBuf,
+    /// Set over the tool's own environment
+    pub env: BTreeMap<String, String>,
+    /// How long the command may run on one frame (`hook_timeout`)
+    pub timeout: Duration,
+    pub shutdown: Arc<AtomicBool>,
+}
+
+impl FrameFilter {
+    /// Filters `frames`, keeping their order. Fails with the first frame the command
+    /// fails on, or whose size it changes.
+    pub fn apply(&self, frames: Vec<RgbaImage>) -> Result<Vec<RgbaImage>> {
+        if let Some(problem) = self.command.problem() {
+            return Err(anyhow::anyhow!("Can't run frame_filter_cmd: {}", problem));
+        }
+        let workers = thread::available_parallelism()
+            .map_or(1, NonZeroUsize::get)
+            .clamp(1, MAX_PARALLEL)
+            .min(frames.len().max(1));
+        if frames.len() >= MANY_FRAMES {
+            println!(
+                "Warning: frame_filter_cmd starts a process for each of the {} frames; \
+                 this can take far longer than the capture itself. Lower the fps or \
+                 duration, or filter the finished file instead, if it is too slow.",
+                frames.len()
+            );
+        }
+        println!(
+            "Filtering {} frames through frame_filter_cmd ({} at a time): {}",
+            frames.len(),
+            workers,
+            self.command.describe()
+        );
+
+        let next = AtomicUsize::new(0);
+        let done = AtomicUsize::new(0);
+        let results: Vec<OnceLock<Result<RgbaImage>>> =
+            frames.iter().map(|_| OnceLock::new()).collect();
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(frame) = frames.get(index) else {
+                        break;
+                    };
+                    let result = self.filter(frame);
+                    let failed = result.is_err();
+                    let _ = results[index].set(result);
+                    if failed {
+                        // No frames after a failure are started
+                        next.store(frames.len(), Ordering::Relaxed);
+                        break;
+                    }
+                    let filtered = done.fetch_add(1, Ordering::Relaxed) + 1;
+                    if filtered.is_multiple_of(10) {
+                        println!("Filtered frame {}/{}", filtered, frames.len());
+                    }
+                });
+            }
+        });
+
+        results
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, result)| Some((index, result.into_inner()?)))
+            .map(|(index, result)| {
+                result.map_err(|e| {
+                    anyhow::anyhow!("frame_filter_cmd failed on frame {}: {:#}", index, e)
+                })
+            })
+            .collect()
+    }
+
+    /// Runs the command on one frame
+    fn filter(&self, frame: &RgbaImage) -> Result<RgbaImage> {
+        let mut png = Vec::new();
+        frame.write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)?;
+
+        let mut child = self
+            .command
+            .command()
+            .current_dir(&self.project_dir)
+            .envs(&self.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| anyhow::anyhow!("failed to start {}: {}", self.command.describe(), e))?;
+        // Written from its own thread, so a command that writes before it has read all of
+        // its input can't block on a full pipe. A command that doesn't read its input at
+        // all closes the pipe early, which its exit status reports better.
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let writer = thread::spawn(move || {
+            let _ = stdin.write_all(&png);
+        });
+        // Its output is read while it runs, so it can't block on a full pipe either
+        let stdout = read_all(child.stdout.take().expect("stdout is piped"));
+        let stderr = read_all(child.stderr.take().expect("stderr is piped"));
+
+        let started = Instant::now();
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            let stopped = if self.shutdown.load(Ordering::Relaxed) {
+                Some("it was stopped by Ctrl+C".to_string())
+            } else if started.elapsed() >= self.timeout {
+                Some(format!(
+                    "it was stopped after running for longer than its timeout of {} (hook_timeout)",
+                    wait_time::format(self.timeout)
+                ))
+            } else {
+                None
+            };
+            if let Some(stopped) = stopped {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(anyhow::anyhow!(stopped));
+            }
+            thread::sleep(Duration::from_millis(POLL_INTERVAL_MS));
+        };
+        let _ = writer.join();
+        let stdout = stdout.join().unwrap_or_default();
+        let stderr = stderr.join().unwrap_or_default();
+
+        if !status.success() {
+            let stderr = String::from_utf8_lossy(&stderr);
+            return Err(match stderr.trim() {
+                "" => anyhow::anyhow!("{}", status),
+                stderr => anyhow::anyhow!("{}: {}", status, stderr),
+            });
+        }
+        let filtered = image::load_from_memory_with_format(&stdout, ImageFormat::Png)
+            .map_err(|e| anyhow::anyhow!("its output isn't a PNG: {}", e))?
+            .to_rgba8();
+        if filtered.dimensions() != frame.dimensions() {
+            return Err(anyhow::anyhow!(
+                "it changed the frame from {}x{} to {}x{}; filtered frames must keep their size",
+                frame.width(),
+                frame.height(),
+                filtered.width(),
+                filtered.height()
+            ));
+        }
+        Ok(filtered)
+    }
+}
+
+/// Reads `pipe` to its end on its own thread
+fn read_all(mut pipe: impl Read + Send + 'static) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut output = Vec::new();
+        let _ = pipe.read_to_end(&mut output);
+        output
+    })
+}
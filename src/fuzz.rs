@@ -0,0 +1,131 @@
+//! Random button mashing for smoke-test captures
+//!
+//! `fuzz:<ms>` actions (and `--fuzz`) expand into random presses and holds of the GBA
+//! buttons, and `--input-jitter` varies the timing of ordinary input. The generator is a
+//! small SplitMix64 rather than an external RNG, so a seed produces the same inputs on
+//! every platform and in every version of this tool, and a run that crashed a demo can
+//! be replayed exactly with `--seed`.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// GBA button names as used in input sequences, with how often each is picked relative
+/// to the others: mostly the D-pad and A/B, like real play
+const BUTTON_WEIGHTS: [(&str, u64); 10] = [
+    ("U", 4),
+    ("D", 4),
+    ("L", 4),
+    ("R", 4),
+    ("A", 5),
+    ("B", 4),
+    ("J", 1),
+    ("I", 1),
+    ("S", 1),
+    ("E", 1),
+];
+
+/// Shortest press, long enough for mGBA to see it in a frame
+const TAP_MS: u64 = 50;
+
+/// One generated press: `button` is held for `hold`, then nothing is pressed for `gap`
+#[derive(Debug, Clone, Copy)]
+pub struct FuzzEvent {
+    pub button: &'static str,
+    pub hold: Duration,
+    pub gap: Duration,
+}
+
+/// SplitMix64 pseudo-random generator
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..bound`
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound.max(1)
+    }
+}
+
+/// Random variation of hold and wait durations
+pub struct Jitter {
+    rng: SplitMix64,
+    max_ms: u64,
+}
+
+impl Jitter {
+    pub fn new(max_ms: u64, seed: u64) -> Self {
+        Self {
+            rng: SplitMix64(seed),
+            max_ms,
+        }
+    }
+
+    /// `duration` moved by up to `max_ms` either way, but by no more than half of it, so
+    /// a hold is never cut to nothing
+    pub fn apply(&mut self, duration: Duration) -> Duration {
+        let max = Duration::from_millis(self.max_ms).min(duration / 2);
+        let span = max.as_micros() as u64;
+        if span == 0 {
+            return duration;
+        }
+        duration - max + Duration::from_micros(self.rng.below(span * 2 + 1))
+    }
+}
+
+/// A seed for runs that don't pick one, printed so the run can be repeated
+pub fn random_seed() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos() as u64)
+        .unwrap_or_default();
+    SplitMix64(nanos ^ u64::from(std::process::id())).next()
+}
+
+/// Generates about `hz` presses a second for exactly `duration`. Most presses are short
+/// taps and some are holds of up to two press intervals.
+pub fn generate(duration: Duration, hz: u32, seed: u64) -> Vec<FuzzEvent> {
+    let mut rng = SplitMix64(seed);
+    let total_weight: u64 = BUTTON_WEIGHTS.iter().map(|(_, weight)| weight).sum();
+    let interval_ms = (1000 / u64::from(hz.max(1))).max(TAP_MS * 2);
+    let total_ms = duration.as_millis() as u64;
+
+    let mut events = Vec::new();
+    let mut elapsed_ms = 0;
+    while elapsed_ms < total_ms {
+        let mut pick = rng.below(total_weight);
+        let button = BUTTON_WEIGHTS
+            .iter()
+            .find(|(_, weight)| {
+                let found = pick < *weight;
+                pick = pick.saturating_sub(*weight);
+                found
+            })
+            .map_or("A", |(button, _)| button);
+
+        // One press in four is a hold; gaps are drawn so a press and its gap take one
+        // interval on average
+        let hold_ms = if rng.below(4) == 0 {
+            TAP_MS + rng.below(interval_ms * 2)
+        } else {
+            TAP_MS
+        };
+        let gap_ms = rng.below(interval_ms * 3 / 2 - TAP_MS * 2);
+
+        // The last press is cut short so the whole stream lasts exactly `duration`
+        let hold_ms = hold_ms.min(total_ms - elapsed_ms);
+        let gap_ms = gap_ms.min(total_ms - elapsed_ms - hold_ms);
+        events.push(FuzzEvent {
+            button,
+            hold: Duration::from_millis(hold_ms),
+            gap: Duration::from_millis(gap_ms),
+        });
+        elapsed_ms += hold_ms + gap_ms;
+    }
+    events
+}
@@ -0,0 +1,194 @@
+//! Virtual game controller for `--input-backend gamepad`
+//!
+//! Instead of typing into the focused window, GBA buttons can be pressed on a game
+//! controller that only exists in software: a uinput device with a D-pad, A/B,
+//! Start/Select and the shoulder buttons. mGBA reads it like any other controller, so
+//! input doesn't depend on window focus, the keyboard layout or the compositor. mGBA
+//! has to be told once which button is which; `agbrs-capture bind-gamepad` presses each
+//! button in turn for its controller settings. The device exists while the tool runs
+//! and disappears when it exits.
+//!
+//! uinput is Linux-only, and the backend is compiled in with the `gamepad` feature.
+
+/// Name the virtual controller shows up as in mGBA
+pub const DEVICE_NAME: &str = "agbrs-capture virtual gamepad";
+
+/// Each GBA button by its `key_mappings` field, with the name used in instructions
+pub const BUTTONS: [(&str, &str); 10] = [
+    ("a", "A"),
+    ("b", "B"),
+    ("select", "Select"),
+    ("start", "Start"),
+    ("right", "Right"),
+    ("left", "Left"),
+    ("up", "Up"),
+    ("down", "Down"),
+    ("r_shoulder", "R"),
+    ("l_shoulder", "L"),
+];
+
+/// The virtual controller, removed again when dropped
+#[cfg(all(feature = "gamepad", target_os = "linux"))]
+pub use uinput::VirtualGamepad;
+
+/// Stands in for the controller in builds without uinput; it can't be created
+#[cfg(not(all(feature = "gamepad", target_os = "linux")))]
+pub struct VirtualGamepad(());
+
+#[cfg(not(all(feature = "gamepad", target_os = "linux")))]
+impl VirtualGamepad {
+    /// Explains how to get a build with the virtual controller
+    pub fn create() -> anyhow::Result<Self> {
+        if cfg!(target_os = "linux") {
+            Err(anyhow::anyhow!(
+                "Input backend 'gamepad' is not compiled in. Reinstall with: cargo install --git https://github.com/zpg6/agbrs-capture --features gamepad"
+            ))
+        } else {
+            Err(anyhow::anyhow!(
+                "Input backend 'gamepad' is only available on Linux"
+            ))
+        }
+    }
+
+    pub fn set(&self, _button: &str, _pressed: bool) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(all(feature = "gamepad", target_os = "linux"))]
+mod uinput {
+    use super::DEVICE_NAME;
+    use anyhow::Result;
+    use std::fs::{File, OpenOptions};
+    use std::io::{ErrorKind, Write};
+    use std::os::fd::AsRawFd;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    const UINPUT_PATH: &str = "/dev/uinput";
+
+    // From linux/uinput.h: _IO('U', 1), _IO('U', 2), _IOW('U', 3, struct uinput_setup),
+    // _IOW('U', 100, int) and _IOW('U', 101, int)
+    const UI_DEV_CREATE: u64 = 0x5501;
+    const UI_DEV_DESTROY: u64 = 0x5502;
+    const UI_DEV_SETUP: u64 = 0x405c_5503;
+    const UI_SET_EVBIT: u64 = 0x4004_5564;
+    const UI_SET_KEYBIT: u64 = 0x4004_5565;
+
+    // From linux/input-event-codes.h
+    const EV_SYN: u16 = 0x00;
+    const EV_KEY: u16 = 0x01;
+    const SYN_REPORT: u16 = 0;
+    const BUS_VIRTUAL: u16 = 0x06;
+
+    /// Event code of each button by its `key_mappings` field. A and B are the south and
+    /// east face buttons, as on a Nintendo layout.
+    const BUTTON_CODES: [(&str, u16); 10] = [
+        ("a", 0x130),          // BTN_SOUTH
+        ("b", 0x131),          // BTN_EAST
+        ("l_shoulder", 0x136), // BTN_TL
+        ("r_shoulder", 0x137), // BTN_TR
+        ("select", 0x13a),     // BTN_SELECT
+        ("start", 0x13b),      // BTN_START
+        ("up", 0x220),         // BTN_DPAD_UP
+        ("down", 0x221),       // BTN_DPAD_DOWN
+        ("left", 0x222),       // BTN_DPAD_LEFT
+        ("right", 0x223),      // BTN_DPAD_RIGHT
+    ];
+
+    /// A uinput controller with the GBA's buttons
+    pub struct VirtualGamepad {
+        device: File,
+    }
+
+    impl VirtualGamepad {
+        /// Creates the controller, failing with instructions when /dev/uinput can't be used
+        pub fn create() -> Result<Self> {
+            let device = OpenOptions::new()
+                .write(true)
+                .custom_flags(libc::O_NONBLOCK)
+                .open(UINPUT_PATH)
+                .map_err(|e| match e.kind() {
+                    ErrorKind::NotFound => anyhow::anyhow!(
+                        "{} does not exist, so the virtual gamepad can't be created. Load the uinput module with: sudo modprobe uinput",
+                        UINPUT_PATH
+                    ),
+                    ErrorKind::PermissionDenied => anyhow::anyhow!(
+                        "No permission to open {} for the virtual gamepad. Let the input group use it with a udev rule, then join the group and log in again:\n  echo 'KERNEL==\"uinput\", GROUP=\"input\", MODE=\"0660\"' | sudo tee /etc/udev/rules.d/99-uinput.rules\n  sudo udevadm control --reload && sudo udevadm trigger\n  sudo usermod -aG input $USER",
+                        UINPUT_PATH
+                    ),
+                    _ => anyhow::anyhow!("Failed to open {}: {}", UINPUT_PATH, e),
+                })?;
+            let gamepad = Self { device };
+
+            gamepad.ioctl(UI_SET_EVBIT, EV_KEY as libc::c_ulong, "enable buttons")?;
+            for (_, code) in BUTTON_CODES {
+                gamepad.ioctl(UI_SET_KEYBIT, code as libc::c_ulong, "add a button")?;
+            }
+            // SAFETY: uinput_setup is plain data, for which all zeroes is valid
+            let mut setup: libc::uinput_setup = unsafe { std::mem::zeroed() };
+            setup.id.bustype = BUS_VIRTUAL;
+            setup.id.version = 1;
+            for (slot, byte) in setup.name.iter_mut().zip(DEVICE_NAME.bytes()) {
+                *slot = byte as libc::c_char;
+            }
+            gamepad.ioctl(
+                UI_DEV_SETUP,
+                &setup as *const libc::uinput_setup as libc::c_ulong,
+                "set up the device",
+            )?;
+            gamepad.ioctl(UI_DEV_CREATE, 0, "create the device")?;
+            Ok(gamepad)
+        }
+
+        /// Presses or releases the button with the `key_mappings` field `button`
+        pub fn set(&self, button: &str, pressed: bool) -> Result<()> {
+            let code = BUTTON_CODES
+                .iter()
+                .find(|(field, _)| *field == button)
+                .map(|&(_, code)| code)
+                .ok_or_else(|| anyhow::anyhow!("The virtual gamepad has no button {}", button))?;
+            self.emit(EV_KEY, code, i32::from(pressed))?;
+            self.emit(EV_SYN, SYN_REPORT, 0)
+        }
+
+        fn emit(&self, kind: u16, code: u16, value: i32) -> Result<()> {
+            // SAFETY: input_event is plain data, for which all zeroes is valid; the
+            // kernel fills in the time
+            let mut event: libc::input_event = unsafe { std::mem::zeroed() };
+            event.type_ = kind;
+            event.code = code;
+            event.value = value;
+            // SAFETY: the bytes of the event, which lives until the write returns
+            let bytes = unsafe {
+                std::slice::from_raw_parts(
+                    &event as *const libc::input_event as *const u8,
+                    std::mem::size_of::<libc::input_event>(),
+                )
+            };
+            (&self.device)
+                .write_all(bytes)
+                .map_err(|e| anyhow::anyhow!("Failed to send a virtual gamepad event: {}", e))
+        }
+
+        fn ioctl(&self, request: u64, argument: libc::c_ulong, action: &str) -> Result<()> {
+            // SAFETY: the requests are uinput's, with an integer or a pointer to a live
+            // uinput_setup as the argument they expect
+            let result = unsafe { libc::ioctl(self.device.as_raw_fd(), request as _, argument) };
+            if result < 0 {
+                return Err(anyhow::anyhow!(
+                    "Failed to {} for the virtual gamepad: {}",
+                    action,
+                    std::io::Error::last_os_error()
+                ));
+            }
+            Ok(())
+        }
+    }
+
+    impl Drop for VirtualGamepad {
+        fn drop(&mut self) {
+            // Releases whatever is still pressed along with the device
+            let _ = self.ioctl(UI_DEV_DESTROY, 0, "remove the device");
+        }
+    }
+}
@@ -0,0 +1,113 @@
+//! Headless capture on a virtual X display
+//!
+//! CI machines have no display at all. `--headless` starts an Xvfb server and runs the
+//! capture in a new process of this tool whose `DISPLAY` points at it, so the `cargo
+//! run`/mGBA child, window lookup, frame capture and key injection all talk to the
+//! virtual display from the start, without the environment of a running process being
+//! changed. The server is stopped when the returned guard is dropped, which also happens
+//! on errors and Ctrl+C.
+
+use anyhow::Result;
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// How long Xvfb gets to report the display it opened
+const STARTUP_TIMEOUT_SECS: u64 = 10;
+
+/// A running Xvfb server, stopped on drop
+pub struct VirtualDisplay {
+    server: Child,
+    /// Display name, such as `:99`
+    display: String,
+}
+
+impl VirtualDisplay {
+    /// Starts Xvfb with one screen of `resolution` (`WIDTHxHEIGHT`) on a free display
+    /// number
+    pub fn start(resolution: &str) -> Result<Self> {
+        if !cfg!(target_os = "linux") {
+            return Err(anyhow::anyhow!("--headless is only supported on Linux"));
+        }
+        let (width, height) = resolution
+            .split_once('x')
+            .and_then(|(width, height)| {
+                Some((width.parse::<u32>().ok()?, height.parse::<u32>().ok()?))
+            })
+            .filter(|&(width, height)| width > 0 && height > 0)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Invalid --headless-resolution '{}', expected WIDTHxHEIGHT such as 1280x720",
+                    resolution
+                )
+            })?;
+
+        // -displayfd picks a free display number and writes it to stdout once listening
+        let mut server = Command::new("Xvfb")
+            .args(["-displayfd", "1", "-nolisten", "tcp", "-screen", "0"])
+            .arg(format!("{}x{}x24", width, height))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| {
+                if e.kind() == std::io::ErrorKind::NotFound {
+                    anyhow::anyhow!(
+                        "--headless needs Xvfb, which was not found on PATH. Install it with:\n  Debian/Ubuntu: sudo apt-get install xvfb\n  Fedora: sudo dnf install xorg-x11-server-Xvfb\n  Arch: sudo pacman -S xorg-server-xvfb"
+                    )
+                } else {
+                    anyhow::anyhow!("Failed to start Xvfb: {}", e)
+                }
+            })?;
+
+        let stdout = server.stdout.take().expect("Xvfb stdout is piped");
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut line = String::new();
+            let _ = BufReader::new(stdout).read_line(&mut line);
+            let _ = sender.send(line);
+        });
+        let number = receiver
+            .recv_timeout(Duration::from_secs(STARTUP_TIMEOUT_SECS))
+            .ok()
+            .map(|line| line.trim().to_string())
+            .filter(|number| !number.is_empty());
+        let Some(number) = number else {
+            let status = server.try_wait().ok().flatten();
+            let _ = server.kill();
+            let _ = server.wait();
+            return Err(match status {
+                Some(status) => anyhow::anyhow!("Xvfb exited during startup ({})", status),
+                None => anyhow::anyhow!(
+                    "Xvfb did not report a display within {}s",
+                    STARTUP_TIMEOUT_SECS
+                ),
+            });
+        };
+
+        let display = format!(":{}", number);
+        println!(
+            "Started virtual display {} ({}x{}) with Xvfb",
+            display, width, height
+        );
+        Ok(Self { server, display })
+    }
+
+    /// Points `command` at the virtual display. A Wayland session of the host doesn't
+    /// apply to it, so the variables that would select one are left out.
+    pub fn run_on<'a>(&self, command: &'a mut Command) -> &'a mut Command {
+        command
+            .env("DISPLAY", &self.display)
+            .env_remove("WAYLAND_DISPLAY")
+            .env_remove("XDG_SESSION_TYPE")
+    }
+}
+
+impl Drop for VirtualDisplay {
+    fn drop(&mut self) {
+        let _ = self.server.kill();
+        let _ = self.server.wait();
+        println!("Stopped virtual display {}", self.display);
+    }
+}
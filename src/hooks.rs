@@ -0,0 +1,214 @@
+//! Commands the config runs around a binary's captures, like `pre_capture_cmd`
+//!
+//! A command is either a string, run by the shell (`sh -c`, or `cmd /C` on Windows), or
+//! an array of the program and its arguments, run directly. It runs in the project
+//! directory with the binary's `env` and the variables of the hook, and its output goes
+//! to the binary's run log. A command that exits with an error, or outlives its
+//! timeout, fails the binary.
+//!
+//! `post_capture_cmd` is told what was written through `AGB_CAPTURE_*` variables, and
+//! `settings.post_run_cmd` gets a manifest of the whole run.
+
+use crate::emulator_log::EmulatorLog;
+use crate::wait_time;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+
+/// How often a running command is checked for having exited
+const POLL_INTERVAL_MS: u64 = 50;
+
+/// How long the rest of a command's output is waited for after it exits. A process it
+/// left running in the background can keep its output open indefinitely.
+const OUTPUT_DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A hook command in the config
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum HookCommand {
+    /// A command line run by the shell
+    Shell(String),
+    /// The program and its arguments, run without a shell
+    Argv(Vec<String>),
+}
+
+impl HookCommand {
+    /// Why the command can't be run, if it can't
+    pub fn problem(&self) -> Option<&'static str> {
+        match self {
+            HookCommand::Shell(line) if line.trim().is_empty() => Some("the command is empty"),
+            HookCommand::Argv(argv) if argv.first().is_none_or(|program| program.is_empty()) => {
+                Some("the array needs the program as its first element")
+            }
+            _ => None,
+        }
+    }
+
+    /// The command as it is printed
+    pub fn describe(&self) -> String {
+        match self {
+            HookCommand::Shell(line) => line.clone(),
+            HookCommand::Argv(argv) => argv.join(" "),
+        }
+    }
+
+    /// The command, ready to be configured and spawned
+    pub fn command(&self) -> Command {
+        match self {
+            HookCommand::Shell(line) => {
+                let (shell, flag) = if cfg!(target_os = "windows") {
+                    ("cmd", "/C")
+                } else {
+                    ("sh", "-c")
+                };
+                let mut command = Command::new(shell);
+                command.args([flag, line]);
+                command
+            }
+            HookCommand::Argv(argv) => {
+                let mut command = Command::new(&argv[0]);
+                command.args(&argv[1..]);
+                command
+            }
+        }
+    }
+}
+
+/// Runs the hook `name` of the config to completion, with `env` set over the tool's
+/// own environment. Returns `false` if shutdown was requested while it ran, after
+/// stopping it.
+pub async fn run(
+    name: &str,
+    hook: &HookCommand,
+    project_dir: &Path,
+    env: &BTreeMap<String, String>,
+    timeout: Duration,
+    log: &EmulatorLog,
+    shutdown: &Arc<AtomicBool>,
+) -> Result<bool> {
+    if let Some(problem) = hook.problem() {
+        return Err(anyhow::anyhow!("Can't run {}: {}", name, problem));
+    }
+    println!("Running {}: {}", name, hook.describe());
+    let mut child = hook
+        .command()
+        .current_dir(project_dir)
+        .envs(env)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("Failed to start {} ({}): {}", name, hook.describe(), e))?;
+    let readers = log.attach(&mut child);
+
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if shutdown.load(Ordering::Relaxed) {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Ok(false);
+        }
+        if started.elapsed() >= timeout {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(anyhow::anyhow!(
+                "{} was stopped after running for longer than its timeout of {} (hook_timeout)",
+                name,
+                wait_time::format(timeout)
+            ));
+        }
+        sleep(Duration::from_millis(POLL_INTERVAL_MS)).await;
+    };
+    // All of its output is in the log before it is reported on
+    let exited = Instant::now();
+    while !readers.iter().all(|reader| reader.is_finished())
+        && exited.elapsed() < OUTPUT_DRAIN_TIMEOUT
+    {
+        sleep(Duration::from_millis(POLL_INTERVAL_MS)).await;
+    }
+    if !status.success() {
+        return Err(anyhow::anyhow!("{} failed ({})", name, status));
+    }
+    Ok(true)
+}
+
+/// A file written by a capture, as hooks and the run manifest describe it
+#[derive(Debug, Clone, Serialize)]
+pub struct CaptureOutput {
+    pub binary: String,
+    /// The capture's label, naming its segment or variant
+    pub label: String,
+    /// Absolute path of the file
+    pub path: PathBuf,
+    pub frames: usize,
+    /// Size of the file in bytes
+    pub size: u64,
+}
+
+impl CaptureOutput {
+    /// Describes the file just written to `path`
+    pub fn new(binary: &str, label: &str, path: &str, frames: usize) -> Result<Self> {
+        let path = std::fs::canonicalize(path)
+            .map_err(|e| anyhow::anyhow!("Failed to find the written {}: {}", path, e))?;
+        let size = std::fs::metadata(&path)?.len();
+        Ok(Self {
+            binary: binary.to_string(),
+            label: label.to_string(),
+            path,
+            frames,
+            size,
+        })
+    }
+}
+
+/// The variables `post_capture_cmd` gets about the files its binary wrote:
+/// `AGB_CAPTURE_OUTPUT` (the first), `AGB_CAPTURE_OUTPUTS` (all of them, one per line),
+/// and `AGB_CAPTURE_FRAMES` and `AGB_CAPTURE_SIZE` (in bytes) summed over them
+pub fn output_env(outputs: &[CaptureOutput]) -> BTreeMap<String, String> {
+    let paths: Vec<String> = outputs
+        .iter()
+        .map(|output| output.path.display().to_string())
+        .collect();
+    let frames: usize = outputs.iter().map(|output| output.frames).sum();
+    let size: u64 = outputs.iter().map(|output| output.size).sum();
+    BTreeMap::from([
+        (
+            "AGB_CAPTURE_OUTPUT".to_string(),
+            paths.first().cloned().unwrap_or_default(),
+        ),
+        ("AGB_CAPTURE_OUTPUTS".to_string(), paths.join("\n")),
+        ("AGB_CAPTURE_FRAMES".to_string(), frames.to_string()),
+        ("AGB_CAPTURE_SIZE".to_string(), size.to_string()),
+    ])
+}
+
+/// Everything a run did, written for `settings.post_run_cmd`
+#[derive(Debug, Serialize)]
+pub struct RunManifest<'a> {
+    pub captures: &'a [CaptureOutput],
+    /// Binaries or captures that failed, with why
+    pub failures: &'a [String],
+    /// Binaries left out by `skip` in the config
+    pub skipped: &'a [String],
+    /// Absolute path of the directory the run wrote to
+    pub output_dir: &'a Path,
+}
+
+impl RunManifest<'_> {
+    /// Writes the manifest as JSON to `path`, returning its absolute path
+    pub fn write(&self, path: &Path) -> Result<PathBuf> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json + "\n")
+            .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", path.display(), e))?;
+        Ok(std::fs::canonicalize(path)?)
+    }
+}
@@ -0,0 +1,109 @@
+//! Input actions typed live while capturing
+//!
+//! `--during-capture -` reads actions from stdin and `--input-pipe` from a named pipe,
+//! one line at a time, so a capture can be driven by hand from another terminal. Lines
+//! are read as they arrive and the reader outlives each capture, so input typed between
+//! captures is picked up by the next one.
+
+use anyhow::Result;
+use std::io::BufRead;
+use std::path::Path;
+use tokio::sync::{mpsc, Mutex};
+
+/// A stream of input lines from stdin or a named pipe
+pub struct LiveInput {
+    description: String,
+    lines: Mutex<mpsc::UnboundedReceiver<std::io::Result<String>>>,
+}
+
+impl LiveInput {
+    /// Reads lines typed on, or piped into, this process's stdin
+    pub fn stdin() -> Self {
+        Self::new(
+            "stdin".to_string(),
+            std::io::BufReader::new(std::io::stdin()),
+        )
+    }
+
+    /// Reads lines written to the named pipe at `path`. The pipe is also opened for
+    /// writing, which keeps the open from waiting for a writer and the pipe from reaching
+    /// end-of-file each time a writer such as `echo` closes it.
+    #[cfg(unix)]
+    pub fn pipe(path: &Path) -> Result<Self> {
+        use std::os::unix::fs::FileTypeExt;
+
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .and_then(|file| {
+                if file.metadata()?.file_type().is_fifo() {
+                    Ok(file)
+                } else {
+                    Err(std::io::Error::other("not a named pipe"))
+                }
+            })
+            .map_err(|e| {
+                anyhow::anyhow!(
+                    "Could not open input pipe {} (create it with mkfifo): {}",
+                    path.display(),
+                    e
+                )
+            })?;
+        Ok(Self::new(
+            format!("pipe {}", path.display()),
+            std::io::BufReader::new(file),
+        ))
+    }
+
+    #[cfg(not(unix))]
+    pub fn pipe(_path: &Path) -> Result<Self> {
+        Err(anyhow::anyhow!(
+            "--input-pipe is only supported on Linux and macOS; use --during-capture - to read from stdin"
+        ))
+    }
+
+    /// Reads `reader` on its own thread. A blocking read can't be cancelled, and a plain
+    /// thread doesn't keep the tool from exiting while it waits for the next line.
+    fn new(description: String, reader: impl BufRead + Send + 'static) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        std::thread::spawn(move || {
+            for line in reader.lines() {
+                let failed = line.is_err();
+                if sender.send(line).is_err() || failed {
+                    break;
+                }
+            }
+        });
+        Self {
+            description,
+            lines: Mutex::new(receiver),
+        }
+    }
+
+    /// Where the lines come from, for messages
+    pub fn describe(&self) -> &str {
+        &self.description
+    }
+
+    /// Drops lines that arrived but weren't read yet
+    pub fn discard_pending(&self) {
+        if let Ok(mut lines) = self.lines.try_lock() {
+            while let Ok(Ok(_)) = lines.try_recv() {}
+        }
+    }
+
+    /// Waits for the next line, or `None` at end of input. Cancelling the wait loses
+    /// no input.
+    pub async fn next_line(&self) -> Result<Option<String>> {
+        match self.lines.lock().await.recv().await {
+            Some(Ok(line)) => Ok(Some(line)),
+            Some(Err(e)) => Err(anyhow::anyhow!(
+                "Failed to read input from {}: {}",
+                self.description,
+                e
+            )),
+            None => Ok(None),
+        }
+    }
+}
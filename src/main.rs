@@ -3,22 +3,435 @@
 //! Captures frames from mGBA windows and creates GIFs automatically
 //! for each binary in an agbrs project.
 
+/// Set by `--verbose`; enables `debug!` output
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+/// Whether the note about bare letters naming GBA buttons has been printed
+static AMBIGUOUS_KEY_NOTED: AtomicBool = AtomicBool::new(false);
+
+/// Prints a diagnostic line when `--verbose` is set
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if crate::VERBOSE.load(std::sync::atomic::Ordering::Relaxed) {
+            println!("[debug] {}", format!($($arg)*));
+        }
+    };
+}
+
+mod binary_keys;
+mod capture;
+mod cargo_config;
+mod cargo_manifest;
+mod config_file;
+mod emulator_log;
+mod env_vars;
+mod frame_filter;
+mod fuzz;
+mod gamepad;
+mod headless;
+mod hooks;
+mod live_input;
+mod mgba_bindings;
+mod mgba_config;
+mod mgba_script;
+mod output_root;
+mod permissions;
+mod post_process;
+mod record;
+mod save_files;
+mod scripting;
+mod wait_time;
+mod wayland;
+mod window;
+
+#[cfg(test)]
+mod tests;
+
 use anyhow::Result;
-use clap::Parser;
-use enigo::{Direction, Enigo, Key, Keyboard, Settings};
-use gif::{Encoder, Frame, Repeat};
-use image::{ImageBuffer, RgbImage, RgbaImage};
+use capture::{
+    changed_pixel_fraction, emulated_frame_delays, emulation_speeds, looks_like_panic_screen,
+    open_window_capture, parse_title_stats, AnimationEncoder, CaptureBackend, FrameSource, Loop,
+    LoopSetting, OutputFormat, SyntheticFrameSource, TitleStats, WindowFrameSource, GBA_FPS,
+    GBA_SCREEN_SIZE,
+};
+use cargo_config::{locate_program, program_emulator, CargoConfig, Runner};
+use cargo_manifest::Features;
+use clap::{CommandFactory, FromArgMatches, Parser, ValueEnum};
+use config_file::{ConfigFile, ConfigFormat, ConfigValue};
+use emulator_log::EmulatorLog;
+use enigo::{Button, Coordinate, Direction, Enigo, Key, Keyboard, Mouse, Settings};
+use frame_filter::FrameFilter;
+use gamepad::VirtualGamepad;
+use headless::VirtualDisplay;
+use hooks::HookCommand;
+use image::RgbaImage;
+use indexmap::IndexMap;
+use live_input::LiveInput;
+use mgba_config::{ConfigHome, ConfigOverrides, ManagedConfig, DISPLAY_DRIVER_SETTING};
+use mgba_script::{ScriptConnection, ScriptServer};
+use output_root::OutputRoot;
+use permissions::check_permissions;
+use post_process::{Crop, Filter, PostChain, PostConfig, Region};
+use regex::Regex;
+use save_files::{save_path, SaveFiles, SavePolicy};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::fs::File;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write;
 use std::path::{Path, PathBuf};
-use std::process::{Child, Command};
+use std::process::{Child, Command, Stdio};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::signal;
 use tokio::time::sleep;
-use xcap::Window;
+use wait_time::WaitTime;
+use window::{
+    cargo_is_building, compile_title_pattern, display_scale, enable_dpi_awareness,
+    find_mgba_window, find_popup_windows, find_stale_emulators, focus_mgba_window,
+    focus_window_by_id, kill_stale_emulator, mgba_has_focus, title_names_rom, track_process_tree,
+    tracked_emulator_pid, tracked_window_found, window_has_focus, Emulator, WindowMatch,
+    WindowQuery, DEFAULT_TITLE_PATTERN,
+};
+
+/// Time for mGBA to return to normal speed after fast-forwarding, before capture starts
+const FAST_FORWARD_SETTLE_MS: u64 = 500;
+
+/// How often the mGBA window title is read with `--sync-to-emulator`
+const TITLE_SAMPLE_INTERVAL_MS: u64 = 250;
+
+/// Frames checked, 200ms apart, for the screen to change after loading a savestate
+const SAVESTATE_CHECK_ATTEMPTS: usize = 10;
+
+/// How long the screen must stay unchanged for `wait_for:stable` without a window
+const DEFAULT_STABLE_MS: u64 = 500;
+
+/// How long a `wait_for:` condition is waited for without a timeout
+const DEFAULT_WAIT_FOR_TIMEOUT_MS: u64 = 10_000;
+
+/// How often the screen is captured while waiting for a `wait_for:` condition
+const WAIT_FOR_POLL_MS: u64 = 50;
+
+/// Per-channel difference allowed by `wait_for:pixel` and `wait_for:region` without a
+/// tolerance
+const DEFAULT_COLOR_TOLERANCE: u8 = 16;
+
+/// Fraction of pixels that may differ between captures of a screen that counts as
+/// unchanged, which absorbs capture noise and small blinking cursors
+const STABLE_PIXEL_TOLERANCE: f32 = 0.005;
+
+/// Marks a sequence that is read from a script file, like `file:scripts/intro.seq`
+const SCRIPT_PREFIX: &str = "file:";
+
+/// Anchors a during-capture step to a frame, like `at:30:A`
+const FRAME_ANCHOR_PREFIX: &str = "at:";
+
+/// Taps per second of `mash:` without a rate
+const DEFAULT_MASH_HZ: u32 = 10;
+
+/// Fastest `mash:` rate at which every tap still spans a frame both down and up
+const MAX_MASH_HZ: u32 = 30;
+
+/// Step that `record` rounds press and wait times to, unless `--granularity` is given
+const DEFAULT_RECORD_GRANULARITY_MS: u64 = 10;
+
+/// Prefix of a key name that always means a GBA button, like `gba:a`
+const GBA_KEY_PREFIX: &str = "gba:";
+
+/// Prefix of a key name that always means a keyboard key, like `key:s`
+const RAW_KEY_PREFIX: &str = "key:";
+
+/// Prefix of a physical key given by its PC scancode, like `scancode:44` for the key in
+/// the Z position
+const SCANCODE_PREFIX: &str = "scancode:";
+
+/// Prefix of a physical key given by the character in its position on a US QWERTY
+/// layout, like `physical_z`
+const PHYSICAL_KEY_PREFIX: &str = "physical_";
+
+/// Set in a `Key::Other` that holds the scancode of a physical key rather than a
+/// platform key code
+const PHYSICAL_KEY_FLAG: u32 = 0x8000_0000;
+
+/// Keys by the character in their position on a US QWERTY layout, with their PC
+/// scancode (set 1, the same as Linux evdev codes) and macOS virtual key code
+const PHYSICAL_KEYS: &[(char, u16, u16)] = &[
+    ('1', 2, 0x12),
+    ('2', 3, 0x13),
+    ('3', 4, 0x14),
+    ('4', 5, 0x15),
+    ('5', 6, 0x17),
+    ('6', 7, 0x16),
+    ('7', 8, 0x1a),
+    ('8', 9, 0x1c),
+    ('9', 10, 0x19),
+    ('0', 11, 0x1d),
+    ('-', 12, 0x1b),
+    ('=', 13, 0x18),
+    ('q', 16, 0x0c),
+    ('w', 17, 0x0d),
+    ('e', 18, 0x0e),
+    ('r', 19, 0x0f),
+    ('t', 20, 0x11),
+    ('y', 21, 0x10),
+    ('u', 22, 0x20),
+    ('i', 23, 0x22),
+    ('o', 24, 0x1f),
+    ('p', 25, 0x23),
+    ('[', 26, 0x21),
+    (']', 27, 0x1e),
+    ('a', 30, 0x00),
+    ('s', 31, 0x01),
+    ('d', 32, 0x02),
+    ('f', 33, 0x03),
+    ('g', 34, 0x05),
+    ('h', 35, 0x04),
+    ('j', 36, 0x26),
+    ('k', 37, 0x28),
+    ('l', 38, 0x25),
+    (';', 39, 0x29),
+    ('\'', 40, 0x27),
+    ('`', 41, 0x32),
+    ('\\', 43, 0x2a),
+    ('z', 44, 0x06),
+    ('x', 45, 0x07),
+    ('c', 46, 0x08),
+    ('v', 47, 0x09),
+    ('b', 48, 0x0b),
+    ('n', 49, 0x2d),
+    ('m', 50, 0x2e),
+    (',', 51, 0x2b),
+    ('.', 52, 0x2f),
+    ('/', 53, 0x2c),
+    (' ', 57, 0x31),
+];
+
+/// Names of the modifier keys, which can be held around another key as in `shift+f1`
+const MODIFIER_NAMES: &[&str] = &[
+    "shift",
+    "lshift",
+    "left_shift",
+    "rshift",
+    "right_shift",
+    "ctrl",
+    "control",
+    "lctrl",
+    "left_ctrl",
+    "rctrl",
+    "right_ctrl",
+    "alt",
+    "lalt",
+    "left_alt",
+    "ralt",
+    "right_alt",
+    "meta",
+    "cmd",
+    "command",
+    "super",
+    "lmeta",
+    "left_meta",
+    "rmeta",
+    "right_meta",
+    "rcmd",
+    "right_cmd",
+];
+
+/// Names of the keys other than letters and digits, as accepted by `parse_raw_key`
+const KEY_NAMES: &[&str] = &[
+    "up",
+    "down",
+    "left",
+    "right",
+    "arrow_up",
+    "arrow_down",
+    "arrow_left",
+    "arrow_right",
+    "space",
+    "enter",
+    "return",
+    "tab",
+    "escape",
+    "esc",
+    "shift",
+    "ctrl",
+    "control",
+    "alt",
+    "meta",
+    "cmd",
+    "command",
+    "super",
+    "backspace",
+    "f1",
+    "f2",
+    "f3",
+    "f4",
+    "f5",
+    "f6",
+    "f7",
+    "f8",
+    "f9",
+    "f10",
+    "f11",
+    "f12",
+    "pageup",
+    "pgup",
+    "page_up",
+    "pagedown",
+    "pgdn",
+    "page_down",
+    "home",
+    "end",
+    "insert",
+    "ins",
+    "delete",
+    "del",
+    "comma",
+    "period",
+    "dot",
+    "slash",
+    "backslash",
+    "semicolon",
+    "quote",
+    "apostrophe",
+    "left_bracket",
+    "lbracket",
+    "right_bracket",
+    "rbracket",
+    "minus",
+    "dash",
+    "equals",
+    "equal",
+    "grave",
+    "backtick",
+    "kp_0",
+    "kp_1",
+    "kp_2",
+    "kp_3",
+    "kp_4",
+    "kp_5",
+    "kp_6",
+    "kp_7",
+    "kp_8",
+    "kp_9",
+    "numpad0",
+    "numpad1",
+    "numpad2",
+    "numpad3",
+    "numpad4",
+    "numpad5",
+    "numpad6",
+    "numpad7",
+    "numpad8",
+    "numpad9",
+    "kp_add",
+    "kp_plus",
+    "numpad_add",
+    "kp_subtract",
+    "kp_minus",
+    "numpad_subtract",
+    "kp_multiply",
+    "numpad_multiply",
+    "kp_divide",
+    "numpad_divide",
+    "kp_decimal",
+    "numpad_decimal",
+    "lshift",
+    "left_shift",
+    "rshift",
+    "right_shift",
+    "lctrl",
+    "left_ctrl",
+    "rctrl",
+    "right_ctrl",
+    "lalt",
+    "left_alt",
+    "ralt",
+    "right_alt",
+    "lmeta",
+    "left_meta",
+    "rmeta",
+    "right_meta",
+    "rcmd",
+    "right_cmd",
+];
+
+/// Names of the GBA buttons in input sequences
+const GBA_BUTTONS: [&str; 10] = ["A", "B", "E", "S", "R", "L", "U", "D", "I", "J"];
+
+/// Presses timed by `calibrate` by default
+const DEFAULT_CALIBRATION_TRIALS: u32 = 7;
+
+/// Longest wait for the screen to react to a press in `calibrate`
+const CALIBRATION_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long the screen must stay still before `calibrate` presses the key
+const CALIBRATION_STILL: Duration = Duration::from_millis(300);
+
+/// Time to click a button's field in mGBA before `bind-gamepad` presses the button
+const BIND_GAMEPAD_DELAY: Duration = Duration::from_secs(3);
+
+/// How long the input script of a freshly started mGBA has to connect
+const SCRIPT_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long `bind-gamepad` holds each button
+const BIND_GAMEPAD_HOLD: Duration = Duration::from_millis(500);
+
+/// Longest duration of a single step without `--allow-long-waits`: 5 minutes
+const MAX_STEP_DURATION_MS: u64 = 5 * 60 * 1000;
+
+/// Most actions a sequence may expand to once its groups and macros are written out,
+/// so a typo like `(A)x1000000` fails to parse rather than exhausting memory
+const MAX_SEQUENCE_ACTIONS: usize = 100_000;
+
+/// Before-capture input longer than this gets a warning, unless
+/// `settings.before_capture_limit_ms` sets another limit: 1 minute
+const DEFAULT_BEFORE_CAPTURE_LIMIT_MS: u64 = 60 * 1000;
+
+/// Time captured after the during-capture input when a capture takes its length from it
+const DEFAULT_SEQUENCE_TAIL_MS: u64 = 500;
+
+/// Presses per second of `fuzz:` without a rate
+const DEFAULT_FUZZ_HZ: u32 = 5;
+
+/// Fastest `fuzz:` rate; above it presses and gaps get too short to vary
+const MAX_FUZZ_HZ: u32 = 10;
+
+/// Default hold for chords without a duration, long enough for mGBA to see the keys
+/// down in the same frame
+const DEFAULT_CHORD_HOLD_MS: u64 = 50;
+
+/// Default hold for taps without a duration: 3 frames, so mGBA's once-a-frame input
+/// polling can't miss them
+const DEFAULT_MIN_PRESS_MS: u64 = 50;
+
+/// Delay enigo leaves after each X11 event by default
+const DEFAULT_LINUX_DELAY_MS: u32 = 12;
+
+/// Lines of emulator output shown when a binary's capture fails
+const RUN_LOG_TAIL_LINES: usize = 20;
+
+/// How often a long `cargo run` build is reported while waiting for the mGBA window
+const BUILD_PROGRESS_INTERVAL_SECS: u64 = 30;
+
+/// Wait after launch before the mGBA window is first looked for, unless configured
+const DEFAULT_STARTUP_WAIT: Duration = Duration::from_secs(2);
+
+/// Time spent looking for the mGBA window before giving up, unless configured
+const DEFAULT_WINDOW_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Time a hook command like `pre_capture_cmd` may run, unless configured
+const DEFAULT_HOOK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Manifest of the run written for `settings.post_run_cmd`, in the output directory
+const RUN_MANIFEST_NAME: &str = "capture-manifest.json";
+
+/// Where the output of `settings.post_run_cmd` goes, in the output directory
+const POST_RUN_LOG_NAME: &str = "post_run.log";
+
+/// Time for the window system to move focus to mGBA before it is checked again
+const FOCUS_SETTLE_MS: u64 = 200;
+
+/// Fraction of full speed below which `--sync-to-emulator` warns about slowdown
+const SLOW_EMULATION_RATIO: f32 = 0.9;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -35,780 +448,10865 @@ struct Args {
     #[arg(help = "GIF framerate (frames per second)")]
     fps: f32,
 
+    /// File format of the captures
+    #[arg(long, value_enum, default_value_t = OutputFormat::Gif)]
+    #[arg(
+        help = "File format of the captures: gif, or apng for an animated PNG with exact colors (binaries can set their own with output.format in the config file; settings.output.format is used when it isn't given)"
+    )]
+    format: OutputFormat,
+
+    /// Directory the captures are written to
+    #[arg(long, value_name = "DIR")]
+    #[arg(
+        help = "Directory the captures, logs and run manifest are written to, created if missing (default: settings.output.dir in the config file, else out)"
+    )]
+    output_dir: Option<PathBuf>,
+
+    /// Allow settings.output.dir outside the project
+    #[arg(long)]
+    #[arg(
+        help = "Allow settings.output.dir in the config file to lead outside the project directory"
+    )]
+    allow_external_output: bool,
+
+    /// How many times captures play
+    #[arg(long = "loop", value_name = "COUNT", value_parser = parse_loop)]
+    #[arg(
+        help = "How many times captures play: infinite (default) or a number of plays, like 1 to play once. Only for binaries whose config doesn't set loop (per binary or in settings.default)"
+    )]
+    loop_count: Option<Loop>,
+
+    /// Trim solid borders from the frames
+    #[arg(long, value_enum, value_name = "MODE")]
+    #[arg(
+        help = "Crop the frames: auto trims borders of one solid color, none keeps them. Only for binaries whose config doesn't set post.crop"
+    )]
+    crop: Option<Crop>,
+
+    /// Crop the frames to a rectangle
+    #[arg(long, value_name = "X,Y,WIDTH,HEIGHT", value_parser = parse_region)]
+    #[arg(
+        help = "Crop the frames to a rectangle of the captured frame, in pixels. Only for binaries whose config doesn't set post.region"
+    )]
+    region: Option<Region>,
+
+    /// Enlarge the frames by a whole factor
+    #[arg(long, value_name = "FACTOR")]
+    #[arg(value_parser = clap::value_parser!(u32).range(1..=post_process::MAX_SCALE as i64))]
+    #[arg(
+        help = "Enlarge the frames by a whole factor, keeping pixels sharp. Only for binaries whose config doesn't set post.scale"
+    )]
+    scale: Option<u32>,
+
+    /// Filters applied to the frames
+    #[arg(long = "filter", value_enum, value_name = "FILTER")]
+    #[arg(
+        help = "Filter the frames after scaling: lcd (a pixel grid, needs --scale 2 or more) or grayscale. Can be repeated; only for binaries whose config doesn't set post.filters"
+    )]
+    filters: Vec<Filter>,
+
+    /// Text shown below the frames
+    #[arg(long, value_name = "TEXT")]
+    #[arg(
+        help = "Show a line of text (printable ASCII) in a band below the frames. Only for binaries whose config doesn't set post.caption"
+    )]
+    caption: Option<String>,
+
     /// Duration of the GIF in seconds
     #[arg(long, default_value_t = 3.0)]
     #[arg(help = "GIF duration in seconds")]
     duration: f32,
 
-    /// Input sequence to execute before capture starts
+    /// Whether --duration was given rather than defaulted
+    #[arg(skip)]
+    duration_given: bool,
+
+    /// Whether --fps was given rather than defaulted
+    #[arg(skip)]
+    fps_given: bool,
+
+    /// Whether --format was given rather than defaulted
+    #[arg(skip)]
+    format_given: bool,
+
+    /// Whether --marker-timeout was given rather than defaulted
+    #[arg(skip)]
+    marker_timeout_given: bool,
+
+    /// Whether --match-by was given rather than defaulted
+    #[arg(skip)]
+    match_by_given: bool,
+
+    /// Capture for as long as the during-capture input takes
     #[arg(long)]
     #[arg(
-        help = "Input sequence before capture (e.g., 'A:500,wait:1000,B' for A held 500ms, wait 1s, B quick press)"
+        help = "Make each capture as long as its during-capture input takes, plus --sequence-tail (an explicit --duration or duration in capture.json wins)"
     )]
-    before_capture: Option<String>,
+    extend_to_sequence: bool,
+
+    /// Time captured after the during-capture input with --extend-to-sequence
+    #[arg(long, value_name = "MS", default_value_t = DEFAULT_SEQUENCE_TAIL_MS)]
+    #[arg(
+        help = "Milliseconds captured after the during-capture input ends, for captures whose length comes from their input"
+    )]
+    sequence_tail: u64,
+
+    /// Input sequence to execute before capture starts
+    #[arg(long, value_name = "[BINARY=]SEQUENCE", value_parser = parse_cli_sequence)]
+    #[arg(
+        help = "Input sequence before capture (e.g., 'A:500,wait:1000,B' for A held 500ms, wait 1s, B quick press); prefix with BINARY= for one binary only, and repeat for others"
+    )]
+    before_capture: Vec<CliSequence>,
 
     /// Input sequence to execute during capture
+    #[arg(long, value_name = "[BINARY=]SEQUENCE", value_parser = parse_cli_sequence)]
+    #[arg(
+        help = "Input sequence during capture (e.g., 'right:100,wait:500,right:100' for directional inputs), or - to execute actions typed on stdin as they arrive; prefix with BINARY= for one binary only, and repeat for others"
+    )]
+    during_capture: Vec<CliSequence>,
+
+    /// Input script file to execute during capture
+    #[arg(long, value_name = "FILE", conflicts_with = "during_capture")]
+    #[arg(
+        help = "Read the during-capture input sequence from a script file with one action per line (# starts a comment)"
+    )]
+    during_capture_file: Option<PathBuf>,
+
+    /// Named pipe to read live input actions from during capture
+    #[arg(long, value_name = "PATH")]
+    #[arg(
+        help = "Execute input actions written to this named pipe (one per line) as they arrive during capture"
+    )]
+    input_pipe: Option<PathBuf>,
+
+    /// Random input to execute during capture
+    #[arg(long, value_name = "MS", conflicts_with_all = ["during_capture", "during_capture_file"])]
+    #[arg(
+        help = "Press random GBA buttons for this many milliseconds during capture, mostly the D-pad and A/B (same as --during-capture fuzz:<MS>)"
+    )]
+    fuzz: Option<u64>,
+
+    /// Presses per second of --fuzz
+    #[arg(long, value_name = "HZ", default_value_t = DEFAULT_FUZZ_HZ)]
+    #[arg(value_parser = clap::value_parser!(u32).range(1..=MAX_FUZZ_HZ as i64))]
+    #[arg(help = "Average number of random presses per second generated by --fuzz")]
+    fuzz_rate: u32,
+
+    /// Random variation of input timing
+    #[arg(long, value_name = "MS")]
+    #[arg(
+        help = "Move every hold and wait by a random amount of up to this many milliseconds either way, seeded by --seed (default: 0, overrides settings.input_jitter_ms)"
+    )]
+    input_jitter: Option<u64>,
+
+    /// Seed of the random input generator
     #[arg(long)]
     #[arg(
-        help = "Input sequence during capture (e.g., 'right:100,wait:500,right:100' for directional inputs)"
+        help = "Seed for fuzz: input and --input-jitter; the same seed generates the same presses and timing (by default a random seed is picked and printed)"
     )]
-    during_capture: Option<String>,
-}
+    seed: Option<u64>,
 
-/// Input actions that can be performed on the mGBA window
-#[derive(Debug, Clone)]
-enum InputAction {
-    /// Press and release a key (optional hold duration in milliseconds)
-    Press { key: Key, duration_ms: Option<u64> },
-    /// Press a key down (manual release required)
-    KeyDown { key: Key },
-    /// Release a previously pressed key
-    KeyUp { key: Key },
-    /// Wait for a specified duration
-    Wait { duration_ms: u64 },
-}
+    /// Write a timeline of the input sent during each capture
+    #[arg(long)]
+    #[arg(
+        help = "Write out/<name>.inputs.json next to each GIF, listing every input action with the step it came from, the keys it pressed and when it started and ended relative to the first frame"
+    )]
+    log_inputs: bool,
 
-/// GBA controller button mappings to keyboard keys
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct GbaKeyMappings {
-    /// A button (default: x)
-    #[serde(default = "default_button_a")]
-    pub a: String,
-    /// B button (default: z)  
-    #[serde(default = "default_button_b")]
-    pub b: String,
-    /// Select button (default: backspace)
-    #[serde(default = "default_select")]
-    pub select: String,
-    /// Start button (default: enter)
-    #[serde(default = "default_start")]
-    pub start: String,
-    /// D-pad Right (default: right)
-    #[serde(default = "default_dpad_right")]
-    pub right: String,
-    /// D-pad Left (default: left)
-    #[serde(default = "default_dpad_left")]
-    pub left: String,
-    /// D-pad Up (default: up)
-    #[serde(default = "default_dpad_up")]
-    pub up: String,
-    /// D-pad Down (default: down)
-    #[serde(default = "default_dpad_down")]
-    pub down: String,
-    /// Right shoulder button (default: s)
-    #[serde(default = "default_button_r")]
-    pub r_shoulder: String,
-    /// Left shoulder button (default: a)
-    #[serde(default = "default_button_l")]
-    pub l_shoulder: String,
-}
+    /// Keys that GBA buttons are mapped to, over the ones in capture.json
+    #[arg(long = "map", value_name = "BUTTON=KEY", value_parser = parse_key_mapping_override)]
+    #[arg(
+        help = "Map a GBA button to a key for every binary, over capture.json (e.g. --map a=k --map start=space); repeat for more buttons"
+    )]
+    map: Vec<KeyMappingOverride>,
 
-// Default key mapping functions using your specified defaults
-fn default_button_a() -> String {
-    "x".to_string()
-}
-fn default_button_b() -> String {
-    "z".to_string()
-}
-fn default_select() -> String {
-    "backspace".to_string()
-}
-fn default_start() -> String {
-    "enter".to_string()
-}
-fn default_dpad_right() -> String {
-    "right".to_string()
-}
-fn default_dpad_left() -> String {
-    "left".to_string()
-}
-fn default_dpad_up() -> String {
-    "up".to_string()
-}
-fn default_dpad_down() -> String {
-    "down".to_string()
-}
-fn default_button_r() -> String {
-    "s".to_string()
-}
-fn default_button_l() -> String {
-    "a".to_string()
-}
+    /// Stop on key-mapping collisions instead of warning
+    #[arg(long)]
+    #[arg(
+        help = "Stop with an error instead of a warning when two GBA buttons are mapped to the same key, or a button to an emulator shortcut the tool sends"
+    )]
+    strict_mappings: bool,
 
-impl Default for GbaKeyMappings {
-    fn default() -> Self {
-        Self {
-            a: default_button_a(),
-            b: default_button_b(),
-            select: default_select(),
-            start: default_start(),
-            right: default_dpad_right(),
-            left: default_dpad_left(),
-            up: default_dpad_up(),
-            down: default_dpad_down(),
-            r_shoulder: default_button_r(),
-            l_shoulder: default_button_l(),
-        }
-    }
-}
+    /// Take the key mappings from mGBA's configuration
+    #[arg(long)]
+    #[arg(
+        help = "Map GBA buttons to the keys mGBA itself is bound to, read from its config.ini, instead of key_mappings in capture.json (--map still applies on top)"
+    )]
+    mappings_from_mgba: bool,
 
-/// Configuration for a single binary's input sequences
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct BinaryConfig {
-    /// Input sequence to execute before capture starts
-    #[serde(skip_serializing_if = "Option::is_none")]
-    before_capture: Option<String>,
-    /// Input sequence to execute during capture
-    #[serde(skip_serializing_if = "Option::is_none")]
-    during_capture: Option<String>,
-    /// Custom GBA key mappings for this binary
-    #[serde(skip_serializing_if = "Option::is_none")]
-    key_mappings: Option<GbaKeyMappings>,
-}
+    /// Send no input at all, whatever capture.json or the command line configure
+    #[arg(long)]
+    #[arg(
+        help = "Send no input: before/during/after sequences, scripts, fuzzing and live input are all skipped, while capture lengths and everything else stay the same"
+    )]
+    no_input: bool,
 
-/// Settings section of configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct ConfigSettings {
-    /// Global GBA key mappings
-    #[serde(skip_serializing_if = "Option::is_none")]
-    key_mappings: Option<GbaKeyMappings>,
-    /// Default configuration applied to all binaries (optional)
-    #[serde(skip_serializing_if = "Option::is_none")]
-    default: Option<BinaryConfig>,
-}
+    /// Record while the game is played by hand
+    #[arg(
+        long,
+        conflicts_with_all = ["before_capture", "during_capture", "during_capture_file", "after_capture", "fuzz", "input_pipe"]
+    )]
+    #[arg(
+        help = "Record while you play in mGBA yourself: no input sequences are sent, and pressing Enter in this terminal ends a recording early"
+    )]
+    interactive: bool,
 
-/// Main configuration structure for capture.json
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct CaptureConfig {
-    /// Global settings (key mappings, defaults, etc.)
-    #[serde(skip_serializing_if = "Option::is_none")]
-    settings: Option<ConfigSettings>,
-    /// Per-binary configurations
-    #[serde(skip_serializing_if = "Option::is_none")]
-    binaries: Option<HashMap<String, BinaryConfig>>,
-}
+    /// Countdown before an interactive recording
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value_t = 3,
+        requires = "interactive"
+    )]
+    #[arg(
+        help = "Seconds counted down in the terminal before each --interactive recording starts"
+    )]
+    countdown: u32,
+
+    /// Wait for Enter before an interactive recording
+    #[arg(long, requires = "interactive")]
+    #[arg(
+        help = "Wait for Enter in this terminal before counting down to each --interactive recording"
+    )]
+    start_on_enter: bool,
 
-/// Loads capture configuration from capture.json file
-fn load_capture_config(project_dir: &Path) -> Result<Option<CaptureConfig>> {
-    let config_path = project_dir.join("capture.json");
+    /// Input sequence to execute after capture
+    #[arg(long, value_name = "[BINARY=]SEQUENCE", value_parser = parse_cli_sequence)]
+    #[arg(
+        help = "Input sequence after the last frame, before mGBA is closed (e.g., 'S,wait:500,A' to save from the pause menu); prefix with BINARY= for one binary only, and repeat for others"
+    )]
+    after_capture: Vec<CliSequence>,
 
-    if !config_path.exists() {
-        return Ok(None);
+    /// Keep one mGBA instance running and load each ROM into it
+    #[arg(long)]
+    #[arg(
+        help = "Launch mGBA once and load each subsequent ROM into the running instance (falls back to a fresh launch on failure)"
+    )]
+    reuse_emulator: bool,
+
+    /// Only capture the segment with this name
+    #[arg(long)]
+    #[arg(
+        help = "Capture only the named segment of binaries that define segments in capture.json"
+    )]
+    segment: Option<String>,
+
+    /// Only build and capture these binaries
+    #[arg(long = "bin", value_name = "BINARY")]
+    #[arg(
+        help = "Build and capture only this binary, even one with skip set in capture.json (repeat for several)"
+    )]
+    bins: Vec<String>,
+
+    /// Capture binaries marked with skip in the config file too
+    #[arg(long)]
+    #[arg(help = "Also build and capture the binaries that have skip set in capture.json")]
+    force_all: bool,
+
+    /// Order in which binaries are built and captured
+    #[arg(long, value_enum, default_value_t = CaptureOrder::Config)]
+    #[arg(
+        help = "Order in which binaries are built and captured: config (settings.order in capture.json, then alphabetical), alpha, or reverse (reverse alphabetical)"
+    )]
+    order: CaptureOrder,
+
+    /// Config file to use instead of the project's capture.toml or capture.json
+    #[arg(long, value_name = "PATH")]
+    #[arg(
+        help = "Use this .toml or .json config file instead of capture.toml or capture.json in the project (settings saved by record and calibrate go there too)"
+    )]
+    config: Option<PathBuf>,
+
+    /// Rewrite the config file in the current version of its format, then exit
+    #[arg(long)]
+    #[arg(
+        help = "Rewrite capture.toml or capture.json in the current version of the config format and exit"
+    )]
+    migrate_config: bool,
+
+    /// Write the capture flags given into the config file, then exit
+    #[arg(long)]
+    #[arg(
+        help = "Write --fps, --duration, --format, sequences, --map and the other capture flags given into the config file and exit; replacing values it already has needs --yes"
+    )]
+    save_config: bool,
+
+    /// Print the resolved settings of every binary, or of one, then exit
+    #[arg(long, value_name = "BINARY", num_args = 0..=1)]
+    #[arg(
+        help = "Print every effective setting of each binary, or only of BINARY, with where its value comes from, and exit without building"
+    )]
+    print_config: Option<Option<String>>,
+
+    /// How --print-config prints the settings
+    #[arg(long, value_enum, default_value = "text")]
+    #[arg(help = "Print --print-config as text or as JSON")]
+    print_format: ReportFormat,
+
+    /// How GBA buttons reach mGBA
+    #[arg(long, value_enum, default_value_t = InputBackendKind::Keyboard)]
+    #[arg(
+        help = "Send GBA buttons as key presses to the mGBA window, press them on a virtual game controller (gamepad, Linux; bind it in mGBA once with the bind-gamepad command), or set them through a Lua script in mGBA (mgba-script)"
+    )]
+    input_backend: InputBackendKind,
+
+    /// Screen-capture backend used to grab frames
+    #[arg(long, value_enum, default_value_t = CaptureBackend::Auto)]
+    #[arg(help = "Screen-capture backend (auto picks the best one compiled in for this platform)")]
+    capture_backend: CaptureBackend,
+
+    /// Regular expression matched against window titles to find the emulator
+    #[arg(long, value_name = "REGEX")]
+    #[arg(
+        help = "Regex matched against window titles to find the emulator window, case-insensitive (default: mgba; overrides window_title_pattern in capture.json)"
+    )]
+    window_title_pattern: Option<String>,
+
+    /// How to tell the mGBA window apart from other windows
+    #[arg(long, value_enum, default_value_t = WindowMatch::Auto)]
+    #[arg(
+        help = "How to find the mGBA window: auto (the process launched through cargo run, then window class or process name, then title), pid (launched process, then title), class (WM_CLASS / application name), process (executable name) or title (--window-title-pattern)"
+    )]
+    match_by: WindowMatch,
+
+    /// Show emulator output on the terminal
+    #[arg(long)]
+    #[arg(
+        help = "Also print the output of cargo run and mGBA to the terminal, instead of only writing it to out/<binary>.run.log"
+    )]
+    inherit_child_output: bool,
+
+    /// Leave the emulator running after the last capture
+    #[arg(long)]
+    #[arg(
+        help = "Leave mGBA running after the last capture and exit, printing its PID (a single binary only, unless --reuse-emulator is set)"
+    )]
+    keep_alive: bool,
+
+    /// Terminate mGBA sessions left over from earlier runs
+    #[arg(long)]
+    #[arg(
+        help = "Terminate mGBA processes that were already running before this run (asks for confirmation unless --yes is given)"
+    )]
+    kill_stale: bool,
+
+    /// Skip confirmation prompts
+    #[arg(short, long)]
+    #[arg(help = "Answer yes to confirmation prompts, such as the one for --kill-stale")]
+    yes: bool,
+
+    /// Emulator frontend whose window is captured
+    #[arg(long, value_enum, default_value_t = Emulator::Auto)]
+    #[arg(
+        help = "Emulator frontend: mgba-qt (title contains mGBA), mgba-sdl (title is the ROM name, found through the launched process) or auto (either)"
+    )]
+    emulator: Emulator,
+
+    /// Refuse to send input unless mGBA has focus
+    #[arg(long)]
+    #[arg(
+        help = "Stop with an error instead of sending input when mGBA can't be given focus (by default a warning is printed and input goes to the focused window)"
+    )]
+    require_focus: bool,
+
+    /// Accept steps longer than five minutes
+    #[arg(long)]
+    #[arg(
+        help = "Accept waits, holds and other steps longer than 5 minutes, which are otherwise rejected as likely typos"
+    )]
+    allow_long_waits: bool,
+
+    /// Continue after a `wait_for:` condition times out
+    #[arg(long)]
+    #[arg(
+        help = "Print a warning and continue the input sequence when a wait_for: condition times out, instead of failing the capture"
+    )]
+    lenient_waits: bool,
+
+    /// Run mGBA on a virtual display
+    #[arg(long)]
+    #[arg(
+        help = "Start an Xvfb virtual display for mGBA and capture from it, for machines without a display such as CI (Linux)"
+    )]
+    headless: bool,
+
+    /// Size of the virtual display
+    #[arg(long, value_name = "WIDTHxHEIGHT", default_value = "1280x720")]
+    #[arg(help = "Screen size of the --headless virtual display")]
+    headless_resolution: String,
+
+    /// Set on the run `--headless` starts on its virtual display
+    #[arg(long, hide = true)]
+    #[arg(help = "Run on the virtual display a --headless run started this process on")]
+    on_virtual_display: bool,
+
+    /// Emulator executable started directly instead of through the cargo runner
+    #[arg(long, value_name = "PATH")]
+    #[arg(
+        help = "Build with cargo build and start this emulator executable with the ROM, instead of going through the cargo runner (overrides emulator.path in capture.json)"
+    )]
+    emulator_path: Option<String>,
+
+    /// Extra arguments for the emulator started with --emulator-path
+    #[arg(long, value_name = "ARGS", allow_hyphen_values = true)]
+    #[arg(
+        help = "Arguments passed to the emulator before the ROM path, separated by spaces, e.g. '-C videoSync=1' (overrides emulator.args in capture.json)"
+    )]
+    emulator_args: Option<String>,
+
+    /// Print diagnostic details
+    #[arg(short, long)]
+    #[arg(
+        help = "Print diagnostic details, such as every window considered as the emulator window"
+    )]
+    verbose: bool,
+
+    /// Record generated test-pattern frames instead of the mGBA window
+    #[arg(long, hide = true)]
+    #[arg(
+        help = "Skip building and launching, and record deterministic test-pattern frames (input sequences are parsed but not injected)"
+    )]
+    synthetic_frames: bool,
+
+    /// Print key events instead of sending them
+    #[arg(long, hide = true)]
+    #[arg(
+        help = "Run input sequences without sending anything, printing each key event with its time instead (also with --synthetic-frames)"
+    )]
+    mock_input: bool,
+
+    /// Don't try to detect and resume a paused emulator
+    #[arg(long)]
+    #[arg(
+        help = "Disable detecting a paused mGBA (title indicator or frozen screen) and sending the pause toggle"
+    )]
+    no_auto_unpause: bool,
+
+    /// Launch mGBA with its software renderer
+    #[arg(long)]
+    #[arg(
+        help = "Launch mGBA with its software display driver instead of OpenGL, whose window can capture as solid black (Linux; otherwise applied automatically when black frames are detected)"
+    )]
+    force_software_renderer: bool,
+
+    /// Configure mGBA for clean captures
+    #[arg(long)]
+    #[arg(
+        help = "Launch mGBA with a temporary configuration: window scale from --emulator-scale, audio muted, no pausing when unfocused and no fps in the title (unless --sync-to-emulator). Your own mGBA config is left untouched"
+    )]
+    managed_emulator_config: bool,
+
+    /// Window scale used with --managed-emulator-config
+    #[arg(long, value_name = "FACTOR", default_value_t = 1)]
+    #[arg(value_parser = clap::value_parser!(u32).range(1..=8))]
+    #[arg(help = "mGBA window scale factor applied by --managed-emulator-config")]
+    emulator_scale: u32,
+
+    /// Write the GIF even when the ROM panicked
+    #[arg(long)]
+    #[arg(
+        help = "Still write the GIF of a binary that panicked (by default only out/<name>_panic.png is saved and the binary is reported as failed)"
+    )]
+    keep_failed_output: bool,
+
+    /// Command each captured frame is piped through before encoding
+    #[arg(long, value_name = "COMMAND")]
+    #[arg(
+        help = "Pipe each captured frame through COMMAND (run by the shell) before it is encoded: it gets the frame as a PNG on stdin and must write a PNG of the same size to stdout (overrides frame_filter_cmd in capture.json)"
+    )]
+    frame_filter: Option<String>,
+
+    /// Go on with the next binary when one fails
+    #[arg(long)]
+    #[arg(
+        help = "Go on with the next binary when a binary's pre_capture_cmd or capture fails, and report every failure at the end (by default the run stops at the first)"
+    )]
+    continue_on_error: bool,
+
+    /// Hold mGBA's fast-forward key for this long before the before-capture sequence
+    #[arg(long, value_name = "MS")]
+    #[arg(
+        help = "Hold mGBA's fast-forward key for MS milliseconds after launch to skip intros (overrides fast_forward_ms in capture.json)"
+    )]
+    fast_forward: Option<u64>,
+
+    /// How long to wait after launch before looking for the emulator window
+    #[arg(long, value_name = "DURATION", value_parser = parse_wait_time)]
+    #[arg(
+        help = "Wait this long after launching a binary before looking for its mGBA window, like 5s or 1m (overrides startup_wait in capture.json; default: 2s)"
+    )]
+    startup_wait: Option<WaitTime>,
+
+    /// How long to look for the emulator window before giving up
+    #[arg(long, value_name = "DURATION", value_parser = parse_wait_time)]
+    #[arg(
+        help = "Give up on a binary whose mGBA window hasn't appeared after this long, like 30s (overrides window_timeout in capture.json; time spent building doesn't count; default: 10s)"
+    )]
+    window_timeout: Option<WaitTime>,
+
+    /// Read mGBA's fps display during capture to warn about slowdown and fix frame timing
+    #[arg(long)]
+    #[arg(
+        help = "Read emulation speed from the mGBA window title during capture, warn when it drops below full speed, and time GIF frames in emulated time"
+    )]
+    sync_to_emulator: bool,
+
+    /// Start capturing when the emulator log prints this text
+    #[arg(long, value_name = "TEXT")]
+    #[arg(
+        help = "Start capture when a line containing TEXT appears in the emulator output (e.g. printed by the ROM to the mGBA log)"
+    )]
+    start_marker: Option<String>,
+
+    /// End capturing early when the emulator log prints this text
+    #[arg(long, value_name = "TEXT")]
+    #[arg(help = "End capture early when a line containing TEXT appears in the emulator output")]
+    stop_marker: Option<String>,
+
+    /// How long to wait for the start marker
+    #[arg(long, value_name = "SECONDS", default_value_t = 30.0)]
+    #[arg(help = "Seconds to wait for --start-marker before capturing anyway")]
+    marker_timeout: f32,
+
+    #[command(subcommand)]
+    command: Option<SubCommand>,
+}
+
+#[derive(clap::Subcommand)]
+enum SubCommand {
+    /// Play a binary in mGBA and print the GBA buttons you press as an input sequence
+    Record(RecordArgs),
+    /// Create the virtual game controller and press each button for mGBA to bind
+    BindGamepad,
+    /// Measure how long mGBA takes to react to input and save it in capture.json
+    Calibrate(CalibrateArgs),
+    /// Check the config against the project's binaries without building or capturing
+    CheckConfig(CheckConfigArgs),
+    /// Write a commented starter config with an entry for each binary of the project
+    Init(InitArgs),
+}
+
+#[derive(clap::Args)]
+struct InitArgs {
+    /// Format of the new config file
+    #[arg(long, value_enum, default_value = "toml")]
+    #[arg(
+        help = "Write capture.toml, which can hold comments, or capture.json (--config picks the format by its extension)"
+    )]
+    format: ConfigFormat,
+
+    /// Replace an existing config
+    #[arg(long)]
+    #[arg(help = "Replace the project's config file if it already has one")]
+    force: bool,
+
+    /// Ask for the framerate and duration
+    #[arg(long)]
+    #[arg(
+        help = "Ask for the fps and duration of the captures instead of leaving them at their defaults"
+    )]
+    interactive: bool,
+}
+
+#[derive(clap::Args)]
+struct CheckConfigArgs {
+    /// How the problems are printed
+    #[arg(long, value_enum, default_value = "text")]
+    #[arg(help = "Print the problems as a list or as JSON")]
+    format: ReportFormat,
+}
+
+/// How `check-config` prints what it found
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ReportFormat {
+    /// One line per problem
+    Text,
+    /// `{"ok": ..., "problems": [{"location": ..., "message": ...}]}`
+    Json,
+}
+
+#[derive(clap::Args)]
+struct CalibrateArgs {
+    /// Binary to measure with
+    #[arg(help = "Name of a binary whose screen changes visibly each time the key is pressed")]
+    binary: String,
+
+    /// Key or GBA button to press
+    #[arg(long, default_value = "A")]
+    #[arg(help = "GBA button or key the binary visibly reacts to")]
+    key: String,
+
+    /// Number of presses to time
+    #[arg(long, default_value_t = DEFAULT_CALIBRATION_TRIALS)]
+    #[arg(value_parser = clap::value_parser!(u32).range(1..))]
+    #[arg(help = "How many presses to time; the median is saved")]
+    trials: u32,
+}
+
+#[derive(clap::Args)]
+struct RecordArgs {
+    /// Binary to play
+    #[arg(help = "Name of the binary to launch and record")]
+    binary: String,
+
+    /// Step that recorded times are rounded to
+    #[arg(long, value_name = "MS", default_value_t = DEFAULT_RECORD_GRANULARITY_MS)]
+    #[arg(value_parser = clap::value_parser!(u64).range(1..))]
+    #[arg(help = "Round recorded press and wait times to a multiple of this many milliseconds")]
+    granularity: u64,
+
+    /// Write the recording into capture.json
+    #[arg(long, value_enum, value_name = "PHASE")]
+    #[arg(
+        help = "Also write the recorded sequence into capture.json as this binary's before, during or after sequence"
+    )]
+    save: Option<SequencePhase>,
+}
+
+/// Order in which binaries are built and captured
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum CaptureOrder {
+    /// The binaries listed in `settings.order` first, then the others alphabetically
+    Config,
+    /// Alphabetical
+    Alpha,
+    /// Reverse alphabetical
+    Reverse,
+}
+
+/// How the GBA buttons of input sequences are sent to mGBA
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum InputBackendKind {
+    /// Key presses to the focused mGBA window
+    Keyboard,
+    /// Buttons of a virtual uinput game controller (Linux, `gamepad` feature)
+    Gamepad,
+    /// Key state set by a Lua script in mGBA (mGBA 0.10+, emulators started directly)
+    MgbaScript,
+}
+
+/// Presses GBA buttons in mGBA without the keyboard, for the input backends other than
+/// `keyboard`
+trait ButtonDevice: Send + Sync {
+    /// Presses or releases the button with the `key_mappings` field `button`
+    fn set(&self, button: &str, pressed: bool) -> Result<()>;
+}
+
+impl ButtonDevice for VirtualGamepad {
+    fn set(&self, button: &str, pressed: bool) -> Result<()> {
+        VirtualGamepad::set(self, button, pressed)
+    }
+}
+
+impl ButtonDevice for ScriptConnection {
+    fn set(&self, button: &str, pressed: bool) -> Result<()> {
+        ScriptConnection::set(self, button, pressed)
+    }
+}
+
+/// Input sequence of a binary in capture.json
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum SequencePhase {
+    Before,
+    During,
+    After,
+}
+
+impl SequencePhase {
+    /// Name of the sequence's field in capture.json
+    fn field(self) -> &'static str {
+        match self {
+            SequencePhase::Before => "before_capture",
+            SequencePhase::During => "during_capture",
+            SequencePhase::After => "after_capture",
+        }
+    }
+}
+
+/// Input actions that can be performed on the mGBA window
+#[derive(Debug, Clone)]
+enum InputAction {
+    /// Press and release a key with its modifiers (optional hold duration)
+    Press {
+        key: KeyCombo,
+        duration: Option<Duration>,
+    },
+    /// Press several keys together, hold them and release them in reverse order. Without
+    /// a duration they are held for `settings.chord_hold_ms`.
+    Chord {
+        keys: Vec<Key>,
+        duration: Option<Duration>,
+    },
+    /// Press a key down (manual release required)
+    KeyDown { key: KeyCombo },
+    /// Release a previously pressed key
+    KeyUp { key: KeyCombo },
+    /// Wait for a specified duration
+    Wait { duration: Duration },
+    /// Type a string of text (used for driving emulator dialogs)
+    Text { text: String },
+    /// Wait until the screen has stopped changing for `window`, giving up after `timeout`
+    WaitForStable { window: Duration, timeout: Duration },
+    /// Wait until the average color of an area of the frame is within `tolerance` of
+    /// `color` on every channel, giving up after `timeout`
+    WaitForColor {
+        area: FrameArea,
+        color: [u8; 3],
+        tolerance: u8,
+        timeout: Duration,
+    },
+    /// Save the current frame as `out/<name>_<label>.png`, numbered without a label
+    Screenshot { label: Option<String> },
+    /// Tap a key at a steady rate for a duration
+    Mash {
+        key: KeyCombo,
+        duration: Duration,
+        hz: u32,
+    },
+    /// Move the mouse to a point of the mGBA window, clicking there unless `button` is
+    /// `None`
+    Mouse {
+        button: Option<Button>,
+        x: u32,
+        y: u32,
+    },
+    /// Wait until frame `frame` of the capture is due, written as an `at:<frame>:` prefix
+    /// of the step it anchors
+    AtFrame { frame: u32 },
+    /// Random presses generated from `seed`, each a key held for a time and a pause after
+    Fuzz {
+        presses: Vec<(KeyCombo, Duration, Duration)>,
+        seed: u64,
+    },
+}
+
+/// A key with the modifiers held around it, like shift+f1
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct KeyCombo {
+    modifiers: Vec<Key>,
+    key: Key,
+}
+
+impl From<Key> for KeyCombo {
+    fn from(key: Key) -> Self {
+        Self {
+            modifiers: Vec::new(),
+            key,
+        }
+    }
+}
+
+impl KeyCombo {
+    /// The modifiers in the order they go down, then the key
+    fn keys(&self) -> Vec<Key> {
+        let mut keys = self.modifiers.clone();
+        keys.push(self.key);
+        keys
+    }
+}
+
+impl std::fmt::Display for KeyCombo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for modifier in &self.modifiers {
+            write!(f, "{}+", key_name(*modifier))?;
+        }
+        write!(f, "{}", key_name(self.key))
+    }
+}
+
+/// A rectangle of captured-frame pixels
+#[derive(Debug, Clone, Copy)]
+struct FrameArea {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+impl FrameArea {
+    fn describe(&self) -> String {
+        if self.width == 1 && self.height == 1 {
+            format!("pixel {},{}", self.x, self.y)
+        } else {
+            format!(
+                "region {},{} {}x{}",
+                self.x, self.y, self.width, self.height
+            )
+        }
+    }
+}
+
+/// GBA controller button mappings to keyboard keys
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct GbaKeyMappings {
+    /// A button (default: x)
+    #[serde(default = "default_button_a")]
+    pub a: String,
+    /// B button (default: z)  
+    #[serde(default = "default_button_b")]
+    pub b: String,
+    /// Select button (default: backspace)
+    #[serde(default = "default_select")]
+    pub select: String,
+    /// Start button (default: enter)
+    #[serde(default = "default_start")]
+    pub start: String,
+    /// D-pad Right (default: right)
+    #[serde(default = "default_dpad_right")]
+    pub right: String,
+    /// D-pad Left (default: left)
+    #[serde(default = "default_dpad_left")]
+    pub left: String,
+    /// D-pad Up (default: up)
+    #[serde(default = "default_dpad_up")]
+    pub up: String,
+    /// D-pad Down (default: down)
+    #[serde(default = "default_dpad_down")]
+    pub down: String,
+    /// Right shoulder button (default: s)
+    #[serde(default = "default_button_r")]
+    pub r_shoulder: String,
+    /// Left shoulder button (default: a)
+    #[serde(default = "default_button_l")]
+    pub l_shoulder: String,
+}
+
+// Default key mapping functions using your specified defaults
+fn default_button_a() -> String {
+    "x".to_string()
+}
+fn default_button_b() -> String {
+    "z".to_string()
+}
+fn default_select() -> String {
+    "backspace".to_string()
+}
+fn default_start() -> String {
+    "enter".to_string()
+}
+fn default_dpad_right() -> String {
+    "right".to_string()
+}
+fn default_dpad_left() -> String {
+    "left".to_string()
+}
+fn default_dpad_up() -> String {
+    "up".to_string()
+}
+fn default_dpad_down() -> String {
+    "down".to_string()
+}
+fn default_button_r() -> String {
+    "s".to_string()
+}
+fn default_button_l() -> String {
+    "a".to_string()
+}
+
+impl Default for GbaKeyMappings {
+    fn default() -> Self {
+        Self {
+            a: default_button_a(),
+            b: default_button_b(),
+            select: default_select(),
+            start: default_start(),
+            right: default_dpad_right(),
+            left: default_dpad_left(),
+            up: default_dpad_up(),
+            down: default_dpad_down(),
+            r_shoulder: default_button_r(),
+            l_shoulder: default_button_l(),
+        }
+    }
+}
+
+impl GbaKeyMappings {
+    /// Each button's field name in capture.json with the key it is mapped to
+    fn buttons(&self) -> [(&'static str, &String); 10] {
+        [
+            ("a", &self.a),
+            ("b", &self.b),
+            ("select", &self.select),
+            ("start", &self.start),
+            ("right", &self.right),
+            ("left", &self.left),
+            ("up", &self.up),
+            ("down", &self.down),
+            ("r_shoulder", &self.r_shoulder),
+            ("l_shoulder", &self.l_shoulder),
+        ]
+    }
+
+    /// The key of the button with this field name
+    fn button(&self, field: &str) -> Option<&String> {
+        self.buttons()
+            .into_iter()
+            .find_map(|(button, key)| (button == field).then_some(key))
+    }
+
+    /// The key of the button with this field name
+    fn button_mut(&mut self, field: &str) -> Option<&mut String> {
+        match field {
+            "a" => Some(&mut self.a),
+            "b" => Some(&mut self.b),
+            "select" => Some(&mut self.select),
+            "start" => Some(&mut self.start),
+            "right" => Some(&mut self.right),
+            "left" => Some(&mut self.left),
+            "up" => Some(&mut self.up),
+            "down" => Some(&mut self.down),
+            "r_shoulder" => Some(&mut self.r_shoulder),
+            "l_shoulder" => Some(&mut self.l_shoulder),
+            _ => None,
+        }
+    }
+}
+
+/// A table of key mappings as written, with only the buttons it sets. The others keep
+/// their default key.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct KeyMappingsTable {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    a: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    b: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    select: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    start: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    right: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    left: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    up: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    down: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    r_shoulder: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    l_shoulder: Option<String>,
+}
+
+impl KeyMappingsTable {
+    /// The buttons the table sets, by their field name, with their keys
+    fn buttons(&self) -> impl Iterator<Item = (&'static str, &String)> {
+        [
+            ("a", &self.a),
+            ("b", &self.b),
+            ("select", &self.select),
+            ("start", &self.start),
+            ("right", &self.right),
+            ("left", &self.left),
+            ("up", &self.up),
+            ("down", &self.down),
+            ("r_shoulder", &self.r_shoulder),
+            ("l_shoulder", &self.l_shoulder),
+        ]
+        .into_iter()
+        .filter_map(|(button, key)| Some((button, key.as_ref()?)))
+    }
+
+    /// Whether the table sets the button with this field name
+    fn sets(&self, button: &str) -> bool {
+        self.buttons().any(|(set, _)| set == button)
+    }
+
+    /// The key of the button with this field name, `None` while the table leaves it out
+    fn button_mut(&mut self, field: &str) -> Option<&mut Option<String>> {
+        match field {
+            "a" => Some(&mut self.a),
+            "b" => Some(&mut self.b),
+            "select" => Some(&mut self.select),
+            "start" => Some(&mut self.start),
+            "right" => Some(&mut self.right),
+            "left" => Some(&mut self.left),
+            "up" => Some(&mut self.up),
+            "down" => Some(&mut self.down),
+            "r_shoulder" => Some(&mut self.r_shoulder),
+            "l_shoulder" => Some(&mut self.l_shoulder),
+            _ => None,
+        }
+    }
+
+    /// The key mappings, with the default key for each button the table leaves out
+    fn mappings(&self) -> GbaKeyMappings {
+        let mut key_mappings = GbaKeyMappings::default();
+        for (button, key) in self.buttons() {
+            if let Some(mapped) = key_mappings.button_mut(button) {
+                *mapped = key.clone();
+            }
+        }
+        key_mappings
+    }
+}
+
+/// Key mappings in capture.json: keys by button, or "mgba" to read mGBA's keyboard
+/// bindings from its config file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum KeyMappingsConfig {
+    Mgba(FromMgba),
+    Keys(Box<KeyMappingsTable>),
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum FromMgba {
+    Mgba,
+}
+
+/// How keys with a character are sent to mGBA
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum KeyInputMode {
+    /// As the character, which the keyboard layout turns into whichever key produces it
+    #[default]
+    Unicode,
+    /// As the key in the character's position on a US QWERTY layout, whatever the layout
+    Scancode,
+}
+
+/// A `--map <button>=<key>` override of the key a GBA button is mapped to
+#[derive(Debug, Clone)]
+struct KeyMappingOverride {
+    /// Field name of the button in capture.json
+    button: &'static str,
+    key: String,
+}
+
+/// A sequence given with `--before-capture`, `--during-capture` or `--after-capture`,
+/// for every binary or, written `<binary>=<sequence>`, for one
+#[derive(Debug, Clone)]
+struct CliSequence {
+    binary: Option<String>,
+    sequence: String,
+}
+
+impl CliSequence {
+    /// A sequence for every binary
+    fn all(sequence: String) -> Self {
+        Self {
+            binary: None,
+            sequence,
+        }
+    }
+}
+
+/// Parses a `--startup-wait` or `--window-timeout` value like `15s` or `1m30s`
+fn parse_wait_time(text: &str) -> std::result::Result<WaitTime, String> {
+    wait_time::parse(text).map_err(|e| e.to_string())?;
+    Ok(WaitTime::Text(text.trim().to_string()))
+}
+
+/// Parses a `--loop` value: `infinite` or a number of plays
+fn parse_loop(text: &str) -> std::result::Result<Loop, String> {
+    Loop::parse(text).map_err(|e| e.to_string())
+}
+
+/// Parses a `--region` value like `0,0,240,160`
+fn parse_region(text: &str) -> std::result::Result<Region, String> {
+    Region::parse(text).map_err(|e| e.to_string())
+}
+
+/// Parses a command-line sequence, taking text up to the first `=` as a binary name when
+/// it looks like one. Sequences only have `=` after a `:`, so they are never mistaken
+/// for a name.
+fn parse_cli_sequence(text: &str) -> std::result::Result<CliSequence, String> {
+    let named = text.split_once('=').filter(|(name, _)| {
+        !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    });
+    Ok(match named {
+        Some((binary, sequence)) => CliSequence {
+            binary: Some(binary.to_string()),
+            sequence: sequence.to_string(),
+        },
+        None => CliSequence::all(text.to_string()),
+    })
+}
+
+/// The command-line sequence of `binary` among `sequences`: its own, or else the one for
+/// every binary
+fn cli_sequence(sequences: &[CliSequence], binary: &str) -> Option<String> {
+    sequences
+        .iter()
+        .find(|sequence| sequence.binary.as_deref() == Some(binary))
+        .or_else(|| sequences.iter().find(|sequence| sequence.binary.is_none()))
+        .map(|sequence| sequence.sequence.clone())
+}
+
+/// Rejects command-line sequences for binaries the project doesn't have, and a phase
+/// given twice for the same binaries
+fn check_cli_sequences(args: &Args, binaries: &[String]) -> Result<()> {
+    for (flag, sequences) in [
+        ("--before-capture", &args.before_capture),
+        ("--during-capture", &args.during_capture),
+        ("--after-capture", &args.after_capture),
+    ] {
+        for (index, sequence) in sequences.iter().enumerate() {
+            if let Some(binary) = sequence.binary.as_ref().filter(|b| !binaries.contains(b)) {
+                return Err(anyhow::anyhow!(
+                    "{} {}=... names no binary of this project (found: {})",
+                    flag,
+                    binary,
+                    binaries.join(", ")
+                ));
+            }
+            if sequences[..index]
+                .iter()
+                .any(|earlier| earlier.binary == sequence.binary)
+            {
+                return Err(anyhow::anyhow!(
+                    "{} is given twice for {}; keep one of them",
+                    flag,
+                    sequence.binary.as_deref().unwrap_or("every binary")
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The capture.json field name of a GBA button, given by that name, by its name or
+/// number in sequences, or by a long name like `start`
+fn mapping_field(name: &str) -> Option<&'static str> {
+    match name.to_lowercase().as_str() {
+        "a" | "0" => Some("a"),
+        "b" | "1" => Some("b"),
+        "e" | "2" | "select" => Some("select"),
+        "s" | "3" | "start" => Some("start"),
+        "r" | "4" | "right" => Some("right"),
+        "l" | "5" | "left" => Some("left"),
+        "u" | "6" | "up" => Some("up"),
+        "d" | "7" | "down" => Some("down"),
+        "i" | "8" | "r_shoulder" => Some("r_shoulder"),
+        "j" | "9" | "l_shoulder" => Some("l_shoulder"),
+        _ => None,
+    }
+}
+
+/// Parses a `--map` value like `a=k` or `start=shift+f1`, checking the key right away
+fn parse_key_mapping_override(text: &str) -> std::result::Result<KeyMappingOverride, String> {
+    let (button, key) = text
+        .split_once('=')
+        .ok_or_else(|| format!("expected BUTTON=KEY, like a=k, not {}", text))?;
+    let button = mapping_field(button.trim()).ok_or_else(|| {
+        format!(
+            "unknown GBA button {} (expected a, b, select, start, up, down, left, right, r_shoulder, l_shoulder or a button name from sequences)",
+            button.trim()
+        )
+    })?;
+    let key = key.trim();
+    parse_mapped_key(key).map_err(|e| e.to_string())?;
+    Ok(KeyMappingOverride {
+        button,
+        key: key.to_string(),
+    })
+}
+
+/// The keys given with `--map` by button, rejecting a button mapped to two different keys
+fn cli_key_mappings(overrides: &[KeyMappingOverride]) -> Result<BTreeMap<&'static str, String>> {
+    let mut mappings = BTreeMap::new();
+    for mapping in overrides {
+        if let Some(previous) = mappings.insert(mapping.button, mapping.key.clone()) {
+            if !previous.eq_ignore_ascii_case(&mapping.key) {
+                return Err(anyhow::anyhow!(
+                    "--map gives the {} button two keys, {} and {}; keep one of them",
+                    mapping.button,
+                    previous,
+                    mapping.key
+                ));
+            }
+        }
+    }
+    Ok(mappings)
+}
+
+/// Configuration for a single binary's input sequences
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BinaryConfig {
+    /// Input sequence to execute before capture starts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    before_capture: Option<String>,
+    /// Input sequence, or concurrent tracks, to execute during capture
+    #[serde(skip_serializing_if = "Option::is_none")]
+    during_capture: Option<InputTracks>,
+    /// Rhai script, relative to the project, run during capture instead of a sequence
+    #[serde(skip_serializing_if = "Option::is_none")]
+    script: Option<String>,
+    /// Input sequence to execute after the last frame, before the emulator is closed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    after_capture: Option<String>,
+    /// How `before_capture` combines with that of `settings.default`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    before_capture_mode: Option<ComposeMode>,
+    /// How `during_capture` combines with that of `settings.default`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    during_capture_mode: Option<ComposeMode>,
+    /// How `after_capture` combines with that of `settings.default`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    after_capture_mode: Option<ComposeMode>,
+    /// GIF framerate for this binary (defaults to --fps)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fps: Option<f32>,
+    /// GIF duration in seconds for this binary, or "auto" to capture for as long as its
+    /// during-capture input takes (defaults to --duration)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration: Option<CaptureLength>,
+    /// Format, name and directory of this binary's captures
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output: Option<OutputConfig>,
+    /// Custom GBA key mappings for this binary, or "mgba" for mGBA's own bindings
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key_mappings: Option<KeyMappingsConfig>,
+    /// Input timing for this binary, field by field over `settings.input_pacing`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    input_pacing: Option<InputPacingConfig>,
+    /// How keys with a character are sent for this binary
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key_input_mode: Option<KeyInputMode>,
+    /// Named scenes captured from a single launch, each into its own GIF
+    #[serde(skip_serializing_if = "Option::is_none")]
+    segments: Option<Vec<SegmentConfig>>,
+    /// Alternative captures of this binary, each from a fresh emulator launch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    variants: Option<BTreeMap<String, VariantConfig>>,
+    /// Milliseconds to fast-forward after launch, before the before-capture sequence
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fast_forward_ms: Option<u64>,
+    /// How long to wait after launch before looking for the emulator window
+    #[serde(skip_serializing_if = "Option::is_none")]
+    startup_wait: Option<WaitTime>,
+    /// How long to look for the emulator window before giving up
+    #[serde(skip_serializing_if = "Option::is_none")]
+    window_timeout: Option<WaitTime>,
+    /// Emulator log text that starts the capture
+    #[serde(skip_serializing_if = "Option::is_none")]
+    start_marker: Option<String>,
+    /// Emulator log text that ends the capture early
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop_marker: Option<String>,
+    /// Regex matched against window titles to find this binary's emulator window
+    #[serde(skip_serializing_if = "Option::is_none")]
+    window_title: Option<String>,
+    /// Emulator started directly for this binary instead of through the cargo runner
+    #[serde(skip_serializing_if = "Option::is_none")]
+    emulator: Option<EmulatorCommand>,
+    /// mGBA savestate, relative to the project, loaded before the before-capture sequence
+    #[serde(skip_serializing_if = "Option::is_none")]
+    savestate: Option<String>,
+    /// Save file, relative to the project, copied into place as the ROM's .sav before
+    /// each launch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    save_file: Option<String>,
+    /// Launch without any save file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    clean_save: Option<bool>,
+    /// Leave this binary out of building and capturing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    skip: Option<bool>,
+    /// Cargo features to build and run this binary with, besides its `required-features`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    features: Option<Vec<String>>,
+    /// Build and run this binary with every cargo feature
+    #[serde(skip_serializing_if = "Option::is_none")]
+    all_features: Option<bool>,
+    /// Build and run this binary without the default cargo features
+    #[serde(skip_serializing_if = "Option::is_none")]
+    no_default_features: Option<bool>,
+    /// Environment variables set for cargo when building and running this binary
+    #[serde(skip_serializing_if = "Option::is_none")]
+    env: Option<BTreeMap<String, String>>,
+    /// Command run after the build, before the emulator is launched
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pre_capture_cmd: Option<HookCommand>,
+    /// Command run once every capture of this binary is written
+    #[serde(skip_serializing_if = "Option::is_none")]
+    post_capture_cmd: Option<HookCommand>,
+    /// Also run `post_capture_cmd` when the binary is skipped by `skip`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    run_on_skip: Option<bool>,
+    /// Command each captured frame is piped through, as a PNG, before encoding
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frame_filter_cmd: Option<HookCommand>,
+    /// How long a hook command, or `frame_filter_cmd` on one frame, may run before it is stopped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hook_timeout: Option<WaitTime>,
+    /// How many times this binary's captures play: `"infinite"` or a number of plays
+    #[serde(rename = "loop", skip_serializing_if = "Option::is_none")]
+    loop_count: Option<LoopSetting>,
+    /// Cropping, scaling, filters and caption applied to the frames before encoding
+    #[serde(skip_serializing_if = "Option::is_none")]
+    post: Option<PostConfig>,
+}
+
+/// Where and how a binary's captures are written, field by field over `settings.default`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct OutputConfig {
+    /// File format (defaults to --format)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<OutputFormat>,
+    /// File name without extension, where `{bin}` stands for the binary's name
+    /// (defaults to `{bin}`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    filename: Option<String>,
+    /// Directory inside the output directory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dir: Option<String>,
+}
+
+/// Where captures go and how they are written unless a binary says otherwise, beneath
+/// the flags
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct OutputSettings {
+    /// Directory everything is written to, relative to the project (defaults to `out`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dir: Option<String>,
+    /// File format of captures whose binary doesn't set `output.format`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<OutputFormat>,
+    /// File name of captures whose binary doesn't set `output.filename`, with `{bin}`
+    /// standing for the binary's name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name_template: Option<String>,
+}
+
+/// How a binary's own sequence for a phase combines with that of `settings.default`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ComposeMode {
+    /// The binary's sequence is used instead of the default one
+    #[default]
+    Replace,
+    /// The default sequence runs first, then the binary's
+    Prepend,
+    /// The binary's sequence runs first, then the default one
+    Append,
+}
+
+/// An alternative capture of a binary, written to `out/<binary>.<variant>.gif`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct VariantConfig {
+    /// Input sequence to execute before this variant's capture starts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    before_capture: Option<String>,
+    /// Input sequence, or concurrent tracks, to execute during this variant's capture
+    #[serde(skip_serializing_if = "Option::is_none")]
+    during_capture: Option<InputTracks>,
+    /// Input sequence to execute after this variant's capture
+    #[serde(skip_serializing_if = "Option::is_none")]
+    after_capture: Option<String>,
+    /// GIF framerate for this variant (defaults to --fps)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fps: Option<f32>,
+    /// GIF duration in seconds for this variant, or "auto" to capture for as long as its
+    /// during-capture input takes (defaults to --duration)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration: Option<CaptureLength>,
+}
+
+/// A named scene of a binary, captured into `out/<binary>_<suffix>.gif`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SegmentConfig {
+    /// Segment name (used by `--segment`)
+    name: String,
+    /// Input sequence to execute before this segment's capture starts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    before_capture: Option<String>,
+    /// Input sequence, or concurrent tracks, to execute during this segment's capture
+    #[serde(skip_serializing_if = "Option::is_none")]
+    during_capture: Option<InputTracks>,
+    /// Input sequence to execute after this segment's capture, before the next one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    after_capture: Option<String>,
+    /// GIF framerate for this segment (defaults to --fps)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fps: Option<f32>,
+    /// GIF duration in seconds for this segment, or "auto" to capture for as long as its
+    /// during-capture input takes (defaults to --duration)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration: Option<CaptureLength>,
+    /// Output file suffix (defaults to the segment name)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suffix: Option<String>,
+}
+
+/// Length of a capture in capture.json: seconds, or "auto" for as long as the
+/// during-capture input takes plus `--sequence-tail`
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(untagged)]
+enum CaptureLength {
+    Seconds(f32),
+    Auto(AutoLength),
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum AutoLength {
+    Auto,
+}
+
+/// A during-capture input sequence, or several sequences run at the same time as
+/// independent tracks, such as one holding a direction while another taps buttons
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum InputTracks {
+    Single(String),
+    Parallel(Vec<String>),
+}
+
+impl InputTracks {
+    fn tracks(&self) -> &[String] {
+        match self {
+            InputTracks::Single(sequence) => std::slice::from_ref(sequence),
+            InputTracks::Parallel(tracks) => tracks,
+        }
+    }
+}
+
+/// A single GIF to capture from a running binary
+#[derive(Debug, Clone)]
+struct CaptureJob {
+    /// Output file name without extension
+    output_name: String,
+    /// Segment name when this capture comes from a configured segment
+    segment: Option<String>,
+    /// Variant name when this capture comes from a configured variant
+    variant: Option<String>,
+    /// Input sequence to execute before capture starts
+    before_input: Option<String>,
+    /// Input sequence, or concurrent tracks, to execute during capture
+    during_input: Option<InputTracks>,
+    /// Input sequence to execute after capture
+    after_input: Option<String>,
+    /// File format of the capture
+    format: OutputFormat,
+    /// GIF framerate (frames per second)
+    fps: f32,
+    /// Where the framerate comes from, for messages
+    fps_source: &'static str,
+    /// GIF duration in seconds
+    duration: f32,
+    /// Where the duration comes from, for messages
+    duration_source: &'static str,
+    /// Take the duration from the during-capture input once it is parsed
+    auto_duration: bool,
+}
+
+impl CaptureJob {
+    /// Names the capture in messages: the binary, with the segment or variant if any
+    fn label(&self, binary: &str) -> String {
+        match (&self.segment, &self.variant) {
+            (Some(segment), _) => format!("{} (segment: {})", binary, segment),
+            (_, Some(variant)) => format!("{} (variant: {})", binary, variant),
+            (None, None) => binary.to_string(),
+        }
+    }
+}
+
+/// An emulator executable started directly with the built ROM, bypassing the cargo runner
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct EmulatorCommand {
+    /// Executable to run, found on PATH or relative to the project directory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
+    /// Arguments passed before the ROM path
+    #[serde(skip_serializing_if = "Option::is_none")]
+    args: Option<Vec<String>>,
+    /// Regex matched against window titles to find this emulator's window
+    #[serde(skip_serializing_if = "Option::is_none")]
+    window_title: Option<String>,
+    /// How this emulator's window is told apart from other windows
+    #[serde(skip_serializing_if = "Option::is_none")]
+    match_by: Option<WindowMatch>,
+    /// Whether holding `settings.emulator_keys.fast_forward` fast-forwards this emulator
+    /// (default: true)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fast_forward: Option<bool>,
+}
+
+/// How ROMs are loaded into a running mGBA instance when `--reuse-emulator` is set
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ReuseEmulatorSettings {
+    /// Shortcut that opens mGBA's Load ROM dialog (default: ctrl+o, meta+o on macOS)
+    #[serde(default = "default_load_rom_shortcut")]
+    pub load_rom_shortcut: String,
+    /// Time to wait for the file dialog to appear before typing the path
+    #[serde(default = "default_dialog_wait_ms")]
+    pub dialog_wait_ms: u64,
+    /// Time to wait for the window title to change to the new ROM
+    #[serde(default = "default_load_timeout_ms")]
+    pub load_timeout_ms: u64,
+}
+
+fn default_load_rom_shortcut() -> String {
+    if cfg!(target_os = "macos") {
+        "meta+o".to_string()
+    } else {
+        "ctrl+o".to_string()
+    }
+}
+fn default_dialog_wait_ms() -> u64 {
+    1000
+}
+fn default_load_timeout_ms() -> u64 {
+    5000
+}
+
+impl Default for ReuseEmulatorSettings {
+    fn default() -> Self {
+        Self {
+            load_rom_shortcut: default_load_rom_shortcut(),
+            dialog_wait_ms: default_dialog_wait_ms(),
+            load_timeout_ms: default_load_timeout_ms(),
+        }
+    }
+}
+
+/// Keyboard shortcuts of the emulator itself, as opposed to GBA buttons
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct EmulatorKeys {
+    /// Toggles pause (default: ctrl+p, meta+p on macOS)
+    #[serde(default = "default_pause_key")]
+    pub pause: String,
+    /// Fast-forwards while held (default: tab)
+    #[serde(default = "default_fast_forward_key")]
+    pub fast_forward: String,
+    /// Loads state slot 1, used for `savestate` (default: f1)
+    #[serde(default = "default_load_state_key")]
+    pub load_state: String,
+}
+
+fn default_pause_key() -> String {
+    if cfg!(target_os = "macos") {
+        "meta+p".to_string()
+    } else {
+        "ctrl+p".to_string()
+    }
+}
+
+fn default_fast_forward_key() -> String {
+    "tab".to_string()
+}
+
+fn default_load_state_key() -> String {
+    "f1".to_string()
+}
+
+impl Default for EmulatorKeys {
+    fn default() -> Self {
+        Self {
+            pause: default_pause_key(),
+            fast_forward: default_fast_forward_key(),
+            load_state: default_load_state_key(),
+        }
+    }
+}
+
+/// How a binary's entry in `binaries` combines with `settings.default`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum MergeMode {
+    /// An entry replaces the default's input sequences, key mappings and input pacing
+    /// as a whole, even the ones it doesn't set
+    #[default]
+    Shallow,
+    /// An entry overrides only the fields it sets, and inherits the others
+    Deep,
+}
+
+/// Settings section of configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ConfigSettings {
+    /// Global GBA key mappings, or "mgba" for mGBA's own bindings
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key_mappings: Option<KeyMappingsConfig>,
+    /// Input timing for all binaries
+    #[serde(skip_serializing_if = "Option::is_none")]
+    input_pacing: Option<InputPacingConfig>,
+    /// How keys with a character are sent: "unicode" (default) or "scancode"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key_input_mode: Option<KeyInputMode>,
+    /// Time between sending a key and mGBA showing its effect, measured by `calibrate`;
+    /// `at:` steps are sent this much early
+    #[serde(skip_serializing_if = "Option::is_none")]
+    input_latency_ms: Option<u64>,
+    /// Default configuration applied to all binaries (optional)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    default: Option<BinaryConfig>,
+    /// How a binary's entry combines with `default`: "shallow" (default) or "deep"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    merge: Option<MergeMode>,
+    /// Binaries to build and capture first, in this order
+    #[serde(skip_serializing_if = "Option::is_none")]
+    order: Option<Vec<String>>,
+    /// Command run once after every binary, given a manifest of the run
+    #[serde(skip_serializing_if = "Option::is_none")]
+    post_run_cmd: Option<HookCommand>,
+    /// In-place ROM loading used by `--reuse-emulator`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reuse_emulator: Option<ReuseEmulatorSettings>,
+    /// Emulator shortcuts used by the tool (pause toggle)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    emulator_keys: Option<EmulatorKeys>,
+    /// Extra window title fragments to dismiss while waiting for mGBA (case-insensitive)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dismiss_popups: Option<Vec<String>>,
+    /// Regex matched against window titles to find the emulator window
+    #[serde(skip_serializing_if = "Option::is_none")]
+    window_title_pattern: Option<String>,
+    /// Regex for the title of the window opened by a cargo runner that isn't mGBA itself,
+    /// such as a wrapper script
+    #[serde(skip_serializing_if = "Option::is_none")]
+    runner_window_pattern: Option<String>,
+    /// Emulator started directly for every binary instead of through the cargo runner
+    #[serde(skip_serializing_if = "Option::is_none")]
+    emulator: Option<EmulatorCommand>,
+    /// How long chords without a duration are held, in milliseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    chord_hold_ms: Option<u64>,
+    /// Random variation of hold and wait durations, in milliseconds either way
+    #[serde(skip_serializing_if = "Option::is_none")]
+    input_jitter_ms: Option<u64>,
+    /// Named input sequences, referenced from other sequences as `@name`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    macros: Option<BTreeMap<String, String>>,
+    /// Before-capture input taking longer than this, in milliseconds, gets a warning
+    #[serde(skip_serializing_if = "Option::is_none")]
+    before_capture_limit_ms: Option<u64>,
+    /// Output directory, format and file names for every binary
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output: Option<OutputSettings>,
+}
+
+/// Main configuration structure for capture.json
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CaptureConfig {
+    /// Version of the config format the file is written in (1 when missing)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<u64>,
+    /// Global settings (key mappings, defaults, etc.)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    settings: Option<ConfigSettings>,
+    /// Per-binary configurations
+    #[serde(skip_serializing_if = "Option::is_none")]
+    binaries: Option<IndexMap<String, BinaryConfig>>,
+}
+
+/// Loads the project's config, from `--config` or its `capture.toml` or `capture.json`,
+/// over the settings of the user-level config, and names the files used
+fn load_capture_config(
+    project_dir: &Path,
+    explicit: Option<&Path>,
+    options: ParseOptions,
+) -> Result<Option<CaptureConfig>> {
+    let project = ConfigFile::find(project_dir, explicit)?;
+    let project_config = project
+        .as_ref()
+        .map(ConfigFile::parse::<CaptureConfig>)
+        .transpose()?;
+    let user = ConfigFile::user()?;
+    let user_config = user
+        .as_ref()
+        .map(ConfigFile::parse::<CaptureConfig>)
+        .transpose()?;
+
+    // Either file's sequences can use the macros the other one gives
+    let macros = layered_macros(project_config.as_ref(), user_config.as_ref());
+    if let (Some(config_file), Some(config)) = (&project, &project_config) {
+        check_capture_config(config_file, config, &macros, options)?;
+        println!("Using {} configuration file", config_file.name());
+    }
+    if let (Some(config_file), Some(config)) = (&user, &user_config) {
+        check_capture_config(config_file, config, &macros, options)?;
+        println!("Using user configuration file {}", config_file.name());
+        if config.binaries.is_some() {
+            println!(
+                "Warning: only the settings of {} are used; its binaries are ignored",
+                config_file.name()
+            );
+        }
+    }
+
+    layer_user_config(project_config, user_config)
+}
+
+/// The project's config with the settings of the user-level config beneath its own
+fn layer_user_config(
+    project_config: Option<CaptureConfig>,
+    user_config: Option<CaptureConfig>,
+) -> Result<Option<CaptureConfig>> {
+    let Some(user_settings) = user_config.and_then(|config| config.settings) else {
+        return Ok(project_config);
+    };
+    Ok(Some(match project_config {
+        Some(mut config) => {
+            config.settings = Some(layer_settings(config.settings.take(), user_settings)?);
+            config
+        }
+        None => CaptureConfig {
+            version: None,
+            settings: Some(user_settings),
+            binaries: None,
+        },
+    }))
+}
+
+/// Checks a config file read into `config`, reporting all of its problems together
+fn check_capture_config(
+    config_file: &ConfigFile,
+    config: &CaptureConfig,
+    macros: &BTreeMap<String, String>,
+    options: ParseOptions,
+) -> Result<()> {
+    let problems = config_problems(config, config_file.unknown_keys(config)?, macros, options);
+    if !problems.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Invalid {}, nothing was captured:\n  {}",
+            config_file.name(),
+            problems.join("\n  ")
+        ));
+    }
+    Ok(())
+}
+
+/// The macros the sequences of either config can use: those of both configs, with the
+/// project's winning over a user-level macro of the same name, as [`layer_settings`]
+/// layers them
+fn layered_macros(
+    project: Option<&CaptureConfig>,
+    user: Option<&CaptureConfig>,
+) -> BTreeMap<String, String> {
+    [user, project]
+        .into_iter()
+        .flatten()
+        .filter_map(|config| config.settings.as_ref()?.macros.clone())
+        .flatten()
+        .collect()
+}
+
+/// The project's settings over those of the user-level config, field by field: tables
+/// like `default`, `key_mappings` and `macros` are merged key by key, the way `extends`
+/// merges files, so the project only replaces the values it sets itself
+fn layer_settings(project: Option<ConfigSettings>, user: ConfigSettings) -> Result<ConfigSettings> {
+    let mut settings = serde_json::to_value(user)?;
+    if let Some(project) = project {
+        config_file::merge_config(&mut settings, serde_json::to_value(project)?);
+    }
+    Ok(serde_json::from_value(settings)?)
+}
+
+/// Everything wrong with a config file that parsed: unknown keys, with the closest known
+/// field when one is likely meant, values out of range, and durations and sequences that
+/// don't parse, with `macros` expanded. Reported all at once, so the file can be fixed in
+/// one go.
+fn config_problems(
+    config: &CaptureConfig,
+    unknown_keys: Vec<String>,
+    macros: &BTreeMap<String, String>,
+    options: ParseOptions,
+) -> Vec<String> {
+    let known_fields: Vec<&str> = [
+        config_file::field_names::<CaptureConfig>(),
+        config_file::field_names::<ConfigSettings>(),
+        config_file::field_names::<BinaryConfig>(),
+        config_file::field_names::<OutputConfig>(),
+        config_file::field_names::<OutputSettings>(),
+        config_file::field_names::<SegmentConfig>(),
+        config_file::field_names::<VariantConfig>(),
+        config_file::field_names::<EmulatorCommand>(),
+        config_file::field_names::<ReuseEmulatorSettings>(),
+        config_file::field_names::<EmulatorKeys>(),
+        config_file::field_names::<KeyMappingsTable>(),
+        config_file::field_names::<InputPacingConfig>(),
+        config_file::field_names::<PostConfig>(),
+    ]
+    .concat();
+    let mut problems: Vec<String> = unknown_keys
+        .into_iter()
+        .map(|path| {
+            let key = path.rsplit('.').next().unwrap_or(&path);
+            let closest = known_fields
+                .iter()
+                .map(|field| (edit_distance(key, field), *field))
+                .filter(|&(distance, _)| distance <= if key.len() <= 4 { 1 } else { 2 })
+                .min();
+            match closest {
+                Some((_, field)) => format!("unknown field {} (did you mean {}?)", path, field),
+                None => format!("unknown field {}", path),
+            }
+        })
+        .collect();
+
+    for key in config.binaries.iter().flat_map(|binaries| binaries.keys()) {
+        if let Err(e) = binary_keys::BinaryKey::parse(key) {
+            problems.push(e.to_string());
+        }
+    }
+
+    if let Some(KeyMappingsConfig::Keys(key_mappings)) = config
+        .settings
+        .as_ref()
+        .and_then(|settings| settings.key_mappings.as_ref())
+    {
+        check_key_mappings("settings", key_mappings, &mut problems);
+    }
+    if let Some(problem) = config
+        .settings
+        .as_ref()
+        .and_then(|settings| settings.post_run_cmd.as_ref())
+        .and_then(HookCommand::problem)
+    {
+        problems.push(format!("settings.post_run_cmd: {}", problem));
+    }
+    if let Some(output) = config
+        .settings
+        .as_ref()
+        .and_then(|settings| settings.output.as_ref())
+    {
+        check_output_settings(output, &mut problems);
+    }
+
+    let default = config
+        .settings
+        .as_ref()
+        .and_then(|settings| settings.default.as_ref())
+        .map(|default| ("settings.default".to_string(), default));
+    let binaries = config.binaries.iter().flatten();
+    let entries = default
+        .into_iter()
+        .chain(binaries.map(|(name, binary)| (format!("binaries.{}", name), binary)));
+    for (owner, binary) in entries {
+        check_capture_values(
+            &owner,
+            CaptureValues {
+                fps: binary.fps,
+                duration: binary.duration,
+                before_capture: binary.before_capture.as_ref(),
+                during_capture: binary.during_capture.as_ref(),
+                after_capture: binary.after_capture.as_ref(),
+            },
+            macros,
+            options,
+            &mut problems,
+        );
+        for (index, segment) in binary.segments.iter().flatten().enumerate() {
+            // The suffix, or else the name, ends the segment's output file name
+            let (field, suffix) = match &segment.suffix {
+                Some(suffix) => ("suffix", suffix),
+                None => ("name", &segment.name),
+            };
+            if let Some(problem) = filename_problem(suffix) {
+                problems.push(format!(
+                    "{}.segments[{}].{} '{}' {}",
+                    owner, index, field, suffix, problem
+                ));
+            }
+            check_capture_values(
+                &format!("{}.segments[{}]", owner, index),
+                CaptureValues {
+                    fps: segment.fps,
+                    duration: segment.duration,
+                    before_capture: segment.before_capture.as_ref(),
+                    during_capture: segment.during_capture.as_ref(),
+                    after_capture: segment.after_capture.as_ref(),
+                },
+                macros,
+                options,
+                &mut problems,
+            );
+        }
+        for (name, variant) in binary.variants.iter().flatten() {
+            // The name ends the variant's output file name
+            if let Some(problem) = filename_problem(name) {
+                problems.push(format!("{}.variants '{}' {}", owner, name, problem));
+            }
+            check_capture_values(
+                &format!("{}.variants.{}", owner, name),
+                CaptureValues {
+                    fps: variant.fps,
+                    duration: variant.duration,
+                    before_capture: variant.before_capture.as_ref(),
+                    during_capture: variant.during_capture.as_ref(),
+                    after_capture: variant.after_capture.as_ref(),
+                },
+                macros,
+                options,
+                &mut problems,
+            );
+        }
+        if let Some(output) = &binary.output {
+            check_output_config(&owner, output, &mut problems);
+        }
+        if let Some(KeyMappingsConfig::Keys(key_mappings)) = &binary.key_mappings {
+            check_key_mappings(&owner, key_mappings, &mut problems);
+        }
+        for (name, value) in binary.env.iter().flatten() {
+            if let Some(reason) = env_vars::invalid_name(name) {
+                problems.push(format!(
+                    "{}.env has an invalid variable name '{}': {}",
+                    owner, name, reason
+                ));
+            } else if let Err(e) = env_vars::interpolate(value) {
+                problems.push(format!("{}.env.{}: {}", owner, name, e));
+            }
+        }
+        let hooks = [
+            ("pre_capture_cmd", &binary.pre_capture_cmd),
+            ("post_capture_cmd", &binary.post_capture_cmd),
+            ("frame_filter_cmd", &binary.frame_filter_cmd),
+        ];
+        for (field, hook) in hooks {
+            if let Some(problem) = hook.as_ref().and_then(HookCommand::problem) {
+                problems.push(format!("{}.{}: {}", owner, field, problem));
+            }
+        }
+        let waits = [
+            ("startup_wait", &binary.startup_wait),
+            ("window_timeout", &binary.window_timeout),
+            ("hook_timeout", &binary.hook_timeout),
+        ];
+        for (field, wait) in waits {
+            if let Some(Err(e)) = wait.as_ref().map(WaitTime::duration) {
+                problems.push(format!("{}.{}: {}", owner, field, e));
+            }
+        }
+        if let Some(Err(e)) = binary.loop_count.as_ref().map(LoopSetting::resolve) {
+            problems.push(format!("{}.loop: {}", owner, e));
+        }
+        for problem in binary.post.iter().flat_map(PostConfig::problems) {
+            problems.push(format!("{}.post.{}", owner, problem));
+        }
+    }
+    problems
+}
+
+/// Adds the keys of `owner`'s `key_mappings` that can't be sent to `problems`
+fn check_key_mappings(owner: &str, key_mappings: &KeyMappingsTable, problems: &mut Vec<String>) {
+    for (button, key) in key_mappings.buttons() {
+        if let Err(e) = parse_mapped_key(key) {
+            problems.push(format!(
+                "{}.key_mappings.{} = '{}': {:#}",
+                owner, button, key, e
+            ));
+        }
+    }
+}
+
+/// The settings of a binary, segment or variant that shape a capture
+struct CaptureValues<'a> {
+    fps: Option<f32>,
+    duration: Option<CaptureLength>,
+    before_capture: Option<&'a String>,
+    during_capture: Option<&'a InputTracks>,
+    after_capture: Option<&'a String>,
+}
+
+/// Adds the values of `owner` that can't be used to `problems`, parsing its sequences
+/// with `macros`
+fn check_capture_values(
+    owner: &str,
+    values: CaptureValues,
+    macros: &BTreeMap<String, String>,
+    options: ParseOptions,
+    problems: &mut Vec<String>,
+) {
+    if let Some(fps) = values.fps.filter(|fps| !(fps.is_finite() && *fps > 0.0)) {
+        problems.push(format!("{}.fps must be greater than 0, not {}", owner, fps));
+    }
+    if let Some(CaptureLength::Seconds(seconds)) = values.duration {
+        if !(seconds.is_finite() && seconds > 0.0) {
+            problems.push(format!(
+                "{}.duration must be greater than 0 or \"auto\", not {}",
+                owner, seconds
+            ));
+        }
+    }
+    let sequences = [
+        ("before_capture", values.before_capture),
+        ("after_capture", values.after_capture),
+    ];
+    for (phase, sequence) in sequences {
+        let Some(sequence) = sequence else {
+            continue;
+        };
+        let field = format!("{}.{}", owner, phase);
+        if sequence.trim().is_empty() {
+            problems.push(format!("{} is empty", field));
+        } else if let Some(problem) =
+            written_sequence_problem(&field, sequence, false, macros, options)
+        {
+            problems.push(problem);
+        }
+    }
+    match values.during_capture {
+        Some(InputTracks::Parallel(tracks)) if tracks.is_empty() => {
+            problems.push(format!("{}.during_capture has no tracks", owner))
+        }
+        Some(during) => {
+            for (index, track) in during.tracks().iter().enumerate() {
+                let field = match during {
+                    InputTracks::Single(_) => format!("{}.during_capture", owner),
+                    InputTracks::Parallel(_) => format!("{}.during_capture[{}]", owner, index),
+                };
+                if track.trim().is_empty() {
+                    problems.push(format!("{} is empty", field));
+                } else if let Some(problem) =
+                    written_sequence_problem(&field, track, true, macros, options)
+                {
+                    problems.push(problem);
+                }
+            }
+        }
+        None => {}
+    }
+}
+
+/// Why a sequence written in the config as `field` doesn't parse, if it doesn't. Key
+/// mappings are checked on their own, so it is parsed with the default ones, and `file:`
+/// scripts are left to the binaries that read them.
+fn written_sequence_problem(
+    field: &str,
+    sequence: &str,
+    during_capture: bool,
+    macros: &BTreeMap<String, String>,
+    options: ParseOptions,
+) -> Option<String> {
+    if sequence.trim_start().starts_with(SCRIPT_PREFIX) {
+        return None;
+    }
+    let error = parse_input_sequence(sequence, &GbaKeyMappings::default(), macros, options)
+        .and_then(|parsed| check_frame_anchors(&parsed, during_capture))
+        .err()?;
+    Some(match error.downcast_ref::<SequenceError>() {
+        Some(error) => format!("{} {}\n{}", field, error, error.excerpt("      ")),
+        None => format!("{}: {:#}", field, error),
+    })
+}
+
+/// Adds the problems of an `output` table to `problems`, such as a file name that would
+/// end up outside `out/`
+fn check_output_config(owner: &str, output: &OutputConfig, problems: &mut Vec<String>) {
+    if let Some(filename) = &output.filename {
+        if let Some(problem) = filename_problem(filename) {
+            problems.push(format!(
+                "{}.output.filename '{}' {}",
+                owner, filename, problem
+            ));
+        }
+    }
+    if let Some(dir) = &output.dir {
+        let inside_out = !dir.trim().is_empty()
+            && !dir.contains('\\')
+            && Path::new(dir).components().all(|component| {
+                matches!(
+                    component,
+                    std::path::Component::Normal(_) | std::path::Component::CurDir
+                )
+            });
+        if !inside_out {
+            problems.push(format!(
+                "{}.output.dir '{}' must be a relative path inside the output directory, without ..",
+                owner, dir
+            ));
+        }
+    }
+}
+
+/// Why a file name template like `output.filename` can't name a capture, if it can't
+fn filename_problem(filename: &str) -> Option<&'static str> {
+    if filename.trim().is_empty() || filename == "." || filename == ".." {
+        Some("is not a file name")
+    } else if filename.contains(['/', '\\']) {
+        Some("must not contain a path; set output.dir for the directory")
+    } else if filename.replace("{bin}", "").contains(['{', '}']) {
+        Some("has an unknown placeholder; only {bin} is supported")
+    } else {
+        None
+    }
+}
+
+/// Adds the problems of `settings.output` to `problems`. Whether its directory is inside
+/// the project is checked once the project is known.
+fn check_output_settings(output: &OutputSettings, problems: &mut Vec<String>) {
+    if let Some(name_template) = &output.name_template {
+        if let Some(problem) = filename_problem(name_template) {
+            problems.push(format!(
+                "settings.output.name_template '{}' {}",
+                name_template, problem
+            ));
+        }
+    }
+    if output.dir.as_ref().is_some_and(|dir| dir.trim().is_empty()) {
+        problems.push("settings.output.dir is empty; leave it out to use out".to_string());
+    }
+}
+
+/// A problem `check-config` found, with the config file or capture it is in
+#[derive(Serialize)]
+struct ConfigProblem {
+    location: String,
+    message: String,
+}
+
+impl ConfigProblem {
+    fn new(location: &str, message: impl std::fmt::Display) -> Self {
+        ConfigProblem {
+            location: location.to_string(),
+            message: format!("{:#}", message),
+        }
+    }
+}
+
+/// What `check-config --format json` prints
+#[derive(Serialize)]
+struct ConfigReport {
+    ok: bool,
+    problems: Vec<ConfigProblem>,
+}
+
+/// Runs `check-config`: prints what is wrong with the config and fails if anything is
+fn run_check_config(project_dir: &Path, args: &Args, format: ReportFormat) -> Result<()> {
+    let problems = check_config(project_dir, args)?;
+    let count = problems.len();
+    match format {
+        ReportFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&ConfigReport {
+                ok: problems.is_empty(),
+                problems,
+            })?
+        ),
+        ReportFormat::Text if count > 0 => {
+            return Err(anyhow::anyhow!(
+                "The config has {} problem(s):\n{}",
+                count,
+                list_problems(&problems)
+            ))
+        }
+        ReportFormat::Text => println!("No problems found in the config"),
+    }
+    if count > 0 {
+        return Err(anyhow::anyhow!("The config has {} problem(s)", count));
+    }
+    Ok(())
+}
+
+/// One problem per line, each with its location
+fn list_problems(problems: &[ConfigProblem]) -> String {
+    problems
+        .iter()
+        .map(|problem| format!("  {}: {}", problem.location, problem.message))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Everything a capture run would refuse in the config before building: the files
+/// themselves and `binaries` entries matching no binary, then, resolved for each binary
+/// found in the project, its sequences, script, key mappings, window title pattern,
+/// emulator, savestate, save file and output paths. Nothing is built or launched, so this stays
+/// fast enough for CI. Skipped binaries are checked too. Every check runs whatever the
+/// others found, and all problems are returned together.
+fn check_config(project_dir: &Path, args: &Args) -> Result<Vec<ConfigProblem>> {
+    let mut problems = Vec::new();
+    let project_config = read_config_file(
+        ConfigFile::find(project_dir, args.config.as_deref()),
+        "--config",
+        &mut problems,
+    );
+    let user_config = read_config_file(ConfigFile::user(), "user config", &mut problems);
+    let macros = layered_macros(
+        project_config.as_ref().map(|(_, config)| config),
+        user_config.as_ref().map(|(_, config)| config),
+    );
+    for (config_file, config) in project_config.iter().chain(&user_config) {
+        match config_file.unknown_keys(config) {
+            Ok(unknown_keys) => problems.extend(
+                config_problems(config, unknown_keys, &macros, ParseOptions::new(args))
+                    .iter()
+                    .map(|problem| ConfigProblem::new(config_file.name(), problem)),
+            ),
+            Err(e) => problems.push(ConfigProblem::new(config_file.name(), e)),
+        }
+    }
+    // The binaries are checked against whatever config did read, problems and all
+    let project_config = project_config.map(|(_, config)| config);
+    let user_config = user_config.map(|(_, config)| config);
+    let capture_config = match layer_user_config(project_config.clone(), user_config) {
+        Ok(capture_config) => capture_config,
+        Err(e) => {
+            problems.push(ConfigProblem::new("user config", e));
+            project_config
+        }
+    };
+
+    let binaries = discover_binaries(project_dir).unwrap_or_else(|e| {
+        problems.push(ConfigProblem::new(&project_dir.display().to_string(), e));
+        Vec::new()
+    });
+    if binaries.is_empty() {
+        problems.push(ConfigProblem::new(
+            &project_dir.display().to_string(),
+            "no binary files found in src/bin/ or src/main.rs",
+        ));
+    }
+    // An entry no binary uses is most likely a typo or a binary that was renamed
+    let keys = capture_config
+        .iter()
+        .flat_map(|config| config.binaries.iter().flat_map(|binaries| binaries.keys()));
+    for key in keys {
+        let Ok(parsed) = binary_keys::BinaryKey::parse(key) else {
+            continue;
+        };
+        if binaries.iter().any(|binary| parsed.matches(binary)) {
+            continue;
+        }
+        let closest = binaries
+            .iter()
+            .map(|binary| (edit_distance(key, binary), binary))
+            .filter(|&(distance, _)| distance <= 2)
+            .min();
+        problems.push(ConfigProblem::new(
+            &format!("binaries.{}", key),
+            match closest {
+                Some((_, binary)) => format!("matches no binary (did you mean {}?)", binary),
+                None => "matches no binary".to_string(),
+            },
+        ));
+    }
+
+    let mut external_mappings = ExternalKeyMappings {
+        cli: cli_key_mappings(&args.map).unwrap_or_else(|e| {
+            problems.push(ConfigProblem::new("--map", e));
+            BTreeMap::new()
+        }),
+        prefer_mgba: args.mappings_from_mgba,
+        ..Default::default()
+    };
+    if args.mappings_from_mgba || config_uses_mgba_mappings(&capture_config) {
+        match load_mgba_key_mappings(args.emulator, false) {
+            Ok(key_mappings) => external_mappings.mgba = Some(key_mappings),
+            Err(e) => problems.push(ConfigProblem::new("key_mappings", e)),
+        }
+    }
+    let settings = capture_config
+        .as_ref()
+        .and_then(|config| config.settings.as_ref());
+    let macros = settings
+        .and_then(|settings| settings.macros.clone())
+        .unwrap_or_default();
+    let title_pattern = settings
+        .and_then(|settings| settings.window_title_pattern.clone())
+        .or_else(|| {
+            settings
+                .and_then(|settings| settings.emulator.as_ref())
+                .and_then(|emulator| emulator.window_title.clone())
+        })
+        .unwrap_or_else(|| DEFAULT_TITLE_PATTERN.to_string());
+
+    let output_root = match OutputRoot::resolve(
+        project_dir,
+        args.output_dir.as_deref(),
+        settings
+            .and_then(|settings| settings.output.as_ref())
+            .and_then(|output| output.dir.as_deref()),
+        args.allow_external_output,
+    ) {
+        Ok(output_root) => output_root,
+        Err(e) => {
+            problems.push(ConfigProblem::new("output", e));
+            OutputRoot {
+                dir: PathBuf::from(output_root::DEFAULT_DIR),
+                source: "builtin",
+            }
+        }
+    };
+    let mut output_paths: HashMap<String, String> = HashMap::new();
+    for binary in &binaries {
+        // The keys themselves were checked where they come from: the config files,
+        // `--map` and mGBA's bindings. Default keys stand in for those that don't parse,
+        // so the sequences that use their buttons aren't reported for them again.
+        let mut key_mappings =
+            get_effective_key_mappings(binary, &capture_config, &external_mappings);
+        for (button, default) in GbaKeyMappings::default().buttons() {
+            if let Some(key) = key_mappings
+                .button_mut(button)
+                .filter(|key| parse_mapped_key(key).is_err())
+            {
+                *key = default.clone();
+            }
+        }
+        if args.strict_mappings {
+            let shortcuts = sent_shortcuts(binary, &capture_config, args);
+            for collision in key_mapping_collisions(&key_mappings, &shortcuts) {
+                problems.push(ConfigProblem::new(binary, collision));
+            }
+        }
+        if let Err(e) = load_binary_script(binary, &capture_config, project_dir) {
+            problems.push(ConfigProblem::new(binary, format!("script: {:#}", e)));
+        }
+        match get_capture_launches(binary, &capture_config, args) {
+            Ok(launches) => {
+                for job in launches.iter().flatten() {
+                    let label = job.label(binary);
+                    let unreported = without_reported_sequences(job, binary, args, &macros);
+                    if let Err(errors) = parse_job_sequences(
+                        &unreported,
+                        &key_mappings,
+                        &macros,
+                        ParseOptions::new(args),
+                        project_dir,
+                    ) {
+                        problems.extend(errors.iter().map(|e| ConfigProblem::new(&label, e)));
+                    }
+                    let path = output_root.file(&format!(
+                        "{}.{}",
+                        job.output_name,
+                        job.format.extension()
+                    ));
+                    if let Some(other) = output_paths.insert(path.clone(), label.clone()) {
+                        problems.push(ConfigProblem::new(
+                            &label,
+                            format!(
+                                "{} would also be written by {}; give one of them another output.filename",
+                                path, other
+                            ),
+                        ));
+                    }
+                }
+            }
+            Err(e) => problems.push(ConfigProblem::new(binary, e)),
+        }
+        let files = [
+            (
+                "savestate",
+                binary_configs(binary, &capture_config)
+                    .find_map(|binary_config| binary_config.savestate.as_ref()),
+            ),
+            (
+                "save_file",
+                binary_configs(binary, &capture_config)
+                    .find_map(|binary_config| binary_config.save_file.as_ref()),
+            ),
+        ];
+        for (setting, file) in files {
+            let Some(file) = file else {
+                continue;
+            };
+            let path = project_dir.join(file);
+            if !path.is_file() {
+                problems.push(ConfigProblem::new(
+                    binary,
+                    format!("{} {} does not exist", setting, path.display()),
+                ));
+            }
+        }
+        let pattern = get_effective_window_title(
+            binary,
+            &capture_config,
+            &args.window_title_pattern,
+            &title_pattern,
+        );
+        if let Err(e) = compile_title_pattern(&pattern) {
+            problems.push(ConfigProblem::new(binary, e));
+        }
+        if let Err(e) = get_effective_post(binary, &capture_config, args) {
+            problems.push(ConfigProblem::new(binary, e));
+        }
+        if let Some((program, _)) = get_effective_emulator_command(binary, &capture_config, args) {
+            if locate_program(&program, project_dir).is_none() {
+                problems.push(ConfigProblem::new(
+                    binary,
+                    format!(
+                        "emulator {} was not found; check the path or put it on PATH",
+                        program
+                    ),
+                ));
+            }
+        }
+    }
+    Ok(problems)
+}
+
+/// A capture with the sequences left out that the check of the config files already
+/// reported: those written in them that don't parse even with the default key mappings.
+/// Parallel tracks are emptied rather than removed, so the others keep their numbers.
+fn without_reported_sequences(
+    job: &CaptureJob,
+    binary: &str,
+    args: &Args,
+    macros: &BTreeMap<String, String>,
+) -> CaptureJob {
+    let options = ParseOptions::new(args);
+    let reported = |cli: &[CliSequence], sequence: &str, during_capture: bool| {
+        cli_sequence(cli, binary).is_none()
+            && written_sequence_problem("", sequence, during_capture, macros, options).is_some()
+    };
+    let mut job = job.clone();
+    job.before_input = job
+        .before_input
+        .filter(|sequence| !reported(&args.before_capture, sequence, false));
+    job.after_input = job
+        .after_input
+        .filter(|sequence| !reported(&args.after_capture, sequence, false));
+    job.during_input = match job.during_input {
+        Some(InputTracks::Parallel(tracks)) => Some(InputTracks::Parallel(
+            tracks
+                .into_iter()
+                .map(|track| match reported(&args.during_capture, &track, true) {
+                    true => String::new(),
+                    false => track,
+                })
+                .collect(),
+        )),
+        during => during.filter(|during| {
+            !during
+                .tracks()
+                .iter()
+                .any(|track| reported(&args.during_capture, track, true))
+        }),
+    };
+    job
+}
+
+/// Reads one config file for `check-config`, adding it to `problems` if it can't be read.
+/// `location` names what failed to be found, when there is no file to name.
+fn read_config_file(
+    config_file: Result<Option<ConfigFile>>,
+    location: &str,
+    problems: &mut Vec<ConfigProblem>,
+) -> Option<(ConfigFile, CaptureConfig)> {
+    let config_file = match config_file {
+        Ok(config_file) => config_file?,
+        Err(e) => {
+            problems.push(ConfigProblem::new(location, e));
+            return None;
+        }
+    };
+    match config_file.parse_quietly() {
+        Ok(config) => Some((config_file, config)),
+        Err(e) => {
+            problems.push(ConfigProblem::new(config_file.name(), e));
+            None
+        }
+    }
+}
+
+/// A setting as `--print-config` prints it: its effective value, and where that comes
+/// from
+#[derive(Serialize)]
+struct EffectiveSetting {
+    value: serde_json::Value,
+    source: String,
+}
+
+impl EffectiveSetting {
+    fn new(value: impl Into<serde_json::Value>, source: impl Into<String>) -> Self {
+        EffectiveSetting {
+            value: value.into(),
+            source: source.into(),
+        }
+    }
+
+    /// The first of `candidates` that is set, or else the tool's own `builtin` value
+    fn first_set(
+        candidates: Vec<Option<(serde_json::Value, &'static str)>>,
+        builtin: impl Into<serde_json::Value>,
+    ) -> Self {
+        candidates.into_iter().flatten().next().map_or_else(
+            || EffectiveSetting::new(builtin, "builtin"),
+            |(value, source)| EffectiveSetting::new(value, source),
+        )
+    }
+
+    /// The value as `--print-config` prints it as text
+    fn text(value: &serde_json::Value) -> String {
+        match value {
+            serde_json::Value::Null => "none".to_string(),
+            serde_json::Value::String(text) => text.clone(),
+            serde_json::Value::Array(items) => items
+                .iter()
+                .map(EffectiveSetting::text)
+                .collect::<Vec<_>>()
+                .join(" | "),
+            other => other.to_string(),
+        }
+    }
+}
+
+/// The effective settings of a binary and of each capture it makes
+#[derive(Serialize)]
+struct EffectiveConfig {
+    binary: String,
+    /// Key of the `binaries` entry that applies, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    entry: Option<String>,
+    settings: IndexMap<String, EffectiveSetting>,
+    captures: Vec<EffectiveCapture>,
+}
+
+/// The settings of one capture of a binary
+#[derive(Serialize)]
+struct EffectiveCapture {
+    label: String,
+    settings: IndexMap<String, EffectiveSetting>,
+}
+
+/// Prints the effective settings of the project's binaries, or only of `only`, for
+/// `--print-config`. The config is checked like `check-config` does first, and nothing
+/// is built.
+fn print_config(project_dir: &Path, args: &Args, only: Option<&str>) -> Result<()> {
+    let problems = check_config(project_dir, args)?;
+    if !problems.is_empty() {
+        return Err(anyhow::anyhow!(
+            "The config has {} problem(s):\n{}",
+            problems.len(),
+            list_problems(&problems)
+        ));
+    }
+    let (config, user_settings) = load_capture_config_quietly(project_dir, args.config.as_deref())?;
+
+    let mut binaries = discover_binaries(project_dir)?;
+    if let Some(only) = only {
+        if !binaries.iter().any(|binary| binary == only) {
+            return Err(anyhow::anyhow!(
+                "No binary named {} (found: {})",
+                only,
+                binaries.join(", ")
+            ));
+        }
+        binaries = vec![only.to_string()];
+    }
+    let mut external_mappings = ExternalKeyMappings {
+        cli: cli_key_mappings(&args.map)?,
+        prefer_mgba: args.mappings_from_mgba,
+        ..Default::default()
+    };
+    if args.mappings_from_mgba || config_uses_mgba_mappings(&config) {
+        external_mappings.mgba = Some(load_mgba_key_mappings(args.emulator, false)?);
+    }
+    let runner = CargoConfig::load(project_dir)?.and_then(|config| config.runner());
+
+    let effective = binaries
+        .iter()
+        .map(|binary| {
+            effective_config(
+                binary,
+                &config,
+                &user_settings,
+                &external_mappings,
+                runner.as_ref(),
+                args,
+                project_dir,
+            )
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if args.print_format == ReportFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&effective)?);
+        return Ok(());
+    }
+    for binary in &effective {
+        match &binary.entry {
+            Some(entry) => println!("{} (binaries.{}):", binary.binary, entry),
+            None => println!("{} (no entry in binaries):", binary.binary),
+        }
+        let print = |indent: &str, settings: &IndexMap<String, EffectiveSetting>| {
+            for (name, setting) in settings {
+                println!(
+                    "{}{}: {} ({})",
+                    indent,
+                    name,
+                    EffectiveSetting::text(&setting.value),
+                    setting.source
+                );
+            }
+        };
+        print("  ", &binary.settings);
+        for capture in &binary.captures {
+            println!("  capture {}:", capture.label);
+            print("    ", &capture.settings);
+        }
+    }
+    Ok(())
+}
+
+/// The config as [`load_capture_config`] loads it, without printing anything, and the
+/// settings it takes from the user-level config rather than the project's
+fn load_capture_config_quietly(
+    project_dir: &Path,
+    explicit: Option<&Path>,
+) -> Result<(Option<CaptureConfig>, Vec<String>)> {
+    let project_config = ConfigFile::find(project_dir, explicit)?
+        .map(|config_file| config_file.parse_quietly::<CaptureConfig>())
+        .transpose()?;
+    let user_config = ConfigFile::user()?
+        .map(|config_file| config_file.parse_quietly::<CaptureConfig>())
+        .transpose()?;
+    let from_user = settings_from_user(
+        project_config
+            .as_ref()
+            .and_then(|config| config.settings.as_ref()),
+        user_config
+            .as_ref()
+            .and_then(|config| config.settings.as_ref()),
+    )?;
+    Ok((layer_user_config(project_config, user_config)?, from_user))
+}
+
+/// The settings the user-level config supplies, as dotted paths like
+/// `default.startup_wait`: those it sets and the project leaves out, whatever their
+/// values, as [`layer_settings`] layers them
+fn settings_from_user(
+    project: Option<&ConfigSettings>,
+    user: Option<&ConfigSettings>,
+) -> Result<Vec<String>> {
+    /// Adds the paths under `prefix` that `user` sets and `project` doesn't
+    fn collect(
+        user: &serde_json::Value,
+        project: Option<&serde_json::Value>,
+        prefix: &str,
+        paths: &mut Vec<String>,
+    ) {
+        let Some(user) = user.as_object() else {
+            return;
+        };
+        for (key, value) in user {
+            let path = format!("{}{}", prefix, key);
+            match project.and_then(|project| project.get(key)) {
+                None => paths.push(path),
+                Some(project) if value.is_object() && project.is_object() => {
+                    collect(value, Some(project), &format!("{}.", path), paths)
+                }
+                Some(_) => {}
+            }
+        }
+    }
+    let mut paths = Vec::new();
+    collect(
+        &serde_json::to_value(user)?,
+        Some(&serde_json::to_value(project)?),
+        "",
+        &mut paths,
+    );
+    Ok(paths)
+}
+
+/// A number as written, without the digits an `f32` gains as an `f64`
+fn f32_value(number: f32) -> serde_json::Value {
+    if number.fract() == 0.0 {
+        return (number as i64).into();
+    }
+    number
+        .to_string()
+        .parse::<f64>()
+        .map_or(serde_json::Value::Null, serde_json::Value::from)
+}
+
+/// A wait as written in the config, whole seconds without a fraction
+fn wait_value(wait: &WaitTime) -> serde_json::Value {
+    match wait {
+        WaitTime::Seconds(seconds) if seconds.fract() == 0.0 => (*seconds as i64).into(),
+        WaitTime::Seconds(seconds) => (*seconds).into(),
+        WaitTime::Text(text) => text.clone().into(),
+    }
+}
+
+/// Resolves every setting of a binary, and of each of its captures, the way a capture
+/// run does, noting where each value comes from: `cli`, `binary` (its entry, segment or
+/// variant), `default` (`settings.default`), `settings`, `user` (the user-level config),
+/// `mgba`, `cargo` (`required-features` in Cargo.toml), `runner` (the cargo runner in
+/// `.cargo/config.toml`) or `builtin`. Sequences are shown with their groups and macros
+/// expanded.
+fn effective_config(
+    binary: &str,
+    config: &Option<CaptureConfig>,
+    user_settings: &[String],
+    external: &ExternalKeyMappings,
+    runner: Option<&Runner>,
+    args: &Args,
+    project_dir: &Path,
+) -> Result<EffectiveConfig> {
+    // Whether the user-level config supplies the setting at `path`, or part of it
+    let from_user = |path: &str| {
+        user_settings.iter().any(|setting| {
+            setting == path
+                || setting.starts_with(&format!("{}.", path))
+                || path.starts_with(&format!("{}.", setting))
+        })
+    };
+    let settings_source = |path: &str| if from_user(path) { "user" } else { "settings" };
+    // The sources of `binary_setting` and capture jobs, which name flags for messages.
+    // Which `settings.default` values the user-level config supplies is told apart once
+    // every setting is named, by `default_from_user`.
+    let source = |source: &'static str| match source {
+        "binary" | "segment" | "variant" => "binary",
+        "settings.default" => "default",
+        "default" => "builtin",
+        _ => "cli",
+    };
+    let configured = |field: &dyn Fn(&BinaryConfig) -> Option<serde_json::Value>| {
+        binary_setting(binary, config, field).map(|(value, from)| (value, source(from)))
+    };
+    let config_settings = config.as_ref().and_then(|config| config.settings.as_ref());
+    let setting = |value: Option<serde_json::Value>, key: &str| {
+        value.map(|value| (value, settings_source(key)))
+    };
+    let cli = |value: Option<serde_json::Value>| value.map(|value| (value, "cli"));
+    let mut settings = IndexMap::new();
+
+    // Key mappings, button by button, from the table that sets each one
+    let key_mappings = get_effective_key_mappings(binary, config, external);
+    let base = base_key_mappings(binary, config, external);
+    let configured_source =
+        if binary_config(binary, config).is_some_and(|entry| entry.key_mappings.is_some()) {
+            Some("binary")
+        } else if merge_mode(config) == MergeMode::Deep
+            && binary_configs(binary, config).any(|entry| entry.key_mappings.is_some())
+        {
+            Some(source("settings.default"))
+        } else {
+            None
+        };
+    for (button, key) in key_mappings.buttons() {
+        let path = format!("key_mappings.{}", button);
+        let key_source = match &base {
+            _ if external.cli.contains_key(button) => "cli",
+            Some((table, source)) if table.sets(button) => match *source {
+                "mGBA" => "mgba",
+                _ => configured_source.unwrap_or_else(|| settings_source(&path)),
+            },
+            _ => "builtin",
+        };
+        settings.insert(path, EffectiveSetting::new(key.as_str(), key_source));
+    }
+    settings.insert(
+        "key_input_mode".to_string(),
+        EffectiveSetting::first_set(
+            vec![
+                configured(&|entry| entry.key_input_mode.map(|mode| serde_json::json!(mode))),
+                setting(
+                    config_settings
+                        .and_then(|settings| settings.key_input_mode)
+                        .map(|mode| serde_json::json!(mode)),
+                    "key_input_mode",
+                ),
+            ],
+            serde_json::json!(KeyInputMode::default()),
+        ),
+    );
+
+    // Input pacing, field by field, with `settings.default` only when merging deeply
+    let pacing = |name: &str, field: fn(&InputPacingConfig) -> Option<serde_json::Value>| {
+        let entry = match merge_mode(config) {
+            MergeMode::Deep => configured(&|entry| entry.input_pacing.as_ref().and_then(field)),
+            MergeMode::Shallow => binary_config(binary, config)
+                .and_then(|entry| entry.input_pacing.as_ref())
+                .and_then(field)
+                .map(|value| (value, "binary")),
+        };
+        let global = setting(
+            config_settings
+                .and_then(|settings| settings.input_pacing.as_ref())
+                .and_then(field),
+            &format!("input_pacing.{}", name),
+        );
+        vec![entry, global]
+    };
+    settings.insert(
+        "input_pacing.min_press_ms".to_string(),
+        EffectiveSetting::first_set(
+            pacing("min_press_ms", |pacing| pacing.min_press_ms.map(Into::into)),
+            DEFAULT_MIN_PRESS_MS,
+        ),
+    );
+    settings.insert(
+        "input_pacing.min_gap_ms".to_string(),
+        EffectiveSetting::first_set(
+            pacing("min_gap_ms", |pacing| pacing.min_gap_ms.map(Into::into)),
+            0,
+        ),
+    );
+    settings.insert(
+        "input_pacing.linux_delay_ms".to_string(),
+        EffectiveSetting::first_set(
+            pacing("linux_delay_ms", |pacing| {
+                pacing.linux_delay_ms.map(Into::into)
+            }),
+            DEFAULT_LINUX_DELAY_MS,
+        ),
+    );
+    settings.insert(
+        "input_pacing.independent_of_keyboard_state".to_string(),
+        EffectiveSetting::first_set(
+            pacing("independent_of_keyboard_state", |pacing| {
+                pacing.independent_of_keyboard_state.map(Into::into)
+            }),
+            true,
+        ),
+    );
+    settings.insert(
+        "input_latency_ms".to_string(),
+        EffectiveSetting::first_set(
+            vec![setting(
+                config_settings
+                    .and_then(|settings| settings.input_latency_ms)
+                    .map(Into::into),
+                "input_latency_ms",
+            )],
+            0,
+        ),
+    );
+
+    // Finding and starting the emulator
+    let runner_pattern = config_settings
+        .and_then(|settings| settings.runner_window_pattern.clone())
+        .filter(|_| runner.is_some_and(|runner| runner.emulator().is_none()));
+    let settings_emulator = config_settings.and_then(|settings| settings.emulator.as_ref());
+    settings.insert(
+        "window_title".to_string(),
+        EffectiveSetting::first_set(
+            vec![
+                cli(args.window_title_pattern.clone().map(Into::into)),
+                configured(&|entry| {
+                    entry
+                        .window_title
+                        .clone()
+                        .or_else(|| entry.emulator.as_ref()?.window_title.clone())
+                        .map(Into::into)
+                }),
+                setting(
+                    config_settings
+                        .and_then(|settings| settings.window_title_pattern.clone())
+                        .map(Into::into),
+                    "window_title_pattern",
+                ),
+                setting(
+                    settings_emulator
+                        .and_then(|emulator| emulator.window_title.clone())
+                        .map(Into::into),
+                    "emulator.window_title",
+                ),
+                setting(runner_pattern.map(Into::into), "runner_window_pattern"),
+            ],
+            DEFAULT_TITLE_PATTERN,
+        ),
+    );
+    let emulator_path = EffectiveSetting::first_set(
+        vec![
+            cli(args.emulator_path.clone().map(Into::into)),
+            configured(&|entry| entry.emulator.as_ref()?.path.clone().map(Into::into)),
+            setting(
+                settings_emulator
+                    .and_then(|emulator| emulator.path.clone())
+                    .map(Into::into),
+                "emulator.path",
+            ),
+        ],
+        serde_json::Value::Null,
+    );
+    // Without a path of its own, the binary runs under the emulator the cargo runner names
+    let emulator_path = match runner {
+        Some(runner) if emulator_path.value.is_null() => {
+            EffectiveSetting::new(runner.program.as_str(), "runner")
+        }
+        _ => emulator_path,
+    };
+    settings.insert("emulator.path".to_string(), emulator_path);
+    settings.insert(
+        "emulator.args".to_string(),
+        EffectiveSetting::first_set(
+            vec![
+                cli(args.emulator_args.clone().map(Into::into)),
+                configured(&|entry| {
+                    entry
+                        .emulator
+                        .as_ref()?
+                        .args
+                        .as_ref()
+                        .map(|args| args.join(" ").into())
+                }),
+                setting(
+                    settings_emulator
+                        .and_then(|emulator| emulator.args.as_ref())
+                        .map(|args| args.join(" ").into()),
+                    "emulator.args",
+                ),
+            ],
+            serde_json::Value::Null,
+        ),
+    );
+    settings.insert(
+        "emulator.match_by".to_string(),
+        EffectiveSetting::first_set(
+            vec![
+                cli(args
+                    .match_by_given
+                    .then(|| serde_json::to_value(args.match_by).ok())
+                    .flatten()),
+                configured(&|entry| serde_json::to_value(entry.emulator.as_ref()?.match_by?).ok()),
+                setting(
+                    settings_emulator
+                        .and_then(|emulator| emulator.match_by)
+                        .and_then(|match_by| serde_json::to_value(match_by).ok()),
+                    "emulator.match_by",
+                ),
+            ],
+            serde_json::to_value(args.match_by)?,
+        ),
+    );
+    settings.insert(
+        "emulator.fast_forward".to_string(),
+        EffectiveSetting::first_set(
+            vec![
+                configured(&|entry| entry.emulator.as_ref()?.fast_forward.map(Into::into)),
+                setting(
+                    settings_emulator
+                        .and_then(|emulator| emulator.fast_forward)
+                        .map(Into::into),
+                    "emulator.fast_forward",
+                ),
+            ],
+            true,
+        ),
+    );
+    settings.insert(
+        "fast_forward_ms".to_string(),
+        EffectiveSetting::first_set(
+            vec![
+                cli(args.fast_forward.map(Into::into)),
+                configured(&|entry| entry.fast_forward_ms.map(Into::into)),
+            ],
+            serde_json::Value::Null,
+        ),
+    );
+    settings.insert(
+        "startup_wait".to_string(),
+        EffectiveSetting::first_set(
+            vec![
+                cli(args
+                    .startup_wait
+                    .as_ref()
+                    .map(|wait| wait.to_string().into())),
+                configured(&|entry| entry.startup_wait.as_ref().map(wait_value)),
+            ],
+            wait_time::format(DEFAULT_STARTUP_WAIT),
+        ),
+    );
+    settings.insert(
+        "window_timeout".to_string(),
+        EffectiveSetting::first_set(
+            vec![
+                cli(args
+                    .window_timeout
+                    .as_ref()
+                    .map(|wait| wait.to_string().into())),
+                configured(&|entry| entry.window_timeout.as_ref().map(wait_value)),
+            ],
+            wait_time::format(DEFAULT_WINDOW_TIMEOUT),
+        ),
+    );
+    settings.insert(
+        "start_marker".to_string(),
+        EffectiveSetting::first_set(
+            vec![
+                cli(args.start_marker.clone().map(Into::into)),
+                configured(&|entry| entry.start_marker.clone().map(Into::into)),
+            ],
+            serde_json::Value::Null,
+        ),
+    );
+    settings.insert(
+        "stop_marker".to_string(),
+        EffectiveSetting::first_set(
+            vec![
+                cli(args.stop_marker.clone().map(Into::into)),
+                configured(&|entry| entry.stop_marker.clone().map(Into::into)),
+            ],
+            serde_json::Value::Null,
+        ),
+    );
+    settings.insert(
+        "marker_timeout".to_string(),
+        EffectiveSetting::new(
+            f32_value(args.marker_timeout),
+            if args.marker_timeout_given {
+                "cli"
+            } else {
+                "builtin"
+            },
+        ),
+    );
+    settings.insert(
+        "before_capture_limit_ms".to_string(),
+        EffectiveSetting::first_set(
+            vec![setting(
+                config_settings
+                    .and_then(|settings| settings.before_capture_limit_ms)
+                    .map(Into::into),
+                "before_capture_limit_ms",
+            )],
+            DEFAULT_BEFORE_CAPTURE_LIMIT_MS,
+        ),
+    );
+
+    // Files and state the binary starts with
+    for (name, field) in [
+        (
+            "script",
+            (|entry| entry.script.clone()) as fn(&BinaryConfig) -> Option<String>,
+        ),
+        ("savestate", |entry| entry.savestate.clone()),
+        ("save_file", |entry| entry.save_file.clone()),
+    ] {
+        settings.insert(
+            name.to_string(),
+            EffectiveSetting::first_set(
+                vec![configured(&|entry| field(entry).map(Into::into))],
+                serde_json::Value::Null,
+            ),
+        );
+    }
+    settings.insert(
+        "clean_save".to_string(),
+        EffectiveSetting::first_set(
+            vec![configured(&|entry| entry.clean_save.map(Into::into))],
+            false,
+        ),
+    );
+    settings.insert(
+        "skip".to_string(),
+        EffectiveSetting::first_set(vec![configured(&|entry| entry.skip.map(Into::into))], false),
+    );
+
+    let loop_value = |loop_count: Loop| match loop_count {
+        Loop::Infinite => serde_json::Value::from("infinite"),
+        Loop::Plays(plays) => plays.into(),
+    };
+    settings.insert(
+        "loop".to_string(),
+        EffectiveSetting::first_set(
+            vec![
+                configured(&|entry| {
+                    entry
+                        .loop_count
+                        .as_ref()
+                        .and_then(|setting| setting.resolve().ok())
+                        .map(loop_value)
+                }),
+                cli(args.loop_count.map(loop_value)),
+            ],
+            loop_value(Loop::Infinite),
+        ),
+    );
+
+    // Post-processing, listed in the order the steps run
+    settings.insert(
+        "post.region".to_string(),
+        EffectiveSetting::first_set(
+            vec![
+                configured(&|entry| {
+                    let region = entry.post.as_ref()?.region?;
+                    Some(region.to_string().into())
+                }),
+                cli(args.region.map(|region| region.to_string().into())),
+            ],
+            serde_json::Value::Null,
+        ),
+    );
+    settings.insert(
+        "post.crop".to_string(),
+        EffectiveSetting::first_set(
+            vec![
+                configured(&|entry| serde_json::to_value(entry.post.as_ref()?.crop?).ok()),
+                cli(args.crop.and_then(|crop| serde_json::to_value(crop).ok())),
+            ],
+            "none",
+        ),
+    );
+    settings.insert(
+        "post.scale".to_string(),
+        EffectiveSetting::first_set(
+            vec![
+                configured(&|entry| entry.post.as_ref()?.scale.map(Into::into)),
+                cli(args.scale.map(Into::into)),
+            ],
+            1,
+        ),
+    );
+    let filters_value = |filters: &[Filter]| {
+        serde_json::Value::from_iter(filters.iter().map(|filter| filter.name()))
+    };
+    settings.insert(
+        "post.filters".to_string(),
+        EffectiveSetting::first_set(
+            vec![
+                configured(&|entry| Some(filters_value(entry.post.as_ref()?.filters.as_ref()?))),
+                cli((!args.filters.is_empty()).then(|| filters_value(&args.filters))),
+            ],
+            serde_json::Value::Array(Vec::new()),
+        ),
+    );
+    settings.insert(
+        "post.caption".to_string(),
+        EffectiveSetting::first_set(
+            vec![
+                configured(&|entry| entry.post.as_ref()?.caption.clone().map(Into::into)),
+                cli(args.caption.clone().map(Into::into)),
+            ],
+            serde_json::Value::Null,
+        ),
+    );
+    settings.insert(
+        "pre_capture_cmd".to_string(),
+        EffectiveSetting::first_set(
+            vec![configured(&|entry| {
+                entry
+                    .pre_capture_cmd
+                    .as_ref()
+                    .and_then(|hook| serde_json::to_value(hook).ok())
+            })],
+            serde_json::Value::Null,
+        ),
+    );
+    settings.insert(
+        "post_capture_cmd".to_string(),
+        EffectiveSetting::first_set(
+            vec![configured(&|entry| {
+                entry
+                    .post_capture_cmd
+                    .as_ref()
+                    .and_then(|hook| serde_json::to_value(hook).ok())
+            })],
+            serde_json::Value::Null,
+        ),
+    );
+    settings.insert(
+        "frame_filter_cmd".to_string(),
+        EffectiveSetting::first_set(
+            vec![
+                cli(args.frame_filter.clone().map(Into::into)),
+                configured(&|entry| {
+                    entry
+                        .frame_filter_cmd
+                        .as_ref()
+                        .and_then(|hook| serde_json::to_value(hook).ok())
+                }),
+            ],
+            serde_json::Value::Null,
+        ),
+    );
+    settings.insert(
+        "run_on_skip".to_string(),
+        EffectiveSetting::first_set(
+            vec![configured(&|entry| entry.run_on_skip.map(Into::into))],
+            false,
+        ),
+    );
+    settings.insert(
+        "hook_timeout".to_string(),
+        EffectiveSetting::first_set(
+            vec![configured(&|entry| {
+                entry.hook_timeout.as_ref().map(wait_value)
+            })],
+            wait_time::format(DEFAULT_HOOK_TIMEOUT),
+        ),
+    );
+
+    // How the binary is built and run
+    let required_features = cargo_manifest::required_features(project_dir)?;
+    let features = get_effective_features(binary, config, &required_features);
+    let features_source = match configured(&|entry| entry.features.clone().map(Into::into)) {
+        Some((value, from)) if value.as_array().map(Vec::len) == Some(features.features.len()) => {
+            from.to_string()
+        }
+        Some((_, from)) => format!("{}+cargo", from),
+        None if required_features.contains_key(binary) => "cargo".to_string(),
+        None => "builtin".to_string(),
+    };
+    settings.insert(
+        "features".to_string(),
+        EffectiveSetting::new(features.features, features_source),
+    );
+    settings.insert(
+        "all_features".to_string(),
+        EffectiveSetting::first_set(
+            vec![configured(&|entry| entry.all_features.map(Into::into))],
+            false,
+        ),
+    );
+    settings.insert(
+        "no_default_features".to_string(),
+        EffectiveSetting::first_set(
+            vec![configured(&|entry| {
+                entry.no_default_features.map(Into::into)
+            })],
+            false,
+        ),
+    );
+    // Values can hold secrets, so they are only shown with --verbose
+    for (name, (value, from)) in get_effective_env(binary, config)? {
+        let value = if args.verbose {
+            value
+        } else {
+            "<redacted>".to_string()
+        };
+        settings.insert(
+            format!("env.{}", name),
+            EffectiveSetting::new(value, source(from)),
+        );
+    }
+
+    // Each capture, with its input as it will be sent
+    let macros = config_settings
+        .and_then(|settings| settings.macros.clone())
+        .unwrap_or_default();
+    let own = binary_config(binary, config);
+    let default = config_settings.and_then(|settings| settings.default.as_ref());
+    let output_settings = config_settings.and_then(|settings| settings.output.as_ref());
+    let output_root = OutputRoot::resolve(
+        project_dir,
+        args.output_dir.as_deref(),
+        output_settings.and_then(|output| output.dir.as_deref()),
+        args.allow_external_output,
+    )?;
+    settings.insert(
+        "output_dir".to_string(),
+        EffectiveSetting::new(
+            output_root.dir.display().to_string(),
+            match output_root.source {
+                "settings" => settings_source("output.dir"),
+                source => source,
+            },
+        ),
+    );
+    let format_source = configured(&|entry| entry.output.as_ref()?.format.map(|_| true.into()))
+        .map_or(
+            if args.format_given {
+                "cli"
+            } else if output_settings.is_some_and(|output| output.format.is_some()) {
+                settings_source("output.format")
+            } else {
+                "builtin"
+            },
+            |(_, from)| from,
+        );
+    let name_source = configured(&|entry| {
+        let output = entry.output.as_ref()?;
+        (output.filename.is_some() || output.dir.is_some()).then(|| true.into())
+    })
+    .map_or(
+        if output_settings.is_some_and(|output| output.name_template.is_some()) {
+            settings_source("output.name_template")
+        } else {
+            "builtin"
+        },
+        |(_, from)| from,
+    );
+    let mut captures = Vec::new();
+    for job in get_capture_launches(binary, config, args)?
+        .into_iter()
+        .flatten()
+    {
+        let phases = parse_job_sequences(
+            &job,
+            &key_mappings,
+            &macros,
+            ParseOptions::new(args),
+            project_dir,
+        )
+        .map_err(|errors| anyhow::anyhow!("{}", errors.join("\n")))?;
+        let in_entry = job.segment.is_some() || job.variant.is_some();
+        let mut capture = IndexMap::new();
+        capture.insert(
+            "output".to_string(),
+            EffectiveSetting::new(
+                output_root.file(&format!("{}.{}", job.output_name, job.format.extension())),
+                if in_entry { "binary" } else { name_source },
+            ),
+        );
+        capture.insert(
+            "format".to_string(),
+            EffectiveSetting::new(job.format.name(), format_source),
+        );
+        capture.insert(
+            "fps".to_string(),
+            EffectiveSetting::new(f32_value(job.fps), source(job.fps_source)),
+        );
+        let duration = if job.auto_duration {
+            let frame = Duration::from_millis(frame_timing(job.fps, job.duration).frame_delay_ms);
+            let chord_hold = get_effective_input_pacing(binary, config).chord_hold;
+            during_input_length(&phases, frame, chord_hold).map_or(args.duration, |input| {
+                (input + Duration::from_millis(args.sequence_tail)).as_secs_f32()
+            })
+        } else {
+            job.duration
+        };
+        capture.insert(
+            "duration".to_string(),
+            EffectiveSetting::new(f32_value(duration), source(job.duration_source)),
+        );
+
+        // A phase comes from the command line, the capture's segment or variant, the
+        // binary's entry, `settings.default`, or both of the last two joined by its mode
+        let phase_source =
+            |cli: &[CliSequence], own_set: bool, default_set: bool, mode: Option<ComposeMode>| {
+                let default_source = source("settings.default");
+                if cli_sequence(cli, binary).is_some() {
+                    "cli".to_string()
+                } else if in_entry || (own_set && (!default_set || mode.is_none())) {
+                    "binary".to_string()
+                } else if !own_set {
+                    default_source.to_string()
+                } else {
+                    match mode {
+                        Some(ComposeMode::Prepend) => format!("{}+binary", default_source),
+                        Some(ComposeMode::Append) => format!("binary+{}", default_source),
+                        _ => "binary".to_string(),
+                    }
+                }
+            };
+        let phase = |set: bool, value: serde_json::Value, source: String| {
+            if set {
+                EffectiveSetting::new(value, source)
+            } else {
+                EffectiveSetting::new(serde_json::Value::Null, "builtin")
+            }
+        };
+        capture.insert(
+            "before_capture".to_string(),
+            phase(
+                job.before_input.is_some(),
+                phases.before.expanded().into(),
+                phase_source(
+                    &args.before_capture,
+                    own.is_some_and(|own| own.before_capture.is_some()),
+                    default.is_some_and(|default| default.before_capture.is_some()),
+                    own.and_then(|own| own.before_capture_mode),
+                ),
+            ),
+        );
+        let tracks: Vec<String> = phases.during.iter().map(InputSequence::expanded).collect();
+        capture.insert(
+            "during_capture".to_string(),
+            phase(
+                job.during_input.is_some(),
+                match &tracks[..] {
+                    [track] => track.as_str().into(),
+                    tracks => tracks.into(),
+                },
+                phase_source(
+                    &args.during_capture,
+                    own.is_some_and(|own| own.during_capture.is_some()),
+                    default.is_some_and(|default| default.during_capture.is_some()),
+                    own.and_then(|own| own.during_capture_mode),
+                ),
+            ),
+        );
+        capture.insert(
+            "after_capture".to_string(),
+            phase(
+                job.after_input.is_some(),
+                phases.after.expanded().into(),
+                phase_source(
+                    &args.after_capture,
+                    own.is_some_and(|own| own.after_capture.is_some()),
+                    default.is_some_and(|default| default.after_capture.is_some()),
+                    own.and_then(|own| own.after_capture_mode),
+                ),
+            ),
+        );
+        captures.push(EffectiveCapture {
+            label: job.label(binary),
+            settings: capture,
+        });
+    }
+
+    // The `settings.default` values the user-level config supplies, setting by setting
+    let default_from_user = |settings: &mut IndexMap<String, EffectiveSetting>| {
+        for (key, setting) in settings.iter_mut() {
+            if from_user(&format!("default.{}", key)) {
+                setting.source = setting
+                    .source
+                    .split('+')
+                    .map(|part| if part == "default" { "user" } else { part })
+                    .collect::<Vec<_>>()
+                    .join("+");
+            }
+        }
+    };
+    default_from_user(&mut settings);
+    for capture in &mut captures {
+        default_from_user(&mut capture.settings);
+    }
+
+    Ok(EffectiveConfig {
+        binary: binary.to_string(),
+        entry: binary_entry_key(binary, config).map(str::to_string),
+        settings,
+        captures,
+    })
+}
+
+/// Format of a binary's captures and their path inside `out/` without extension, from
+/// its `output` settings
+fn binary_output(
+    binary_name: &str,
+    config: &Option<CaptureConfig>,
+    args: &Args,
+) -> (OutputFormat, String) {
+    let output = |field: fn(&OutputConfig) -> Option<&String>| {
+        binary_setting(binary_name, config, |binary| {
+            binary.output.as_ref().and_then(field).cloned()
+        })
+        .map(|(value, _)| value)
+    };
+    let settings = config
+        .as_ref()
+        .and_then(|config| config.settings.as_ref())
+        .and_then(|settings| settings.output.as_ref());
+    // `settings.output` is beneath the flags, which are beneath the binary's own output
+    let format = binary_setting(binary_name, config, |binary| binary.output.as_ref()?.format)
+        .map(|(format, _)| format)
+        .or_else(|| args.format_given.then_some(args.format))
+        .or_else(|| settings?.format)
+        .unwrap_or(args.format);
+    let filename = output(|output| output.filename.as_ref())
+        .or_else(|| settings?.name_template.clone())
+        .map_or_else(
+            || binary_name.to_string(),
+            |filename| filename.replace("{bin}", binary_name),
+        );
+    let name = match output(|output| output.dir.as_ref()) {
+        Some(dir) => format!("{}/{}", dir.trim_end_matches('/'), filename),
+        None => filename,
+    };
+    (format, name)
+}
+
+/// Gets the input sequences for a specific binary from config or CLI args
+fn get_binary_input_sequences(
+    binary_name: &str,
+    config: &Option<CaptureConfig>,
+    cli_before: &Option<String>,
+    cli_during: &Option<String>,
+    cli_after: &Option<String>,
+) -> Result<(Option<String>, Option<InputTracks>, Option<String>)> {
+    // Each CLI arg takes precedence over its own phase in the config file
+    let (before, during, after) = configured_input_sequences(binary_name, config)?;
+    Ok((
+        cli_before.clone().or(before),
+        cli_during.clone().map(InputTracks::Single).or(during),
+        cli_after.clone().or(after),
+    ))
+}
+
+/// The input sequences of a binary in the config file: from its own entry or
+/// `settings.default` as `settings.merge` says, or both for the phases whose
+/// `<phase>_mode` in its entry is "prepend" or "append"
+fn configured_input_sequences(
+    binary_name: &str,
+    config: &Option<CaptureConfig>,
+) -> Result<(Option<String>, Option<InputTracks>, Option<String>)> {
+    let (before, during, after) = merged_input_sequences(binary_name, config);
+    let Some(own) = binary_config(binary_name, config) else {
+        return Ok((before, during, after));
+    };
+    let default = config
+        .as_ref()
+        .and_then(|config| config.settings.as_ref())
+        .and_then(|settings| settings.default.as_ref());
+    let before = match own.before_capture_mode {
+        Some(mode) if mode != ComposeMode::Replace => compose_sequences(
+            "before_capture",
+            mode,
+            own.before_capture.as_ref(),
+            default.and_then(|default| default.before_capture.as_ref()),
+        )?,
+        _ => before,
+    };
+    // A script replaces the during-capture input whatever the mode
+    let during = match own.during_capture_mode {
+        Some(mode) if mode != ComposeMode::Replace && own.script.is_none() => {
+            match (
+                own.during_capture.as_ref(),
+                default.and_then(|default| default.during_capture.as_ref()),
+            ) {
+                (Some(InputTracks::Single(own)), Some(InputTracks::Single(default))) => {
+                    compose_sequences("during_capture", mode, Some(own), Some(default))?
+                        .map(InputTracks::Single)
+                }
+                (Some(_), Some(_)) => {
+                    return Err(anyhow::anyhow!(
+                        "{}: during_capture_mode can't combine parallel tracks; write them out in the binary's entry",
+                        binary_name
+                    ))
+                }
+                (own, default) => own.or(default).cloned(),
+            }
+        }
+        _ => during,
+    };
+    let after = match own.after_capture_mode {
+        Some(mode) if mode != ComposeMode::Replace => compose_sequences(
+            "after_capture",
+            mode,
+            own.after_capture.as_ref(),
+            default.and_then(|default| default.after_capture.as_ref()),
+        )?,
+        _ => after,
+    };
+    Ok((before, during, after))
+}
+
+/// Joins a binary's own sequence for `phase` with that of `settings.default`, in the
+/// order `mode` gives. Script files can't be joined, as `file:` names a whole sequence.
+fn compose_sequences(
+    phase: &str,
+    mode: ComposeMode,
+    own: Option<&String>,
+    default: Option<&String>,
+) -> Result<Option<String>> {
+    let present = |sequence: Option<&String>| sequence.filter(|s| !s.trim().is_empty()).cloned();
+    let (Some(own), Some(default)) = (present(own), present(default)) else {
+        return Ok(present(own).or(present(default)));
+    };
+    if [&own, &default]
+        .iter()
+        .any(|sequence| sequence.trim_start().starts_with(SCRIPT_PREFIX))
+    {
+        return Err(anyhow::anyhow!(
+            "{}_mode can't combine a {} script file with another sequence; move the shared part into a macro",
+            phase,
+            SCRIPT_PREFIX
+        ));
+    }
+    Ok(Some(match mode {
+        ComposeMode::Replace => own,
+        ComposeMode::Prepend => format!("{},{}", default, own),
+        ComposeMode::Append => format!("{},{}", own, default),
+    }))
+}
+
+/// The input sequences of a binary from its own entry or `settings.default`, as
+/// `settings.merge` says
+fn merged_input_sequences(
+    binary_name: &str,
+    config: &Option<CaptureConfig>,
+) -> (Option<String>, Option<InputTracks>, Option<String>) {
+    // Field by field, with a binary's own script standing in for its during-capture input
+    if merge_mode(config) == MergeMode::Deep {
+        let own = binary_config(binary_name, config);
+        let default = config
+            .as_ref()
+            .and_then(|config| config.settings.as_ref())
+            .and_then(|settings| settings.default.as_ref());
+        let during = match own {
+            Some(own) if own.during_capture.is_some() || own.script.is_some() => {
+                own.during_capture.clone()
+            }
+            _ => default.and_then(|default| default.during_capture.clone()),
+        };
+        return (
+            binary_configs(binary_name, config).find_map(|c| c.before_capture.clone()),
+            during,
+            binary_configs(binary_name, config).find_map(|c| c.after_capture.clone()),
+        );
+    }
+
+    // Check for binary-specific config first
+    if let Some(binary_config) = binary_config(binary_name, config) {
+        return (
+            binary_config.before_capture.clone(),
+            binary_config.during_capture.clone(),
+            binary_config.after_capture.clone(),
+        );
+    }
+
+    // Try to get from config file
+    if let Some(config) = config {
+        // Fall back to default config in settings
+        if let Some(settings) = &config.settings {
+            if let Some(default_config) = &settings.default {
+                return (
+                    default_config.before_capture.clone(),
+                    default_config.during_capture.clone(),
+                    default_config.after_capture.clone(),
+                );
+            }
+        }
+    }
+
+    // No config found
+    (None, None, None)
+}
+
+/// Expands a binary into emulator launches and the GIFs captured from each: one launch
+/// per variant, one launch shared by all segments (filtered by `--segment`), or a single
+/// capture of the whole binary when it has neither
+fn get_capture_launches(
+    binary_name: &str,
+    config: &Option<CaptureConfig>,
+    args: &Args,
+) -> Result<Vec<Vec<CaptureJob>>> {
+    let binary_config = binary_config(binary_name, config);
+    let segments = binary_config
+        .and_then(|binary_config| binary_config.segments.as_ref())
+        .filter(|segments| !segments.is_empty());
+    let variants = binary_config
+        .and_then(|binary_config| binary_config.variants.as_ref())
+        .filter(|variants| !variants.is_empty());
+
+    // The binary's fps and duration stand in for the command-line defaults, but not for
+    // values given on the command line
+    let (fps, fps_source) = match binary_setting(binary_name, config, |binary| binary.fps) {
+        Some(configured) if !args.fps_given => configured,
+        _ if args.fps_given => (args.fps, "--fps"),
+        _ => (args.fps, "default"),
+    };
+    let binary_duration = binary_setting(binary_name, config, |binary| binary.duration)
+        .filter(|_| !args.duration_given);
+    let (format, output_name) = binary_output(binary_name, config, args);
+
+    // An explicit length wins over auto mode, whichever of them comes from the command line
+    let resolve_duration = |length: Option<(CaptureLength, &'static str)>, name: &str| match length
+        .or(binary_duration)
+    {
+        Some((CaptureLength::Seconds(seconds), source)) => {
+            if args.extend_to_sequence {
+                println!(
+                    "Warning: {} has a duration of {}s in capture.json, which wins over --extend-to-sequence",
+                    name, seconds
+                );
+            }
+            (seconds, false, source)
+        }
+        Some((CaptureLength::Auto(_), _)) if args.duration_given => {
+            println!(
+                "Warning: --duration {} wins over duration \"auto\" of {} in capture.json",
+                args.duration, name
+            );
+            (args.duration, false, "--duration")
+        }
+        Some((CaptureLength::Auto(_), source)) => (args.duration, true, source),
+        None if args.duration_given => (args.duration, false, "--duration"),
+        None if args.extend_to_sequence => (args.duration, true, "--extend-to-sequence"),
+        None => (args.duration, false, "default"),
+    };
+
+    // CLI args for this binary take precedence over the same phase of segments and variants
+    let cli_before = cli_sequence(&args.before_capture, binary_name);
+    let cli_during = cli_sequence(&args.during_capture, binary_name);
+    let cli_after = cli_sequence(&args.after_capture, binary_name);
+    let resolve_sequences =
+        |before: &Option<String>, during: &Option<InputTracks>, after: &Option<String>| {
+            (
+                cli_before.clone().or_else(|| before.clone()),
+                cli_during
+                    .clone()
+                    .map(InputTracks::Single)
+                    .or_else(|| during.clone()),
+                cli_after.clone().or_else(|| after.clone()),
+            )
+        };
+
+    match (segments, variants) {
+        (Some(_), Some(_)) => Err(anyhow::anyhow!(
+            "Binary {} defines both segments and variants in capture.json; use one or the other",
+            binary_name
+        )),
+        // A segment filter only selects binaries that define that segment
+        (None, _) if args.segment.is_some() => Ok(Vec::new()),
+        (Some(segments), None) => {
+            let jobs = segments
+                .iter()
+                .filter(|segment| {
+                    args.segment
+                        .as_ref()
+                        .is_none_or(|name| *name == segment.name)
+                })
+                .map(|segment| {
+                    let (before_input, during_input, after_input) = resolve_sequences(
+                        &segment.before_capture,
+                        &segment.during_capture,
+                        &segment.after_capture,
+                    );
+                    let suffix = segment.suffix.as_deref().unwrap_or(&segment.name);
+                    let (duration, auto_duration, duration_source) = resolve_duration(
+                        segment.duration.map(|length| (length, "segment")),
+                        &format!("{} (segment: {})", binary_name, segment.name),
+                    );
+
+                    CaptureJob {
+                        output_name: format!("{}_{}", output_name, suffix),
+                        segment: Some(segment.name.clone()),
+                        variant: None,
+                        before_input,
+                        during_input,
+                        after_input,
+                        format,
+                        fps: segment.fps.unwrap_or(fps),
+                        fps_source: segment.fps.map_or(fps_source, |_| "segment"),
+                        duration,
+                        duration_source,
+                        auto_duration,
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            if jobs.is_empty() {
+                Ok(Vec::new())
+            } else {
+                Ok(vec![jobs])
+            }
+        }
+        // Variants are kept in a BTreeMap, so they expand in alphabetical order
+        (None, Some(variants)) => Ok(variants
+            .iter()
+            .map(|(name, variant)| {
+                let (before_input, during_input, after_input) = resolve_sequences(
+                    &variant.before_capture,
+                    &variant.during_capture,
+                    &variant.after_capture,
+                );
+                let (duration, auto_duration, duration_source) = resolve_duration(
+                    variant.duration.map(|length| (length, "variant")),
+                    &format!("{} (variant: {})", binary_name, name),
+                );
+
+                vec![CaptureJob {
+                    output_name: format!("{}.{}", output_name, name),
+                    segment: None,
+                    variant: Some(name.clone()),
+                    before_input,
+                    during_input,
+                    after_input,
+                    format,
+                    fps: variant.fps.unwrap_or(fps),
+                    fps_source: variant.fps.map_or(fps_source, |_| "variant"),
+                    duration,
+                    duration_source,
+                    auto_duration,
+                }]
+            })
+            .collect()),
+        (None, None) => {
+            let (before_input, during_input, after_input) = get_binary_input_sequences(
+                binary_name,
+                config,
+                &cli_before,
+                &cli_during,
+                &cli_after,
+            )?;
+            let (duration, auto_duration, duration_source) = resolve_duration(None, binary_name);
+            Ok(vec![vec![CaptureJob {
+                output_name,
+                segment: None,
+                variant: None,
+                before_input,
+                during_input,
+                after_input,
+                format,
+                fps,
+                fps_source,
+                duration,
+                duration_source,
+                auto_duration,
+            }]])
+        }
+    }
+}
+
+/// Parsed actions of an input sequence, with the step of the sequence each came from
+#[derive(Debug, Clone, Default)]
+struct InputSequence {
+    actions: Vec<InputAction>,
+    /// Text of the step behind each action, as written; actions from a group or macro
+    /// have the step inside it
+    steps: Vec<String>,
+}
+
+impl InputSequence {
+    fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    fn push(&mut self, action: InputAction, step: &str) {
+        self.actions.push(action);
+        self.steps.push(step.to_string());
+    }
+
+    fn extend(&mut self, other: InputSequence) {
+        self.actions.extend(other.actions);
+        self.steps.extend(other.steps);
+    }
+
+    /// The steps of the sequence written out, with groups and macros expanded
+    fn expanded(&self) -> String {
+        self.actions
+            .iter()
+            .zip(&self.steps)
+            .filter(|(action, _)| !matches!(action, InputAction::AtFrame { .. }))
+            .map(|(_, step)| step.as_str())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+/// Parsed input sequences of a capture, one per phase
+#[derive(Debug, Clone, Default)]
+struct InputPhases {
+    /// Sent before the first frame
+    before: InputSequence,
+    /// Tracks sent at the same time while frames are captured
+    during: Vec<InputSequence>,
+    /// Sent after the last frame, before the emulator is closed
+    after: InputSequence,
+    /// Run while frames are captured, for a binary whose `script` takes the place of
+    /// its during-capture sequence
+    script: Option<Arc<scripting::Script>>,
+}
+
+impl InputPhases {
+    /// Seed of the random input in these sequences, if any
+    fn fuzz_seed(&self) -> Option<u64> {
+        std::iter::once(&self.before)
+            .chain(&self.during)
+            .chain(std::iter::once(&self.after))
+            .flat_map(|sequence| &sequence.actions)
+            .find_map(|action| match action {
+                InputAction::Fuzz { seed, .. } => Some(*seed),
+                _ => None,
+            })
+    }
+}
+
+/// Warns when the during-capture input outlasts the capture by more than a frame, so its
+/// end would be missing from the GIF, or when the before-capture input takes longer than
+/// `before_limit`. Chords without a duration count as held for `chord_hold`.
+fn warn_about_sequence_durations(
+    label: &str,
+    job: &CaptureJob,
+    phases: &InputPhases,
+    before_limit: Duration,
+    chord_hold: Duration,
+) {
+    let capture = Duration::from_secs_f32(job.duration);
+    let frame = Duration::from_secs_f32(1.0 / job.fps);
+    let during = phases
+        .during
+        .iter()
+        .map(|track| sequence_duration(&track.actions, frame, chord_hold))
+        .max()
+        .unwrap_or_default();
+    if during > capture + frame {
+        println!(
+            "Warning: {}: the during-capture input takes at least {:.2}s but the capture lasts {:.2}s, so its end won't be in the GIF",
+            label,
+            during.as_secs_f32(),
+            capture.as_secs_f32()
+        );
+    }
+
+    let before = sequence_duration(&phases.before.actions, frame, chord_hold);
+    if before > before_limit {
+        println!(
+            "Warning: {}: the before-capture input takes at least {:.2}s, more than the {:.2}s expected at most (settings.before_capture_limit_ms)",
+            label,
+            before.as_secs_f32(),
+            before_limit.as_secs_f32()
+        );
+    }
+}
+
+/// Length in seconds of a capture that lasts as long as its during-capture input, plus
+/// `tail`, with frames `frame_interval` apart. Without during-capture input there is
+/// nothing to go by and `None` is returned.
+fn auto_capture_length(
+    label: &str,
+    phases: &InputPhases,
+    tail: Duration,
+    frame_interval: Duration,
+    chord_hold: Duration,
+) -> Option<f32> {
+    let Some(input) = during_input_length(phases, frame_interval, chord_hold) else {
+        println!(
+            "Warning: {} has no during-capture input to take its length from; using --duration",
+            label
+        );
+        return None;
+    };
+    let seconds = (input + tail).as_secs_f32();
+    println!(
+        "{}: capturing {:.2}s, the length of its during-capture input plus {}ms",
+        label,
+        seconds,
+        tail.as_millis()
+    );
+    Some(seconds)
+}
+
+/// The shortest time the longest during-capture track takes, `None` without any
+fn during_input_length(
+    phases: &InputPhases,
+    frame_interval: Duration,
+    chord_hold: Duration,
+) -> Option<Duration> {
+    phases
+        .during
+        .iter()
+        .map(|track| sequence_duration(&track.actions, frame_interval, chord_hold))
+        .max()
+}
+
+/// The shortest time a sequence can take: its holds, waits, mashes and random input, and
+/// the stable window of `wait_for:stable`. Other conditions may be met right away. Steps
+/// anchored with `at:` start no earlier than their frame, `frame_interval` apart, and
+/// chords without a duration are held for `chord_hold`.
+fn sequence_duration(
+    actions: &[InputAction],
+    frame_interval: Duration,
+    chord_hold: Duration,
+) -> Duration {
+    actions.iter().fold(Duration::ZERO, |elapsed, action| {
+        let taken = match action {
+            InputAction::Press { duration, .. } => duration.unwrap_or_default(),
+            InputAction::Chord { duration, .. } => duration.unwrap_or(chord_hold),
+            InputAction::Wait { duration } | InputAction::Mash { duration, .. } => *duration,
+            InputAction::WaitForStable { window, .. } => *window,
+            InputAction::Fuzz { presses, .. } => {
+                presses.iter().map(|(_, hold, gap)| *hold + *gap).sum()
+            }
+            InputAction::KeyDown { .. }
+            | InputAction::KeyUp { .. }
+            | InputAction::Mouse { .. }
+            | InputAction::Text { .. }
+            | InputAction::WaitForColor { .. }
+            | InputAction::Screenshot { .. } => Duration::ZERO,
+            InputAction::AtFrame { frame } => {
+                return elapsed.max(frame_interval * *frame);
+            }
+        };
+        elapsed + taken
+    })
+}
+
+/// Parses a capture's before/during/after input sequences with the given key mappings
+/// and macros. Script files are read relative to `project_dir`. Every sequence is parsed
+/// even after one fails, and the errors are returned together, each naming its phase.
+fn parse_job_sequences(
+    job: &CaptureJob,
+    key_mappings: &GbaKeyMappings,
+    macros: &BTreeMap<String, String>,
+    options: ParseOptions,
+    project_dir: &Path,
+) -> std::result::Result<InputPhases, Vec<String>> {
+    let mut errors = Vec::new();
+    let mut parse = |phase: &str, input: &str| {
+        let parsed = match input.strip_prefix(SCRIPT_PREFIX) {
+            Some(path) => parse_script_file(
+                &project_dir.join(path.trim()),
+                key_mappings,
+                macros,
+                options,
+            ),
+            None => parse_input_sequence(input, key_mappings, macros, options),
+        }
+        .and_then(|sequence| {
+            check_frame_anchors(&sequence, phase.starts_with("during_capture"))?;
+            Ok(sequence)
+        });
+        parsed.unwrap_or_else(|e| {
+            errors.push(match e.downcast_ref::<SequenceError>() {
+                Some(error) => format!("{} {}\n{}", phase, error, error.excerpt("      ")),
+                None => format!("{}: {}", phase, e),
+            });
+            InputSequence::default()
+        })
+    };
+
+    let before = match &job.before_input {
+        Some(input) => parse("before_capture", input),
+        None => InputSequence::default(),
+    };
+    let parallel = matches!(job.during_input, Some(InputTracks::Parallel(_)));
+    let during: Vec<_> = job
+        .during_input
+        .iter()
+        .flat_map(InputTracks::tracks)
+        .enumerate()
+        .map(|(index, track)| match parallel {
+            true => parse(&format!("during_capture track {}", index + 1), track),
+            false => parse("during_capture", track),
+        })
+        .collect();
+    let after = match &job.after_input {
+        Some(input) => parse("after_capture", input),
+        None => InputSequence::default(),
+    };
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(InputPhases {
+        before,
+        during: during
+            .into_iter()
+            .filter(|track| !track.is_empty())
+            .collect(),
+        after,
+        script: None,
+    })
+}
+
+/// Number of frames in a capture and the wall-clock time between them
+#[derive(Debug, Clone, Copy)]
+struct FrameTiming {
+    frame_count: u32,
+    frame_delay_ms: u64,
+}
+
+/// Computes the frame count and per-frame delay for a capture
+fn frame_timing(fps: f32, duration: f32) -> FrameTiming {
+    FrameTiming {
+        frame_count: (fps * duration).ceil() as u32,
+        frame_delay_ms: (1000.0 / fps) as u64,
+    }
+}
+
+/// Gets the cargo features a binary is built and run with: `features`, `all_features`
+/// and `no_default_features` resolved binary > default, plus the `required-features`
+/// Cargo.toml gives it
+fn get_effective_features(
+    binary_name: &str,
+    config: &Option<CaptureConfig>,
+    required_features: &HashMap<String, Vec<String>>,
+) -> Features {
+    let flag = |field: fn(&BinaryConfig) -> Option<bool>| {
+        binary_setting(binary_name, config, field).is_some_and(|(value, _)| value)
+    };
+    let mut features = Features {
+        features: binary_setting(binary_name, config, |binary| binary.features.clone())
+            .map(|(features, _)| features)
+            .unwrap_or_default(),
+        all_features: flag(|binary| binary.all_features),
+        no_default_features: flag(|binary| binary.no_default_features),
+    };
+    if let Some(required) = required_features.get(binary_name) {
+        features.require(required);
+    }
+    features
+}
+
+/// Gets the environment variables cargo builds and runs a binary with, its own entry's
+/// over those of `settings.default`, with `${VAR}` references filled in and where each
+/// comes from
+fn get_effective_env(
+    binary_name: &str,
+    config: &Option<CaptureConfig>,
+) -> Result<BTreeMap<String, (String, &'static str)>> {
+    let default_config = config
+        .as_ref()
+        .and_then(|config| config.settings.as_ref())
+        .and_then(|settings| settings.default.as_ref());
+    let entries = [
+        (default_config, "settings.default"),
+        (binary_config(binary_name, config), "binary"),
+    ];
+    let mut env = BTreeMap::new();
+    for (entry, source) in entries {
+        for (name, value) in entry
+            .and_then(|entry| entry.env.as_ref())
+            .into_iter()
+            .flatten()
+        {
+            let value = env_vars::interpolate(value)
+                .map_err(|e| anyhow::anyhow!("env.{} of {}: {}", name, binary_name, e))?;
+            env.insert(name.clone(), (value, source));
+        }
+    }
+    Ok(env)
+}
+
+/// Gets how long a binary's hook commands may run (binary > default > built-in)
+fn get_effective_hook_timeout(
+    binary_name: &str,
+    config: &Option<CaptureConfig>,
+) -> Result<Duration> {
+    match binary_setting(binary_name, config, |binary| binary.hook_timeout.clone()) {
+        Some((timeout, _)) => timeout.duration(),
+        None => Ok(DEFAULT_HOOK_TIMEOUT),
+    }
+}
+
+/// Gets how many times a binary's captures play (binary > default > CLI > built-in). The
+/// flag is only the default for binaries whose config leaves it out.
+fn get_effective_loop(
+    binary_name: &str,
+    config: &Option<CaptureConfig>,
+    args: &Args,
+) -> Result<Loop> {
+    match binary_setting(binary_name, config, |binary| binary.loop_count.clone()) {
+        Some((loop_count, _)) => loop_count.resolve(),
+        None => Ok(args.loop_count.unwrap_or_default()),
+    }
+}
+
+/// Gets the post-processing steps of a binary, each field resolved binary > default >
+/// CLI, as the flags are only defaults for binaries whose config leaves them out. Fails
+/// when the steps don't work together.
+fn get_effective_post(
+    binary_name: &str,
+    config: &Option<CaptureConfig>,
+    args: &Args,
+) -> Result<PostChain> {
+    let region = binary_setting(binary_name, config, |binary| binary.post.as_ref()?.region);
+    let crop = binary_setting(binary_name, config, |binary| binary.post.as_ref()?.crop);
+    let scale = binary_setting(binary_name, config, |binary| binary.post.as_ref()?.scale);
+    let filters = binary_setting(binary_name, config, |binary| {
+        binary.post.as_ref()?.filters.clone()
+    });
+    let caption = binary_setting(binary_name, config, |binary| {
+        binary.post.as_ref()?.caption.clone()
+    });
+    let chain = PostChain {
+        region: region.map(|(region, _)| region).or(args.region),
+        auto_crop: crop.map(|(crop, _)| crop).or(args.crop) == Some(Crop::Auto),
+        scale: scale.map(|(scale, _)| scale).or(args.scale).unwrap_or(1),
+        filters: filters
+            .map(|(filters, _)| filters)
+            .unwrap_or_else(|| args.filters.clone()),
+        caption: caption
+            .map(|(caption, _)| caption)
+            .or_else(|| args.caption.clone()),
+    };
+    chain
+        .check()
+        .map_err(|e| anyhow::anyhow!("Invalid post-processing: {:#}", e))?;
+    Ok(chain)
+}
+
+/// The environment of a binary's hook commands: its `env`, and `AGB_CAPTURE_BIN` with
+/// the binary's name
+fn hook_env(
+    binary_name: &str,
+    binary_env: Option<&BTreeMap<String, String>>,
+) -> BTreeMap<String, String> {
+    let mut env = binary_env.cloned().unwrap_or_default();
+    env.insert("AGB_CAPTURE_BIN".to_string(), binary_name.to_string());
+    env
+}
+
+/// A binary whose hook `name` failed: the error that stops the run, or with
+/// `--continue-on-error` the failure and summary row to list, after printing the error
+fn hook_failure(
+    name: &str,
+    binary_name: &str,
+    error: anyhow::Error,
+    with_run_log: impl FnOnce(anyhow::Error) -> anyhow::Error,
+    run_log: &Path,
+    continue_on_error: bool,
+) -> Result<(String, (String, String))> {
+    let message = format!("{:#}", error);
+    let error = with_run_log(error);
+    if !continue_on_error {
+        return Err(error);
+    }
+    println!("Error: {:#}", error);
+    Ok((
+        format!("{}: {}", binary_name, message),
+        (
+            binary_name.to_string(),
+            format!("FAILED ({}) (log: {})", name, run_log.display()),
+        ),
+    ))
+}
+
+/// How cargo builds and runs a binary. The run uses the same features and environment as
+/// the build, so `cargo run` doesn't rebuild.
+#[derive(Debug, Clone, Default)]
+struct BinaryBuild {
+    features: Features,
+    env: BTreeMap<String, String>,
+}
+
+/// Gets how long to fast-forward a binary after launch (CLI > binary > default)
+fn get_effective_fast_forward(
+    binary_name: &str,
+    config: &Option<CaptureConfig>,
+    cli_fast_forward: Option<u64>,
+) -> Option<u64> {
+    cli_fast_forward.or_else(|| {
+        binary_configs(binary_name, config).find_map(|binary_config| binary_config.fast_forward_ms)
+    })
+}
+
+/// How long to wait for a binary's mGBA window, with where each value comes from
+#[derive(Debug, Clone, Copy)]
+struct WindowWait {
+    /// Wait after launch before the window is first looked for
+    startup_wait: Duration,
+    startup_wait_source: &'static str,
+    /// Time spent looking for the window, not counting a build, before giving up
+    window_timeout: Duration,
+    window_timeout_source: &'static str,
+}
+
+impl Default for WindowWait {
+    fn default() -> Self {
+        Self {
+            startup_wait: DEFAULT_STARTUP_WAIT,
+            startup_wait_source: "default",
+            window_timeout: DEFAULT_WINDOW_TIMEOUT,
+            window_timeout_source: "default",
+        }
+    }
+}
+
+/// Gets the startup wait and window timeout of a binary, each resolved CLI > binary >
+/// default > built-in
+fn get_effective_window_wait(
+    binary_name: &str,
+    config: &Option<CaptureConfig>,
+    args: &Args,
+) -> Result<WindowWait> {
+    let resolve = |cli: &Option<WaitTime>,
+                   flag: &'static str,
+                   field: fn(&BinaryConfig) -> Option<WaitTime>,
+                   builtin: Duration|
+     -> Result<(Duration, &'static str)> {
+        match cli
+            .clone()
+            .map(|wait| (wait, flag))
+            .or_else(|| binary_setting(binary_name, config, field))
+        {
+            Some((wait, source)) => Ok((wait.duration()?, source)),
+            None => Ok((builtin, "default")),
+        }
+    };
+    let (startup_wait, startup_wait_source) = resolve(
+        &args.startup_wait,
+        "--startup-wait",
+        |config| config.startup_wait.clone(),
+        DEFAULT_STARTUP_WAIT,
+    )?;
+    let (window_timeout, window_timeout_source) = resolve(
+        &args.window_timeout,
+        "--window-timeout",
+        |config| config.window_timeout.clone(),
+        DEFAULT_WINDOW_TIMEOUT,
+    )?;
+    Ok(WindowWait {
+        startup_wait,
+        startup_wait_source,
+        window_timeout,
+        window_timeout_source,
+    })
+}
+
+/// Gets the start and stop log markers for a binary, each resolved CLI > binary > default
+fn get_effective_markers(
+    binary_name: &str,
+    config: &Option<CaptureConfig>,
+    args: &Args,
+) -> (Option<String>, Option<String>) {
+    let resolve = |cli: &Option<String>, field: fn(&BinaryConfig) -> &Option<String>| {
+        cli.clone().or_else(|| {
+            binary_configs(binary_name, config)
+                .find_map(|binary_config| field(binary_config).clone())
+        })
+    };
+
+    (
+        resolve(&args.start_marker, |config| &config.start_marker),
+        resolve(&args.stop_marker, |config| &config.stop_marker),
+    )
+}
+
+/// Gets the window title pattern for a binary (CLI > binary > default > global pattern),
+/// where an entry's `window_title` wins over the one in its `emulator` block
+fn get_effective_window_title(
+    binary_name: &str,
+    config: &Option<CaptureConfig>,
+    cli_pattern: &Option<String>,
+    global_pattern: &str,
+) -> String {
+    cli_pattern
+        .clone()
+        .or_else(|| {
+            binary_configs(binary_name, config).find_map(|binary_config| {
+                binary_config
+                    .window_title
+                    .clone()
+                    .or_else(|| binary_config.emulator.as_ref()?.window_title.clone())
+            })
+        })
+        .unwrap_or_else(|| global_pattern.to_string())
+}
+
+/// Gets the emulator executable and arguments a binary is started with directly, each
+/// resolved CLI > binary > default > settings. `None` means launching through `cargo run`.
+fn get_effective_emulator_command(
+    binary_name: &str,
+    config: &Option<CaptureConfig>,
+    args: &Args,
+) -> Option<(String, Vec<String>)> {
+    let path = args
+        .emulator_path
+        .clone()
+        .or_else(|| emulator_setting(binary_name, config, |command| command.path.clone()))?;
+    let emulator_args = args
+        .emulator_args
+        .as_ref()
+        .map(|cli_args| cli_args.split_whitespace().map(str::to_string).collect())
+        .or_else(|| emulator_setting(binary_name, config, |command| command.args.clone()))
+        .unwrap_or_default();
+    Some((path, emulator_args))
+}
+
+/// A field of the `emulator` blocks that apply to a binary (binary > default > settings)
+fn emulator_setting<T>(
+    binary_name: &str,
+    config: &Option<CaptureConfig>,
+    field: impl Fn(&EmulatorCommand) -> Option<T>,
+) -> Option<T> {
+    let settings_command = config
+        .as_ref()
+        .and_then(|config| config.settings.as_ref())
+        .and_then(|settings| settings.emulator.as_ref());
+    binary_configs(binary_name, config)
+        .filter_map(|binary_config| binary_config.emulator.as_ref())
+        .chain(settings_command)
+        .find_map(field)
+}
+
+/// How a binary's emulator window is found, and what its emulator can do
+#[derive(Debug, Clone)]
+struct EmulatorProfile {
+    /// Frontend whose window is looked for
+    frontend: Emulator,
+    match_by: WindowMatch,
+    title_pattern: Regex,
+    /// Whether `fast_forward_ms` can be applied
+    fast_forward: bool,
+    /// Whether the window is on the virtual display of `--headless`
+    virtual_display: bool,
+}
+
+impl EmulatorProfile {
+    /// The query for the binary's window, which mGBA titles with the ROM's name: the
+    /// binary's name or the file name of `rom_path`
+    fn window_query(&self, binary_name: &str, rom_path: Option<&Path>) -> WindowQuery {
+        let rom_stem = rom_path
+            .and_then(|path| path.file_stem())
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        WindowQuery::new(self.frontend, self.match_by, self.title_pattern.clone())
+            .with_rom_hints(&[binary_name, &rom_stem])
+            .on_virtual_display(self.virtual_display)
+    }
+}
+
+/// Gets the emulator profile of a binary. The frontend is told by the program started:
+/// the binary's emulator, or else `runner_emulator`, the one the cargo runner starts.
+fn get_effective_emulator_profile(
+    binary_name: &str,
+    config: &Option<CaptureConfig>,
+    args: &Args,
+    global_pattern: &str,
+    runner_emulator: Option<Emulator>,
+) -> Result<EmulatorProfile> {
+    let launched = match get_effective_emulator_command(binary_name, config, args) {
+        Some((path, _)) => program_emulator(&path),
+        None => runner_emulator,
+    };
+    let frontend = match (args.emulator, launched) {
+        (Emulator::Auto, Some(emulator)) => emulator,
+        (emulator, _) => emulator,
+    };
+    let match_by = if args.match_by_given {
+        args.match_by
+    } else {
+        emulator_setting(binary_name, config, |emulator| emulator.match_by).unwrap_or(args.match_by)
+    };
+    let title_pattern = get_effective_window_title(
+        binary_name,
+        config,
+        &args.window_title_pattern,
+        global_pattern,
+    );
+    Ok(EmulatorProfile {
+        frontend,
+        match_by,
+        title_pattern: compile_title_pattern(&title_pattern)?,
+        fast_forward: emulator_setting(binary_name, config, |emulator| emulator.fast_forward)
+            .unwrap_or(true),
+        virtual_display: args.on_virtual_display,
+    })
+}
+
+/// Timing of the input sent to mGBA, under `input_pacing` in capture.json
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct InputPacingConfig {
+    /// How long taps without a duration hold their key, in milliseconds (default: 50)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min_press_ms: Option<u64>,
+    /// Shortest time between one key or mouse action and the next, in milliseconds
+    /// (default: 0)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min_gap_ms: Option<u64>,
+    /// Delay enigo leaves after each X11 event, in milliseconds (Linux, default: 12)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    linux_delay_ms: Option<u32>,
+    /// Whether keys are sent unaffected by modifiers held on the physical keyboard
+    /// (macOS, default: true)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    independent_of_keyboard_state: Option<bool>,
+}
+
+/// Input timing and how keys are sent, with the defaults filled in
+#[derive(Debug, Clone, Copy)]
+struct InputPacing {
+    min_press: Duration,
+    min_gap: Duration,
+    linux_delay_ms: u32,
+    independent_of_keyboard_state: bool,
+    key_input_mode: KeyInputMode,
+    /// Time mGBA takes to show the effect of input (`settings.input_latency_ms`)
+    latency: Duration,
+    /// How long a chord without a duration is held (`settings.chord_hold_ms`)
+    chord_hold: Duration,
+    /// Most that hold and wait durations are moved at random (`--input-jitter`)
+    jitter: Duration,
+    /// Seed of that random movement (`--seed`)
+    jitter_seed: u64,
+    /// Refuse to send input unless mGBA has focus (`--require-focus`)
+    require_focus: bool,
+    /// A timed-out `wait_for:` warns instead of failing (`--lenient-waits`)
+    lenient_waits: bool,
+}
+
+impl Default for InputPacing {
+    fn default() -> Self {
+        Self {
+            min_press: Duration::from_millis(DEFAULT_MIN_PRESS_MS),
+            min_gap: Duration::ZERO,
+            linux_delay_ms: DEFAULT_LINUX_DELAY_MS,
+            independent_of_keyboard_state: true,
+            key_input_mode: KeyInputMode::default(),
+            latency: Duration::ZERO,
+            chord_hold: Duration::from_millis(DEFAULT_CHORD_HOLD_MS),
+            jitter: Duration::ZERO,
+            jitter_seed: 0,
+            require_focus: false,
+            lenient_waits: false,
+        }
+    }
+}
+
+/// Gets the effective input timing for a binary, each field on its own
+/// (binary > global > default, with `settings.default` after the binary when
+/// `settings.merge` is "deep"), and its key input mode (binary > default > global)
+fn get_effective_input_pacing(binary_name: &str, config: &Option<CaptureConfig>) -> InputPacing {
+    let entries: Vec<&BinaryConfig> = match merge_mode(config) {
+        MergeMode::Shallow => binary_config(binary_name, config).into_iter().collect(),
+        MergeMode::Deep => binary_configs(binary_name, config).collect(),
+    };
+    let configs: Vec<&InputPacingConfig> = entries
+        .iter()
+        .map(|entry| entry.input_pacing.as_ref())
+        .chain([config
+            .as_ref()
+            .and_then(|config| config.settings.as_ref())
+            .and_then(|settings| settings.input_pacing.as_ref())])
+        .flatten()
+        .collect();
+    let defaults = InputPacing::default();
+    InputPacing {
+        min_press: configs
+            .iter()
+            .find_map(|pacing| pacing.min_press_ms)
+            .map_or(defaults.min_press, Duration::from_millis),
+        min_gap: configs
+            .iter()
+            .find_map(|pacing| pacing.min_gap_ms)
+            .map_or(defaults.min_gap, Duration::from_millis),
+        linux_delay_ms: configs
+            .iter()
+            .find_map(|pacing| pacing.linux_delay_ms)
+            .unwrap_or(defaults.linux_delay_ms),
+        independent_of_keyboard_state: configs
+            .iter()
+            .find_map(|pacing| pacing.independent_of_keyboard_state)
+            .unwrap_or(defaults.independent_of_keyboard_state),
+        key_input_mode: binary_configs(binary_name, config)
+            .find_map(|binary_config| binary_config.key_input_mode)
+            .or_else(|| {
+                config
+                    .as_ref()
+                    .and_then(|config| config.settings.as_ref())
+                    .and_then(|settings| settings.key_input_mode)
+            })
+            .unwrap_or(defaults.key_input_mode),
+        latency: config
+            .as_ref()
+            .and_then(|config| config.settings.as_ref())
+            .and_then(|settings| settings.input_latency_ms)
+            .map_or(defaults.latency, Duration::from_millis),
+        chord_hold: config
+            .as_ref()
+            .and_then(|config| config.settings.as_ref())
+            .and_then(|settings| settings.chord_hold_ms)
+            .map_or(defaults.chord_hold, Duration::from_millis),
+        // Set for the whole run, from --input-jitter, --seed, --require-focus and
+        // --lenient-waits
+        ..defaults
+    }
+}
+
+/// Parsed captures of each binary, one list of jobs per emulator launch
+type CapturePlans = HashMap<String, Vec<Vec<(CaptureJob, InputPhases)>>>;
+
+/// Key mappings from outside capture.json's own values: `--map`, and mGBA's bindings
+#[derive(Debug, Default)]
+struct ExternalKeyMappings {
+    /// Keys given with `--map`, by button
+    cli: BTreeMap<&'static str, String>,
+    /// mGBA's keyboard bindings, loaded when `--mappings-from-mgba` or a `"key_mappings":
+    /// "mgba"` asks for them
+    mgba: Option<KeyMappingsTable>,
+    /// `--mappings-from-mgba`: mGBA's bindings win over capture.json
+    prefer_mgba: bool,
+}
+
+/// Captures planned for each binary, one list of jobs per emulator launch, with their
+/// input sequences parsed. Fails listing every problem found, so a bad setting or
+/// sequence of one binary doesn't hide those of the others.
+fn plan_captures(
+    binaries: &[String],
+    capture_config: &Option<CaptureConfig>,
+    args: &Args,
+    external_mappings: &mut ExternalKeyMappings,
+    project_dir: &Path,
+    output_root: &OutputRoot,
+) -> Result<CapturePlans> {
+    let mut problems = Vec::new();
+    let macros = capture_config
+        .as_ref()
+        .and_then(|config| config.settings.as_ref())
+        .and_then(|settings| settings.macros.clone())
+        .unwrap_or_default();
+    let before_limit = Duration::from_millis(
+        capture_config
+            .as_ref()
+            .and_then(|config| config.settings.as_ref())
+            .and_then(|settings| settings.before_capture_limit_ms)
+            .unwrap_or(DEFAULT_BEFORE_CAPTURE_LIMIT_MS),
+    );
+    if args.mappings_from_mgba || config_uses_mgba_mappings(capture_config) {
+        match load_mgba_key_mappings(args.emulator, true) {
+            Ok(key_mappings) => external_mappings.mgba = Some(key_mappings),
+            Err(e) => problems.push(format!("  key_mappings: {:#}", e)),
+        }
+    }
+    let mut capture_plans = HashMap::new();
+    for binary in binaries {
+        let key_mappings = get_effective_key_mappings(binary, capture_config, external_mappings);
+        debug!(
+            "Key mappings of {}: {}",
+            binary,
+            describe_key_mappings(binary, capture_config, external_mappings)
+        );
+        debug!(
+            "Key input mode of {}: {}",
+            binary,
+            match get_effective_input_pacing(binary, capture_config).key_input_mode {
+                KeyInputMode::Unicode =>
+                    "unicode (keys with a character are sent as the character)",
+                KeyInputMode::Scancode =>
+                    "scancode (keys with a character are sent by their QWERTY position)",
+            }
+        );
+        let shortcuts = sent_shortcuts(binary, capture_config, args);
+        for collision in key_mapping_collisions(&key_mappings, &shortcuts) {
+            if args.strict_mappings {
+                problems.push(format!("  {}: {} (--strict-mappings)", binary, collision));
+            } else {
+                println!("Warning: {}: {}", binary, collision);
+            }
+        }
+        // Sequences given on the command line, or played by hand, leave scripts unused
+        let script = if args.interactive
+            || args.no_input
+            || cli_sequence(&args.during_capture, binary).is_some()
+        {
+            None
+        } else {
+            load_binary_script(binary, capture_config, project_dir).unwrap_or_else(|e| {
+                problems.push(format!("  {} script: {}", binary, e));
+                None
+            })
+        };
+        let chord_hold = get_effective_input_pacing(binary, capture_config).chord_hold;
+        let launches = match get_capture_launches(binary, capture_config, args) {
+            Ok(launches) => launches,
+            Err(e) => {
+                problems.push(format!("  {}: {:#}", binary, e));
+                continue;
+            }
+        };
+        let mut parsed_launches = Vec::new();
+        for jobs in launches {
+            let mut parsed_jobs = Vec::with_capacity(jobs.len());
+            for mut job in jobs {
+                // Played by hand, so configured sequences are neither sent nor checked
+                if args.interactive {
+                    job.before_input = None;
+                    job.during_input = None;
+                    job.after_input = None;
+                }
+                match parse_job_sequences(
+                    &job,
+                    &key_mappings,
+                    &macros,
+                    ParseOptions::new(args),
+                    project_dir,
+                ) {
+                    Ok(mut phases) => {
+                        // Captures with their own during-capture sequence keep it
+                        if job.during_input.is_none() {
+                            phases.script = script.clone();
+                        }
+                        if job.auto_duration {
+                            job.duration = auto_capture_length(
+                                &job.label(binary),
+                                &phases,
+                                Duration::from_millis(args.sequence_tail),
+                                Duration::from_millis(
+                                    frame_timing(job.fps, job.duration).frame_delay_ms,
+                                ),
+                                chord_hold,
+                            )
+                            .unwrap_or(args.duration);
+                        }
+                        warn_about_sequence_durations(
+                            &job.label(binary),
+                            &job,
+                            &phases,
+                            before_limit,
+                            chord_hold,
+                        );
+                        // Parsed all the same, so captures keep the length their input gives them
+                        if args.no_input {
+                            job.before_input = None;
+                            job.during_input = None;
+                            job.after_input = None;
+                            phases = InputPhases::default();
+                        }
+                        parsed_jobs.push((job, phases));
+                    }
+                    Err(errors) => {
+                        let label = job.label(binary);
+                        problems.extend(errors.into_iter().map(|e| format!("  {} {}", label, e)));
+                    }
+                }
+            }
+            parsed_launches.push(parsed_jobs);
+        }
+        capture_plans.insert(binary.clone(), parsed_launches);
+    }
+    // Output names from the config could otherwise make one capture overwrite another
+    let mut output_paths: HashMap<String, String> = HashMap::new();
+    for binary in binaries {
+        for (job, _) in capture_plans.get(binary).into_iter().flatten().flatten() {
+            let path = output_root.file(&format!("{}.{}", job.output_name, job.format.extension()));
+            if let Some(other) = output_paths.insert(path.clone(), job.label(binary)) {
+                problems.push(format!(
+                    "  {} and {} would both be written to {}; give one of them another output.filename",
+                    other,
+                    job.label(binary),
+                    path
+                ));
+            }
+        }
+    }
+    if !problems.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Invalid input sequences or settings, nothing was captured:\n{}",
+            problems.join("\n")
+        ));
+    }
+    Ok(capture_plans)
+}
+
+/// Gets the effective key mappings for a binary
+/// (`--map` > `--mappings-from-mgba` > binary > global > default)
+fn get_effective_key_mappings(
+    binary_name: &str,
+    config: &Option<CaptureConfig>,
+    external: &ExternalKeyMappings,
+) -> GbaKeyMappings {
+    let mut key_mappings = base_key_mappings(binary_name, config, external)
+        .map(|(table, _)| table.mappings())
+        .unwrap_or_default();
+    for (button, key) in &external.cli {
+        if let Some(mapped) = key_mappings.button_mut(button) {
+            *mapped = key.clone();
+        }
+    }
+    key_mappings
+}
+
+/// The table of key mappings a binary's `--map` keys are applied over, if not the
+/// defaults, with where it comes from
+fn base_key_mappings(
+    binary_name: &str,
+    config: &Option<CaptureConfig>,
+    external: &ExternalKeyMappings,
+) -> Option<(KeyMappingsTable, &'static str)> {
+    let from_mgba = || external.mgba.clone().map(|mappings| (mappings, "mGBA"));
+    if external.prefer_mgba {
+        return from_mgba();
+    }
+    match configured_key_mappings(binary_name, config)? {
+        KeyMappingsConfig::Keys(key_mappings) => Some((*key_mappings.clone(), "capture.json")),
+        KeyMappingsConfig::Mgba(_) => from_mgba(),
+    }
+}
+
+/// The key mappings of a binary in capture.json, its own or the global ones. With
+/// `settings.merge` "deep", those of `settings.default` come in between.
+fn configured_key_mappings<'a>(
+    binary_name: &str,
+    config: &'a Option<CaptureConfig>,
+) -> Option<&'a KeyMappingsConfig> {
+    let own = match merge_mode(config) {
+        MergeMode::Shallow => binary_config(binary_name, config)
+            .and_then(|binary_config| binary_config.key_mappings.as_ref()),
+        MergeMode::Deep => binary_configs(binary_name, config)
+            .find_map(|binary_config| binary_config.key_mappings.as_ref()),
+    };
+    own.or_else(|| {
+        config
+            .as_ref()
+            .and_then(|config| config.settings.as_ref())
+            .and_then(|settings| settings.key_mappings.as_ref())
+    })
+}
+
+/// Whether any key mappings in capture.json are to be read from mGBA
+fn config_uses_mgba_mappings(config: &Option<CaptureConfig>) -> bool {
+    let Some(config) = config else {
+        return false;
+    };
+    let settings = config.settings.as_ref();
+    settings
+        .and_then(|settings| settings.key_mappings.as_ref())
+        .into_iter()
+        .chain(
+            settings
+                .and_then(|settings| settings.default.as_ref())
+                .and_then(|default| default.key_mappings.as_ref()),
+        )
+        .chain(
+            config
+                .binaries
+                .iter()
+                .flat_map(|binaries| binaries.values())
+                .filter_map(|binary| binary.key_mappings.as_ref()),
+        )
+        .any(|key_mappings| matches!(key_mappings, KeyMappingsConfig::Mgba(_)))
+}
+
+/// Key mappings from mGBA's keyboard bindings. Buttons mGBA binds to no key, or to a key
+/// this tool can't send, are left out to keep their default key, with a note if
+/// `announce` is set.
+fn load_mgba_key_mappings(emulator: Emulator, announce: bool) -> Result<KeyMappingsTable> {
+    let bindings = mgba_bindings::load(emulator)?;
+    if announce {
+        println!(
+            "Using mGBA's key bindings from {} [{}]",
+            bindings.path.display(),
+            bindings.section
+        );
+    }
+    let defaults = GbaKeyMappings::default();
+    let mut table = KeyMappingsTable::default();
+    for (button, key) in bindings.keys {
+        let (Some(mapped), Some(default)) = (table.button_mut(button), defaults.button(button))
+        else {
+            continue;
+        };
+        let key = key.and_then(|key| {
+            parse_mapped_key(&key)
+                .map(|_| key)
+                .map_err(|e| format!("{:#}", e))
+        });
+        match key {
+            Ok(key) => *mapped = Some(key),
+            Err(reason) if announce => println!(
+                "Note: mGBA's {} binding can't be used ({}), keeping the default {}",
+                button, reason, default
+            ),
+            Err(_) => {}
+        }
+    }
+    Ok(table)
+}
+
+/// The emulator shortcuts the tool sends for a binary, by their setting name. Only these
+/// can be mistaken for a button.
+fn sent_shortcuts(
+    binary_name: &str,
+    config: &Option<CaptureConfig>,
+    args: &Args,
+) -> Vec<(&'static str, String)> {
+    let settings = config.as_ref().and_then(|config| config.settings.as_ref());
+    let emulator_keys = settings
+        .and_then(|settings| settings.emulator_keys.clone())
+        .unwrap_or_default();
+    let mut shortcuts = Vec::new();
+    if !args.no_auto_unpause {
+        shortcuts.push(("settings.emulator_keys.pause", emulator_keys.pause));
+    }
+    let fast_forwards =
+        emulator_setting(binary_name, config, |emulator| emulator.fast_forward).unwrap_or(true);
+    if fast_forwards
+        && get_effective_fast_forward(binary_name, config, args.fast_forward)
+            .is_some_and(|ms| ms > 0)
+    {
+        shortcuts.push((
+            "settings.emulator_keys.fast_forward",
+            emulator_keys.fast_forward,
+        ));
+    }
+    if binary_configs(binary_name, config).any(|config| config.savestate.is_some()) {
+        shortcuts.push((
+            "settings.emulator_keys.load_state",
+            emulator_keys.load_state,
+        ));
+    }
+    if args.reuse_emulator {
+        let reuse_settings = settings
+            .and_then(|settings| settings.reuse_emulator.clone())
+            .unwrap_or_default();
+        shortcuts.push((
+            "settings.reuse_emulator.load_rom_shortcut",
+            reuse_settings.load_rom_shortcut,
+        ));
+    }
+    shortcuts
+}
+
+/// Finds GBA buttons mapped to the same key as another button, or as one of the emulator
+/// `shortcuts` the tool sends, given by their setting name. Keys are compared once
+/// parsed, so `Enter` and `return` collide; keys that don't parse are left to the
+/// sequences that use them.
+fn key_mapping_collisions(
+    key_mappings: &GbaKeyMappings,
+    shortcuts: &[(&str, String)],
+) -> Vec<String> {
+    let buttons: Vec<(&str, &String, KeyCombo)> = key_mappings
+        .buttons()
+        .into_iter()
+        .filter_map(|(button, key)| Some((button, key, parse_raw_combo(key).ok()?)))
+        .collect();
+    let mut collisions = Vec::new();
+    for (index, (button, key, combo)) in buttons.iter().enumerate() {
+        for (other, _, other_combo) in &buttons[index + 1..] {
+            if combo == other_combo {
+                collisions.push(format!(
+                    "the {} and {} buttons are both mapped to {}, so one can't be told from the other",
+                    button, other, key
+                ));
+            }
+        }
+        for (setting, shortcut) in shortcuts {
+            if parse_raw_combo(shortcut).is_ok_and(|shortcut| shortcut == *combo) {
+                collisions.push(format!(
+                    "the {} button is mapped to {}, which is also the emulator shortcut {}",
+                    button, key, setting
+                ));
+            }
+        }
+    }
+    collisions
+}
+
+/// Lists a binary's effective key mappings with where each comes from: `--map`, mGBA,
+/// capture.json, or the default (also for buttons left out or mapped to their default
+/// key)
+fn describe_key_mappings(
+    binary_name: &str,
+    config: &Option<CaptureConfig>,
+    external: &ExternalKeyMappings,
+) -> String {
+    let base_source = base_key_mappings(binary_name, config, external).map(|(_, source)| source);
+    let defaults = GbaKeyMappings::default();
+    get_effective_key_mappings(binary_name, config, external)
+        .buttons()
+        .iter()
+        .zip(defaults.buttons())
+        .map(|((button, key), (_, default))| {
+            let source = match base_source {
+                _ if external.cli.contains_key(button) => "--map",
+                Some(source) if !key.eq_ignore_ascii_case(default) => source,
+                _ => "default",
+            };
+            format!("{}={} ({})", button, key, source)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// How binary entries combine with `settings.default`
+fn merge_mode(config: &Option<CaptureConfig>) -> MergeMode {
+    config
+        .as_ref()
+        .and_then(|config| config.settings.as_ref())
+        .and_then(|settings| settings.merge)
+        .unwrap_or_default()
+}
+
+/// The binary's own entry in `capture.json`: the one named after it, or else the best
+/// of the patterns that match its name
+fn binary_config<'a>(
+    binary_name: &str,
+    config: &'a Option<CaptureConfig>,
+) -> Option<&'a BinaryConfig> {
+    let binaries = config.as_ref()?.binaries.as_ref()?;
+    binaries.get(binary_entry_key(binary_name, config)?)
+}
+
+/// The key of the binary's own entry in `capture.json`
+fn binary_entry_key<'a>(binary_name: &str, config: &'a Option<CaptureConfig>) -> Option<&'a str> {
+    let binaries = config.as_ref()?.binaries.as_ref()?;
+    if let Some((key, _)) = binaries.get_key_value(binary_name) {
+        return Some(key);
+    }
+    let keys = binary_keys::matching_keys(binaries.keys().map(String::as_str), binary_name);
+    keys.first().copied()
+}
+
+/// Warns about binaries that several patterns of the `binaries` table match equally
+/// well, so only the order of the entries in the file decides which one applies
+fn warn_about_ambiguous_patterns(binaries: &[String], config: &Option<CaptureConfig>) {
+    let Some(entries) = config.as_ref().and_then(|config| config.binaries.as_ref()) else {
+        return;
+    };
+    for binary in binaries {
+        let keys = binary_keys::matching_keys(entries.keys().map(String::as_str), binary);
+        if let [first, second, ..] = keys[..] {
+            if binary_keys::ambiguous(first, second) {
+                println!(
+                    "Warning: {} matches both '{}' and '{}' in the binaries table; using '{}', which comes first",
+                    binary, first, second, first
+                );
+            }
+        }
+    }
+}
+
+/// The binary's own entry in `capture.json` followed by `settings.default`, for settings
+/// resolved binary > default
+fn binary_configs<'a>(
+    binary_name: &str,
+    config: &'a Option<CaptureConfig>,
+) -> impl Iterator<Item = &'a BinaryConfig> {
+    let default_config = config
+        .as_ref()
+        .and_then(|config| config.settings.as_ref())
+        .and_then(|settings| settings.default.as_ref());
+    binary_config(binary_name, config)
+        .into_iter()
+        .chain(default_config)
+}
+
+/// A setting of the binary's own entry, or else of `settings.default`, with which of
+/// them it came from
+fn binary_setting<T>(
+    binary_name: &str,
+    config: &Option<CaptureConfig>,
+    field: impl Fn(&BinaryConfig) -> Option<T>,
+) -> Option<(T, &'static str)> {
+    let default_config = config
+        .as_ref()
+        .and_then(|config| config.settings.as_ref())
+        .and_then(|settings| settings.default.as_ref());
+    binary_config(binary_name, config)
+        .and_then(&field)
+        .map(|value| (value, "binary"))
+        .or_else(|| {
+            default_config
+                .and_then(&field)
+                .map(|value| (value, "settings.default"))
+        })
+}
+
+/// The compiled `script` of a binary, resolved binary > default, with its path relative
+/// to the project. A binary's own entry can't set both a script and a during-capture
+/// sequence.
+fn load_binary_script(
+    binary_name: &str,
+    config: &Option<CaptureConfig>,
+    project_dir: &Path,
+) -> Result<Option<Arc<scripting::Script>>> {
+    if binary_config(binary_name, config)
+        .is_some_and(|config| config.script.is_some() && config.during_capture.is_some())
+    {
+        return Err(anyhow::anyhow!(
+            "both script and during_capture are set in capture.json; use one or the other"
+        ));
+    }
+    binary_configs(binary_name, config)
+        .find_map(|config| config.script.as_deref())
+        .map(|path| scripting::Script::load(&project_dir.join(path)).map(Arc::new))
+        .transpose()
+}
+
+/// Parses a string like "A:500,wait:1000,B" into a sequence of input actions.
+/// Parenthesized groups with a repeat count, like "(right:100,wait:100)x20", and macro
+/// references, like "@skip_intro", are expanded here, so execution only ever sees plain
+/// actions. Groups can be nested, and a count of 0 expands to nothing.
+fn parse_input_sequence(
+    input: &str,
+    key_mappings: &GbaKeyMappings,
+    macros: &BTreeMap<String, String>,
+    options: ParseOptions,
+) -> Result<InputSequence> {
+    expand_input_sequence(input, key_mappings, macros, options, &mut Vec::new())
+}
+
+/// Command-line settings that change how input sequences parse
+#[derive(Debug, Clone, Copy, Default)]
+struct ParseOptions {
+    /// Seed of the `fuzz:` input generator (`--seed`, or random)
+    fuzz_seed: u64,
+    /// Steps may last longer than `MAX_STEP_DURATION_MS` (`--allow-long-waits`)
+    allow_long_waits: bool,
+}
+
+impl ParseOptions {
+    fn new(args: &Args) -> Self {
+        Self {
+            fuzz_seed: args.seed.unwrap_or_default(),
+            allow_long_waits: args.allow_long_waits,
+        }
+    }
+}
+
+/// Parses an input script: one or more actions per line, joined into one sequence.
+/// Blank lines are skipped and `#` starts a comment at the start of a line or after a
+/// space, so colors like `#ff0000` in `wait_for:pixel` still work. Errors name the file
+/// and line.
+fn parse_script_file(
+    path: &Path,
+    key_mappings: &GbaKeyMappings,
+    macros: &BTreeMap<String, String>,
+    options: ParseOptions,
+) -> Result<InputSequence> {
+    let script = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Could not read input script {}: {}", path.display(), e))?;
+
+    let mut sequence = InputSequence::default();
+    for (index, line) in script.lines().enumerate() {
+        let line = strip_script_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        let line_actions = parse_input_sequence(line, key_mappings, macros, options)
+            .map_err(|e| anyhow::anyhow!("{}:{}: {}", path.display(), index + 1, e))?;
+        sequence.extend(line_actions);
+    }
+    Ok(sequence)
+}
+
+fn strip_script_comment(line: &str) -> &str {
+    let mut previous = ' ';
+    for (index, c) in line.char_indices() {
+        if c == '#' && previous.is_whitespace() {
+            return &line[..index];
+        }
+        previous = c;
+    }
+    line
+}
+
+/// Parses a sequence inside the macros named in `expanding`, which catches macros that
+/// reference themselves
+fn expand_input_sequence(
+    input: &str,
+    key_mappings: &GbaKeyMappings,
+    macros: &BTreeMap<String, String>,
+    options: ParseOptions,
+    expanding: &mut Vec<String>,
+) -> Result<InputSequence> {
+    let mut sequence = InputSequence::default();
+
+    for part in split_sequence(input)? {
+        let step_error = |e| SequenceError::at(input, part, e);
+        if part.starts_with('(') {
+            let (group, count) = parse_repeat_group(part).map_err(step_error)?;
+            // Errors inside the group are positioned within the whole sequence
+            let group_actions =
+                expand_input_sequence(group, key_mappings, macros, options, expanding)
+                    .map_err(|e| SequenceError::within(e, input, group))?;
+            let expanded = group_actions
+                .actions
+                .len()
+                .checked_mul(count)
+                .and_then(|repeated| repeated.checked_add(sequence.actions.len()));
+            if expanded.is_none_or(|expanded| expanded > MAX_SEQUENCE_ACTIONS) {
+                return Err(step_error(too_many_actions()));
+            }
+            for _ in 0..count {
+                sequence.extend(group_actions.clone());
+            }
+        } else if let Some(name) = part.strip_prefix('@') {
+            let macro_actions = expand_macro(name.trim(), key_mappings, macros, options, expanding)
+                .map_err(step_error)?;
+            if sequence.actions.len() + macro_actions.actions.len() > MAX_SEQUENCE_ACTIONS {
+                return Err(step_error(too_many_actions()));
+            }
+            sequence.extend(macro_actions);
+        } else if let Some((frame, step)) = split_frame_anchor(part) {
+            let frame = frame.trim().parse().map_err(|_| {
+                step_error(anyhow::anyhow!(
+                    "Invalid frame in {}: {} (expected at:<frame>:<step>, with the frame numbered from 0)",
+                    part,
+                    frame
+                ))
+            })?;
+            let step = step.trim();
+            if step.starts_with('(') || step.starts_with('@') {
+                return Err(step_error(anyhow::anyhow!(
+                    "at: anchors a single step, not a group or macro: {}",
+                    part
+                )));
+            }
+            sequence.push(InputAction::AtFrame { frame }, part);
+            sequence.push(
+                parse_sequence_step(step, key_mappings, options).map_err(step_error)?,
+                part,
+            );
+        } else {
+            sequence.push(
+                parse_sequence_step(part, key_mappings, options).map_err(step_error)?,
+                part,
+            );
+        }
+    }
+
+    Ok(sequence)
+}
+
+/// The error of a sequence that expands past `MAX_SEQUENCE_ACTIONS`
+fn too_many_actions() -> anyhow::Error {
+    anyhow::anyhow!(
+        "The sequence expands to more than {} actions; lower the repeat count, or use mash: for fast repeated taps",
+        MAX_SEQUENCE_ACTIONS
+    )
+}
+
+/// Parses the body of the macro `name`
+fn expand_macro(
+    name: &str,
+    key_mappings: &GbaKeyMappings,
+    macros: &BTreeMap<String, String>,
+    options: ParseOptions,
+    expanding: &mut Vec<String>,
+) -> Result<InputSequence> {
+    let body = macros.get(name).ok_or_else(|| {
+        anyhow::anyhow!(
+            "Undefined macro @{} (define it in settings.macros in capture.json)",
+            name
+        )
+    })?;
+    if expanding.iter().any(|expanded| expanded == name) {
+        return Err(anyhow::anyhow!(
+            "Macro @{} references itself: @{} -> @{}",
+            name,
+            expanding.join(" -> @"),
+            name
+        ));
+    }
+    expanding.push(name.to_string());
+    let actions = expand_input_sequence(body, key_mappings, macros, options, expanding)
+        .map_err(|e| anyhow::anyhow!("In macro @{}: {}", name, e))?;
+    expanding.pop();
+    Ok(actions)
+}
+
+/// A step of an input sequence that failed to parse, and where it is in the sequence
+#[derive(Debug)]
+struct SequenceError {
+    sequence: String,
+    /// Byte offset of `step` in `sequence`
+    offset: usize,
+    step: String,
+    message: String,
+}
+
+impl SequenceError {
+    /// The error `e` of `step`, a slice of `sequence`
+    fn at(sequence: &str, step: &str, e: anyhow::Error) -> anyhow::Error {
+        anyhow::Error::new(SequenceError {
+            sequence: sequence.to_string(),
+            offset: offset_in(sequence, step),
+            step: step.to_string(),
+            message: e.to_string(),
+        })
+    }
+
+    /// Moves an error in `inner`, a slice of `outer`, to its place in `outer`
+    fn within(e: anyhow::Error, outer: &str, inner: &str) -> anyhow::Error {
+        match e.downcast::<SequenceError>() {
+            Ok(error) => anyhow::Error::new(SequenceError {
+                sequence: outer.to_string(),
+                offset: offset_in(outer, inner) + error.offset,
+                ..error
+            }),
+            Err(e) => e,
+        }
+    }
+
+    /// Position of the step for people: the number of its first character
+    fn position(&self) -> usize {
+        self.sequence[..self.offset].chars().count() + 1
+    }
+
+    /// The step in the sequence, underlined on a second line. Long sequences are cut to
+    /// the text around the step.
+    fn excerpt(&self, indent: &str) -> String {
+        const CONTEXT: usize = 30;
+
+        let chars: Vec<char> = self.sequence.chars().collect();
+        let start = self.position() - 1;
+        let width = self.step.chars().count().max(1);
+        let from = start.saturating_sub(CONTEXT);
+        let to = (start + width + CONTEXT).min(chars.len());
+
+        let mut line: String = chars[from..to].iter().collect();
+        let mut caret_column = start - from;
+        if from > 0 {
+            line.insert_str(0, "...");
+            caret_column += 3;
+        }
+        if to < chars.len() {
+            line.push_str("...");
+        }
+        format!(
+            "{}{}\n{}{}{}",
+            indent,
+            line,
+            indent,
+            " ".repeat(caret_column),
+            "^".repeat(width)
+        )
+    }
+}
+
+impl std::fmt::Display for SequenceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "at position {} ('{}'): {}",
+            self.position(),
+            self.step,
+            self.message
+        )
+    }
+}
+
+impl std::error::Error for SequenceError {}
+
+/// Byte offset of `part`, a slice of `text`, within it
+fn offset_in(text: &str, part: &str) -> usize {
+    part.as_ptr() as usize - text.as_ptr() as usize
+}
+
+/// Splits a sequence at the commas outside parentheses, dropping empty parts
+fn split_sequence(input: &str) -> Result<Vec<&str>> {
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+
+    for (index, c) in input.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth = depth.checked_sub(1).ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Unbalanced parentheses in input sequence: ')' at position {} has no matching '('",
+                        index + 1
+                    )
+                })?;
+            }
+            ',' if depth == 0 => {
+                // The coordinates of color conditions and mouse actions are
+                // comma-separated too
+                let part = input[start..index].trim_start();
+                let fields = comma_separated_fields(part);
+                if fields.is_some_and(|fields| part.matches(',').count() + 1 < fields) {
+                    continue;
+                }
+                parts.push(&input[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    if depth > 0 {
+        return Err(anyhow::anyhow!(
+            "Unbalanced parentheses in input sequence: {} '(' not closed",
+            depth
+        ));
+    }
+    parts.push(&input[start..]);
+
+    Ok(parts
+        .into_iter()
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .collect())
+}
+
+/// Splits a repeat group like "(A,wait:100)x3" into its contents and repeat count
+fn parse_repeat_group(part: &str) -> Result<(&str, usize)> {
+    // Parentheses are balanced here, so the group ends at the last ')'
+    let close = part
+        .rfind(')')
+        .ok_or_else(|| anyhow::anyhow!("Unclosed group: {}", part))?;
+    let group = &part[1..close];
+    let suffix = part[close + 1..].trim();
+    let count = suffix
+        .strip_prefix(['x', 'X'])
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "Group '{}' needs a repeat count after it, e.g. ({})x3",
+                part,
+                group
+            )
+        })?
+        .trim()
+        .parse::<usize>()
+        .map_err(|_| anyhow::anyhow!("Invalid repeat count in '{}'", part))?;
+    Ok((group, count))
+}
+
+/// Parses a duration in milliseconds (`500`) or GBA frames (`30f`). Frames are kept
+/// at full precision rather than rounded to whole milliseconds, so long frame counts
+/// don't drift.
+fn parse_duration(text: &str) -> Option<Duration> {
+    let text = text.trim();
+    match text.strip_suffix('f') {
+        Some(frames) => frames
+            .parse::<u64>()
+            .ok()
+            .map(|frames| Duration::from_secs_f64(frames as f64 / f64::from(GBA_FPS))),
+        None => text.parse::<u64>().ok().map(Duration::from_millis),
+    }
+}
+
+/// The frame and the step of an `at:<frame>:<step>` step
+fn split_frame_anchor(part: &str) -> Option<(&str, &str)> {
+    part.strip_prefix(FRAME_ANCHOR_PREFIX)?.split_once(':')
+}
+
+/// Checks the `at:` anchors of a sequence: they are only allowed in during-capture
+/// sequences, and a sequence anchors either every step or none, since a step after an
+/// anchored one would otherwise start at a time that depends on how long it took.
+fn check_frame_anchors(sequence: &InputSequence, during_capture: bool) -> Result<()> {
+    let Some(anchored) = sequence
+        .actions
+        .iter()
+        .position(|action| matches!(action, InputAction::AtFrame { .. }))
+    else {
+        return Ok(());
+    };
+    if !during_capture {
+        return Err(anyhow::anyhow!(
+            "at:<frame> can only be used in during-capture sequences: {}",
+            sequence.steps[anchored]
+        ));
+    }
+    let mut after_anchor = false;
+    for (action, step) in sequence.actions.iter().zip(&sequence.steps) {
+        let is_anchor = matches!(action, InputAction::AtFrame { .. });
+        if !is_anchor && !after_anchor {
+            return Err(anyhow::anyhow!(
+                "Step '{}' has no at:<frame> anchor, but other steps of the sequence do; anchor every step or none",
+                step
+            ));
+        }
+        after_anchor = is_anchor;
+    }
+    Ok(())
+}
+
+/// Number of comma-separated values in a `wait_for:pixel` or `wait_for:region` step, or
+/// in a mouse action, either of them possibly anchored with `at:`
+fn comma_separated_fields(part: &str) -> Option<usize> {
+    let part = split_frame_anchor(part).map_or(part, |(_, step)| step.trim_start());
+    if part.starts_with("wait_for:pixel:") {
+        Some(3)
+    } else if part.starts_with("wait_for:region:") {
+        Some(5)
+    } else if ["click:", "rightclick:", "move:"]
+        .iter()
+        .any(|action| part.starts_with(action))
+    {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+/// Parses the condition of a `wait_for:` action: `stable[:<ms>[:<timeout_ms>]]`,
+/// `pixel:<x>,<y>,<#rrggbb>[:<tolerance>][:<timeout_ms>]` or
+/// `region:<x>,<y>,<width>,<height>,<#rrggbb>[:<tolerance>][:<timeout_ms>]`
+fn parse_wait_for(condition: &str, options: ParseOptions) -> Result<InputAction> {
+    let mut fields = condition.split(':');
+    match fields.next().unwrap_or("").trim() {
+        kind @ ("pixel" | "region") => {
+            let format = if kind == "pixel" {
+                "wait_for:pixel:x,y,#rrggbb[:tolerance][:timeout_ms]"
+            } else {
+                "wait_for:region:x,y,width,height,#rrggbb[:tolerance][:timeout_ms]"
+            };
+            let format_error = || {
+                anyhow::anyhow!(
+                    "Invalid wait_for format: wait_for:{} (expected {})",
+                    condition,
+                    format
+                )
+            };
+
+            let values: Vec<&str> = fields.next().unwrap_or("").split(',').collect();
+            let (coordinates, color) = match values.split_last() {
+                Some((color, coordinates)) if coordinates.len() == 2 || coordinates.len() == 4 => {
+                    (coordinates, color.trim())
+                }
+                _ => return Err(format_error()),
+            };
+            if (kind == "pixel") != (coordinates.len() == 2) {
+                return Err(format_error());
+            }
+            let coordinates = coordinates
+                .iter()
+                .map(|value| {
+                    value
+                        .trim()
+                        .parse::<u32>()
+                        .map_err(|_| anyhow::anyhow!("Invalid {} coordinate: {}", kind, value))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let area = match coordinates[..] {
+                [x, y] => FrameArea {
+                    x,
+                    y,
+                    width: 1,
+                    height: 1,
+                },
+                [x, y, width, height] if width > 0 && height > 0 => FrameArea {
+                    x,
+                    y,
+                    width,
+                    height,
+                },
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "Invalid region size in wait_for:{}: width and height must be at least 1",
+                        condition
+                    ))
+                }
+            };
+            let color = parse_hex_color(color)?;
+
+            let tolerance = match fields.next() {
+                Some(text) => text.trim().parse::<u8>().map_err(|_| {
+                    anyhow::anyhow!("Invalid color tolerance: {} (expected 0 to 255)", text)
+                })?,
+                None => DEFAULT_COLOR_TOLERANCE,
+            };
+            let timeout = match fields.next() {
+                Some(text) => parse_step_duration("wait_for timeout", text, options)?,
+                None => Duration::from_millis(DEFAULT_WAIT_FOR_TIMEOUT_MS),
+            };
+            if fields.next().is_some() {
+                return Err(format_error());
+            }
+            Ok(InputAction::WaitForColor {
+                area,
+                color,
+                tolerance,
+                timeout,
+            })
+        }
+        "stable" => {
+            let mut duration_field = |what: &str, default_ms: u64| match fields.next() {
+                Some(text) => parse_step_duration(what, text, options),
+                None => Ok(Duration::from_millis(default_ms)),
+            };
+            let window = duration_field("stable window", DEFAULT_STABLE_MS)?;
+            let timeout = duration_field("wait_for timeout", DEFAULT_WAIT_FOR_TIMEOUT_MS)?;
+            if fields.next().is_some() {
+                return Err(anyhow::anyhow!(
+                    "Invalid wait_for format: wait_for:{} (expected wait_for:stable[:ms[:timeout_ms]])",
+                    condition
+                ));
+            }
+            Ok(InputAction::WaitForStable { window, timeout })
+        }
+        other => Err(anyhow::anyhow!(
+            "Unknown wait_for condition: {} (expected stable, pixel or region)",
+            other
+        )),
+    }
+}
+
+/// Makes a screenshot label safe to use in a file name: characters other than letters,
+/// digits, `-` and `_` become `_`
+fn sanitize_screenshot_label(label: &str) -> Result<String> {
+    let label = label.trim();
+    if label.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Empty screenshot label (use 'screenshot' for a numbered screenshot)"
+        ));
+    }
+    Ok(label
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect())
+}
+
+/// Parses a `#rrggbb` color; the `#` is optional
+fn parse_hex_color(text: &str) -> Result<[u8; 3]> {
+    let hex = text.strip_prefix('#').unwrap_or(text);
+    let channel = |index: usize| {
+        hex.get(index..index + 2)
+            .and_then(|channel| u8::from_str_radix(channel, 16).ok())
+    };
+    match (hex.len(), channel(0), channel(2), channel(4)) {
+        (6, Some(red), Some(green), Some(blue)) => Ok([red, green, blue]),
+        _ => Err(anyhow::anyhow!(
+            "Invalid color: {} (expected #rrggbb, such as #000000)",
+            text
+        )),
+    }
+}
+
+fn format_color(color: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+/// Parses the duration of a step, which must be longer than zero and, unless
+/// `options` allow long waits, no longer than `MAX_STEP_DURATION_MS`
+fn parse_step_duration(what: &str, text: &str, options: ParseOptions) -> Result<Duration> {
+    let duration = parse_duration(text).ok_or_else(|| duration_error(what, text))?;
+    if duration.is_zero() {
+        return Err(anyhow::anyhow!(
+            "Invalid {}: {} (must be longer than zero)",
+            what,
+            text.trim()
+        ));
+    }
+    if duration > Duration::from_millis(MAX_STEP_DURATION_MS) && !options.allow_long_waits {
+        return Err(anyhow::anyhow!(
+            "The {} {} is longer than {} minutes, likely a typo (pass --allow-long-waits if it is meant)",
+            what,
+            text.trim(),
+            MAX_STEP_DURATION_MS / 60_000
+        ));
+    }
+    Ok(duration)
+}
+
+fn duration_error(what: &str, text: &str) -> anyhow::Error {
+    anyhow::anyhow!(
+        "Invalid {}: {} (expected milliseconds such as 500, or frames with an f suffix such as 30f)",
+        what,
+        text
+    )
+}
+
+/// Parses a single step of a sequence: a key press, a held key, a chord, a wait, or a key
+/// put down or released on its own
+fn parse_sequence_step(
+    part: &str,
+    key_mappings: &GbaKeyMappings,
+    options: ParseOptions,
+) -> Result<InputAction> {
+    // "screenshot[:<label>]"
+    if part == "screenshot" {
+        return Ok(InputAction::Screenshot { label: None });
+    }
+    if let Some(label) = part.strip_prefix("screenshot:") {
+        return Ok(InputAction::Screenshot {
+            label: Some(sanitize_screenshot_label(label)?),
+        });
+    }
+
+    // "click:<x>,<y>", "rightclick:<x>,<y>" and "move:<x>,<y>"
+    if let Some((action, point)) = part.split_once(':') {
+        let button = match action.trim() {
+            "click" => Some(Some(Button::Left)),
+            "rightclick" => Some(Some(Button::Right)),
+            "move" => Some(None),
+            _ => None,
+        };
+        if let Some(button) = button {
+            let (x, y) = point
+                .split_once(',')
+                .and_then(|(x, y)| Some((x.trim().parse().ok()?, y.trim().parse().ok()?)))
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Invalid {} position: {} (expected x,y in pixels from the top-left of the mGBA window)",
+                        action.trim(),
+                        point
+                    )
+                })?;
+            return Ok(InputAction::Mouse { button, x, y });
+        }
+    }
+
+    // "wait_for:<condition>[:<arguments>]"
+    if let Some(condition) = part.strip_prefix("wait_for:") {
+        return parse_wait_for(condition, options);
+    }
+
+    // "mash:<key>:<duration_ms>[:<hz>]"
+    if let Some(mash) = part.strip_prefix("mash:") {
+        let (key_str, rest) = split_key(mash);
+        let key = parse_combo(key_str, key_mappings)?;
+        let mut fields = rest.into_iter().flat_map(|rest| rest.split(':'));
+        let duration_str = fields.next().ok_or_else(|| {
+            anyhow::anyhow!(
+                "Invalid mash format: {} (expected mash:key:duration[:hz])",
+                part
+            )
+        })?;
+        let duration = parse_step_duration("mash duration", duration_str, options)?;
+        let hz = match fields.next() {
+            Some(hz_str) => hz_str
+                .trim()
+                .parse::<u32>()
+                .ok()
+                .filter(|hz| (1..=MAX_MASH_HZ).contains(hz))
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Invalid mash rate: {} (expected 1 to {} taps per second)",
+                        hz_str,
+                        MAX_MASH_HZ
+                    )
+                })?,
+            None => DEFAULT_MASH_HZ,
+        };
+        if fields.next().is_some() {
+            return Err(anyhow::anyhow!(
+                "Invalid mash format: {} (expected mash:key:duration[:hz])",
+                part
+            ));
+        }
+        return Ok(InputAction::Mash { key, duration, hz });
+    }
+
+    // "fuzz:<duration_ms>[:<hz>]"
+    if let Some(fuzz) = part.strip_prefix("fuzz:") {
+        let (duration_str, hz_str) = match fuzz.split_once(':') {
+            Some((duration_str, hz_str)) => (duration_str, Some(hz_str)),
+            None => (fuzz, None),
+        };
+        let duration = parse_step_duration("fuzz duration", duration_str, options)?;
+        let hz = match hz_str {
+            Some(hz_str) => hz_str
+                .trim()
+                .parse::<u32>()
+                .ok()
+                .filter(|hz| (1..=MAX_FUZZ_HZ).contains(hz))
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Invalid fuzz rate: {} (expected 1 to {} presses per second)",
+                        hz_str,
+                        MAX_FUZZ_HZ
+                    )
+                })?,
+            None => DEFAULT_FUZZ_HZ,
+        };
+        let seed = options.fuzz_seed;
+        let presses = fuzz::generate(duration, hz, seed)
+            .into_iter()
+            .map(|event| {
+                Ok((
+                    parse_key(&format!("{}{}", GBA_KEY_PREFIX, event.button), key_mappings)?,
+                    event.hold,
+                    event.gap,
+                ))
+            })
+            .collect::<Result<_>>()?;
+        return Ok(InputAction::Fuzz { presses, seed });
+    }
+
+    // "down:<key>" and "up:<key>"; "down:300" and "up:300" still hold an arrow key
+    if let Some((action, key_str)) = part.split_once(':') {
+        if parse_duration(key_str).is_none() {
+            match action.trim().to_lowercase().as_str() {
+                "down" => {
+                    let key = parse_combo(key_str, key_mappings)?;
+                    return Ok(InputAction::KeyDown { key });
+                }
+                "up" => {
+                    let key = parse_combo(key_str, key_mappings)?;
+                    return Ok(InputAction::KeyUp { key });
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Keys joined with '+' are pressed together, optionally held for a duration, unless
+    // they are modifiers and a key: "shift+f1:200" holds f1 for 200 ms with shift
+    // pressed around it
+    let (keys_str, duration_str) = split_step_duration(part);
+    if keys_str.contains('+') {
+        let duration = duration_str
+            .map(|duration_str| parse_step_duration("duration", duration_str, options))
+            .transpose()?;
+        return Ok(match parse_joined_keys(keys_str, key_mappings)? {
+            JoinedKeys::Combo(key) => InputAction::Press { key, duration },
+            JoinedKeys::Chord(keys) => InputAction::Chord { keys, duration },
+        });
+    }
+
+    if part.starts_with("wait:") {
+        let duration_str = part.strip_prefix("wait:").unwrap();
+        let duration = parse_step_duration("wait duration", duration_str, options)?;
+        Ok(InputAction::Wait { duration })
+    } else if let Some(duration_str) = duration_str {
+        // Key with duration (hold)
+        let duration = parse_step_duration("duration", duration_str, options)?;
+        let key = parse_key(keys_str, key_mappings)?;
+        Ok(InputAction::Press {
+            key,
+            duration: Some(duration),
+        })
+    } else {
+        // Simple key press
+        let key = parse_key(part, key_mappings)?;
+        Ok(InputAction::Press {
+            key,
+            duration: None,
+        })
+    }
+}
+
+/// `text` after a `gba:` or `key:` prefix, ignoring case and leading whitespace
+fn strip_key_prefix<'a>(text: &'a str, prefix: &str) -> Option<&'a str> {
+    let text = text.trim_start();
+    text.get(..prefix.len())
+        .filter(|start| start.eq_ignore_ascii_case(prefix))
+        .map(|_| &text[prefix.len()..])
+}
+
+/// Splits the key at the start of `text` from whatever follows the next ':'. The ':' of a
+/// `gba:`, `key:` or `scancode:` prefix belongs to the key.
+fn split_key(text: &str) -> (&str, Option<&str>) {
+    let prefix_len = [GBA_KEY_PREFIX, RAW_KEY_PREFIX, SCANCODE_PREFIX]
+        .iter()
+        .find_map(|prefix| strip_key_prefix(text, prefix))
+        .map_or(0, |name| text.len() - name.len());
+    match text[prefix_len..].find(':') {
+        Some(index) => (
+            &text[..prefix_len + index],
+            Some(&text[prefix_len + index + 1..]),
+        ),
+        None => (text, None),
+    }
+}
+
+/// Splits a step like `A:500`, `U+R:500` or `key:s:500` into its keys and its duration,
+/// if it has one
+fn split_step_duration(part: &str) -> (&str, Option<&str>) {
+    let last_key = part.rfind('+').map_or(0, |index| index + 1);
+    let (key, duration) = split_key(&part[last_key..]);
+    (&part[..last_key + key.len()], duration)
+}
+
+/// Parses a raw keyboard key string into an enigo Key (no GBA mappings)
+fn parse_raw_key(key_str: &str) -> Result<Key> {
+    match key_str.to_lowercase().as_str() {
+        // Letters
+        "a" => Ok(character_key('a')),
+        "b" => Ok(character_key('b')),
+        "c" => Ok(character_key('c')),
+        "d" => Ok(character_key('d')),
+        "e" => Ok(character_key('e')),
+        "f" => Ok(character_key('f')),
+        "g" => Ok(character_key('g')),
+        "h" => Ok(character_key('h')),
+        "i" => Ok(character_key('i')),
+        "j" => Ok(character_key('j')),
+        "k" => Ok(character_key('k')),
+        "l" => Ok(character_key('l')),
+        "m" => Ok(character_key('m')),
+        "n" => Ok(character_key('n')),
+        "o" => Ok(character_key('o')),
+        "p" => Ok(character_key('p')),
+        "q" => Ok(character_key('q')),
+        "r" => Ok(character_key('r')),
+        "s" => Ok(character_key('s')),
+        "t" => Ok(character_key('t')),
+        "u" => Ok(character_key('u')),
+        "v" => Ok(character_key('v')),
+        "w" => Ok(character_key('w')),
+        "x" => Ok(character_key('x')),
+        "y" => Ok(character_key('y')),
+        "z" => Ok(character_key('z')),
+
+        // Arrow keys (common for GBA games)
+        "up" | "arrow_up" => Ok(Key::UpArrow),
+        "down" | "arrow_down" => Ok(Key::DownArrow),
+        "left" | "arrow_left" => Ok(Key::LeftArrow),
+        "right" | "arrow_right" => Ok(Key::RightArrow),
+
+        // Special keys
+        "space" => Ok(character_key(' ')),
+        "enter" | "return" => Ok(Key::Return),
+        "tab" => Ok(Key::Tab),
+        "escape" | "esc" => Ok(Key::Escape),
+        "shift" => Ok(Key::Shift),
+        "ctrl" | "control" => Ok(Key::Control),
+        "alt" => Ok(Key::Alt),
+        "meta" | "cmd" | "command" | "super" => Ok(Key::Meta),
+        "backspace" => Ok(Key::Backspace),
+
+        // Function keys (emulator shortcuts such as loading state slots)
+        "f1" => Ok(Key::F1),
+        "f2" => Ok(Key::F2),
+        "f3" => Ok(Key::F3),
+        "f4" => Ok(Key::F4),
+        "f5" => Ok(Key::F5),
+        "f6" => Ok(Key::F6),
+        "f7" => Ok(Key::F7),
+        "f8" => Ok(Key::F8),
+        "f9" => Ok(Key::F9),
+        "f10" => Ok(Key::F10),
+        "f11" => Ok(Key::F11),
+        "f12" => Ok(Key::F12),
+
+        // Navigation
+        "pageup" | "pgup" | "page_up" => Ok(Key::PageUp),
+        "pagedown" | "pgdn" | "page_down" => Ok(Key::PageDown),
+        "home" => Ok(Key::Home),
+        "end" => Ok(Key::End),
+        "insert" | "ins" => Ok(insert_key()),
+        "delete" | "del" => Ok(Key::Delete),
+
+        // Punctuation, by the character on a US layout
+        "comma" => Ok(character_key(',')),
+        "period" | "dot" => Ok(character_key('.')),
+        "slash" => Ok(character_key('/')),
+        "backslash" => Ok(character_key('\\')),
+        "semicolon" => Ok(character_key(';')),
+        "quote" | "apostrophe" => Ok(character_key('\'')),
+        "left_bracket" | "lbracket" => Ok(character_key('[')),
+        "right_bracket" | "rbracket" => Ok(character_key(']')),
+        "minus" | "dash" => Ok(character_key('-')),
+        "equals" | "equal" => Ok(character_key('=')),
+        "grave" | "backtick" => Ok(character_key('`')),
+
+        // Numpad
+        "kp_0" | "numpad0" => Ok(Key::Numpad0),
+        "kp_1" | "numpad1" => Ok(Key::Numpad1),
+        "kp_2" | "numpad2" => Ok(Key::Numpad2),
+        "kp_3" | "numpad3" => Ok(Key::Numpad3),
+        "kp_4" | "numpad4" => Ok(Key::Numpad4),
+        "kp_5" | "numpad5" => Ok(Key::Numpad5),
+        "kp_6" | "numpad6" => Ok(Key::Numpad6),
+        "kp_7" | "numpad7" => Ok(Key::Numpad7),
+        "kp_8" | "numpad8" => Ok(Key::Numpad8),
+        "kp_9" | "numpad9" => Ok(Key::Numpad9),
+        "kp_add" | "kp_plus" | "numpad_add" => Ok(Key::Add),
+        "kp_subtract" | "kp_minus" | "numpad_subtract" => Ok(Key::Subtract),
+        "kp_multiply" | "numpad_multiply" => Ok(Key::Multiply),
+        "kp_divide" | "numpad_divide" => Ok(Key::Divide),
+        "kp_decimal" | "numpad_decimal" => Ok(Key::Decimal),
+
+        // Left and right modifiers, for emulators bound to one side only
+        "lshift" | "left_shift" => Ok(Key::LShift),
+        "rshift" | "right_shift" => Ok(Key::RShift),
+        "lctrl" | "left_ctrl" => Ok(Key::LControl),
+        "rctrl" | "right_ctrl" => Ok(Key::RControl),
+        "lalt" | "left_alt" => Ok(Key::Alt),
+        "ralt" | "right_alt" => Ok(right_alt_key()),
+        "lmeta" | "left_meta" => Ok(Key::Meta),
+        "rmeta" | "right_meta" | "rcmd" | "right_cmd" => Ok(right_meta_key()),
+
+        // Numbers
+        "0" => Ok(character_key('0')),
+        "1" => Ok(character_key('1')),
+        "2" => Ok(character_key('2')),
+        "3" => Ok(character_key('3')),
+        "4" => Ok(character_key('4')),
+        "5" => Ok(character_key('5')),
+        "6" => Ok(character_key('6')),
+        "7" => Ok(character_key('7')),
+        "8" => Ok(character_key('8')),
+        "9" => Ok(character_key('9')),
+
+        // Physical keys, sent by position whatever the keyboard layout
+        name if name.starts_with(SCANCODE_PREFIX) => {
+            let scancode = name[SCANCODE_PREFIX.len()..]
+                .trim()
+                .parse::<u16>()
+                .ok()
+                .filter(|&scancode| scancode > 0 && platform_keycode(scancode).is_some())
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Invalid scancode: {} (expected a PC scancode such as 44, the key in the Z position{})",
+                        key_str,
+                        if cfg!(target_os = "macos") {
+                            ", of a letter, digit, space or punctuation key on macOS"
+                        } else {
+                            ""
+                        }
+                    )
+                })?;
+            Ok(physical_key(scancode))
+        }
+        name if name.starts_with(PHYSICAL_KEY_PREFIX) => {
+            let position = &name[PHYSICAL_KEY_PREFIX.len()..];
+            let scancode = parse_raw_key(position)
+                .ok()
+                .and_then(character_scancode)
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Unsupported physical key: {} (expected physical_ and a letter, digit, space or punctuation key name, like physical_z)",
+                        key_str
+                    )
+                })?;
+            Ok(physical_key(scancode))
+        }
+
+        _ => Err(anyhow::anyhow!("Unsupported key: {}", key_str)),
+    }
+}
+
+/// Names a key for messages, physical keys by their scancode
+fn key_name(key: Key) -> String {
+    match key {
+        Key::Other(code) if code & PHYSICAL_KEY_FLAG != 0 => {
+            format!("{}{}", SCANCODE_PREFIX, code as u16)
+        }
+        _ => format!("{:?}", key),
+    }
+}
+
+/// A physical key, sent by its scancode
+fn physical_key(scancode: u16) -> Key {
+    Key::Other(PHYSICAL_KEY_FLAG | u32::from(scancode))
+}
+
+/// The scancode of a key with a character, by the character's position on a US QWERTY
+/// layout
+fn character_scancode(key: Key) -> Option<u16> {
+    PHYSICAL_KEYS
+        .iter()
+        .find(|&&(c, _, _)| character_key(c) == key)
+        .map(|&(_, scancode, _)| scancode)
+}
+
+/// The scancode a key is sent as, for physical keys and, in scancode mode, keys with a
+/// character
+fn key_scancode(key: Key, mode: KeyInputMode) -> Option<u16> {
+    match key {
+        Key::Other(code) if code & PHYSICAL_KEY_FLAG != 0 => Some(code as u16),
+        _ if mode == KeyInputMode::Scancode => character_scancode(key),
+        _ => None,
+    }
+}
+
+/// The code enigo sends a scancode as: the X11 keycode on Linux, the scancode itself on
+/// Windows and the virtual key code on macOS, where only keys with a character are known
+fn platform_keycode(scancode: u16) -> Option<u16> {
+    if cfg!(target_os = "macos") {
+        PHYSICAL_KEYS
+            .iter()
+            .find(|&&(_, known, _)| known == scancode)
+            .map(|&(_, _, keycode)| keycode)
+    } else if cfg!(target_os = "windows") {
+        (scancode <= 0xff).then_some(scancode)
+    } else {
+        (scancode <= 0xff - 8).then_some(scancode + 8)
+    }
+}
+
+/// Presses, releases or taps `key`, by its scancode for physical keys and in scancode
+/// mode
+fn send_key(
+    enigo: &mut Enigo,
+    key: Key,
+    direction: Direction,
+    mode: KeyInputMode,
+) -> enigo::InputResult<()> {
+    match key_scancode(key, mode).and_then(platform_keycode) {
+        Some(keycode) => enigo.raw(keycode, direction),
+        None => enigo.key(key, direction),
+    }
+}
+
+/// The Insert key; Mac keyboards have Help in its place
+#[cfg(not(target_os = "macos"))]
+fn insert_key() -> Key {
+    Key::Insert
+}
+
+#[cfg(target_os = "macos")]
+fn insert_key() -> Key {
+    Key::Help
+}
+
+/// The right Alt (Option) key
+#[cfg(target_os = "windows")]
+fn right_alt_key() -> Key {
+    Key::RMenu
+}
+
+#[cfg(target_os = "macos")]
+fn right_alt_key() -> Key {
+    Key::ROption
+}
+
+/// The right Alt key, by its X11 keysym (Alt_R)
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn right_alt_key() -> Key {
+    Key::Other(0xffea)
+}
+
+/// The right Windows (Command, Super) key
+#[cfg(target_os = "windows")]
+fn right_meta_key() -> Key {
+    Key::RWin
+}
+
+#[cfg(target_os = "macos")]
+fn right_meta_key() -> Key {
+    Key::RCommand
+}
+
+/// The right Super key, by its X11 keysym (Super_R)
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn right_meta_key() -> Key {
+    Key::Other(0xffec)
+}
+
+/// Key for a letter, digit, space or punctuation mark. Windows gets virtual-key codes, as
+/// mGBA reads key presses rather than the characters Unicode injection produces.
+#[cfg(target_os = "windows")]
+fn character_key(c: char) -> Key {
+    match c {
+        'a' => Key::A,
+        'b' => Key::B,
+        'c' => Key::C,
+        'd' => Key::D,
+        'e' => Key::E,
+        'f' => Key::F,
+        'g' => Key::G,
+        'h' => Key::H,
+        'i' => Key::I,
+        'j' => Key::J,
+        'k' => Key::K,
+        'l' => Key::L,
+        'm' => Key::M,
+        'n' => Key::N,
+        'o' => Key::O,
+        'p' => Key::P,
+        'q' => Key::Q,
+        'r' => Key::R,
+        's' => Key::S,
+        't' => Key::T,
+        'u' => Key::U,
+        'v' => Key::V,
+        'w' => Key::W,
+        'x' => Key::X,
+        'y' => Key::Y,
+        'z' => Key::Z,
+        '0' => Key::Num0,
+        '1' => Key::Num1,
+        '2' => Key::Num2,
+        '3' => Key::Num3,
+        '4' => Key::Num4,
+        '5' => Key::Num5,
+        '6' => Key::Num6,
+        '7' => Key::Num7,
+        '8' => Key::Num8,
+        '9' => Key::Num9,
+        ' ' => Key::Space,
+        ',' => Key::OEMComma,
+        '.' => Key::OEMPeriod,
+        '-' => Key::OEMMinus,
+        '=' => Key::OEMPlus,
+        ';' => Key::OEM1,
+        '/' => Key::OEM2,
+        '`' => Key::OEM3,
+        '[' => Key::OEM4,
+        '\\' => Key::OEM5,
+        ']' => Key::OEM6,
+        '\'' => Key::OEM7,
+        _ => Key::Unicode(c),
+    }
+}
+
+/// Key for a letter, digit, space or punctuation mark
+#[cfg(not(target_os = "windows"))]
+fn character_key(c: char) -> Key {
+    Key::Unicode(c)
+}
+
+/// The GBA button a name or number stands for, as its description and the key it is
+/// mapped to
+fn gba_button<'a>(name: &str, key_mappings: &'a GbaKeyMappings) -> Option<(&'static str, &'a str)> {
+    let (button, key) = match name.to_uppercase().as_str() {
+        // GBA Controller mappings using the button names/numbers you specified
+        "A" | "0" => ("A", &key_mappings.a),
+        "B" | "1" => ("B", &key_mappings.b),
+        "E" | "2" => ("Select", &key_mappings.select),
+        "S" | "3" => ("Start", &key_mappings.start),
+        "R" | "4" => ("Right", &key_mappings.right),
+        "L" | "5" => ("Left", &key_mappings.left),
+        "U" | "6" => ("Up", &key_mappings.up),
+        "D" | "7" => ("Down", &key_mappings.down),
+        "I" | "8" => ("R shoulder", &key_mappings.r_shoulder),
+        "J" | "9" => ("L shoulder", &key_mappings.l_shoulder),
+        _ => return None,
+    };
+    Some((button, key.as_str()))
+}
+
+/// Parses a string into an enigo Key, supporting GBA controller names. `gba:` only
+/// accepts GBA buttons and `key:` only keyboard keys; without a prefix GBA buttons win.
+/// A GBA button mapped to a combination like `shift+f1` gives the key with its modifiers.
+fn parse_key(key_str: &str, key_mappings: &GbaKeyMappings) -> Result<KeyCombo> {
+    if let Some(name) = strip_key_prefix(key_str, GBA_KEY_PREFIX) {
+        let (_, mapped) = gba_button(name.trim(), key_mappings).ok_or_else(|| {
+            anyhow::anyhow!(
+                "Unknown GBA button: {} (expected A, B, S, E, U, D, L, R, I, J or 0-9)",
+                name.trim()
+            )
+        })?;
+        return parse_raw_combo(mapped);
+    }
+    if let Some(name) = strip_key_prefix(key_str, RAW_KEY_PREFIX) {
+        let name = name.trim();
+        return parse_raw_key(name).map(KeyCombo::from).map_err(|e| {
+            let suggestions = suggest_keys(name, key_mappings);
+            with_suggestions(e, RAW_KEY_PREFIX, &suggestions)
+        });
+    }
+
+    if let Some((button, mapped)) = gba_button(key_str, key_mappings) {
+        // A lowercase letter reads like a keyboard key, so say once what it means
+        if key_str.len() == 1
+            && key_str.chars().all(|c| c.is_ascii_lowercase())
+            && !AMBIGUOUS_KEY_NOTED.swap(true, Ordering::Relaxed)
+        {
+            println!(
+                "Note: '{}' is the GBA {} button (mapped to {}); write {}{} for the keyboard key, or {}{} to make this explicit",
+                key_str, button, mapped, RAW_KEY_PREFIX, key_str, GBA_KEY_PREFIX, key_str
+            );
+        }
+        return parse_raw_combo(mapped);
+    }
+
+    // Fall back to raw key parsing for regular keyboard keys
+    parse_raw_key(key_str)
+        .map(KeyCombo::from)
+        .map_err(|e| with_suggestions(e, "", &suggest_keys(key_str, key_mappings)))
+}
+
+/// Keys joined with '+' in a sequence step
+enum JoinedKeys {
+    /// Modifiers followed by one other key, like `shift+f1`: the key with the modifiers
+    /// held around it
+    Combo(KeyCombo),
+    /// Any other keys, like `U+R`, pressed together
+    Chord(Vec<Key>),
+}
+
+/// Parses keys joined with '+', or a single key
+fn parse_joined_keys(keys_str: &str, key_mappings: &GbaKeyMappings) -> Result<JoinedKeys> {
+    let key_strs: Vec<&str> = keys_str.split('+').map(str::trim).collect();
+    let combos = key_strs
+        .iter()
+        .map(|key_str| parse_key(key_str, key_mappings))
+        .collect::<Result<Vec<_>>>()?;
+    let mut keys: Vec<Key> = combos.iter().flat_map(KeyCombo::keys).collect();
+    let (last, modifiers) = key_strs.split_last().expect("split yields a part");
+    if let [combo] = combos.as_slice() {
+        Ok(JoinedKeys::Combo(combo.clone()))
+    } else if modifiers.iter().all(|key_str| is_modifier_name(key_str)) && !is_modifier_name(last) {
+        let key = keys.pop().expect("a combo has a key");
+        Ok(JoinedKeys::Combo(KeyCombo {
+            modifiers: keys,
+            key,
+        }))
+    } else {
+        Ok(JoinedKeys::Chord(keys))
+    }
+}
+
+/// Parses a key, or modifiers and a key like `shift+f1`, where one key is expected
+fn parse_combo(keys_str: &str, key_mappings: &GbaKeyMappings) -> Result<KeyCombo> {
+    match parse_joined_keys(keys_str, key_mappings)? {
+        JoinedKeys::Combo(combo) => Ok(combo),
+        JoinedKeys::Chord(_) => Err(anyhow::anyhow!(
+            "Invalid key: {} (expected one key, or modifiers and a key like shift+f1)",
+            keys_str.trim()
+        )),
+    }
+}
+
+/// Whether `key_str` names a modifier key, with or without the `key:` prefix
+fn is_modifier_name(key_str: &str) -> bool {
+    let name = strip_key_prefix(key_str, RAW_KEY_PREFIX).unwrap_or(key_str);
+    MODIFIER_NAMES
+        .iter()
+        .any(|modifier| modifier.eq_ignore_ascii_case(name.trim()))
+}
+
+/// Parses a raw key with optional modifiers, like "f1", "shift+f1" or "ctrl+shift+r"
+fn parse_raw_combo(combo: &str) -> Result<KeyCombo> {
+    let key_strs: Vec<&str> = combo.split('+').map(str::trim).collect();
+    let (key_str, modifier_strs) = key_strs.split_last().expect("split yields a part");
+    if let Some(other) = modifier_strs.iter().find(|name| !is_modifier_name(name)) {
+        return Err(anyhow::anyhow!(
+            "Invalid key combination: {} ('{}' is not a modifier; only shift, ctrl, alt and meta can come before the key)",
+            combo,
+            other
+        ));
+    }
+    Ok(KeyCombo {
+        modifiers: modifier_strs
+            .iter()
+            .map(|name| parse_raw_key(name))
+            .collect::<Result<_>>()?,
+        key: parse_raw_key(key_str)?,
+    })
+}
+
+/// Parses the key a GBA button is mapped to, suggesting the likely intended key when a
+/// part of it isn't one
+fn parse_mapped_key(key: &str) -> Result<KeyCombo> {
+    parse_raw_combo(key).map_err(|e| {
+        let unknown = key
+            .split('+')
+            .map(str::trim)
+            .find(|part| parse_raw_key(part).is_err());
+        match unknown {
+            Some(part) => with_suggestions(e, "", &suggest_keys(part, &GbaKeyMappings::default())),
+            None => e,
+        }
+    })
+}
+
+/// Appends the likely intended keys, written with `prefix`, to an unknown key error
+fn with_suggestions(e: anyhow::Error, prefix: &str, suggestions: &[String]) -> anyhow::Error {
+    let quoted: Vec<String> = suggestions
+        .iter()
+        .map(|name| format!("'{}{}'", prefix, name))
+        .collect();
+    match quoted.as_slice() {
+        [] => e,
+        [only] => anyhow::anyhow!("{} (did you mean {}?)", e, only),
+        [rest @ .., last] => {
+            anyhow::anyhow!("{} (did you mean {} or {}?)", e, rest.join(", "), last)
+        }
+    }
+}
+
+/// Up to three key names closest to a misspelled `key_str`, among the named keys and the
+/// keys the GBA buttons are mapped to, that are close enough to be a likely typo
+fn suggest_keys(key_str: &str, key_mappings: &GbaKeyMappings) -> Vec<String> {
+    let key_str = key_str.to_lowercase();
+    // One edit for short names, two for longer ones
+    let max_distance = if key_str.chars().count() <= 4 { 1 } else { 2 };
+    let mapped = [
+        &key_mappings.a,
+        &key_mappings.b,
+        &key_mappings.select,
+        &key_mappings.start,
+        &key_mappings.right,
+        &key_mappings.left,
+        &key_mappings.up,
+        &key_mappings.down,
+        &key_mappings.r_shoulder,
+        &key_mappings.l_shoulder,
+    ];
+    KEY_NAMES
+        .iter()
+        .copied()
+        .chain(mapped.into_iter().map(String::as_str))
+        // Single letters are a single edit away from any short word
+        .filter(|name| name.chars().count() > 1)
+        .map(|name| (edit_distance(&key_str, &name.to_lowercase()), name))
+        .filter(|&(distance, _)| distance <= max_distance)
+        .collect::<std::collections::BTreeSet<_>>()
+        .into_iter()
+        .take(3)
+        .map(|(_, name)| name.to_string())
+        .collect()
+}
+
+/// Number of single-character insertions, deletions, substitutions and swaps of
+/// neighbouring characters that turn `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![(0..=b.len()).collect::<Vec<_>>()];
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            row[j] = (rows[i - 1][j] + 1)
+                .min(row[j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(rows[i - 2][j - 2] + 1);
+            }
+        }
+        rows.push(row);
+    }
+    rows[a.len()][b.len()]
+}
+
+/// Parses a shortcut like "ctrl+o" into a press of the key with its modifiers held
+fn parse_key_combo(combo: &str) -> Result<Vec<InputAction>> {
+    Ok(vec![InputAction::Press {
+        key: parse_raw_combo(combo)?,
+        duration: None,
+    }])
+}
+
+/// Executes a sequence of input actions using enigo, with mouse positions in the window
+/// `window` finds
+async fn execute_input_sequence(actions: &[InputAction], window: &WindowQuery) -> Result<()> {
+    let session = InputSession::default();
+    session.lock().window = window.clone();
+    session.run(actions).await?;
+    // A key left down would stay pressed in mGBA for the rest of the run
+    let held = session.lock().held.clone();
+    if !held.is_empty() {
+        println!(
+            "Warning: input sequence ended with {} still held down; releasing",
+            format_combos(&held)
+        );
+    }
+    Ok(())
+}
+
+/// Input sent over the phases of one capture. Keys put down with `down:` in one phase
+/// stay held into the next until an `up:` releases them; whatever is still pressed is
+/// released by `finish` after the last phase, or when the last handle to the session is
+/// dropped on errors and Ctrl+C.
+///
+/// Clones share the session, so concurrent tracks press keys through the same state.
+/// Presses are counted per key: a key held by two tracks goes up only when both let go,
+/// and a quick tap of a key another track is holding has no effect.
+#[derive(Clone, Default)]
+struct InputSession {
+    state: Arc<std::sync::Mutex<InputState>>,
+    /// Set on Ctrl+C, ending long-running actions such as `mash:` early
+    shutdown: Option<Arc<AtomicBool>>,
+    /// The captured window, watched by `wait_for:` conditions
+    source: Option<Arc<dyn FrameSource>>,
+    /// Output file name of the capture, the prefix of `screenshot` files
+    output_name: Option<String>,
+    /// Directory of the run, which screenshots and the input timeline are written to
+    output_dir: PathBuf,
+    /// Shortest taps and gaps, and settings of the input device
+    pacing: InputPacing,
+}
+
+#[derive(Default)]
+struct InputState {
+    /// Where key events go; the keyboard unless the session chose another backend
+    backend: Option<Box<dyn InputBackend>>,
+    /// The mGBA window, which is focused for input and which mouse positions are in
+    window: WindowQuery,
+    /// Keys that are down, with the number of holds keeping each of them down
+    pressed: Vec<(Key, usize)>,
+    /// Keys put down with KeyDown and not released yet
+    held: Vec<KeyCombo>,
+    /// Paths of the screenshots taken so far
+    screenshots: Vec<String>,
+    /// Settings the input device is connected with
+    pacing: InputPacing,
+    /// Actions performed so far with when they ran, kept with `--log-inputs`
+    input_log: Option<Vec<LoggedInput>>,
+    /// When the first frame was captured, which logged times and `at:` anchors are
+    /// relative to
+    capture_start: Option<Instant>,
+    /// Time between the capture's frames
+    frame_interval: Duration,
+    /// Random movement of hold and wait durations, one stream for the whole session so
+    /// sequences run one after another and concurrent tracks don't repeat it
+    jitter: Option<fuzz::Jitter>,
+}
+
+/// An input action performed in a capture, for `--log-inputs`
+struct LoggedInput {
+    phase: String,
+    step: String,
+    keys: Option<String>,
+    start: Instant,
+    end: Instant,
+}
+
+/// An entry of `out/<name>.inputs.json`; times are in milliseconds from the first frame,
+/// negative before it
+#[derive(Serialize)]
+struct InputLogEntry {
+    phase: String,
+    step: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keys: Option<String>,
+    start_ms: f64,
+    end_ms: f64,
+}
+
+impl InputSession {
+    /// A session for the capture `output_name` written to `output_root`, whose
+    /// long-running actions stop once `shutdown` is set, whose `wait_for:` and
+    /// `screenshot` actions use `source`, whose input goes to the window `window` finds
+    /// and is timed by `pacing`
+    fn new(
+        shutdown: &Arc<AtomicBool>,
+        source: Arc<dyn FrameSource>,
+        window: &WindowQuery,
+        output_root: &OutputRoot,
+        output_name: &str,
+        pacing: InputPacing,
+    ) -> Self {
+        let mut state = InputState::default();
+        state.pacing = pacing;
+        state.window = window.clone();
+        Self {
+            state: Arc::new(std::sync::Mutex::new(state)),
+            shutdown: Some(shutdown.clone()),
+            source: Some(source),
+            output_name: Some(output_name.to_string()),
+            output_dir: output_root.dir.clone(),
+            pacing,
+        }
+    }
+
+    /// Path of `name` in the run's output directory, as it is printed
+    fn output_file(&self, name: &str) -> String {
+        self.output_dir.join(name).display().to_string()
+    }
+
+    /// Presses GBA buttons on `device` rather than the keyboard, recognizing them by
+    /// the keys `key_mappings` maps them to. Buttons mapped to a combination with
+    /// modifiers, and keys that aren't a button, are still typed.
+    fn use_button_device(&self, device: Arc<dyn ButtonDevice>, key_mappings: &GbaKeyMappings) {
+        let buttons = key_mappings
+            .buttons()
+            .into_iter()
+            .filter_map(|(button, key)| {
+                let combo = parse_raw_combo(key).ok()?;
+                combo.modifiers.is_empty().then_some((combo.key, button))
+            })
+            .collect();
+        let mut state = self.lock();
+        let keyboard = EnigoBackend::new(state.pacing, state.window.clone());
+        state.backend = Some(Box::new(ButtonBackend {
+            device,
+            buttons,
+            keyboard,
+        }));
+    }
+
+    /// Records key events with their times instead of sending them, printing them with
+    /// `print` (`--mock-input`), and returns where they are recorded
+    fn use_mock_input(&self, print: bool) -> MockEvents {
+        let events = MockEvents::default();
+        self.lock().backend = Some(Box::new(MockBackend {
+            start: Instant::now(),
+            events: events.clone(),
+            print,
+        }));
+        events
+    }
+
+    /// Whether input of this session goes to the focused window
+    fn needs_focus(&self) -> bool {
+        self.lock().backend().needs_focus()
+    }
+
+    /// The mGBA window input of this session goes to
+    fn window(&self) -> WindowQuery {
+        self.lock().window.clone()
+    }
+
+    /// Paths of the screenshots taken in this session
+    fn screenshots(&self) -> Vec<String> {
+        self.lock().screenshots.clone()
+    }
+
+    /// Starts keeping a timeline of the actions performed in this session
+    fn log_inputs(&self) {
+        self.lock().input_log = Some(Vec::new());
+    }
+
+    /// Starts the capture's frame schedule, frames `frame_interval` apart from now, and
+    /// returns when it started. This is the zero point of the input timeline.
+    fn start_frame_schedule(&self, frame_interval: Duration) -> Instant {
+        let start = Instant::now();
+        let mut state = self.lock();
+        state.capture_start = Some(start);
+        state.frame_interval = frame_interval;
+        start
+    }
+
+    /// When input for frame `frame` of the capture is due, once the capture has started:
+    /// the input latency ahead of the frame, but not before the capture started
+    fn frame_time(&self, frame: u32) -> Option<Instant> {
+        let state = self.lock();
+        let offset = (state.frame_interval * frame).saturating_sub(self.pacing.latency);
+        Some(state.capture_start? + offset)
+    }
+
+    /// Writes the input timeline, if one was kept, to `out/<output_name>.inputs.json`
+    /// and returns its path
+    fn write_input_log(&self) -> Result<Option<String>> {
+        let Some(output_name) = self.output_name.as_ref() else {
+            return Ok(None);
+        };
+        let state = self.lock();
+        let Some(log) = state.input_log.as_ref() else {
+            return Ok(None);
+        };
+        let zero = state
+            .capture_start
+            .or_else(|| log.first().map(|input| input.start))
+            .unwrap_or_else(Instant::now);
+        let offset_ms = |time: Instant| match time.checked_duration_since(zero) {
+            Some(after) => after.as_secs_f64() * 1000.0,
+            None => -zero.duration_since(time).as_secs_f64() * 1000.0,
+        };
+        let entries: Vec<InputLogEntry> = log
+            .iter()
+            .map(|input| InputLogEntry {
+                phase: input.phase.clone(),
+                step: input.step.clone(),
+                keys: input.keys.clone(),
+                start_ms: (offset_ms(input.start) * 10.0).round() / 10.0,
+                end_ms: (offset_ms(input.end) * 10.0).round() / 10.0,
+            })
+            .collect();
+        let path = self.output_file(&format!("{}.inputs.json", output_name));
+        std::fs::write(&path, serde_json::to_string_pretty(&entries)? + "\n")
+            .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", path, e))?;
+        Ok(Some(path))
+    }
+
+    fn interrupted(&self) -> bool {
+        self.shutdown
+            .as_ref()
+            .is_some_and(|shutdown| shutdown.load(Ordering::Relaxed))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, InputState> {
+        // A panicking track can't leave the key counts half-updated, so keep going
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Performs one sequence of input; on failure every pressed key is released
+    async fn run(&self, actions: &[InputAction]) -> Result<()> {
+        self.run_logged(actions, None).await
+    }
+
+    /// Performs a parsed input sequence of the capture phase `phase`, logging its steps
+    /// with `--log-inputs`
+    async fn run_sequence(&self, phase: &str, sequence: &InputSequence) -> Result<()> {
+        self.run_logged(&sequence.actions, Some((phase, &sequence.steps)))
+            .await
+    }
+
+    async fn run_logged(
+        &self,
+        actions: &[InputAction],
+        log: Option<(&str, &[String])>,
+    ) -> Result<()> {
+        if actions.is_empty() {
+            return Ok(());
+        }
+        let result = run_input_actions(self, actions, log).await;
+        if result.is_err() {
+            self.lock().release_all();
+        }
+        result
+    }
+
+    /// Ends the session after its last phase, releasing keys that are still pressed
+    fn finish(&self) {
+        let mut state = self.lock();
+        if !state.pressed.is_empty() {
+            let keys: Vec<Key> = state.pressed.iter().map(|&(key, _)| key).collect();
+            println!(
+                "Releasing {}, still held at the end of the input sequences",
+                format_keys(&keys)
+            );
+            state.release_all();
+        }
+        state.restore_cursor();
+    }
+}
+
+impl InputState {
+    /// The backend key events go to, the keyboard unless another was chosen
+    fn backend(&mut self) -> &mut dyn InputBackend {
+        let pacing = self.pacing;
+        let window = &self.window;
+        self.backend
+            .get_or_insert_with(|| Box::new(EnigoBackend::new(pacing, window.clone())))
+            .as_mut()
+    }
+
+    /// `duration` moved at random by up to the pacing's jitter
+    fn jittered(&mut self, duration: Duration) -> Duration {
+        let pacing = self.pacing;
+        self.jitter
+            .get_or_insert_with(|| {
+                fuzz::Jitter::new(pacing.jitter.as_millis() as u64, pacing.jitter_seed)
+            })
+            .apply(duration)
+    }
+
+    /// Adds a hold on `key`, pressing it if nothing held it yet
+    fn press(&mut self, key: Key) -> Result<()> {
+        if let Some((_, count)) = self.pressed.iter_mut().find(|(pressed, _)| *pressed == key) {
+            *count += 1;
+            return Ok(());
+        }
+        self.backend().press(key)?;
+        self.pressed.push((key, 1));
+        Ok(())
+    }
+
+    /// Drops a hold on `key`, releasing it once no hold is left. A key that isn't held
+    /// is released anyway.
+    fn release(&mut self, key: Key) -> Result<()> {
+        match self.pressed.iter().position(|&(pressed, _)| pressed == key) {
+            Some(index) if self.pressed[index].1 > 1 => {
+                self.pressed[index].1 -= 1;
+                return Ok(());
+            }
+            Some(index) => {
+                self.pressed.remove(index);
+            }
+            None => {}
+        }
+        self.backend().release(key)
+    }
+
+    /// Adds holds on `keys` in order; if one can't be pressed, those already pressed are
+    /// released again
+    fn press_all(&mut self, keys: &[Key]) -> Result<()> {
+        for (index, &key) in keys.iter().enumerate() {
+            if let Err(e) = self.press(key) {
+                let _ = self.release_each(&keys[..index]);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Drops holds on `keys` in reverse order, releasing every one of them even when a
+    /// release fails
+    fn release_each(&mut self, keys: &[Key]) -> Result<()> {
+        let mut result = Ok(());
+        for &key in keys.iter().rev() {
+            if let Err(e) = self.release(key) {
+                if result.is_ok() {
+                    result = Err(e);
+                }
+            }
+        }
+        result
+    }
+
+    /// Presses the modifiers of `combo`, then its key
+    fn press_combo(&mut self, combo: &KeyCombo) -> Result<()> {
+        self.press_all(&combo.keys())
+    }
+
+    /// Releases the key of `combo`, then its modifiers
+    fn release_combo(&mut self, combo: &KeyCombo) -> Result<()> {
+        self.release_each(&combo.keys())
+    }
+
+    /// Taps the key of `combo` with its modifiers held around the tap. The modifiers
+    /// are released even if the tap fails.
+    fn click_combo(&mut self, combo: &KeyCombo) -> Result<()> {
+        self.press_all(&combo.modifiers)?;
+        let clicked = self.click(combo.key);
+        let released = self.release_each(&combo.modifiers);
+        clicked.and(released)
+    }
+
+    /// Taps `key`, unless another hold already keeps it down
+    fn click(&mut self, key: Key) -> Result<()> {
+        if self.pressed.iter().any(|&(pressed, _)| pressed == key) {
+            return Ok(());
+        }
+        self.backend().click(key)
+    }
+
+    fn release_all(&mut self) {
+        self.held.clear();
+        let pressed: Vec<(Key, usize)> = self.pressed.drain(..).collect();
+        for (key, _) in pressed.into_iter().rev() {
+            let _ = self.backend().release(key);
+        }
+    }
+
+    /// Puts the mouse cursor back where it was before the first mouse action
+    fn restore_cursor(&mut self) {
+        if let Some(backend) = &mut self.backend {
+            backend.restore_cursor();
+        }
+    }
+}
+
+impl Drop for InputState {
+    fn drop(&mut self) {
+        self.release_all();
+        self.restore_cursor();
+    }
+}
+
+/// Where the executor sends key events. Sequences are timed and their keys counted the
+/// same whichever backend performs them, so a backend that records events instead of
+/// sending them shows what a sequence would do.
+trait InputBackend: Send {
+    /// Puts `key` down
+    fn press(&mut self, key: Key) -> Result<()>;
+
+    /// Lets `key` up
+    fn release(&mut self, key: Key) -> Result<()>;
+
+    /// Presses and releases `key` at once
+    fn click(&mut self, key: Key) -> Result<()>;
+
+    /// Types `text`
+    fn text(&mut self, text: &str) -> Result<()>;
+
+    /// Moves the mouse to `x`,`y` of the mGBA window and clicks `button` there
+    fn mouse(&mut self, button: Option<Button>, x: u32, y: u32) -> Result<()>;
+
+    /// Puts the mouse cursor back where it was before the first mouse action
+    fn restore_cursor(&mut self) {}
+
+    /// Whether events go to the focused window, so mGBA has to be focused first
+    fn needs_focus(&self) -> bool {
+        true
+    }
+}
+
+/// Keys and mouse actions sent to the focused window through enigo
+struct EnigoBackend {
+    /// Connected on the first event, so runs without input never need one
+    enigo: Option<Enigo>,
+    /// Where the mouse cursor was before the first mouse action, to put it back
+    cursor: Option<(i32, i32)>,
+    pacing: InputPacing,
+    /// The window mouse positions are in
+    window: WindowQuery,
+}
+
+impl EnigoBackend {
+    fn new(pacing: InputPacing, window: WindowQuery) -> Self {
+        Self {
+            enigo: None,
+            cursor: None,
+            pacing,
+            window,
+        }
+    }
+
+    fn enigo(&mut self) -> Result<&mut Enigo> {
+        if self.enigo.is_none() {
+            self.enigo = Some(input_device(&self.pacing)?);
+        }
+        Ok(self
+            .enigo
+            .as_mut()
+            .expect("input device was just connected"))
+    }
+
+    fn send(&mut self, key: Key, direction: Direction, action: &str) -> Result<()> {
+        let mode = self.pacing.key_input_mode;
+        send_key(self.enigo()?, key, direction, mode)
+            .map_err(|e| anyhow::anyhow!("Failed to {} key: {}", action, e))
+    }
+}
+
+impl InputBackend for EnigoBackend {
+    fn press(&mut self, key: Key) -> Result<()> {
+        self.send(key, Direction::Press, "press")
+    }
+
+    fn release(&mut self, key: Key) -> Result<()> {
+        self.send(key, Direction::Release, "release")
+    }
+
+    fn click(&mut self, key: Key) -> Result<()> {
+        self.send(key, Direction::Click, "click")
+    }
+
+    fn text(&mut self, text: &str) -> Result<()> {
+        self.enigo()?
+            .text(text)
+            .map_err(|e| anyhow::anyhow!("Failed to type text: {}", e))
+    }
+
+    /// The window is looked up again for every action, so the point follows it
+    fn mouse(&mut self, button: Option<Button>, x: u32, y: u32) -> Result<()> {
+        let window = find_mgba_window(&self.window)?;
+        if x >= window.width() || y >= window.height() {
+            return Err(anyhow::anyhow!(
+                "Mouse position {},{} is outside the mGBA window ({}x{})",
+                x,
+                y,
+                window.width(),
+                window.height()
+            ));
+        }
+        let enigo = self.enigo()?;
+        let location = enigo
+            .location()
+            .map_err(|e| anyhow::anyhow!("Failed to read the mouse position: {}", e))?;
+        enigo
+            .move_mouse(
+                window.x() + x as i32,
+                window.y() + y as i32,
+                Coordinate::Abs,
+            )
+            .map_err(|e| anyhow::anyhow!("Failed to move the mouse: {}", e))?;
+        self.cursor.get_or_insert(location);
+        if let Some(button) = button {
+            self.enigo()?
+                .button(button, Direction::Click)
+                .map_err(|e| anyhow::anyhow!("Failed to click: {}", e))?;
+        }
+        Ok(())
+    }
+
+    fn restore_cursor(&mut self) {
+        if let (Some((x, y)), Some(enigo)) = (self.cursor.take(), &mut self.enigo) {
+            let _ = enigo.move_mouse(x, y, Coordinate::Abs);
+        }
+    }
+}
+
+/// GBA buttons pressed on a button device, for `--input-backend gamepad` and
+/// `mgba-script`; other keys, text and the mouse still go through the keyboard backend
+struct ButtonBackend {
+    device: Arc<dyn ButtonDevice>,
+    /// The key each GBA button is mapped to, with the button's `key_mappings` field
+    buttons: Vec<(Key, &'static str)>,
+    keyboard: EnigoBackend,
+}
+
+impl ButtonBackend {
+    fn button(&self, key: Key) -> Option<&'static str> {
+        self.buttons
+            .iter()
+            .find(|&&(mapped, _)| mapped == key)
+            .map(|&(_, button)| button)
+    }
+}
+
+impl InputBackend for ButtonBackend {
+    fn press(&mut self, key: Key) -> Result<()> {
+        match self.button(key) {
+            Some(button) => self.device.set(button, true),
+            None => self.keyboard.press(key),
+        }
+    }
+
+    fn release(&mut self, key: Key) -> Result<()> {
+        match self.button(key) {
+            Some(button) => self.device.set(button, false),
+            None => self.keyboard.release(key),
+        }
+    }
+
+    fn click(&mut self, key: Key) -> Result<()> {
+        match self.button(key) {
+            Some(button) => {
+                self.device.set(button, true)?;
+                self.device.set(button, false)
+            }
+            None => self.keyboard.click(key),
+        }
+    }
+
+    fn text(&mut self, text: &str) -> Result<()> {
+        self.keyboard.text(text)
+    }
+
+    fn mouse(&mut self, button: Option<Button>, x: u32, y: u32) -> Result<()> {
+        self.keyboard.mouse(button, x, y)
+    }
+
+    fn restore_cursor(&mut self) {
+        self.keyboard.restore_cursor();
+    }
+}
+
+/// An event the mock backend received instead of sending it
+#[derive(Debug, Clone, PartialEq)]
+enum MockEvent {
+    Press(Key),
+    Release(Key),
+    Click(Key),
+    Text(String),
+    Mouse(Option<Button>, u32, u32),
+}
+
+impl std::fmt::Display for MockEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MockEvent::Press(key) => write!(f, "press {}", key_name(*key)),
+            MockEvent::Release(key) => write!(f, "release {}", key_name(*key)),
+            MockEvent::Click(key) => write!(f, "click {}", key_name(*key)),
+            MockEvent::Text(text) => write!(f, "text {:?}", text),
+            MockEvent::Mouse(Some(button), x, y) => write!(f, "{:?} click at {},{}", button, x, y),
+            MockEvent::Mouse(None, x, y) => write!(f, "mouse move to {},{}", x, y),
+        }
+    }
+}
+
+/// The events a mock backend received, with when it received them
+type MockEvents = Arc<std::sync::Mutex<Vec<(Instant, MockEvent)>>>;
+
+/// Records each event with its time instead of sending it, and prints it for
+/// `--mock-input`
+struct MockBackend {
+    /// Printed event times are relative to this
+    start: Instant,
+    events: MockEvents,
+    print: bool,
+}
+
+impl MockBackend {
+    fn record(&self, event: MockEvent) -> Result<()> {
+        let now = Instant::now();
+        if self.print {
+            println!(
+                "[mock input] {:>8.1}ms {}",
+                now.duration_since(self.start).as_secs_f64() * 1000.0,
+                event
+            );
+        }
+        self.events
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push((now, event));
+        Ok(())
+    }
+}
+
+impl InputBackend for MockBackend {
+    fn press(&mut self, key: Key) -> Result<()> {
+        self.record(MockEvent::Press(key))
+    }
+
+    fn release(&mut self, key: Key) -> Result<()> {
+        self.record(MockEvent::Release(key))
+    }
+
+    fn click(&mut self, key: Key) -> Result<()> {
+        self.record(MockEvent::Click(key))
+    }
+
+    fn text(&mut self, text: &str) -> Result<()> {
+        self.record(MockEvent::Text(text.to_string()))
+    }
+
+    fn mouse(&mut self, button: Option<Button>, x: u32, y: u32) -> Result<()> {
+        self.record(MockEvent::Mouse(button, x, y))
+    }
+
+    fn needs_focus(&self) -> bool {
+        false
+    }
+}
+
+/// Connects to the platform's input system
+fn input_device(pacing: &InputPacing) -> Result<Enigo> {
+    let settings = Settings {
+        linux_delay: pacing.linux_delay_ms,
+        independent_of_keyboard_state: pacing.independent_of_keyboard_state,
+        ..Settings::default()
+    };
+    Enigo::new(&settings).map_err(|e| anyhow::anyhow!("Failed to initialize input system: {}", e))
+}
+
+/// Lists keys for messages
+fn format_keys(keys: &[Key]) -> String {
+    keys.iter()
+        .map(|&key| key_name(key))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Lists keys with their modifiers for messages
+fn format_combos(combos: &[KeyCombo]) -> String {
+    combos
+        .iter()
+        .map(KeyCombo::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Polls `source` until the screen has stayed within `STABLE_PIXEL_TOLERANCE` of one
+/// frame for `window`. Returns `false` if that didn't happen within `timeout`; an
+/// interrupted wait returns `true` so the sequence can wind down.
+async fn wait_for_stable(
+    source: &dyn FrameSource,
+    window: Duration,
+    timeout: Duration,
+    session: &InputSession,
+) -> Result<bool> {
+    let start = Instant::now();
+    let mut reference = source.capture()?;
+    let mut stable_since = start;
+    loop {
+        if session.interrupted() || stable_since.elapsed() >= window {
+            debug!("Screen stable after {}ms", start.elapsed().as_millis());
+            return Ok(true);
+        }
+        if start.elapsed() >= timeout {
+            return Ok(false);
+        }
+        sleep(Duration::from_millis(WAIT_FOR_POLL_MS)).await;
+        let frame = source.capture()?;
+        if changed_pixel_fraction(&reference, &frame) > STABLE_PIXEL_TOLERANCE {
+            reference = frame;
+            stable_since = Instant::now();
+        }
+    }
+}
+
+/// Polls `source` until the average color of `area` is within `tolerance` of `color`.
+/// Returns the last color seen if that didn't happen within `timeout`; an interrupted
+/// wait returns `None` so the sequence can wind down.
+async fn wait_for_color(
+    source: &dyn FrameSource,
+    area: FrameArea,
+    color: [u8; 3],
+    tolerance: u8,
+    timeout: Duration,
+    session: &InputSession,
+) -> Result<Option<[u8; 3]>> {
+    let start = Instant::now();
+    loop {
+        let frame = source.capture()?;
+        let average = average_color(&frame, area)?;
+        if (0..3).all(|channel| average[channel].abs_diff(color[channel]) <= tolerance) {
+            debug!(
+                "{} matched {} after {}ms",
+                area.describe(),
+                format_color(color),
+                start.elapsed().as_millis()
+            );
+            return Ok(None);
+        }
+        if session.interrupted() {
+            return Ok(None);
+        }
+        if start.elapsed() >= timeout {
+            return Ok(Some(average));
+        }
+        sleep(Duration::from_millis(WAIT_FOR_POLL_MS)).await;
+    }
+}
+
+/// Average RGB color of `area` in `frame`
+fn average_color(frame: &RgbaImage, area: FrameArea) -> Result<[u8; 3]> {
+    let fits = area
+        .x
+        .checked_add(area.width)
+        .is_some_and(|right| right <= frame.width())
+        && area
+            .y
+            .checked_add(area.height)
+            .is_some_and(|bottom| bottom <= frame.height());
+    if !fits {
+        return Err(anyhow::anyhow!(
+            "{} is outside the {}x{} captured frame",
+            area.describe(),
+            frame.width(),
+            frame.height()
+        ));
+    }
+
+    let mut sums = [0u64; 3];
+    for y in area.y..area.y + area.height {
+        for x in area.x..area.x + area.width {
+            let pixel = frame.get_pixel(x, y);
+            for channel in 0..3 {
+                sums[channel] += u64::from(pixel[channel]);
+            }
+        }
+    }
+    let count = u64::from(area.width) * u64::from(area.height);
+    Ok(sums.map(|sum| (sum / count) as u8))
+}
+
+/// Fails the sequence for a `wait_for:` condition that timed out, or warns and lets it
+/// continue when `lenient` (`--lenient-waits`)
+fn wait_for_timed_out(condition: &str, message: String, lenient: bool) -> Result<()> {
+    if !lenient {
+        return Err(anyhow::anyhow!(
+            "wait_for:{} timed out: {} (pass --lenient-waits to continue anyway)",
+            condition,
+            message
+        ));
+    }
+    println!(
+        "Warning: wait_for:{} timed out: {}; continuing",
+        condition, message
+    );
+    Ok(())
+}
+
+/// Executes actions from `live_input` as the lines arrive, until end of input or until
+/// the capture cancels it. A line that doesn't parse or fails is reported and skipped.
+async fn run_live_input(
+    live_input: &LiveInput,
+    session: &InputSession,
+    key_mappings: &GbaKeyMappings,
+    macros: &BTreeMap<String, String>,
+    options: ParseOptions,
+) {
+    println!(
+        "Executing input actions from {} as they arrive...",
+        live_input.describe()
+    );
+    loop {
+        let line = match live_input.next_line().await {
+            Ok(Some(line)) => line,
+            Ok(None) => {
+                println!("End of input from {}", live_input.describe());
+                return;
+            }
+            Err(e) => {
+                println!("Warning: {}; no more live input", e);
+                return;
+            }
+        };
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match parse_input_sequence(line, key_mappings, macros, options)
+            .and_then(|sequence| check_frame_anchors(&sequence, true).map(|()| sequence))
+        {
+            Ok(sequence) => {
+                debug!("Live input: {}", line);
+                if let Err(e) = send_to_mgba_in(session, "live", &sequence).await {
+                    println!("Warning: live input '{}' failed: {}", line, e);
+                }
+            }
+            Err(e) => println!("Warning: skipping live input '{}': {}", line, e),
+        }
+    }
+}
+
+/// Sleeps until `deadline`, returning at once if it has passed
+async fn sleep_until(deadline: Instant) {
+    sleep(deadline.saturating_duration_since(Instant::now())).await;
+}
+
+/// Performs input actions through `session`, which is only locked around key events so
+/// concurrent tracks can act while this one waits. With `log`, the capture phase and the
+/// steps the actions were parsed from, each finished action is added to the session's
+/// input timeline.
+async fn run_input_actions(
+    session: &InputSession,
+    actions: &[InputAction],
+    log: Option<(&str, &[String])>,
+) -> Result<()> {
+    let pacing = session.pacing;
+    // When the last key or mouse action ended, for the minimum gap before the next
+    let mut last_input: Option<Instant> = None;
+    for (index, action) in actions.iter().enumerate() {
+        let sends_input = matches!(
+            action,
+            InputAction::Press { .. }
+                | InputAction::Chord { .. }
+                | InputAction::KeyDown { .. }
+                | InputAction::KeyUp { .. }
+                | InputAction::Mouse { .. }
+                | InputAction::Mash { .. }
+                | InputAction::Fuzz { .. }
+                | InputAction::Text { .. }
+        );
+        if let Some(last_input) = last_input.filter(|_| sends_input) {
+            sleep_until(last_input + pacing.min_gap).await;
+        }
+        let start = Instant::now();
+        match action {
+            InputAction::Press { key, duration } => {
+                match duration {
+                    Some(duration) => {
+                        // Hold key for specified duration
+                        session.lock().press_combo(key)?;
+                        let held = session.lock().jittered(*duration);
+                        sleep(held).await;
+                        session.lock().release_combo(key)?;
+                    }
+                    None if pacing.min_press.is_zero() => {
+                        // Quick press and release
+                        session.lock().click_combo(key)?;
+                    }
+                    None => {
+                        // A tap held just long enough for mGBA to poll it
+                        session.lock().press_combo(key)?;
+                        sleep(pacing.min_press).await;
+                        session.lock().release_combo(key)?;
+                    }
+                }
+            }
+            InputAction::Chord { keys, duration } => {
+                let mut pressed = Vec::with_capacity(keys.len());
+                let mut result = Ok(());
+                {
+                    let mut state = session.lock();
+                    for key in keys {
+                        match state.press(*key) {
+                            Ok(()) => pressed.push(*key),
+                            Err(e) => {
+                                result = Err(e);
+                                break;
+                            }
+                        }
+                    }
+                }
+                if result.is_ok() {
+                    let hold = session
+                        .lock()
+                        .jittered(duration.unwrap_or(pacing.chord_hold));
+                    sleep(hold).await;
+                }
+                // Every key that went down is released, even when one release fails
+                let mut state = session.lock();
+                for key in pressed.iter().rev() {
+                    if let Err(e) = state.release(*key) {
+                        if result.is_ok() {
+                            result = Err(e);
+                        }
+                    }
+                }
+                result?;
+            }
+            InputAction::KeyDown { key } => {
+                let mut state = session.lock();
+                if state.held.contains(key) {
+                    println!(
+                        "Warning: {} is already held down; ignoring the second down",
+                        key
+                    );
+                    continue;
+                }
+                state.press_combo(key)?;
+                state.held.push(key.clone());
+            }
+            InputAction::KeyUp { key } => {
+                let mut state = session.lock();
+                match state.held.iter().position(|held_key| held_key == key) {
+                    Some(index) => {
+                        state.held.remove(index);
+                    }
+                    None => println!("Warning: {} is released without being held down", key),
+                }
+                state.release_combo(key)?;
+            }
+            InputAction::Wait { duration } => {
+                let waited = session.lock().jittered(*duration);
+                sleep(waited).await;
+            }
+            InputAction::WaitForStable { window, timeout } => {
+                let source = session.source.as_deref().ok_or_else(|| {
+                    anyhow::anyhow!("wait_for:stable can only be used in capture input sequences")
+                })?;
+                if !wait_for_stable(source, *window, *timeout, session).await? {
+                    wait_for_timed_out(
+                        "stable",
+                        format!(
+                            "screen did not stay unchanged for {}ms within {}ms",
+                            window.as_millis(),
+                            timeout.as_millis()
+                        ),
+                        pacing.lenient_waits,
+                    )?;
+                }
+            }
+            InputAction::WaitForColor {
+                area,
+                color,
+                tolerance,
+                timeout,
+            } => {
+                let source = session.source.as_deref().ok_or_else(|| {
+                    anyhow::anyhow!("wait_for:pixel and wait_for:region can only be used in capture input sequences")
+                })?;
+                if let Some(last) =
+                    wait_for_color(source, *area, *color, *tolerance, *timeout, session).await?
+                {
+                    wait_for_timed_out(
+                        if area.width == 1 && area.height == 1 {
+                            "pixel"
+                        } else {
+                            "region"
+                        },
+                        format!(
+                            "{} was {}, not within {} of {} after {}ms",
+                            area.describe(),
+                            format_color(last),
+                            tolerance,
+                            format_color(*color),
+                            timeout.as_millis()
+                        ),
+                        pacing.lenient_waits,
+                    )?;
+                }
+            }
+            InputAction::Screenshot { label } => {
+                let (Some(source), Some(output_name)) =
+                    (session.source.clone(), session.output_name.as_ref())
+                else {
+                    return Err(anyhow::anyhow!(
+                        "screenshot can only be used in capture input sequences"
+                    ));
+                };
+                let path = {
+                    let mut state = session.lock();
+                    let name = match label {
+                        Some(label) => label.clone(),
+                        None => (state.screenshots.len() + 1).to_string(),
+                    };
+                    let path = session.output_file(&format!("{}_{}.png", output_name, name));
+                    // Reserved before saving, so concurrent tracks number theirs apart
+                    state.screenshots.push(path.clone());
+                    path
+                };
+                // Captured and encoded off the async workers, so frames scheduled for the
+                // GIF at the same time aren't held up
+                tokio::task::spawn_blocking({
+                    let path = path.clone();
+                    move || -> Result<()> {
+                        source
+                            .capture()?
+                            .save(&path)
+                            .map_err(|e| anyhow::anyhow!("Failed to save {}: {}", path, e))
+                    }
+                })
+                .await
+                .map_err(|e| anyhow::anyhow!("Screenshot task failed: {}", e))??;
+                println!("Saved screenshot {}", path);
+            }
+            InputAction::Mash { key, duration, hz } => {
+                // Taps are scheduled from the start rather than after the previous one,
+                // so the time spent sending keys doesn't add up over a long mash
+                let start = Instant::now();
+                let end = start + *duration;
+                let period = Duration::from_secs(1) / *hz;
+                let mut tap_start = start;
+                while tap_start < end && !session.interrupted() {
+                    session.lock().press_combo(key)?;
+                    sleep_until((tap_start + period / 2).min(end)).await;
+                    session.lock().release_combo(key)?;
+                    tap_start += period;
+                    sleep_until(tap_start.min(end)).await;
+                }
+            }
+            InputAction::Fuzz { presses, .. } => {
+                // Scheduled from the start like mash:, so the stream lasts its duration
+                let mut press_start = Instant::now();
+                for (key, hold, gap) in presses {
+                    if session.interrupted() {
+                        break;
+                    }
+                    session.lock().press_combo(key)?;
+                    sleep_until(press_start + *hold).await;
+                    session.lock().release_combo(key)?;
+                    press_start += *hold + *gap;
+                    sleep_until(press_start).await;
+                }
+            }
+            InputAction::Mouse { button, x, y } => {
+                session.lock().backend().mouse(*button, *x, *y)?;
+            }
+            InputAction::AtFrame { frame } => {
+                let due = session.frame_time(*frame).ok_or_else(|| {
+                    anyhow::anyhow!("at:{} can only be used while frames are captured", frame)
+                })?;
+                let now = Instant::now();
+                if now > due {
+                    println!(
+                        "Warning: at:{} is {}ms late, sending its step now",
+                        frame,
+                        (now - due).as_millis()
+                    );
+                } else {
+                    sleep_until(due).await;
+                }
+            }
+            InputAction::Text { text } => {
+                session.lock().backend().text(text)?;
+            }
+        }
+        if sends_input {
+            last_input = Some(Instant::now());
+        }
+        if let Some((phase, steps)) = log {
+            let mut state = session.lock();
+            if let Some(input_log) = state.input_log.as_mut() {
+                input_log.push(LoggedInput {
+                    phase: phase.to_string(),
+                    step: steps.get(index).cloned().unwrap_or_default(),
+                    keys: action_keys(action),
+                    start,
+                    end: Instant::now(),
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The keys an action presses, for the input timeline
+fn action_keys(action: &InputAction) -> Option<String> {
+    match action {
+        InputAction::Press { key, .. }
+        | InputAction::KeyDown { key }
+        | InputAction::KeyUp { key }
+        | InputAction::Mash { key, .. } => Some(key.to_string()),
+        InputAction::Chord { keys, .. } => Some(
+            keys.iter()
+                .map(|&key| key_name(key))
+                .collect::<Vec<_>>()
+                .join("+"),
+        ),
+        InputAction::Fuzz { presses, .. } => {
+            let mut keys: Vec<String> = Vec::new();
+            for (key, _, _) in presses {
+                let key = key.to_string();
+                if !keys.contains(&key) {
+                    keys.push(key);
+                }
+            }
+            Some(keys.join(", "))
+        }
+        _ => None,
+    }
+}
+
+/// Makes sure the mGBA window `window` finds has focus, bringing it to the front if
+/// another window took it. Returns whether mGBA ended up focused.
+async fn focus_mgba(window: &WindowQuery) -> Result<bool> {
+    if mgba_has_focus(window)? {
+        return Ok(true);
+    }
+    debug!("mGBA is not focused, bringing it to the front");
+    focus_mgba_window(window)?;
+    sleep(Duration::from_millis(FOCUS_SETTLE_MS)).await;
+    mgba_has_focus(window)
+}
+
+/// Sends an input phase to the mGBA window `window` finds on its own input session,
+/// refusing to unless mGBA has focus with `require_focus`
+async fn send_to_mgba(
+    actions: &[InputAction],
+    window: &WindowQuery,
+    require_focus: bool,
+) -> Result<()> {
+    if actions.is_empty() {
+        return Ok(());
+    }
+    prepare_mgba_for_input(window, require_focus).await?;
+    execute_input_sequence(actions, window).await
+}
+
+/// Sends the input of the capture phase `phase` to mGBA as part of `session`
+async fn send_to_mgba_in(
+    session: &InputSession,
+    phase: &str,
+    sequence: &InputSequence,
+) -> Result<()> {
+    if sequence.is_empty() {
+        return Ok(());
+    }
+    if session.needs_focus() {
+        prepare_mgba_for_input(&session.window(), session.pacing.require_focus).await?;
+    }
+    session.run_sequence(phase, sequence).await
+}
+
+/// Sends concurrent input tracks to mGBA as part of `session` and waits for all of them,
+/// cancelling them together on shutdown. Fails with the first track that failed.
+async fn send_tracks_to_mgba(
+    session: &InputSession,
+    tracks: &[InputSequence],
+    shutdown: &Arc<AtomicBool>,
+) -> Result<()> {
+    if tracks.is_empty() {
+        return Ok(());
+    }
+    if session.needs_focus() {
+        prepare_mgba_for_input(&session.window(), session.pacing.require_focus).await?;
+    }
+
+    let mut running = tokio::task::JoinSet::new();
+    for (index, sequence) in tracks.iter().enumerate() {
+        let session = session.clone();
+        let sequence = sequence.clone();
+        let phase = match tracks.len() {
+            1 => "during_capture".to_string(),
+            _ => format!("during_capture track {}", index + 1),
+        };
+        running.spawn(async move { session.run_sequence(&phase, &sequence).await });
+    }
+
+    let mut result = Ok(());
+    loop {
+        tokio::select! {
+            finished = running.join_next() => match finished {
+                None => break,
+                Some(Ok(Ok(()))) => {}
+                Some(Err(e)) if e.is_cancelled() => {}
+                Some(Ok(Err(e))) => {
+                    if result.is_ok() {
+                        result = Err(e);
+                    }
+                }
+                Some(Err(e)) => {
+                    if result.is_ok() {
+                        result = Err(anyhow::anyhow!("Input track panicked: {}", e));
+                    }
+                }
+            },
+            // Keys held by cancelled tracks stay counted in the session until it finishes
+            _ = sleep(Duration::from_millis(100)) => {
+                if shutdown.load(Ordering::Relaxed) && result.is_ok() {
+                    running.abort_all();
+                    result = Err(anyhow::anyhow!("interrupted"));
+                }
+            }
+        }
+    }
+    result
+}
+
+/// Carries out the calls of a during-capture script on the capture's input session. Only
+/// scripts call it, which builds without the `scripting` feature can't load.
+#[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+struct CaptureScriptHost {
+    session: InputSession,
+    key_mappings: GbaKeyMappings,
+    runtime: tokio::runtime::Handle,
+    /// Set once the last frame is captured, which ends the script
+    done: Arc<AtomicBool>,
+    /// The screen when `frame_index` returned each index, for `screen_changed_since`
+    snapshots: std::sync::Mutex<HashMap<i64, RgbaImage>>,
+}
+
+#[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+impl CaptureScriptHost {
+    fn new(session: &InputSession, key_mappings: &GbaKeyMappings, done: &Arc<AtomicBool>) -> Self {
+        Self {
+            session: session.clone(),
+            key_mappings: key_mappings.clone(),
+            runtime: tokio::runtime::Handle::current(),
+            done: done.clone(),
+            snapshots: std::sync::Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn source(&self) -> Result<&dyn FrameSource> {
+        self.session
+            .source
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("Scripts can only run during a capture"))
+    }
+}
+
+impl scripting::ScriptHost for CaptureScriptHost {
+    fn press(&self, key: &str, ms: Option<u64>) -> Result<()> {
+        if self.interrupted() {
+            return Ok(());
+        }
+        let action = InputAction::Press {
+            key: parse_key(key, &self.key_mappings)?,
+            duration: ms.map(Duration::from_millis),
+        };
+        let step = match ms {
+            Some(ms) => format!("{}:{}", key, ms),
+            None => key.to_string(),
+        };
+        self.runtime.block_on(self.session.run_logged(
+            &[action],
+            Some(("during_capture script", std::slice::from_ref(&step))),
+        ))
+    }
+
+    fn wait(&self, ms: u64) {
+        let end = Instant::now() + Duration::from_millis(ms);
+        while !self.interrupted() {
+            let Some(left) = end.checked_duration_since(Instant::now()) else {
+                break;
+            };
+            std::thread::sleep(left.min(Duration::from_millis(WAIT_FOR_POLL_MS)));
+        }
+    }
+
+    fn wait_stable(&self, ms: u64) -> Result<bool> {
+        let source = self.source()?;
+        self.runtime.block_on(async {
+            tokio::select! {
+                stable = wait_for_stable(
+                    source,
+                    Duration::from_millis(ms),
+                    Duration::from_millis(DEFAULT_WAIT_FOR_TIMEOUT_MS),
+                    &self.session,
+                ) => stable,
+                _ = async {
+                    while !self.done.load(Ordering::Relaxed) {
+                        sleep(Duration::from_millis(WAIT_FOR_POLL_MS)).await;
+                    }
+                } => Ok(true),
+            }
+        })
+    }
+
+    fn frame_index(&self) -> Result<i64> {
+        let (start, interval) = {
+            let state = self.session.lock();
+            let start = state
+                .capture_start
+                .ok_or_else(|| anyhow::anyhow!("The capture has not started"))?;
+            (start, state.frame_interval)
+        };
+        let index = (start.elapsed().as_nanos() / interval.as_nanos().max(1)) as i64;
+        let frame = self.source()?.capture()?;
+        self.snapshots
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(index, frame);
+        Ok(index)
+    }
+
+    fn screen_changed_since(&self, frame: i64) -> Result<bool> {
+        let current = self.source()?.capture()?;
+        let snapshots = self.snapshots.lock().unwrap_or_else(|e| e.into_inner());
+        let snapshot = snapshots.get(&frame).ok_or_else(|| {
+            anyhow::anyhow!("Frame {} was never returned by frame_index()", frame)
+        })?;
+        Ok(changed_pixel_fraction(snapshot, &current) > STABLE_PIXEL_TOLERANCE)
+    }
+
+    fn interrupted(&self) -> bool {
+        self.done.load(Ordering::Relaxed) || self.session.interrupted()
+    }
+}
+
+/// Key presses go to whichever window is focused, so mGBA is focused before input is
+/// sent; if that fails the input is refused with `require_focus` (`--require-focus`), and
+/// otherwise sent anyway after a warning.
+async fn prepare_mgba_for_input(window: &WindowQuery, require_focus: bool) -> Result<()> {
+    let problem = match focus_mgba(window).await {
+        Ok(true) => None,
+        Ok(false) => Some("another window has focus".to_string()),
+        Err(e) => Some(format!("could not focus it: {}", e)),
+    };
+    if let Some(problem) = problem {
+        if require_focus {
+            return Err(anyhow::anyhow!(
+                "Refusing to send input because mGBA is not focused ({})",
+                problem
+            ));
+        }
+        println!(
+            "WARNING: mGBA is not focused ({}); input goes to whichever window has focus! Pass --require-focus to stop instead.",
+            problem
+        );
+    }
+    Ok(())
+}
+
+/// Runs this tool again with the same arguments on a new Xvfb display for `--headless`,
+/// and exits the way that run exits once the display is stopped. Only the new process
+/// is pointed at the display, so nothing changes the environment of a running process.
+async fn run_on_virtual_display(args: &Args) -> Result<()> {
+    if args.keep_alive {
+        return Err(anyhow::anyhow!(
+            "--keep-alive can't be combined with --headless, whose virtual display is stopped when the run ends"
+        ));
+    }
+    let display = VirtualDisplay::start(&args.headless_resolution)?;
+    let mut command = Command::new(std::env::current_exe()?);
+    // Ahead of a subcommand, whose own arguments follow it
+    command
+        .arg("--on-virtual-display")
+        .args(std::env::args_os().skip(1));
+    display.run_on(&mut command);
+
+    // Ctrl+C reaches the run on the display too, which shuts down on its own; this
+    // process waits for it rather than leaving it without a display
+    tokio::spawn(async {
+        loop {
+            if signal::ctrl_c().await.is_err() {
+                return;
+            }
+        }
+    });
+    let status = tokio::task::spawn_blocking(move || command.status())
+        .await?
+        .map_err(|e| anyhow::anyhow!("Failed to start the run on the virtual display: {}", e))?;
+    drop(display);
+    // The run reported its own errors
+    std::process::exit(status.code().unwrap_or(1));
+}
+
+/// Main entry point: validates directory, discovers binaries, and captures GIFs
+#[tokio::main]
+async fn main() -> Result<()> {
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    args.duration_given =
+        matches.value_source("duration") == Some(clap::parser::ValueSource::CommandLine);
+    args.fps_given = matches.value_source("fps") == Some(clap::parser::ValueSource::CommandLine);
+    args.format_given =
+        matches.value_source("format") == Some(clap::parser::ValueSource::CommandLine);
+    args.marker_timeout_given =
+        matches.value_source("marker_timeout") == Some(clap::parser::ValueSource::CommandLine);
+    args.match_by_given =
+        matches.value_source("match_by") == Some(clap::parser::ValueSource::CommandLine);
+    // Captures and calibration need a display; config commands run here as they are
+    let uses_display = !args.migrate_config
+        && !args.save_config
+        && args.print_config.is_none()
+        && matches!(args.command, None | Some(SubCommand::Calibrate(_)));
+    if args.headless && !args.on_virtual_display && !args.synthetic_frames && uses_display {
+        return run_on_virtual_display(&args).await;
+    }
+    if args.duration_given && args.extend_to_sequence {
+        println!(
+            "Warning: --duration {} wins over --extend-to-sequence",
+            args.duration
+        );
+    }
+    if let Some(duration) = args.fuzz {
+        args.during_capture = vec![CliSequence::all(format!(
+            "fuzz:{}:{}",
+            duration, args.fuzz_rate
+        ))];
+    }
+    let mut external_mappings = ExternalKeyMappings {
+        cli: cli_key_mappings(&args.map)?,
+        prefer_mgba: args.mappings_from_mgba,
+        ..Default::default()
+    };
+    // Picked once, so every sequence and the input jitter use the seed that is printed
+    args.seed = Some(args.seed.unwrap_or_else(fuzz::random_seed));
+    if let Some(path) = args.during_capture_file.take() {
+        // Relative to where the tool was started, unlike paths in capture.json
+        let path = std::path::absolute(&path)
+            .map_err(|e| anyhow::anyhow!("Invalid script path {}: {}", path.display(), e))?;
+        args.during_capture = vec![CliSequence::all(format!(
+            "{}{}",
+            SCRIPT_PREFIX,
+            path.display()
+        ))];
+    }
+    // Live input runs alongside the configured sequences rather than replacing them
+    let is_stdin = |sequence: &CliSequence| sequence.binary.is_none() && sequence.sequence == "-";
+    let read_stdin = args.during_capture.iter().any(is_stdin);
+    args.during_capture.retain(|sequence| !is_stdin(sequence));
+    if args.no_input {
+        args.input_pipe = None;
+        if args.log_inputs {
+            println!("Note: --log-inputs writes nothing with --no-input");
+            args.log_inputs = false;
+        }
+    }
+    let live_input = match (&args.input_pipe, read_stdin && !args.no_input) {
+        (Some(_), true) => {
+            return Err(anyhow::anyhow!(
+                "Use either --during-capture - or --input-pipe, not both"
+            ))
+        }
+        (Some(path), false) => Some(Arc::new(LiveInput::pipe(path)?)),
+        (None, true) if args.kill_stale && !args.yes => {
+            return Err(anyhow::anyhow!(
+                "--during-capture - reads input from stdin, so --kill-stale can't ask for confirmation; add --yes"
+            ))
+        }
+        (None, true) => Some(Arc::new(LiveInput::stdin())),
+        (None, false) => None,
+    };
+    let interactive = if args.interactive {
+        if args.kill_stale && !args.yes {
+            return Err(anyhow::anyhow!(
+                "--interactive reads Enter from stdin, so --kill-stale can't ask for confirmation; add --yes"
+            ));
+        }
+        Some(Interactive {
+            terminal: Arc::new(LiveInput::stdin()),
+            countdown: args.countdown,
+            start_on_enter: args.start_on_enter,
+        })
+    } else {
+        None
+    };
+    VERBOSE.store(args.verbose, Ordering::Relaxed);
+    enable_dpi_awareness();
+
+    // Set up signal handling for graceful shutdown
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let shutdown_clone = shutdown.clone();
+
+    tokio::spawn(async move {
+        signal::ctrl_c().await.expect("Failed to listen for ctrl+c");
+        println!("\nReceived Ctrl+C, shutting down gracefully...");
+        shutdown_clone.store(true, Ordering::Relaxed);
+    });
+
+    if let Some(SubCommand::BindGamepad) = &args.command {
+        return bind_gamepad(&shutdown).await;
+    }
+
+    // Use current directory if no project directory is provided
+    let project_dir = args
+        .project_dir
+        .clone()
+        .unwrap_or_else(|| std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")));
+
+    if !project_dir.exists() {
+        return Err(anyhow::anyhow!(
+            "Directory does not exist: {}",
+            project_dir.display()
+        ));
+    }
+
+    if !is_agbrs_project_dir(&project_dir) {
+        return Err(anyhow::anyhow!(
+            "Directory does not appear to be an agbrs project: {}",
+            project_dir.display()
+        ));
+    }
+
+    if args.migrate_config {
+        let config_file =
+            ConfigFile::find(&project_dir, args.config.as_deref())?.ok_or_else(|| {
+                anyhow::anyhow!(
+                    "{} has no {} or {} to migrate",
+                    project_dir.display(),
+                    config_file::TOML_NAME,
+                    config_file::JSON_NAME
+                )
+            })?;
+        let version = config_file.migrate()?;
+        if version == config_file::CONFIG_VERSION {
+            println!(
+                "{} is at version {}, the current version of the config format",
+                config_file.name(),
+                version
+            );
+        } else {
+            println!(
+                "Migrated {} from version {} to version {}",
+                config_file.name(),
+                version,
+                config_file::CONFIG_VERSION
+            );
+        }
+        return Ok(());
+    }
+
+    if let Some(SubCommand::Init(init)) = &args.command {
+        return init_config(&project_dir, &args, init);
+    }
+    if args.save_config {
+        return save_cli_config(&project_dir, &args);
+    }
+    if let Some(SubCommand::CheckConfig(check)) = &args.command {
+        return run_check_config(&project_dir, &args, check.format);
+    }
+    if let Some(binary) = &args.print_config {
+        return print_config(&project_dir, &args, binary.as_deref());
+    }
+
+    let frame_count = frame_timing(args.fps, args.duration).frame_count;
+
+    println!("Using agbrs project at: {}", project_dir.display());
+    if args.extend_to_sequence && !args.duration_given {
+        println!(
+            "GIF settings: {}fps, as long as the during-capture input plus {}ms",
+            args.fps, args.sequence_tail
+        );
+    } else {
+        println!(
+            "GIF settings: {}fps, {}s duration, {} frames",
+            args.fps, args.duration, frame_count
+        );
+    }
+
+    // Load capture configuration from capture.toml or capture.json if it exists
+    let capture_config = load_capture_config(
+        &project_dir,
+        args.config.as_deref(),
+        ParseOptions::new(&args),
+    )?;
+
+    let mut binaries = discover_binaries(&project_dir)?;
+    if binaries.is_empty() {
+        return Err(anyhow::anyhow!(
+            "No binary files found in {}/src/bin/ or {}/src/main.rs",
+            project_dir.display(),
+            project_dir.display()
+        ));
+    }
+
+    order_binaries(&mut binaries, args.order, &capture_config);
+    println!("Found {} binaries: {}", binaries.len(), binaries.join(", "));
+    check_cli_sequences(&args, &binaries)?;
+    warn_about_ambiguous_patterns(&binaries, &capture_config);
+    // Recording and calibrating build and launch just the binary they use
+    let single_binary = match &args.command {
+        Some(SubCommand::Record(record)) => Some(("record", &record.binary)),
+        Some(SubCommand::Calibrate(calibrate)) => Some(("calibrate", &calibrate.binary)),
+        _ => None,
+    };
+    if let Some((command, binary)) = single_binary {
+        if args.synthetic_frames {
+            return Err(anyhow::anyhow!(
+                "{} needs mGBA and can't be combined with --synthetic-frames",
+                command
+            ));
+        }
+        if !binaries.contains(binary) {
+            return Err(anyhow::anyhow!(
+                "No binary named {} (found: {})",
+                binary,
+                binaries.join(", ")
+            ));
+        }
+        binaries = vec![binary.clone()];
+    }
+
+    // Binaries named with --bin are the only ones built and captured
+    if let Some(unknown) = args.bins.iter().find(|bin| !binaries.contains(bin)) {
+        return Err(anyhow::anyhow!(
+            "No binary named {} (found: {})",
+            unknown,
+            binaries.join(", ")
+        ));
+    }
+    if single_binary.is_none() && !args.bins.is_empty() {
+        binaries.retain(|binary| args.bins.contains(binary));
+    }
+
+    // Binaries with `skip` are neither built nor captured, unless asked for by name
+    let mut skipped = Vec::new();
+    if single_binary.is_none() && args.bins.is_empty() && !args.force_all {
+        binaries.retain(|binary| {
+            let skip = binary_setting(binary, &capture_config, |binary| binary.skip)
+                .is_some_and(|(skip, _)| skip);
+            if skip {
+                println!("Skipping {} (skip is set in the config file)", binary);
+                skipped.push(binary.clone());
+            }
+            !skip
+        });
+        if binaries.is_empty() {
+            return Err(anyhow::anyhow!(
+                "All binaries have skip set in the config file; use --bin or --force-all to capture them"
+            ));
+        }
+    }
+
+    let macros = capture_config
+        .as_ref()
+        .and_then(|config| config.settings.as_ref())
+        .and_then(|settings| settings.macros.clone())
+        .unwrap_or_default();
+    let config_settings = capture_config
+        .as_ref()
+        .and_then(|config| config.settings.as_ref());
+    let output_root = OutputRoot::resolve(
+        &project_dir,
+        args.output_dir.as_deref(),
+        config_settings
+            .and_then(|settings| settings.output.as_ref())
+            .and_then(|output| output.dir.as_deref()),
+        args.allow_external_output,
+    )?;
+    let input_jitter_ms = args
+        .input_jitter
+        .or_else(|| config_settings.and_then(|settings| settings.input_jitter_ms))
+        .unwrap_or(0);
+    let reuse_settings = capture_config
+        .as_ref()
+        .and_then(|config| config.settings.as_ref())
+        .and_then(|settings| settings.reuse_emulator.clone())
+        .unwrap_or_default();
+    let emulator_keys = capture_config
+        .as_ref()
+        .and_then(|config| config.settings.as_ref())
+        .and_then(|settings| settings.emulator_keys.clone())
+        .unwrap_or_default();
+    // Every input sequence is parsed before anything is set up or built, so a typo in the
+    // last binary doesn't surface after the others were captured
+    let mut capture_plans = plan_captures(
+        &binaries,
+        &capture_config,
+        &args,
+        &mut external_mappings,
+        &project_dir,
+        &output_root,
+    )?;
+    if args.interactive {
+        println!("Interactive mode: you play in mGBA, input sequences are not sent");
+    }
+    let fuzzed = capture_plans
+        .values()
+        .flatten()
+        .flatten()
+        .any(|(_, phases)| phases.fuzz_seed().is_some());
+    let parse_options = ParseOptions::new(&args);
+    let seed = parse_options.fuzz_seed;
+    if fuzzed {
+        println!(
+            "Fuzz seed: {} (replay the same input with --seed {})",
+            seed, seed
+        );
+    } else if input_jitter_ms > 0 && !args.interactive {
+        println!(
+            "Input jitter: up to {}ms, seed {} (replay the same timing with --seed {})",
+            input_jitter_ms, seed, seed
+        );
+    }
+
+    let settings = capture_config
+        .as_ref()
+        .and_then(|config| config.settings.as_ref());
+    // An emulator started directly for every binary makes the cargo runner irrelevant
+    let emulator_path = args.emulator_path.clone().or_else(|| {
+        settings
+            .and_then(|settings| settings.emulator.as_ref())
+            .and_then(|emulator| emulator.path.clone())
+    });
+
+    // The cargo runner tells which emulator `cargo run` will start
+    let runner = CargoConfig::load(&project_dir)?.and_then(|config| config.runner());
+    let runner_emulator = runner.as_ref().and_then(Runner::emulator);
+    if let Some(runner) = &runner {
+        if !args.synthetic_frames
+            && emulator_path.is_none()
+            && runner.locate(&project_dir).is_none()
+        {
+            return Err(anyhow::anyhow!(
+                "The cargo runner '{}' set in .cargo/config.toml was not found; install it or put it on PATH",
+                runner.program
+            ));
+        }
+        debug!("Cargo runner: {}", runner.program);
+    }
+
+    // Compile the window title pattern up front so an invalid one fails before building.
+    // A wrapper script as runner can name the window it ends up opening.
+    let title_pattern = args
+        .window_title_pattern
+        .clone()
+        .or_else(|| settings.and_then(|settings| settings.window_title_pattern.clone()))
+        .or_else(|| {
+            settings
+                .and_then(|settings| settings.emulator.as_ref())
+                .and_then(|emulator| emulator.window_title.clone())
+        })
+        .or_else(|| {
+            settings
+                .and_then(|settings| settings.runner_window_pattern.clone())
+                .filter(|_| runner.is_some() && runner_emulator.is_none())
+        })
+        .unwrap_or_else(|| DEFAULT_TITLE_PATTERN.to_string());
+    let title_regex = compile_title_pattern(&title_pattern)?;
+    let launched_emulator = match &emulator_path {
+        Some(path) => program_emulator(path),
+        None => runner_emulator,
+    };
+    let emulator = match (args.emulator, launched_emulator) {
+        (Emulator::Auto, Some(emulator)) => emulator,
+        (emulator, _) => emulator,
+    };
+    if let Some(name) = emulator.to_possible_value() {
+        debug!("Emulator: {}", name.get_name());
+    }
+    // Looks for any mGBA window, as binaries configure their own queries
+    let run_window = WindowQuery::new(emulator, args.match_by, title_regex)
+        .on_virtual_display(args.on_virtual_display);
+    debug!("Window detection order: {}", run_window.detection_order());
+
+    if !args.synthetic_frames {
+        check_permissions()?;
+        handle_stale_emulators(&run_window, args.kill_stale, args.yes).await?;
+    }
+
+    // Pick the capture backend up front so an unavailable one fails before building
+    let window_capture = if args.synthetic_frames {
+        None
+    } else {
+        wayland::check_session(args.capture_backend)?;
+        let (backend, capture) = open_window_capture(args.capture_backend)?;
+        println!("Capture backend: {}", backend.name());
+        Some(capture)
+    };
+    // Created before mGBA starts, so it finds the controller already connected
+    let recording = matches!(args.command, Some(SubCommand::Record(_)));
+    let gamepad = if args.input_backend == InputBackendKind::Gamepad
+        && !args.synthetic_frames
+        && !recording
+    {
+        let gamepad = Arc::new(VirtualGamepad::create()?);
+        println!(
+            "Input backend: virtual gamepad '{}' (bind it in mGBA once with: agbrs-capture bind-gamepad)",
+            gamepad::DEVICE_NAME
+        );
+        Some(gamepad)
+    } else {
+        None
+    };
+
+    output_root.create()?;
+    println!(
+        "Output directory: {} ({})",
+        output_root.dir.display(),
+        output_root.source
+    );
+
+    // Binaries can run under another emulator and look for a different window, checked
+    // here so a bad pattern fails early
+    let mut emulator_profiles = HashMap::new();
+    for binary in &binaries {
+        let profile = get_effective_emulator_profile(
+            binary,
+            &capture_config,
+            &args,
+            &title_pattern,
+            runner_emulator,
+        )?;
+        emulator_profiles.insert(binary.clone(), profile);
+    }
+
+    // Emulators started directly are looked up before building, like the cargo runner
+    let mut emulator_commands = HashMap::new();
+    if !args.synthetic_frames {
+        for binary in &binaries {
+            let Some((program, emulator_args)) =
+                get_effective_emulator_command(binary, &capture_config, &args)
+            else {
+                continue;
+            };
+            let path = locate_program(&program, &project_dir).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "The emulator '{}' configured for {} was not found; check the path or put it on PATH",
+                    program,
+                    binary
+                )
+            })?;
+            debug!(
+                "{} is started with {} {}",
+                binary,
+                path.display(),
+                emulator_args.join(" ")
+            );
+            emulator_commands.insert(binary.clone(), (path, emulator_args));
+        }
+    }
+
+    // Savestates are checked before building too
+    let mut savestates = HashMap::new();
+    for binary in &binaries {
+        let Some(savestate) = binary_configs(binary, &capture_config)
+            .find_map(|binary_config| binary_config.savestate.as_ref())
+        else {
+            continue;
+        };
+        let path = project_dir.join(savestate);
+        if !path.is_file() {
+            return Err(anyhow::anyhow!(
+                "Savestate {} for {} does not exist",
+                path.display(),
+                binary
+            ));
+        }
+        savestates.insert(binary.clone(), path);
+    }
+
+    // Save files to provide are checked before building as well
+    let mut save_policies = HashMap::new();
+    for binary in &binaries {
+        let save_file = binary_configs(binary, &capture_config)
+            .find_map(|binary_config| binary_config.save_file.as_ref());
+        let clean_save = binary_configs(binary, &capture_config)
+            .find_map(|binary_config| binary_config.clean_save)
+            .unwrap_or(false);
+        let policy = match save_file {
+            Some(save_file) => {
+                let path = project_dir.join(save_file);
+                if !path.is_file() {
+                    return Err(anyhow::anyhow!(
+                        "Save file {} for {} does not exist",
+                        path.display(),
+                        binary
+                    ));
+                }
+                SavePolicy::Provide(path)
+            }
+            None if clean_save => SavePolicy::Clean,
+            None => SavePolicy::Keep,
+        };
+        save_policies.insert(binary.clone(), policy);
+    }
+
+    let required_features = cargo_manifest::required_features(&project_dir)?;
+    let builds = binaries
+        .iter()
+        .map(|binary| {
+            let build = BinaryBuild {
+                features: get_effective_features(binary, &capture_config, &required_features),
+                env: get_effective_env(binary, &capture_config)?
+                    .into_iter()
+                    .map(|(name, (value, _))| (name, value))
+                    .collect(),
+            };
+            Ok((binary.clone(), build))
+        })
+        .collect::<Result<HashMap<_, _>>>()?;
+
+    let rom_artifacts = if args.synthetic_frames {
+        println!("Using synthetic frames: skipping build and emulator launch\n");
+        HashMap::new()
+    } else {
+        println!("Setting up GBA development environment...");
+        setup_gba_target().await?;
+        println!("Pre-building all GBA binaries...");
+        let rom_artifacts = prebuild_binaries(&binaries, &project_dir, &builds).await?;
+        println!("All binaries built successfully!\n");
+        rom_artifacts
+    };
+
+    let mut direct_launches = emulator_commands
+        .into_iter()
+        .map(|(binary, (program, emulator_args))| {
+            let rom = rom_artifacts
+                .get(&binary)
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("No ROM artifact was found for {}", binary))?;
+            Ok((
+                binary,
+                DirectLaunch {
+                    program,
+                    args: emulator_args,
+                    rom,
+                },
+            ))
+        })
+        .collect::<Result<HashMap<_, _>>>()?;
+
+    // mGBA loads the input script from its command line, which only emulators started
+    // directly can be given
+    let mut script_binaries = HashSet::new();
+    let script_server = if args.input_backend == InputBackendKind::MgbaScript
+        && !args.synthetic_frames
+        && !recording
+    {
+        let server = ScriptServer::start()?;
+        for binary in &binaries {
+            match direct_launches.get_mut(binary) {
+                Some(direct) if mgba_script::supports_scripts(&direct.program) => {
+                    direct.args.extend(server.arguments());
+                    script_binaries.insert(binary.clone());
+                }
+                Some(direct) => println!(
+                    "Warning: {} can't load scripts from its command line (mGBA 0.10 or newer is needed); {} uses keyboard input",
+                    direct.program.display(),
+                    binary
+                ),
+                None => println!(
+                    "Warning: {} is started through the cargo runner, which can't load mGBA's input script; it uses keyboard input. Start mGBA directly with --emulator-path or settings.emulator",
+                    binary
+                ),
+            }
+        }
+        Some(server)
+    } else {
+        None
+    };
+
+    let pause_actions = parse_key_combo(&emulator_keys.pause)?;
+    let fast_forward_key = parse_raw_key(&emulator_keys.fast_forward)?;
+    let load_state_actions = parse_key_combo(&emulator_keys.load_state)?;
+    let popup_patterns = capture_config
+        .as_ref()
+        .and_then(|config| config.settings.as_ref())
+        .and_then(|settings| settings.dismiss_popups.clone())
+        .unwrap_or_default();
+
+    let load_rom_actions = if args.reuse_emulator {
+        println!("Reusing a single mGBA instance across binaries");
+        parse_key_combo(&reuse_settings.load_rom_shortcut)?
+    } else {
+        Vec::new()
+    };
+
+    if args.keep_alive && binaries.len() > 1 && !args.reuse_emulator {
+        return Err(anyhow::anyhow!(
+            "--keep-alive leaves a single emulator running, so it needs a single binary or --reuse-emulator ({} binaries found)",
+            binaries.len()
+        ));
+    }
+
+    let emulator_log = EmulatorLog::new(args.inherit_child_output);
+    let managed_config = args.managed_emulator_config.then_some(ManagedConfig {
+        scale: args.emulator_scale,
+        title_stats: args.sync_to_emulator,
+    });
+    let window_waits = binaries
+        .iter()
+        .map(|binary| {
+            let wait = get_effective_window_wait(binary, &capture_config, &args)?;
+            Ok((binary.clone(), wait))
+        })
+        .collect::<Result<HashMap<_, _>>>()?;
+    let launch_context = LaunchContext {
+        config_path: args.config.as_deref(),
+        project_dir: &project_dir,
+        load_rom_actions: &load_rom_actions,
+        reuse_settings: &reuse_settings,
+        popup_patterns: &popup_patterns,
+        direct_launches: &direct_launches,
+        window_waits: &window_waits,
+        builds: &builds,
+        log: &emulator_log,
+        software_renderer: AtomicBool::new(args.force_software_renderer),
+        managed_config,
+        // Left behind with --keep-alive, for the emulator still running after the run
+        config_home: ConfigHome::new(args.keep_alive),
+        detach_output: AtomicBool::new(false),
+        require_focus: args.require_focus,
+        shutdown: &shutdown,
+    };
+    // The runner's command line is out of reach, so only a config home can carry settings
+    let runner_launches = direct_launches.len() < binaries.len();
+    if runner_launches && !mgba_config::overrides_supported() {
+        if args.force_software_renderer {
+            println!(
+                "Note: --force-software-renderer only applies to emulators started through the cargo runner on Linux; select the software renderer in {}",
+                DISPLAY_DRIVER_SETTING
+            );
+        }
+        if args.managed_emulator_config {
+            println!(
+                "Note: --managed-emulator-config only applies to emulators started through the cargo runner on Linux; use --emulator-path to have it applied here"
+            );
+        }
+    }
+
+    if let Some(SubCommand::Record(record)) = &args.command {
+        let key_mappings =
+            get_effective_key_mappings(&record.binary, &capture_config, &external_mappings);
+        let window = emulator_profiles[&record.binary].window_query(
+            &record.binary,
+            rom_artifacts.get(&record.binary).map(PathBuf::as_path),
+        );
+        return record_input(record, &key_mappings, &window, &launch_context).await;
+    }
+    if let Some(SubCommand::Calibrate(calibrate)) = &args.command {
+        let key_mappings =
+            get_effective_key_mappings(&calibrate.binary, &capture_config, &external_mappings);
+        let window = emulator_profiles[&calibrate.binary].window_query(
+            &calibrate.binary,
+            rom_artifacts.get(&calibrate.binary).map(PathBuf::as_path),
+        );
+        let capture = window_capture
+            .clone()
+            .expect("a window capture is opened unless frames are synthetic");
+        let source: Arc<dyn FrameSource> =
+            Arc::new(WindowFrameSource::new(capture, window.clone()));
+        let device: Option<Arc<dyn ButtonDevice>> = match &script_server {
+            Some(server) if script_binaries.contains(&calibrate.binary) => {
+                Some(server.connection(SCRIPT_CONNECT_TIMEOUT)?)
+            }
+            _ => gamepad
+                .clone()
+                .map(|gamepad| gamepad as Arc<dyn ButtonDevice>),
+        };
+        let session = InputSession::new(
+            &shutdown,
+            source.clone(),
+            &window,
+            &output_root,
+            &calibrate.binary,
+            InputPacing {
+                require_focus: args.require_focus,
+                ..get_effective_input_pacing(&calibrate.binary, &capture_config)
+            },
+        );
+        if let Some(device) = device {
+            session.use_button_device(device, &key_mappings);
+        }
+        return calibrate_latency(calibrate, &key_mappings, &session, &launch_context).await;
+    }
+
+    // Saves replaced for captures; the user's own are restored when this is dropped, after
+    // the emulators using them are closed
+    let mut save_files = SaveFiles::default();
+
+    // mGBA instance kept running between binaries in --reuse-emulator mode, with the
+    // window query of the binary it was launched for
+    let mut shared_emulator: Option<(Child, WindowQuery)> = None;
+
+    // One row per GIF written, printed as a summary at the end of the run
+    let mut summary: Vec<(String, String)> = Vec::new();
+    // Captures whose ROM panicked, with the panic message
+    let mut failures: Vec<String> = Vec::new();
+    // Every file written, for the post-capture and post-run commands
+    let mut outputs: Vec<hooks::CaptureOutput> = Vec::new();
+
+    for (binary_index, binary) in binaries.iter().enumerate() {
+        // Check for shutdown signal before starting each binary
+        if shutdown.load(Ordering::Relaxed) {
+            println!("Shutdown requested, stopping capture process.");
+            break;
+        }
+
+        let parsed_launches = capture_plans.remove(binary).unwrap_or_default();
+        if parsed_launches.is_empty() {
+            println!(
+                "Skipping {}: no segment named '{}'\n",
+                binary,
+                args.segment.as_deref().unwrap_or_default()
+            );
+            continue;
+        }
+
+        println!("Capturing {}...", binary);
+        if !args.synthetic_frames {
+            let wait = window_waits[binary];
+            println!(
+                "Window wait: {} after launch ({}), then up to {} to find the window ({})",
+                wait_time::format(wait.startup_wait),
+                wait.startup_wait_source,
+                wait_time::format(wait.window_timeout),
+                wait.window_timeout_source
+            );
+        }
+        if args.no_input {
+            println!("Note: input to {} is suppressed (--no-input)", binary);
+        }
+        let save_policy = &save_policies[binary];
+        if !args.synthetic_frames {
+            println!("Save file: {}", save_policy.describe());
+        }
+
+        // mGBA titles name the loaded ROM, which tells its window apart from others
+        let profile = &emulator_profiles[binary];
+        let mut window =
+            profile.window_query(binary, rom_artifacts.get(binary).map(PathBuf::as_path));
+        debug!(
+            "Window detection order for {}: {}",
+            binary,
+            window.detection_order()
+        );
+
+        // Emulator output for this binary goes to its own log, quoted when the capture fails
+        let run_log = output_root.dir.join(format!("{}.run.log", binary));
+        let binary_cursor = emulator_log.line_count();
+        if !args.synthetic_frames {
+            emulator_log.write_to(&run_log)?;
+        }
+        let with_run_log = |e| run_log_error(e, &emulator_log, binary_cursor, &run_log);
+
+        let failures_before = failures.len();
+        let outputs_before = outputs.len();
+
+        // Runs once the binary is built, before its first emulator launch
+        let pre_capture_cmd = binary_setting(binary, &capture_config, |entry| {
+            entry.pre_capture_cmd.clone()
+        });
+        if let Some((hook, _)) = pre_capture_cmd {
+            if args.synthetic_frames {
+                println!(
+                    "Note: skipping pre_capture_cmd of {} with --synthetic-frames",
+                    binary
+                );
+            } else {
+                let ran = hooks::run(
+                    "pre_capture_cmd",
+                    &hook,
+                    &project_dir,
+                    &hook_env(binary, builds.get(binary).map(|build| &build.env)),
+                    get_effective_hook_timeout(binary, &capture_config)?,
+                    &emulator_log,
+                    &shutdown,
+                )
+                .await;
+                match ran {
+                    Ok(true) => {}
+                    Ok(false) => break,
+                    Err(e) => {
+                        let (failure, row) = hook_failure(
+                            "pre_capture_cmd",
+                            binary,
+                            e,
+                            with_run_log,
+                            &run_log,
+                            args.continue_on_error,
+                        )?;
+                        failures.push(failure);
+                        summary.push(row);
+                        println!();
+                        continue;
+                    }
+                }
+            }
+        }
+
+        let key_mappings = get_effective_key_mappings(binary, &capture_config, &external_mappings);
+        let frame_filter = args
+            .frame_filter
+            .clone()
+            .map(HookCommand::Shell)
+            .or_else(|| {
+                binary_setting(binary, &capture_config, |entry| {
+                    entry.frame_filter_cmd.clone()
+                })
+                .map(|(hook, _)| hook)
+            })
+            .map(|command| -> Result<FrameFilter> {
+                Ok(FrameFilter {
+                    command,
+                    project_dir: project_dir.clone(),
+                    env: hook_env(binary, builds.get(binary).map(|build| &build.env)),
+                    timeout: get_effective_hook_timeout(binary, &capture_config)?,
+                    shutdown: shutdown.clone(),
+                })
+            })
+            .transpose()?;
+        let input_pacing = InputPacing {
+            jitter: Duration::from_millis(input_jitter_ms),
+            jitter_seed: seed,
+            require_focus: args.require_focus,
+            lenient_waits: args.lenient_waits,
+            ..get_effective_input_pacing(binary, &capture_config)
+        };
+        let loop_count = get_effective_loop(binary, &capture_config, &args)?;
+        let post = get_effective_post(binary, &capture_config, &args)?;
+        if !post.is_empty() {
+            println!("Post-processing for {}: {}", binary, post.describe());
+        }
+        let mut fast_forward_ms =
+            get_effective_fast_forward(binary, &capture_config, args.fast_forward)
+                .filter(|&ms| ms > 0);
+        if fast_forward_ms.is_some() && !profile.fast_forward {
+            println!(
+                "Note: not fast-forwarding {}: its emulator has no fast-forward key (emulator.fast_forward = false)",
+                binary
+            );
+            fast_forward_ms = None;
+        }
+        // Synthetic runs have no emulator output to watch
+        let (start_marker, stop_marker) = if args.synthetic_frames {
+            (None, None)
+        } else {
+            get_effective_markers(binary, &capture_config, &args)
+        };
+
+        for (launch_index, parsed_jobs) in parsed_launches.iter().enumerate() {
+            if shutdown.load(Ordering::Relaxed) {
+                break;
+            }
+
+            // With --keep-alive the emulator started last outlives this run; in
+            // --reuse-emulator mode that is the one shared instance
+            let keep_alive = args.keep_alive
+                && (args.reuse_emulator
+                    || (binary_index + 1 == binaries.len()
+                        && launch_index + 1 == parsed_launches.len()));
+            launch_context
+                .detach_output
+                .store(keep_alive, Ordering::Relaxed);
+
+            // Markers printed from launch on count towards the first capture
+            let mut launch_cursor = emulator_log.line_count();
+            let (source, mut child): (Arc<dyn FrameSource>, Option<Child>) = match window_capture {
+                None => {
+                    let (width, height) = GBA_SCREEN_SIZE;
+                    (Arc::new(SyntheticFrameSource::new(width, height)), None)
+                }
+                Some(ref window_capture) => {
+                    // Each launch starts from the same save, so variants are reproducible
+                    if !matches!(save_policy, SavePolicy::Keep) {
+                        let rom_path = rom_artifacts.get(binary).ok_or_else(|| {
+                            anyhow::anyhow!("No ROM artifact was found for {}", binary)
+                        })?;
+                        save_files
+                            .prepare(&save_path(rom_path), save_policy)
+                            .map_err(with_run_log)?;
+                    }
+                    // The running emulator's processes are still the ones to look in
+                    let in_place = shared_emulator.take().map(|(child, launched_for)| {
+                        window = window.clone().sharing_processes_of(&launched_for);
+                        let rom_path = rom_artifacts.get(binary).map(PathBuf::as_path);
+                        (child, rom_path)
+                    });
+                    match start_emulator(binary, in_place, &window, &launch_context)
+                        .await
+                        .map_err(with_run_log)?
+                    {
+                        Some(child) => {
+                            let capture = window_capture.clone();
+                            let source = WindowFrameSource::new(capture, window.clone());
+                            (Arc::new(source) as Arc<dyn FrameSource>, Some(child))
+                        }
+                        None => break,
+                    }
+                }
+            };
+
+            // From here on a failure fails the binary like a failed capture, which
+            // --continue-on-error moves on from
+            let mut result = Ok(());
+            if let Some(running) = child.take() {
+                match check_renderer(binary, source.as_ref(), running, &window, &launch_context)
+                    .await
+                {
+                    Ok(Some(running)) => child = Some(running),
+                    Ok(None) => break,
+                    Err(e) => result = Err(e),
+                }
+            }
+
+            if child.is_some() && !args.no_auto_unpause {
+                if let Err(e) = auto_unpause(
+                    source.as_ref(),
+                    &pause_actions,
+                    &window,
+                    launch_context.require_focus,
+                )
+                .await
+                {
+                    println!("Warning: could not check whether mGBA is paused: {}", e);
+                }
+            }
+
+            if let Some(savestate) = savestates
+                .get(binary)
+                .filter(|_| child.is_some() && result.is_ok())
+            {
+                // A state that didn't load fails the binary, and its emulator is closed
+                let loaded = match rom_artifacts.get(binary) {
+                    Some(rom_path) => {
+                        load_savestate(
+                            savestate,
+                            rom_path,
+                            &load_state_actions,
+                            source.as_ref(),
+                            &window,
+                            launch_context.require_focus,
+                        )
+                        .await
+                    }
+                    None => Err(anyhow::anyhow!("no ROM artifact was found")),
+                };
+                result = loaded.map_err(|e| {
+                    anyhow::anyhow!(
+                        "Failed to load savestate {} for {}: {}",
+                        savestate.display(),
+                        binary,
+                        e
+                    )
+                });
+                // Markers printed before the state was loaded belong to the discarded run
+                launch_cursor = emulator_log.line_count();
+            }
+
+            if let Some(duration_ms) = fast_forward_ms.filter(|_| child.is_some() && result.is_ok())
+            {
+                // mGBA's own shortcut rather than a GBA button, so it is typed on the
+                // keyboard unless input is mocked
+                let session = InputSession::new(
+                    &shutdown,
+                    source.clone(),
+                    &window,
+                    &output_root,
+                    binary,
+                    input_pacing,
+                );
+                if args.mock_input {
+                    session.use_mock_input(true);
+                }
+                // Fails like a capture: the emulator is closed and the run log quoted
+                result = fast_forward(fast_forward_key, duration_ms, &session).await;
+                session.finish();
+            }
+
+            // Segments run back to back in the same emulator, each picking up where the last left off
+            for (index, (job, phases)) in parsed_jobs.iter().enumerate() {
+                if shutdown.load(Ordering::Relaxed) || result.is_err() {
+                    break;
+                }
+
+                let timing = frame_timing(job.fps, job.duration);
+                let label = job.label(binary);
+                println!(
+                    "  {}: {}fps ({}), {}s duration ({}), {} frames",
+                    label,
+                    job.fps,
+                    job.fps_source,
+                    job.duration,
+                    job.duration_source,
+                    timing.frame_count
+                );
+                if let Some(dir) = output_root.dir.join(&job.output_name).parent() {
+                    std::fs::create_dir_all(dir)?;
+                }
+
+                // Show what input sequences will be used for this capture
+                if !phases.before.is_empty() {
+                    println!(
+                        "  Before-capture sequence: {}",
+                        job.before_input.as_ref().unwrap()
+                    );
+                }
+                if let Some(during_input) = job.during_input.as_ref() {
+                    match during_input {
+                        InputTracks::Single(sequence) if !phases.during.is_empty() => {
+                            println!("  During-capture sequence: {}", sequence)
+                        }
+                        InputTracks::Single(_) => {}
+                        InputTracks::Parallel(tracks) => {
+                            for (track, sequence) in tracks.iter().enumerate() {
+                                println!("  During-capture track {}: {}", track + 1, sequence);
+                            }
+                        }
+                    }
+                }
+                if let Some(script) = &phases.script {
+                    println!("  During-capture script: {}", script.path().display());
+                }
+                if !phases.after.is_empty() {
+                    println!(
+                        "  After-capture sequence: {}",
+                        job.after_input.as_ref().unwrap()
+                    );
+                }
+
+                // Close the emulator after the last capture of this launch unless it is reused
+                let is_last = index + 1 == parsed_jobs.len();
+                let emulator_to_close = child
+                    .as_mut()
+                    .filter(|_| is_last && !args.reuse_emulator && !keep_alive);
+                // Synthetic frames have no emulator to receive input, unless it is mocked
+                let no_input = InputPhases::default();
+                let phases = if args.synthetic_frames && !args.mock_input {
+                    &no_input
+                } else {
+                    phases
+                };
+
+                let input_session = InputSession::new(
+                    &shutdown,
+                    source.clone(),
+                    &window,
+                    &output_root,
+                    &job.output_name,
+                    input_pacing,
+                );
+                if args.log_inputs {
+                    input_session.log_inputs();
+                }
+                if let Some(gamepad) = &gamepad {
+                    input_session.use_button_device(gamepad.clone(), &key_mappings);
+                }
+                if let Some(server) = script_server
+                    .as_ref()
+                    .filter(|_| script_binaries.contains(binary) && !args.synthetic_frames)
+                {
+                    match server.connection(SCRIPT_CONNECT_TIMEOUT) {
+                        Ok(connection) => {
+                            input_session.use_button_device(connection, &key_mappings)
+                        }
+                        Err(e) => {
+                            println!("Warning: {}; {} uses keyboard input", e, job.output_name)
+                        }
+                    }
+                }
+                if args.mock_input {
+                    input_session.use_mock_input(true);
+                }
+                let outcome = capture_binary_gif(
+                    job,
+                    source.clone(),
+                    emulator_to_close,
+                    timing,
+                    phases,
+                    &input_session,
+                    &CaptureControl {
+                        sync_to_emulator: args.sync_to_emulator && !args.synthetic_frames,
+                        log: &emulator_log,
+                        log_cursor: if index == 0 {
+                            launch_cursor
+                        } else {
+                            emulator_log.line_count()
+                        },
+                        start_marker: start_marker.as_deref(),
+                        stop_marker: stop_marker.as_deref(),
+                        marker_timeout: Duration::from_secs_f32(args.marker_timeout.max(0.0)),
+                        keep_failed_output: args.keep_failed_output,
+                        live_input: live_input.as_ref().filter(|_| !args.synthetic_frames),
+                        interactive: interactive.as_ref(),
+                        key_mappings: &key_mappings,
+                        macros: &macros,
+                        parse_options,
+                        frame_filter: frame_filter.as_ref(),
+                        post: (!post.is_empty()).then_some(&post),
+                        output_root: &output_root,
+                        loop_count,
+                        shutdown: &shutdown,
+                    },
+                )
+                .await;
+                let mut output_rows: Vec<_> = input_session
+                    .screenshots()
+                    .into_iter()
+                    .map(|path| (format!("{} screenshot", label), path))
+                    .collect();
+                if outcome.is_ok() {
+                    match input_session.write_input_log() {
+                        Ok(Some(path)) => output_rows.push((format!("{} inputs", label), path)),
+                        Ok(None) => {}
+                        Err(e) => println!("Warning: {}", e),
+                    }
+                }
+                match outcome {
+                    Ok(CaptureOutcome::Written {
+                        output_path,
+                        frames,
+                    }) => {
+                        outputs.push(hooks::CaptureOutput::new(
+                            binary,
+                            &label,
+                            &output_path,
+                            frames,
+                        )?);
+                        // Files that don't loop forever say how often they play
+                        let row = match loop_count {
+                            Loop::Infinite => output_path,
+                            _ => format!("{} ({})", output_path, loop_count.describe()),
+                        };
+                        summary.push((label, row));
+                        summary.extend(output_rows);
+                    }
+                    Ok(CaptureOutcome::Panicked {
+                        message,
+                        screenshot,
+                        output_path,
+                    }) => {
+                        failures.push(format!("{}: {}", label, message));
+                        let mut output = match output_path {
+                            Some(output_path) => format!("{}, {}", screenshot, output_path),
+                            None => screenshot,
+                        };
+                        if !args.synthetic_frames {
+                            output.push_str(&format!(" (log: {})", run_log.display()));
+                        }
+                        summary.push((label, format!("FAILED (panicked) {}", output)));
+                        summary.extend(output_rows);
+                        // Later segments would only capture the panic screen
+                        break;
+                    }
+                    Ok(CaptureOutcome::ScriptFailed { message }) => {
+                        failures.push(format!("{}: {}", label, message));
+                        summary.push((label, "FAILED (script error)".to_string()));
+                        summary.extend(output_rows);
+                    }
+                    Err(e) => {
+                        result = Err(e);
+                        break;
+                    }
+                }
+            }
+
+            if let Some(mut child) = child {
+                if (args.reuse_emulator || keep_alive) && result.is_ok() {
+                    shared_emulator = Some((child, window.clone()));
+                } else {
+                    let _ = child.kill();
+                }
+            }
+            if let Err(e) = result {
+                let message = format!("{:#}", e);
+                let e = with_run_log(e);
+                if !args.continue_on_error {
+                    return Err(e);
+                }
+                println!("Error: {:#}", e);
+                failures.push(format!("{}: {}", binary, message));
+                summary.push((
+                    binary.clone(),
+                    format!("FAILED (log: {})", run_log.display()),
+                ));
+                break;
+            }
+        }
+
+        // Runs once every capture of the binary is written, unless one of them failed
+        let post_capture_cmd = binary_setting(binary, &capture_config, |entry| {
+            entry.post_capture_cmd.clone()
+        });
+        if let Some((hook, _)) = post_capture_cmd
+            .filter(|_| failures.len() == failures_before && !shutdown.load(Ordering::Relaxed))
+        {
+            if args.synthetic_frames {
+                println!(
+                    "Note: skipping post_capture_cmd of {} with --synthetic-frames",
+                    binary
+                );
+            } else {
+                let mut env = hook_env(binary, builds.get(binary).map(|build| &build.env));
+                env.extend(hooks::output_env(&outputs[outputs_before..]));
+                let ran = hooks::run(
+                    "post_capture_cmd",
+                    &hook,
+                    &project_dir,
+                    &env,
+                    get_effective_hook_timeout(binary, &capture_config)?,
+                    &emulator_log,
+                    &shutdown,
+                )
+                .await;
+                if let Err(e) = ran {
+                    let (failure, row) = hook_failure(
+                        "post_capture_cmd",
+                        binary,
+                        e,
+                        with_run_log,
+                        &run_log,
+                        args.continue_on_error,
+                    )?;
+                    failures.push(failure);
+                    summary.push(row);
+                }
+            }
+        }
+        println!();
+    }
+
+    // A binary skipped by the config only runs its post-capture command with run_on_skip
+    for binary in &skipped {
+        let post_capture_cmd = binary_setting(binary, &capture_config, |entry| {
+            entry.post_capture_cmd.clone()
+        });
+        let run_on_skip = binary_setting(binary, &capture_config, |entry| entry.run_on_skip)
+            .is_some_and(|(run_on_skip, _)| run_on_skip);
+        let Some((hook, _)) = post_capture_cmd.filter(|_| run_on_skip) else {
+            continue;
+        };
+        if shutdown.load(Ordering::Relaxed) {
+            break;
+        }
+        if args.synthetic_frames {
+            println!(
+                "Note: skipping post_capture_cmd of {} with --synthetic-frames",
+                binary
+            );
+            continue;
+        }
+        println!("Running post_capture_cmd of skipped {}...", binary);
+        let run_log = output_root.dir.join(format!("{}.run.log", binary));
+        let binary_cursor = emulator_log.line_count();
+        emulator_log.write_to(&run_log)?;
+        let binary_env: BTreeMap<String, String> = get_effective_env(binary, &capture_config)?
+            .into_iter()
+            .map(|(name, (value, _))| (name, value))
+            .collect();
+        let mut env = hook_env(binary, Some(&binary_env));
+        env.extend(hooks::output_env(&[]));
+        env.insert("AGB_CAPTURE_SKIPPED".to_string(), "1".to_string());
+        let ran = hooks::run(
+            "post_capture_cmd",
+            &hook,
+            &project_dir,
+            &env,
+            get_effective_hook_timeout(binary, &capture_config)?,
+            &emulator_log,
+            &shutdown,
+        )
+        .await;
+        if let Err(e) = ran {
+            let (failure, row) = hook_failure(
+                "post_capture_cmd",
+                binary,
+                e,
+                |e| run_log_error(e, &emulator_log, binary_cursor, &run_log),
+                &run_log,
+                args.continue_on_error,
+            )?;
+            failures.push(failure);
+            summary.push(row);
+        }
+        println!();
+    }
+
+    if let Some((mut child, window)) = shared_emulator {
+        if args.keep_alive && !shutdown.load(Ordering::Relaxed) {
+            // Dropping the handle leaves the process running after this tool exits
+            let pid = tracked_emulator_pid(&window).unwrap_or(child.id());
+            println!(
+                "Leaving mGBA running (PID {}); close it when you're done.",
+                pid
+            );
+            save_files.leave_in_place();
+        } else {
+            let _ = child.kill();
+            println!("Closed shared mGBA instance.");
+        }
+    }
+
+    if !summary.is_empty() {
+        println!("Summary:");
+        let width = summary
+            .iter()
+            .map(|(label, _)| label.len())
+            .max()
+            .unwrap_or(0);
+        for (label, output_path) in &summary {
+            println!("  {:<width$}  {}", label, output_path);
+        }
+        println!();
+    }
+    if !skipped.is_empty() {
+        println!("Skipped by config: {}", skipped.join(", "));
+        println!();
+    }
+
+    let post_run_cmd = config_settings.and_then(|settings| settings.post_run_cmd.as_ref());
+    if let Some(hook) = post_run_cmd.filter(|_| !shutdown.load(Ordering::Relaxed)) {
+        if args.synthetic_frames {
+            println!("Note: skipping post_run_cmd with --synthetic-frames");
+        } else {
+            let manifest = hooks::RunManifest {
+                captures: &outputs,
+                failures: &failures,
+                skipped: &skipped,
+                output_dir: &std::fs::canonicalize(&output_root.dir)?,
+            }
+            .write(&output_root.dir.join(RUN_MANIFEST_NAME))?;
+            let post_run_log = output_root.dir.join(POST_RUN_LOG_NAME);
+            emulator_log.write_to(&post_run_log)?;
+            let env = BTreeMap::from([(
+                "AGB_CAPTURE_MANIFEST".to_string(),
+                manifest.display().to_string(),
+            )]);
+            let timeout = config_settings
+                .and_then(|settings| settings.default.as_ref())
+                .and_then(|default| default.hook_timeout.as_ref())
+                .map_or(Ok(DEFAULT_HOOK_TIMEOUT), WaitTime::duration)?;
+            let ran = hooks::run(
+                "post_run_cmd",
+                hook,
+                &project_dir,
+                &env,
+                timeout,
+                &emulator_log,
+                &shutdown,
+            )
+            .await;
+            // Reported with the failed captures, if there are any
+            if let Err(e) = ran {
+                println!("Error: {:#} (log: {})", e, post_run_log.display());
+                failures.push(format!("{:#}", e));
+            }
+            println!();
+        }
+    }
+
+    if !failures.is_empty() {
+        return Err(anyhow::anyhow!(
+            "{} capture(s) failed:\n  {}",
+            failures.len(),
+            failures.join("\n  ")
+        ));
+    }
+
+    println!(
+        "All captures created successfully in {}/ directory!",
+        output_root.dir.display()
+    );
+    Ok(())
+}
+
+/// Adds the last lines of a binary's emulator output, printed since `from`, and the path
+/// of its run log to a capture error
+fn run_log_error(
+    error: anyhow::Error,
+    log: &EmulatorLog,
+    from: usize,
+    log_path: &Path,
+) -> anyhow::Error {
+    let tail = log.tail(from, RUN_LOG_TAIL_LINES);
+    if tail.is_empty() {
+        return error;
+    }
+    anyhow::anyhow!(
+        "{}\nLast emulator output:\n  {}\nFull output: {}",
+        error,
+        tail.join("\n  "),
+        log_path.display()
+    )
+}
+
+/// Discovers all Rust binary files in src/bin directory or src/main.rs
+fn discover_binaries(project_dir: &Path) -> Result<Vec<String>> {
+    let src_bin_dir = project_dir.join("src/bin");
+    let src_main = project_dir.join("src/main.rs");
+    let mut binaries = Vec::new();
+
+    // Check for src/bin/*.rs files first
+    if src_bin_dir.exists() {
+        for entry in std::fs::read_dir(&src_bin_dir)? {
+            let entry = entry?;
+            let path = entry.path();
+
+            if path.is_file() {
+                if let Some(extension) = path.extension() {
+                    if extension == "rs" {
+                        if let Some(file_name) = path.file_stem() {
+                            if let Some(binary_name) = file_name.to_str() {
+                                binaries.push(binary_name.to_string());
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // If no binaries found in src/bin/, check for src/main.rs
+    if binaries.is_empty() && src_main.exists() {
+        // For src/main.rs projects, use the package name from Cargo.toml
+        let cargo_toml_path = project_dir.join("Cargo.toml");
+        if let Ok(cargo_content) = std::fs::read_to_string(&cargo_toml_path) {
+            // Parse the package name from Cargo.toml
+            for line in cargo_content.lines() {
+                if line.trim().starts_with("name") {
+                    if let Some(name_part) = line.split('=').nth(1) {
+                        let name = name_part.trim().trim_matches('"').trim_matches('\'');
+                        binaries.push(name.to_string());
+                        break;
+                    }
+                }
+            }
+        }
+
+        // Fallback to directory name if package name not found
+        if binaries.is_empty() {
+            if let Some(dir_name) = project_dir.file_name() {
+                if let Some(name_str) = dir_name.to_str() {
+                    binaries.push(name_str.to_string());
+                }
+            }
+        }
+    }
+
+    binaries.sort();
+    Ok(binaries)
+}
+
+/// Puts the alphabetically sorted `binaries` in the order `--order` asks for, warning
+/// about names in `settings.order` that aren't binaries of the project
+fn order_binaries(binaries: &mut [String], order: CaptureOrder, config: &Option<CaptureConfig>) {
+    match order {
+        CaptureOrder::Alpha => {}
+        CaptureOrder::Reverse => binaries.reverse(),
+        CaptureOrder::Config => {
+            let listed = config
+                .as_ref()
+                .and_then(|config| config.settings.as_ref())
+                .and_then(|settings| settings.order.as_deref())
+                .unwrap_or_default();
+            for name in listed.iter().filter(|name| !binaries.contains(name)) {
+                println!(
+                    "Warning: settings.order names {}, which is not a binary of this project",
+                    name
+                );
+            }
+            // A stable sort keeps the unlisted binaries in alphabetical order
+            binaries.sort_by_key(|binary| {
+                listed
+                    .iter()
+                    .position(|name| name == binary)
+                    .unwrap_or(listed.len())
+            });
+        }
+    }
+}
+
+/// Validates that a directory contains an agbrs project
+fn is_agbrs_project_dir(path: &Path) -> bool {
+    let cargo_toml = path.join("Cargo.toml");
+    let src_bin = path.join("src/bin");
+    let src_main = path.join("src/main.rs");
+    let cargo_config = path.join(".cargo/config.toml");
+
+    // Must have Cargo.toml and either src/bin/ or src/main.rs
+    if !cargo_toml.exists() || (!src_bin.exists() && !src_main.exists()) {
+        return false;
+    }
+
+    // Look for GBA-specific configuration, falling back to a text search when the config
+    // can't be parsed
+    match CargoConfig::load(path) {
+        Ok(Some(config)) => {
+            config.targets_gba()
+                || config
+                    .runner()
+                    .is_some_and(|runner| runner.emulator().is_some())
+        }
+        Ok(None) => false,
+        Err(_) => std::fs::read_to_string(&cargo_config).is_ok_and(|config_content| {
+            config_content.contains("thumbv4t-none-eabi") || config_content.contains("mgba")
+        }),
+    }
+}
+
+/// Ensures nightly toolchain is installed (required for GBA build-std)
+async fn setup_gba_target() -> Result<()> {
+    println!("Checking nightly toolchain for GBA development...");
+
+    let output = Command::new("rustup")
+        .args(["toolchain", "list"])
+        .output()?;
+
+    let toolchains = String::from_utf8_lossy(&output.stdout);
+
+    if !toolchains.contains("nightly") {
+        println!("Installing nightly toolchain (required for build-std)...");
+        let output = Command::new("rustup")
+            .args(["toolchain", "install", "nightly"])
+            .output()?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(anyhow::anyhow!(
+                "Failed to install nightly toolchain: {}",
+                stderr
+            ));
+        }
+        println!("Nightly toolchain installed successfully!");
+    } else {
+        println!("Nightly toolchain is available.");
+    }
+
+    Ok(())
+}
+
+/// Pre-builds all binaries to eliminate compilation delays during capture, each with its
+/// cargo features and environment. Returns the built ROM artifact path for each binary.
+async fn prebuild_binaries(
+    binaries: &[String],
+    project_dir: &Path,
+    builds: &HashMap<String, BinaryBuild>,
+) -> Result<HashMap<String, PathBuf>> {
+    let has_src_bin = project_dir.join("src/bin").exists();
+    let mut artifacts = HashMap::new();
+
+    for binary in binaries {
+        let mut args: Vec<String> = [
+            "+nightly",
+            "build",
+            "--release",
+            "--message-format=json-render-diagnostics",
+        ]
+        .map(String::from)
+        .to_vec();
+
+        // Only use --bin flag for src/bin projects
+        if has_src_bin {
+            args.extend(["--bin".to_string(), binary.clone()]);
+        }
+        let build = builds.get(binary).cloned().unwrap_or_default();
+        args.extend(build.features.arguments());
+        println!("Building {}: cargo {}", binary, args.join(" "));
+        if !build.env.is_empty() {
+            let names: Vec<&str> = build.env.keys().map(String::as_str).collect();
+            println!("  with environment variables {}", names.join(", "));
+        }
+
+        let output = Command::new("cargo")
+            .current_dir(project_dir)
+            .args(&args)
+            .envs(&build.env)
+            .output()?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(anyhow::anyhow!("Failed to build {}: {}", binary, stderr));
+        }
+
+        // Pick the executable produced for this binary out of cargo's JSON messages
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        for line in stdout.lines() {
+            let Ok(message) = serde_json::from_str::<serde_json::Value>(line) else {
+                continue;
+            };
+            if message["reason"] == "compiler-artifact" && message["target"]["name"] == **binary {
+                if let Some(executable) = message["executable"].as_str() {
+                    artifacts.insert(binary.clone(), PathBuf::from(executable));
+                }
+            }
+        }
+    }
+    Ok(artifacts)
+}
+
+/// Settings shared by every emulator launch in a run
+struct LaunchContext<'a> {
+    project_dir: &'a Path,
+    /// Config file given with `--config`, which settings are saved to
+    config_path: Option<&'a Path>,
+    /// Load ROM shortcut, used to load into a running mGBA in `--reuse-emulator` mode
+    load_rom_actions: &'a [InputAction],
+    reuse_settings: &'a ReuseEmulatorSettings,
+    /// Extra popup titles dismissed while waiting for the mGBA window
+    popup_patterns: &'a [String],
+    /// Binaries whose emulator is started directly instead of through `cargo run`
+    direct_launches: &'a HashMap<String, DirectLaunch>,
+    /// How long to wait for each binary's mGBA window
+    window_waits: &'a HashMap<String, WindowWait>,
+    /// Cargo features and environment each binary is run with, the same it was built with
+    builds: &'a HashMap<String, BinaryBuild>,
+    /// Receives the output of every launched emulator
+    log: &'a EmulatorLog,
+    /// Launch mGBA with the software renderer (set once black frames are detected)
+    software_renderer: AtomicBool,
+    /// Settings for clean captures (`--managed-emulator-config`)
+    managed_config: Option<ManagedConfig>,
+    /// Where config overrides are written for emulators started through the runner
+    config_home: ConfigHome,
+    /// Let the next launch write to the terminal directly instead of through `log`, so
+    /// it keeps working after this tool exits (`--keep-alive`)
+    detach_output: AtomicBool,
+    /// Refuse to send input unless mGBA has focus (`--require-focus`)
+    require_focus: bool,
+    shutdown: &'a Arc<AtomicBool>,
+}
+
+/// Launches the binary of `calibrate`, times how long the screen takes to change after
+/// each press of its key, prints the times and saves the median in capture.json
+async fn calibrate_latency(
+    calibrate: &CalibrateArgs,
+    key_mappings: &GbaKeyMappings,
+    session: &InputSession,
+    launch_context: &LaunchContext<'_>,
+) -> Result<()> {
+    let key = parse_key(&calibrate.key, key_mappings)?;
+    let source = session
+        .source
+        .clone()
+        .expect("calibration sessions capture the mGBA window");
+
+    println!("Launching {} to calibrate...", calibrate.binary);
+    let window = session.window();
+    let Some(mut child) = start_emulator(&calibrate.binary, None, &window, launch_context).await?
+    else {
+        return Ok(());
+    };
+    if session.needs_focus() {
+        prepare_mgba_for_input(&window, session.pacing.require_focus).await?;
+    }
+    let result = time_reactions(calibrate, &key, session, source.as_ref()).await;
+    session.finish();
+    let _ = child.kill();
+    let mut latencies = result?;
+    if latencies.is_empty() {
+        return Ok(());
+    }
+
+    latencies.sort();
+    let millis = |latency: Duration| latency.as_millis() as u64;
+    let median = millis(latencies[latencies.len() / 2]);
+    println!(
+        "\nInput latency over {} presses: min {}ms, median {}ms, max {}ms",
+        latencies.len(),
+        millis(latencies[0]),
+        median,
+        millis(latencies[latencies.len() - 1])
+    );
+    println!(
+        "  {}",
+        latencies
+            .iter()
+            .map(|&latency| format!("{}ms", millis(latency)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    let config_file = save_input_latency(
+        launch_context.project_dir,
+        launch_context.config_path,
+        median,
+    )?;
+    println!(
+        "Saved settings.input_latency_ms = {} in {}",
+        median,
+        config_file.name()
+    );
+    Ok(())
+}
+
+/// Presses `key` `calibrate.trials` times, each once the screen has been still for a
+/// moment, and returns how long the screen took to change after each press. Stops early
+/// on Ctrl+C.
+async fn time_reactions(
+    calibrate: &CalibrateArgs,
+    key: &KeyCombo,
+    session: &InputSession,
+    source: &dyn FrameSource,
+) -> Result<Vec<Duration>> {
+    let mut latencies = Vec::new();
+    for trial in 1..=calibrate.trials {
+        if !wait_for_stable(source, CALIBRATION_STILL, CALIBRATION_TIMEOUT * 3, session).await? {
+            return Err(anyhow::anyhow!(
+                "The screen of {} never stayed still for {}ms; calibrate needs a binary that only changes when the key is pressed",
+                calibrate.binary,
+                CALIBRATION_STILL.as_millis()
+            ));
+        }
+        if session.interrupted() {
+            break;
+        }
+
+        let reference = source.capture()?;
+        session.lock().press_combo(key)?;
+        let pressed_at = Instant::now();
+        let mut released = false;
+        let latency = loop {
+            if !released && pressed_at.elapsed() >= session.pacing.min_press {
+                session.lock().release_combo(key)?;
+                released = true;
+            }
+            let captured_at = Instant::now();
+            let frame = source.capture()?;
+            if changed_pixel_fraction(&reference, &frame) > STABLE_PIXEL_TOLERANCE {
+                break Some(captured_at - pressed_at);
+            }
+            if pressed_at.elapsed() >= CALIBRATION_TIMEOUT || session.interrupted() {
+                break None;
+            }
+        };
+        if !released {
+            session.lock().release_combo(key)?;
+        }
+        let Some(latency) = latency else {
+            if session.interrupted() {
+                break;
+            }
+            return Err(anyhow::anyhow!(
+                "{} did not visibly react to {} within {}ms; pick a key it reacts to with --key",
+                calibrate.binary,
+                key,
+                CALIBRATION_TIMEOUT.as_millis()
+            ));
+        };
+        println!("Press {}: {}ms", trial, latency.as_millis());
+        latencies.push(latency);
+        // Lets the reaction to the release settle before the next press
+        sleep(session.pacing.min_press).await;
+    }
+    Ok(latencies)
+}
+
+/// Writes `latency_ms` into the project's config as `settings.input_latency_ms`, keeping
+/// the rest of the file as it was, and returns the file written
+fn save_input_latency(
+    project_dir: &Path,
+    config_path: Option<&Path>,
+    latency_ms: u64,
+) -> Result<ConfigFile> {
+    let config_file = ConfigFile::for_writing(project_dir, config_path)?;
+    config_file.set(
+        &["settings", "input_latency_ms"],
+        ConfigValue::Integer(latency_ms),
+    )?;
+    Ok(config_file)
+}
+
+/// Creates the virtual game controller and, one GBA button at a time, presses the button
+/// for mGBA's controller settings to bind once Enter is pressed
+async fn bind_gamepad(shutdown: &AtomicBool) -> Result<()> {
+    let gamepad = VirtualGamepad::create()?;
+    let terminal = LiveInput::stdin();
+    println!(
+        "Created virtual gamepad '{}'. In mGBA, open Settings > Controllers and select it as the gamepad.",
+        gamepad::DEVICE_NAME
+    );
+    println!(
+        "For each button, press Enter here, then within {} seconds click that button's field in mGBA; the gamepad holds the button for mGBA to bind.",
+        BIND_GAMEPAD_DELAY.as_secs()
+    );
+    for (button, name) in gamepad::BUTTONS {
+        print!("\n{}: press Enter when ready ", name);
+        std::io::stdout().flush()?;
+        loop {
+            tokio::select! {
+                line = terminal.next_line() => {
+                    if line?.is_none() {
+                        println!();
+                        return Ok(());
+                    }
+                    break;
+                }
+                _ = sleep(Duration::from_millis(100)) => {
+                    if shutdown.load(Ordering::Relaxed) {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+        sleep(BIND_GAMEPAD_DELAY).await;
+        println!("Pressing {}...", name);
+        gamepad.set(button, true)?;
+        sleep(BIND_GAMEPAD_HOLD).await;
+        gamepad.set(button, false)?;
+    }
+    println!("\nAll buttons pressed. Save the bindings in mGBA; captures can now use --input-backend gamepad.");
+    Ok(())
+}
+
+/// Launches the binary of `record`, records the GBA buttons pressed until mGBA is closed
+/// or Ctrl+C is pressed, and prints them as an input sequence
+async fn record_input(
+    record: &RecordArgs,
+    key_mappings: &GbaKeyMappings,
+    window: &WindowQuery,
+    launch_context: &LaunchContext<'_>,
+) -> Result<()> {
+    let buttons = GBA_BUTTONS
+        .iter()
+        .map(|&button| {
+            let key = parse_key(&format!("{}{}", GBA_KEY_PREFIX, button), key_mappings)?;
+            Ok((button, key.key))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    println!("Launching {} to record...", record.binary);
+    let Some(mut child) = start_emulator(&record.binary, None, window, launch_context).await?
+    else {
+        return Ok(());
+    };
+    println!("Recording: play in the mGBA window, then close mGBA or press Ctrl+C to finish");
+    let presses = record::record(&buttons, launch_context.shutdown, || {
+        matches!(child.try_wait(), Ok(None))
+    })
+    .await;
+    let _ = child.kill();
+    let presses = presses?;
+
+    if presses.is_empty() {
+        println!("\nNo GBA buttons were pressed; nothing recorded");
+        return Ok(());
+    }
+    let sequence = record::to_sequence(&presses, Duration::from_millis(record.granularity));
+    println!("\nRecorded {} presses:\n{}", presses.len(), sequence);
+
+    if let Some(phase) = record.save {
+        let config_file = save_recorded_sequence(
+            launch_context.project_dir,
+            launch_context.config_path,
+            &record.binary,
+            phase,
+            &sequence,
+        )?;
+        println!(
+            "Saved as {} of {} in {}",
+            phase.field(),
+            record.binary,
+            config_file.name()
+        );
+    }
+    Ok(())
+}
+
+/// Sets a binary's input sequence in the project's config, creating the file if needed,
+/// and returns the file written. The rest of the file is kept as it is, in the same order.
+fn save_recorded_sequence(
+    project_dir: &Path,
+    config_path: Option<&Path>,
+    binary: &str,
+    phase: SequencePhase,
+    sequence: &str,
+) -> Result<ConfigFile> {
+    let config_file = ConfigFile::for_writing(project_dir, config_path)?;
+    config_file.set(
+        &["binaries", binary, phase.field()],
+        ConfigValue::String(sequence),
+    )?;
+    Ok(config_file)
+}
+
+/// Writes a starter config for `init`: the default key mappings, `settings.default`
+/// with example sequences and an entry for each binary the project has. Replacing an
+/// existing config needs `--force`.
+fn init_config(project_dir: &Path, args: &Args, init: &InitArgs) -> Result<()> {
+    let config_file = match &args.config {
+        Some(path) => ConfigFile::for_writing(project_dir, Some(path))?,
+        None => ConfigFile::in_project(project_dir, init.format),
+    };
+    let existing = match &args.config {
+        Some(_) => Some(config_file.clone()).filter(|config_file| config_file.path.exists()),
+        None => ConfigFile::find(project_dir, None)?,
+    };
+    if let Some(existing) = existing {
+        if !init.force {
+            return Err(anyhow::anyhow!(
+                "{} already exists; use init --force to replace it",
+                existing.name()
+            ));
+        }
+        if existing.path != config_file.path {
+            return Err(anyhow::anyhow!(
+                "{} already exists, and a project can't have both {} and {}; remove it first or run init --format {}",
+                existing.name(),
+                config_file::TOML_NAME,
+                config_file::JSON_NAME,
+                match existing.format {
+                    ConfigFormat::Toml => "toml",
+                    ConfigFormat::Json => "json",
+                }
+            ));
+        }
+    }
+
+    let binaries = discover_binaries(project_dir)?;
+    let timing = if init.interactive {
+        Some((
+            ask_number("Frames per second of the captures?", args.fps)?,
+            ask_number("Seconds each capture lasts?", args.duration)?,
+        ))
+    } else {
+        None
+    };
+    std::fs::write(
+        &config_file.path,
+        starter_config(config_file.format, &binaries, timing)?,
+    )
+    .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", config_file.name(), e))?;
+
+    if binaries.is_empty() {
+        println!(
+            "Created {}; no binaries were found to add entries for",
+            config_file.name()
+        );
+    } else {
+        println!(
+            "Created {} with entries for {}",
+            config_file.name(),
+            binaries.join(", ")
+        );
+    }
+    let example = binaries.first().map_or("my_game", String::as_str);
+    println!("Next steps:");
+    match config_file.format {
+        ConfigFormat::Toml => println!(
+            "  Give a binary input to send while capturing, like during_capture = \"wait:500,A,R:1000\" under [binaries.{}]",
+            example
+        ),
+        ConfigFormat::Json => println!(
+            "  Give a binary input to send while capturing, like \"during_capture\": \"wait:500,A,R:1000\" in binaries.{}",
+            example
+        ),
+    }
+    println!("  See \"Input Sequences\" in the README for everything a sequence can do");
+    let command = match &args.config {
+        Some(path) => format!("agbrs-capture --config {}", path.display()),
+        None => "agbrs-capture".to_string(),
+    };
+    println!("  Check the config with: {} check-config", command);
+    println!(
+        "  Capture a single binary with: {} --bin {}",
+        command, example
+    );
+    Ok(())
+}
+
+/// The text of the config `init` writes. TOML gets comments with examples of the
+/// settings left out; JSON, which can't have comments, only the settings themselves.
+fn starter_config(
+    format: ConfigFormat,
+    binaries: &[String],
+    timing: Option<(f32, f32)>,
+) -> Result<String> {
+    let key_mappings = GbaKeyMappings::default();
+    let timing = timing.map(|(fps, duration)| (f32_value(fps), f32_value(duration)));
+    let mut text = String::new();
+    match format {
+        ConfigFormat::Toml => {
+            text.push_str("# agbrs-capture configuration. Every setting is optional; the README lists them all.\n");
+            text.push_str(&format!("version = {}\n\n", config_file::CONFIG_VERSION));
+            text.push_str("# Keys mGBA has bound to the GBA buttons; these are mGBA's defaults\n");
+            text.push_str("[settings.key_mappings]\n");
+            for (button, key) in key_mappings.buttons() {
+                text.push_str(&format!("{} = {}\n", button, serde_json::to_string(key)?));
+            }
+            text.push_str(
+                "\n# Settings for every binary, used where its own entry doesn't set them\n",
+            );
+            text.push_str("[settings.default]\n");
+            match &timing {
+                Some((fps, duration)) => {
+                    text.push_str(&format!("fps = {}\nduration = {}\n", fps, duration))
+                }
+                None => text.push_str("# fps = 10\n# duration = 3\n"),
+            }
+            text.push_str(
+                "# Input sent before the first frame, while capturing and after the last frame:\n",
+            );
+            text.push_str("# before_capture = \"wait:1000,S\"\n");
+            text.push_str("# during_capture = \"R:500,wait:200,A\"\n");
+            text.push_str("# after_capture = \"S\"\n");
+            if !binaries.is_empty() {
+                text.push_str("\n# One entry per binary, for the settings of its own captures\n");
+            }
+            for (index, binary) in binaries.iter().enumerate() {
+                if index > 0 {
+                    text.push('\n');
+                }
+                text.push_str(&format!("[binaries.{}]\n", toml_edit::Key::new(binary)));
+                text.push_str("# during_capture = \"wait:500,A\"\n");
+            }
+        }
+        ConfigFormat::Json => {
+            let quote = |text: &str| serde_json::to_string(text);
+            text.push_str(&format!(
+                "{{\n  \"version\": {},\n  \"settings\": {{\n    \"key_mappings\": {{\n",
+                config_file::CONFIG_VERSION
+            ));
+            let buttons = key_mappings.buttons();
+            for (index, (button, key)) in buttons.iter().enumerate() {
+                let comma = if index + 1 < buttons.len() { "," } else { "" };
+                text.push_str(&format!(
+                    "      {}: {}{}\n",
+                    quote(button)?,
+                    quote(key)?,
+                    comma
+                ));
+            }
+            text.push_str("    },\n");
+            match &timing {
+                Some((fps, duration)) => text.push_str(&format!(
+                    "    \"default\": {{\n      \"fps\": {},\n      \"duration\": {}\n    }}\n",
+                    fps, duration
+                )),
+                None => text.push_str("    \"default\": {}\n"),
+            }
+            text.push_str("  },\n  \"binaries\": {");
+            for (index, binary) in binaries.iter().enumerate() {
+                let comma = if index + 1 < binaries.len() { "," } else { "" };
+                text.push_str(&format!("\n    {}: {{}}{}", quote(binary)?, comma));
+            }
+            text.push_str(if binaries.is_empty() {
+                "}\n}\n"
+            } else {
+                "\n  }\n}\n"
+            });
+        }
+    }
+    Ok(text)
+}
+
+/// Writes the capture flags of the command line into the config file for
+/// `--save-config`: those for every binary under `settings.default`, sequences for one
+/// binary under its entry, and `--map` under `settings.key_mappings`. Values the file
+/// already has are only replaced with `--yes`, and what was written is listed.
+fn save_cli_config(project_dir: &Path, args: &Args) -> Result<()> {
+    let binaries = discover_binaries(project_dir)?;
+    check_cli_sequences(args, &binaries)?;
+
+    // Whole numbers are written without a fraction, as they would be by hand
+    let number = |number: f32| {
+        if number.fract() == 0.0 && number >= 0.0 {
+            ConfigValue::Integer(number as u64)
+        } else {
+            ConfigValue::Float(number.to_string().parse().unwrap_or(number as f64))
+        }
+    };
+    let mut values: Vec<(Vec<&str>, ConfigValue)> = Vec::new();
+    if args.fps_given {
+        values.push((vec!["settings", "default", "fps"], number(args.fps)));
+    }
+    if args.duration_given {
+        values.push((
+            vec!["settings", "default", "duration"],
+            number(args.duration),
+        ));
+    } else if args.extend_to_sequence {
+        values.push((
+            vec!["settings", "default", "duration"],
+            ConfigValue::String("auto"),
+        ));
+    }
+    if args.format_given {
+        values.push((
+            vec!["settings", "default", "output", "format"],
+            ConfigValue::String(args.format.name()),
+        ));
+    }
+    match args.loop_count {
+        Some(Loop::Infinite) => values.push((
+            vec!["settings", "default", "loop"],
+            ConfigValue::String("infinite"),
+        )),
+        Some(Loop::Plays(plays)) => values.push((
+            vec!["settings", "default", "loop"],
+            ConfigValue::Integer(plays.into()),
+        )),
+        None => {}
+    }
+    for (field, sequences) in [
+        ("before_capture", &args.before_capture),
+        ("during_capture", &args.during_capture),
+        ("after_capture", &args.after_capture),
+    ] {
+        for sequence in sequences {
+            let keys = match &sequence.binary {
+                Some(binary) => vec!["binaries", binary.as_str(), field],
+                None => vec!["settings", "default", field],
+            };
+            values.push((keys, ConfigValue::String(&sequence.sequence)));
+        }
+    }
+    if let Some(pattern) = &args.window_title_pattern {
+        values.push((
+            vec!["settings", "default", "window_title"],
+            ConfigValue::String(pattern),
+        ));
+    }
+    if let Some(fast_forward_ms) = args.fast_forward {
+        values.push((
+            vec!["settings", "default", "fast_forward_ms"],
+            ConfigValue::Integer(fast_forward_ms),
+        ));
+    }
+    // Waits from the command line are kept as written
+    for (field, wait) in [
+        ("startup_wait", &args.startup_wait),
+        ("window_timeout", &args.window_timeout),
+    ] {
+        if let Some(WaitTime::Text(wait)) = wait {
+            values.push((
+                vec!["settings", "default", field],
+                ConfigValue::String(wait),
+            ));
+        }
+    }
+    for (field, text) in [
+        ("start_marker", &args.start_marker),
+        ("stop_marker", &args.stop_marker),
+        ("frame_filter_cmd", &args.frame_filter),
+    ] {
+        if let Some(text) = text {
+            values.push((
+                vec!["settings", "default", field],
+                ConfigValue::String(text),
+            ));
+        }
+    }
+    for mapping in &args.map {
+        values.push((
+            vec!["settings", "key_mappings", mapping.button],
+            ConfigValue::String(&mapping.key),
+        ));
+    }
+    if values.is_empty() {
+        return Err(anyhow::anyhow!(
+            "--save-config found no capture flags to save; give the ones to keep, like --fps 15 --save-config"
+        ));
+    }
+
+    let config_file = ConfigFile::for_writing(project_dir, args.config.as_deref())?;
+    let same = |old: &serde_json::Value, new: &serde_json::Value| match (old, new) {
+        (serde_json::Value::Number(old), serde_json::Value::Number(new)) => {
+            old.as_f64() == new.as_f64()
+        }
+        (old, new) => old == new,
+    };
+    let mut added = Vec::new();
+    let mut changed = Vec::new();
+    let mut unchanged = Vec::new();
+    for (index, (keys, value)) in values.iter().enumerate() {
+        let new = value.to_json();
+        match config_file.get(keys)? {
+            None => added.push(format!("  + {} = {}", keys.join("."), new)),
+            Some(old) if same(&old, &new) => unchanged.push(index),
+            Some(old) => changed.push(format!("  ~ {} = {} (was {})", keys.join("."), new, old)),
+        }
+    }
+    if !changed.is_empty() && !args.yes {
+        return Err(anyhow::anyhow!(
+            "--save-config would change values {} already has, so nothing was saved; add --yes to replace them:\n{}",
+            config_file.name(),
+            changed.join("\n")
+        ));
+    }
+    if added.is_empty() && changed.is_empty() {
+        println!(
+            "{} already has these settings; nothing to save",
+            config_file.name()
+        );
+        return Ok(());
     }
 
-    let config_content = std::fs::read_to_string(&config_path)
-        .map_err(|e| anyhow::anyhow!("Failed to read capture.json: {}", e))?;
-
-    let config: CaptureConfig = serde_json::from_str(&config_content)
-        .map_err(|e| anyhow::anyhow!("Failed to parse capture.json: {}", e))?;
-
-    Ok(Some(config))
-}
-
-/// Gets the input sequences for a specific binary from config or CLI args
-fn get_binary_input_sequences(
-    binary_name: &str,
-    config: &Option<CaptureConfig>,
-    cli_before: &Option<String>,
-    cli_during: &Option<String>,
-) -> (Option<String>, Option<String>) {
-    // CLI args take precedence over config file
-    if cli_before.is_some() || cli_during.is_some() {
-        return (cli_before.clone(), cli_during.clone());
+    // Values the file already has are left as written, in case they span several lines
+    let values: Vec<_> = values
+        .into_iter()
+        .enumerate()
+        .filter(|(index, _)| !unchanged.contains(index))
+        .map(|(_, value)| value)
+        .collect();
+    config_file.set_all(&values)?;
+    println!("Saved to {}:", config_file.name());
+    for line in added.iter().chain(&changed) {
+        println!("{}", line);
     }
 
-    // Try to get from config file
-    if let Some(config) = config {
-        // Check for binary-specific config first
-        if let Some(binaries) = &config.binaries {
-            if let Some(binary_config) = binaries.get(binary_name) {
-                return (
-                    binary_config.before_capture.clone(),
-                    binary_config.during_capture.clone(),
-                );
+    // Entries of their own can keep a binary from using what was saved for all of them
+    let deep =
+        config_file.get(&["settings", "merge"])?.as_ref() == Some(&serde_json::Value::from("deep"));
+    let entries = config_file.get(&["binaries"])?;
+    let entries = entries
+        .iter()
+        .flat_map(|entries| entries.as_object())
+        .flatten();
+    for (key, entry) in entries {
+        for (keys, _) in values
+            .iter()
+            .filter(|(keys, _)| keys[..2] == ["settings", "default"])
+        {
+            let field = &keys[2..];
+            let mut own = Some(entry);
+            for part in field {
+                own = own.and_then(|value| value.get(part));
             }
-        }
-
-        // Fall back to default config in settings
-        if let Some(settings) = &config.settings {
-            if let Some(default_config) = &settings.default {
-                return (
-                    default_config.before_capture.clone(),
-                    default_config.during_capture.clone(),
+            if own.is_some() {
+                println!(
+                    "Note: binaries.{}.{} wins over the saved settings.default.{}",
+                    key,
+                    field.join("."),
+                    field.join(".")
+                );
+            } else if !deep
+                && field[0].ends_with("_capture")
+                && entry
+                    .get(format!("{}_mode", field[0]))
+                    .is_none_or(|mode| mode == "replace")
+            {
+                println!(
+                    "Note: settings.default.{} doesn't apply to {}, which has an entry of its own (settings.merge is \"shallow\")",
+                    field[0], key
                 );
             }
         }
     }
-
-    // No config found
-    (None, None)
+    Ok(())
 }
 
-/// Gets the effective key mappings for a binary (binary > global > default)
-fn get_effective_key_mappings(binary_name: &str, config: &Option<CaptureConfig>) -> GbaKeyMappings {
-    if let Some(config) = config {
-        // Check for binary-specific key mappings first
-        if let Some(binaries) = &config.binaries {
-            if let Some(binary_config) = binaries.get(binary_name) {
-                if let Some(ref mappings) = binary_config.key_mappings {
-                    return mappings.clone();
-                }
-            }
-        }
-
-        // Fall back to global key mappings in settings
-        if let Some(settings) = &config.settings {
-            if let Some(ref mappings) = settings.key_mappings {
-                return mappings.clone();
-            }
+/// Warns about mGBA sessions that were running before this run, which window matching
+/// could capture instead of the emulator it launches, and terminates them with
+/// `--kill-stale`
+async fn handle_stale_emulators(window: &WindowQuery, kill: bool, assume_yes: bool) -> Result<()> {
+    // Not being able to look is no reason to stop; window matching reports its own errors
+    let stale = match find_stale_emulators(window) {
+        Ok(stale) => stale,
+        Err(e) => {
+            debug!("Could not check for running mGBA sessions: {}", e);
+            return Ok(());
         }
+    };
+    if stale.processes.is_empty() && stale.windows.is_empty() {
+        return Ok(());
     }
 
-    // Use default mappings
-    GbaKeyMappings::default()
-}
-
-/// Parses a string like "A:500,wait:1000,B" into a sequence of input actions
-fn parse_input_sequence(input: &str, key_mappings: &GbaKeyMappings) -> Result<Vec<InputAction>> {
-    let mut actions = Vec::new();
+    println!("WARNING: mGBA is already running:");
+    for (pid, name) in &stale.processes {
+        println!("  process {} (pid {})", name, pid);
+    }
+    for title in &stale.windows {
+        println!("  window '{}'", title);
+    }
 
-    for part in input.split(',') {
-        let part = part.trim();
-        if part.is_empty() {
-            continue;
-        }
+    if !kill {
+        println!("These windows can be captured or sent input instead of the emulator launched by this run. Close them, or pass --kill-stale to terminate them.\n");
+        return Ok(());
+    }
+    if stale.processes.is_empty() {
+        println!("No mGBA process owns these windows, so they must be closed by hand.\n");
+        return Ok(());
+    }
+    if !assume_yes
+        && !confirm(&format!(
+            "Terminate {} mGBA process(es)?",
+            stale.processes.len()
+        ))?
+    {
+        println!("Leaving them running.\n");
+        return Ok(());
+    }
 
-        if part.starts_with("wait:") {
-            let duration_str = part.strip_prefix("wait:").unwrap();
-            let duration_ms = duration_str
-                .parse::<u64>()
-                .map_err(|_| anyhow::anyhow!("Invalid wait duration: {}", duration_str))?;
-            actions.push(InputAction::Wait { duration_ms });
-        } else if part.contains(':') {
-            // Key with duration (hold)
-            let mut split = part.split(':');
-            let key_str = split.next().unwrap();
-            let duration_str = split
-                .next()
-                .ok_or_else(|| anyhow::anyhow!("Invalid key:duration format: {}", part))?;
-            let duration_ms = duration_str
-                .parse::<u64>()
-                .map_err(|_| anyhow::anyhow!("Invalid duration: {}", duration_str))?;
-            let key = parse_key(key_str, key_mappings)?;
-            actions.push(InputAction::Press {
-                key,
-                duration_ms: Some(duration_ms),
-            });
+    for (pid, name) in &stale.processes {
+        if kill_stale_emulator(*pid) {
+            println!("Terminated {} (pid {})", name, pid);
         } else {
-            // Simple key press
-            let key = parse_key(part, key_mappings)?;
-            actions.push(InputAction::Press {
-                key,
-                duration_ms: None,
-            });
+            println!("Could not terminate {} (pid {})", name, pid);
         }
     }
-
-    Ok(actions)
+    // Give the windows time to close before window matching starts
+    sleep(Duration::from_millis(500)).await;
+    println!();
+    Ok(())
 }
 
-/// Parses a raw keyboard key string into an enigo Key (no GBA mappings)
-fn parse_raw_key(key_str: &str) -> Result<Key> {
-    match key_str.to_lowercase().as_str() {
-        // Letters
-        "a" => Ok(Key::Unicode('a')),
-        "b" => Ok(Key::Unicode('b')),
-        "c" => Ok(Key::Unicode('c')),
-        "d" => Ok(Key::Unicode('d')),
-        "e" => Ok(Key::Unicode('e')),
-        "f" => Ok(Key::Unicode('f')),
-        "g" => Ok(Key::Unicode('g')),
-        "h" => Ok(Key::Unicode('h')),
-        "i" => Ok(Key::Unicode('i')),
-        "j" => Ok(Key::Unicode('j')),
-        "k" => Ok(Key::Unicode('k')),
-        "l" => Ok(Key::Unicode('l')),
-        "m" => Ok(Key::Unicode('m')),
-        "n" => Ok(Key::Unicode('n')),
-        "o" => Ok(Key::Unicode('o')),
-        "p" => Ok(Key::Unicode('p')),
-        "q" => Ok(Key::Unicode('q')),
-        "r" => Ok(Key::Unicode('r')),
-        "s" => Ok(Key::Unicode('s')),
-        "t" => Ok(Key::Unicode('t')),
-        "u" => Ok(Key::Unicode('u')),
-        "v" => Ok(Key::Unicode('v')),
-        "w" => Ok(Key::Unicode('w')),
-        "x" => Ok(Key::Unicode('x')),
-        "y" => Ok(Key::Unicode('y')),
-        "z" => Ok(Key::Unicode('z')),
-
-        // Arrow keys (common for GBA games)
-        "up" | "arrow_up" => Ok(Key::UpArrow),
-        "down" | "arrow_down" => Ok(Key::DownArrow),
-        "left" | "arrow_left" => Ok(Key::LeftArrow),
-        "right" | "arrow_right" => Ok(Key::RightArrow),
-
-        // Special keys
-        "space" => Ok(Key::Unicode(' ')),
-        "enter" | "return" => Ok(Key::Return),
-        "tab" => Ok(Key::Tab),
-        "escape" | "esc" => Ok(Key::Escape),
-        "shift" => Ok(Key::Shift),
-        "ctrl" | "control" => Ok(Key::Control),
-        "alt" => Ok(Key::Alt),
-        "backspace" => Ok(Key::Backspace),
-
-        // Numbers
-        "0" => Ok(Key::Unicode('0')),
-        "1" => Ok(Key::Unicode('1')),
-        "2" => Ok(Key::Unicode('2')),
-        "3" => Ok(Key::Unicode('3')),
-        "4" => Ok(Key::Unicode('4')),
-        "5" => Ok(Key::Unicode('5')),
-        "6" => Ok(Key::Unicode('6')),
-        "7" => Ok(Key::Unicode('7')),
-        "8" => Ok(Key::Unicode('8')),
-        "9" => Ok(Key::Unicode('9')),
-
-        _ => Err(anyhow::anyhow!("Unsupported key: {}", key_str)),
+/// Asks for a number greater than zero on the terminal until one is given, with
+/// `default` for an empty answer
+fn ask_number(question: &str, default: f32) -> Result<f32> {
+    loop {
+        print!("{} [{}] ", question, default);
+        std::io::stdout().flush()?;
+        let mut answer = String::new();
+        if std::io::stdin().read_line(&mut answer)? == 0 {
+            return Ok(default);
+        }
+        match answer.trim() {
+            "" => return Ok(default),
+            answer => match answer.parse::<f32>() {
+                Ok(number) if number > 0.0 => return Ok(number),
+                _ => println!("Please enter a number greater than 0"),
+            },
+        }
     }
 }
 
-/// Parses a string into an enigo Key, supporting GBA controller names
-fn parse_key(key_str: &str, key_mappings: &GbaKeyMappings) -> Result<Key> {
-    match key_str.to_uppercase().as_str() {
-        // GBA Controller mappings using the button names/numbers you specified
-        "A" | "0" => parse_raw_key(&key_mappings.a), // A button
-        "B" | "1" => parse_raw_key(&key_mappings.b), // B button
-        "E" | "2" => parse_raw_key(&key_mappings.select), // Select button
-        "S" | "3" => parse_raw_key(&key_mappings.start), // Start button
-        "R" | "4" => parse_raw_key(&key_mappings.right), // D-pad Right
-        "L" | "5" => parse_raw_key(&key_mappings.left), // D-pad Left
-        "U" | "6" => parse_raw_key(&key_mappings.up), // D-pad Up
-        "D" | "7" => parse_raw_key(&key_mappings.down), // D-pad Down
-        "I" | "8" => parse_raw_key(&key_mappings.r_shoulder), // Right shoulder
-        "J" | "9" => parse_raw_key(&key_mappings.l_shoulder), // Left shoulder
-
-        // Fall back to raw key parsing for regular keyboard keys
-        _ => parse_raw_key(key_str),
-    }
+/// Asks a yes/no question on the terminal, defaulting to no
+fn confirm(question: &str) -> Result<bool> {
+    print!("{} [y/N] ", question);
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
 }
 
-/// Executes a sequence of input actions using enigo
-async fn execute_input_sequence(actions: &[InputAction]) -> Result<()> {
-    if actions.is_empty() {
-        return Ok(());
-    }
+/// An emulator started directly with a built ROM (`settings.emulator` / `--emulator-path`)
+struct DirectLaunch {
+    program: PathBuf,
+    args: Vec<String>,
+    rom: PathBuf,
+}
 
-    let mut enigo = Enigo::new(&Settings::default())
-        .map_err(|e| anyhow::anyhow!("Failed to initialize input system: {}", e))?;
+/// Starts mGBA for a binary, loading it into an already running instance when one is
+/// provided and falling back to a fresh launch if that fails, and waits for the window
+/// `window` finds. Returns `None` if shutdown was requested before the window appeared.
+async fn start_emulator(
+    binary_name: &str,
+    in_place: Option<(Child, Option<&Path>)>,
+    window: &WindowQuery,
+    context: &LaunchContext<'_>,
+) -> Result<Option<Child>> {
+    let shutdown = context.shutdown;
 
-    for action in actions {
-        match action {
-            InputAction::Press { key, duration_ms } => {
-                match duration_ms {
-                    Some(duration) => {
-                        // Hold key for specified duration
-                        enigo
-                            .key(*key, Direction::Press)
-                            .map_err(|e| anyhow::anyhow!("Failed to press key: {}", e))?;
-                        sleep(Duration::from_millis(*duration)).await;
-                        enigo
-                            .key(*key, Direction::Release)
-                            .map_err(|e| anyhow::anyhow!("Failed to release key: {}", e))?;
-                    }
-                    None => {
-                        // Quick press and release
-                        enigo
-                            .key(*key, Direction::Click)
-                            .map_err(|e| anyhow::anyhow!("Failed to click key: {}", e))?;
-                    }
-                }
-            }
-            InputAction::KeyDown { key } => {
-                enigo
-                    .key(*key, Direction::Press)
-                    .map_err(|e| anyhow::anyhow!("Failed to press key down: {}", e))?;
+    if let Some((mut child, rom_path)) = in_place {
+        let loaded = match (child.try_wait(), rom_path) {
+            (Ok(Some(status)), _) => Err(anyhow::anyhow!("mGBA exited ({})", status)),
+            (_, None) => Err(anyhow::anyhow!(
+                "no ROM artifact was found for {}",
+                binary_name
+            )),
+            (_, Some(rom_path)) => {
+                println!("Loading {} into running mGBA...", rom_path.display());
+                load_rom_in_place(
+                    rom_path,
+                    context.load_rom_actions,
+                    context.reuse_settings,
+                    window,
+                    context.require_focus,
+                    shutdown,
+                )
+                .await
             }
-            InputAction::KeyUp { key } => {
-                enigo
-                    .key(*key, Direction::Release)
-                    .map_err(|e| anyhow::anyhow!("Failed to release key: {}", e))?;
+        };
+
+        match loaded {
+            Ok(()) => {
+                println!("ROM loaded into running mGBA!");
+                return Ok(Some(child));
             }
-            InputAction::Wait { duration_ms } => {
-                sleep(Duration::from_millis(*duration_ms)).await;
+            Err(e) => {
+                println!(
+                    "Could not load ROM in place ({}), launching a fresh mGBA...",
+                    e
+                );
+                let _ = child.kill();
             }
         }
     }
 
-    Ok(())
-}
-
-/// Main entry point: validates directory, discovers binaries, and captures GIFs
-#[tokio::main]
-async fn main() -> Result<()> {
-    let args = Args::parse();
-
-    // Set up signal handling for graceful shutdown
-    let shutdown = Arc::new(AtomicBool::new(false));
-    let shutdown_clone = shutdown.clone();
-
-    tokio::spawn(async move {
-        signal::ctrl_c().await.expect("Failed to listen for ctrl+c");
-        println!("\nReceived Ctrl+C, shutting down gracefully...");
-        shutdown_clone.store(true, Ordering::Relaxed);
-    });
+    if shutdown.load(Ordering::Relaxed) {
+        return Ok(None);
+    }
 
-    // Use current directory if no project directory is provided
-    let project_dir = args
-        .project_dir
-        .unwrap_or_else(|| std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")));
+    let overrides = ConfigOverrides {
+        software_renderer: context.software_renderer.load(Ordering::Relaxed),
+        managed: context.managed_config,
+    };
 
-    if !project_dir.exists() {
-        return Err(anyhow::anyhow!(
-            "Directory does not exist: {}",
-            project_dir.display()
-        ));
+    let detach_output = context.detach_output.load(Ordering::Relaxed);
+    let mut child = launch_binary(
+        binary_name,
+        context.project_dir,
+        context.direct_launches.get(binary_name),
+        context.builds.get(binary_name),
+        &overrides,
+        &context.config_home,
+        detach_output,
+    )?;
+    if !detach_output {
+        context.log.attach(&mut child);
     }
-
-    if !is_agbrs_project_dir(&project_dir) {
-        return Err(anyhow::anyhow!(
-            "Directory does not appear to be an agbrs project: {}",
-            project_dir.display()
-        ));
+    let wait = context
+        .window_waits
+        .get(binary_name)
+        .copied()
+        .unwrap_or_default();
+    if wait_for_mgba_window(&mut child, wait, window, context, shutdown).await? {
+        Ok(Some(child))
+    } else {
+        Ok(None)
     }
+}
 
-    let frame_count = (args.fps * args.duration).ceil() as u32;
-    let frame_delay_ms = (1000.0 / args.fps) as u64;
-
-    println!("Using agbrs project at: {}", project_dir.display());
-    println!(
-        "GIF settings: {}fps, {}s duration, {} frames",
-        args.fps, args.duration, frame_count
-    );
+/// Spawns `cargo run` for a binary, which launches mGBA through the project's runner, or
+/// the configured emulator directly with the built ROM. Config overrides are passed as
+/// arguments to an emulator started directly, and through a temporary config home to
+/// one started by the runner where that is supported.
+fn launch_binary(
+    binary_name: &str,
+    project_dir: &Path,
+    direct: Option<&DirectLaunch>,
+    build: Option<&BinaryBuild>,
+    overrides: &ConfigOverrides,
+    config_home: &ConfigHome,
+    inherit_output: bool,
+) -> Result<Child> {
+    let mut command = match direct {
+        Some(direct) => {
+            let mut command = Command::new(&direct.program);
+            command
+                .args(&direct.args)
+                .args(overrides.arguments())
+                .arg(&direct.rom);
+            command
+        }
+        None => {
+            let has_src_bin = project_dir.join("src/bin").exists();
+            let mut args = vec!["+nightly", "run", "--release"];
 
-    std::fs::create_dir_all("out")?;
+            // Only use --bin flag for src/bin projects
+            if has_src_bin {
+                args.extend(["--bin", binary_name]);
+            }
+            let mut command = Command::new("cargo");
+            command.args(&args);
+            if let Some(build) = build {
+                command.args(build.features.arguments()).envs(&build.env);
+            }
+            command
+        }
+    };
 
-    let binaries = discover_binaries(&project_dir)?;
-    if binaries.is_empty() {
-        return Err(anyhow::anyhow!(
-            "No binary files found in {}/src/bin/ or {}/src/main.rs",
-            project_dir.display(),
-            project_dir.display()
-        ));
+    // Output is piped so it can be watched for log markers; EmulatorLog echoes it. An
+    // emulator that outlives this tool would lose its output pipes, so it inherits ours.
+    let output = || {
+        if inherit_output {
+            Stdio::inherit()
+        } else {
+            Stdio::piped()
+        }
+    };
+    // Stdin is left to this tool, which reads live input from it with `--during-capture -`
+    command
+        .current_dir(project_dir)
+        .stdin(Stdio::null())
+        .stdout(output())
+        .stderr(output());
+    if direct.is_none() && !overrides.is_empty() && mgba_config::overrides_supported() {
+        command.env("XDG_CONFIG_HOME", config_home.write(overrides)?);
     }
+    if wayland::is_wayland_session() {
+        command.envs(wayland::xwayland_env());
+    }
+    let child = command.spawn()?;
+    Ok(child)
+}
 
-    println!("Found {} binaries: {}", binaries.len(), binaries.join(", "));
-
-    println!("Setting up GBA development environment...");
-    setup_gba_target().await?;
-    println!("Pre-building all GBA binaries...");
-    prebuild_binaries(&binaries, &project_dir).await?;
-    println!("All binaries built successfully!\n");
+/// Waits for the mGBA window to appear, killing the child on failure or shutdown.
+/// Returns `false` if shutdown was requested.
+///
+/// `cargo run` may still be compiling, which takes minutes on a cold cache, so the window
+/// timeout only runs while no build is running. A `cargo run` that fails is reported as
+/// soon as it exits.
+async fn wait_for_mgba_window(
+    child: &mut Child,
+    wait: WindowWait,
+    window: &WindowQuery,
+    context: &LaunchContext<'_>,
+    shutdown: &Arc<AtomicBool>,
+) -> Result<bool> {
+    println!("Waiting for mGBA to start...");
+    sleep(wait.startup_wait).await;
 
-    // Load capture configuration from capture.json if it exists
-    let capture_config = load_capture_config(&project_dir)?;
-    if capture_config.is_some() {
-        println!("Using capture.json configuration file");
+    // Check for shutdown during initial wait
+    if shutdown.load(Ordering::Relaxed) {
+        println!("Shutdown requested, terminating mGBA process...");
+        let _ = child.kill();
+        return Ok(false);
     }
 
-    for binary in &binaries {
-        // Check for shutdown signal before starting each binary
+    // Look for the mGBA window until the timeout, counted from the first search after
+    // any build
+    let mut attempts = 0;
+    let mut searching_since: Option<Instant> = None;
+    let timeout = wait_time::format(wait.window_timeout);
+    // When the build was first seen and last reported
+    let mut build_progress: Option<(Instant, Instant)> = None;
+
+    loop {
+        // Check for shutdown during window search
         if shutdown.load(Ordering::Relaxed) {
-            println!("Shutdown requested, stopping capture process.");
-            break;
+            println!("Shutdown requested, terminating mGBA process...");
+            let _ = child.kill();
+            return Ok(false);
         }
 
-        println!("Capturing {}...", binary);
-
-        // Get input sequences and key mappings for this specific binary
-        let (before_input, during_input) = get_binary_input_sequences(
-            binary,
-            &capture_config,
-            &args.before_capture,
-            &args.during_capture,
-        );
-
-        let key_mappings = get_effective_key_mappings(binary, &capture_config);
-
-        // Parse input sequences with key mappings
-        let before_capture_actions = if let Some(ref input) = before_input {
-            parse_input_sequence(input, &key_mappings)?
-        } else {
-            Vec::new()
-        };
-
-        let during_capture_actions = if let Some(ref input) = during_input {
-            parse_input_sequence(input, &key_mappings)?
-        } else {
-            Vec::new()
-        };
-
-        // Show what input sequences will be used for this binary
-        if !before_capture_actions.is_empty() {
-            println!(
-                "  Before-capture sequence: {}",
-                before_input.as_ref().unwrap()
-            );
-        }
-        if !during_capture_actions.is_empty() {
-            println!(
-                "  During-capture sequence: {}",
-                during_input.as_ref().unwrap()
-            );
+        // A runner shim may exit successfully after starting the emulator
+        if let Some(status) = child.try_wait()? {
+            if !status.success() {
+                return Err(anyhow::anyhow!(
+                    "The launched process exited with {} before an mGBA window appeared",
+                    status
+                ));
+            }
         }
 
-        capture_binary_gif(
-            binary,
-            &project_dir,
-            frame_count,
-            frame_delay_ms,
-            &before_capture_actions,
-            &during_capture_actions,
-            &shutdown,
-        )
-        .await?;
-        println!();
-    }
+        if cargo_is_building(child.id()) {
+            let now = Instant::now();
+            match build_progress {
+                None => {
+                    println!("cargo is still building, waiting for it to finish...");
+                    build_progress = Some((now, now));
+                }
+                Some((started, reported))
+                    if reported.elapsed() >= Duration::from_secs(BUILD_PROGRESS_INTERVAL_SECS) =>
+                {
+                    println!("Still building ({}s)...", started.elapsed().as_secs());
+                    build_progress = Some((started, now));
+                }
+                Some(_) => {}
+            }
+            searching_since = None;
+            sleep(Duration::from_secs(1)).await;
+            continue;
+        }
 
-    println!("All GIFs created successfully in out/ directory!");
-    Ok(())
-}
+        attempts += 1;
+        let searched = searching_since.get_or_insert_with(Instant::now).elapsed();
 
-/// Discovers all Rust binary files in src/bin directory or src/main.rs
-fn discover_binaries(project_dir: &Path) -> Result<Vec<String>> {
-    let src_bin_dir = project_dir.join("src/bin");
-    let src_main = project_dir.join("src/main.rs");
-    let mut binaries = Vec::new();
+        // cargo starts mGBA some time after launch, so refresh its process tree each time
+        if window.match_mode.tracks_process() {
+            track_process_tree(window, child.id());
+        }
 
-    // Check for src/bin/*.rs files first
-    if src_bin_dir.exists() {
-        for entry in std::fs::read_dir(&src_bin_dir)? {
-            let entry = entry?;
-            let path = entry.path();
+        // First-run and update popups can appear before or after the main window
+        dismiss_popups(window, context.popup_patterns).await;
 
-            if path.is_file() {
-                if let Some(extension) = path.extension() {
-                    if extension == "rs" {
-                        if let Some(file_name) = path.file_stem() {
-                            if let Some(binary_name) = file_name.to_str() {
-                                binaries.push(binary_name.to_string());
-                            }
-                        }
+        let found = find_mgba_window(window);
+        // A window not tied to the launched process may be an unrelated mGBA session
+        let untracked = window.match_mode.tracks_process() && !tracked_window_found(window);
+        match found {
+            Ok(_) if untracked && searched < wait.window_timeout / 2 => {
+                println!(
+                    "Found an mGBA window that wasn't started by this run, waiting for ours... (attempt {}, {}s of {})",
+                    attempts,
+                    searched.as_secs(),
+                    timeout
+                );
+                sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+            Ok(found) => {
+                if untracked {
+                    let fallback = if window.match_mode == WindowMatch::Pid {
+                        "title"
+                    } else {
+                        "window class, process name or title"
+                    };
+                    println!(
+                        "WARNING: no window of the launched process appeared within {}s; capturing '{}', matched by {} instead, which may belong to another mGBA session. Close other mGBA windows, or pass --match-by to pick how the window is matched.",
+                        searched.as_secs(),
+                        found.title(),
+                        fallback
+                    );
+                }
+                println!("mGBA window found!");
+                if window.virtual_display {
+                    if let Err(e) = focus_mgba_window(window) {
+                        println!("Warning: could not focus the mGBA window: {}", e);
                     }
                 }
+                sleep(Duration::from_millis(500)).await;
+                dismiss_popups(window, context.popup_patterns).await;
+                return Ok(true);
+            }
+            Err(_) if searched < wait.window_timeout => {
+                println!(
+                    "mGBA window not found yet, waiting... (attempt {}, {}s of {})",
+                    attempts,
+                    searched.as_secs(),
+                    timeout
+                );
+                sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+            Err(e) => {
+                let _ = child.kill();
+                return Err(anyhow::anyhow!(
+                    "Failed to find mGBA window within {} ({} attempts; raise window_timeout or --window-timeout if the binary is slow to start): {}",
+                    timeout,
+                    attempts,
+                    e
+                ));
             }
         }
     }
+}
 
-    // If no binaries found in src/bin/, check for src/main.rs
-    if binaries.is_empty() && src_main.exists() {
-        // For src/main.rs projects, use the package name from Cargo.toml
-        let cargo_toml_path = project_dir.join("Cargo.toml");
-        if let Ok(cargo_content) = std::fs::read_to_string(&cargo_toml_path) {
-            // Parse the package name from Cargo.toml
-            for line in cargo_content.lines() {
-                if line.trim().starts_with("name") {
-                    if let Some(name_part) = line.split('=').nth(1) {
-                        let name = name_part.trim().trim_matches('"').trim_matches('\'');
-                        binaries.push(name.to_string());
-                        break;
-                    }
-                }
-            }
+/// Closes popups of the emulator covering the mGBA window (update checks, first-run
+/// prompts) by sending Escape, then Return if the popup has no cancel action. Each key
+/// is only sent once the popup itself has focus. Failures are only reported.
+async fn dismiss_popups(window: &WindowQuery, popup_patterns: &[String]) {
+    let popups = match find_popup_windows(window, popup_patterns) {
+        Ok(popups) => popups,
+        Err(e) => {
+            println!("Warning: could not check for popups: {}", e);
+            return;
         }
+    };
 
-        // Fallback to directory name if package name not found
-        if binaries.is_empty() {
-            if let Some(dir_name) = project_dir.file_name() {
-                if let Some(name_str) = dir_name.to_str() {
-                    binaries.push(name_str.to_string());
+    for (id, title) in popups {
+        println!("Dismissing popup window '{}'", title);
+        for key in [Key::Escape, Key::Return] {
+            match focus_popup(window, id, &title).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    println!(
+                        "Warning: could not focus popup '{}', leaving it open rather than sending keys to another window",
+                        title
+                    );
+                    break;
                 }
+                Err(e) => {
+                    println!("Warning: could not focus popup '{}': {}", title, e);
+                    break;
+                }
+            }
+            let press = [InputAction::Press {
+                key: key.into(),
+                duration: None,
+            }];
+            if let Err(e) = execute_input_sequence(&press, window).await {
+                println!("Warning: could not dismiss popup '{}': {}", title, e);
+                break;
+            }
+            sleep(Duration::from_millis(300)).await;
+
+            let still_open = find_popup_windows(window, popup_patterns)
+                .map(|popups| popups.iter().any(|(popup_id, _)| *popup_id == id))
+                .unwrap_or(false);
+            if !still_open {
+                break;
             }
         }
     }
-
-    binaries.sort();
-    Ok(binaries)
 }
 
-/// Validates that a directory contains an agbrs project
-fn is_agbrs_project_dir(path: &Path) -> bool {
-    let cargo_toml = path.join("Cargo.toml");
-    let src_bin = path.join("src/bin");
-    let src_main = path.join("src/main.rs");
-    let cargo_config = path.join(".cargo/config.toml");
+/// Makes sure the popup `id` of the emulator `window` finds, titled `title`, has focus, bringing it to the front if
+/// needed. Returns whether it ended up focused.
+async fn focus_popup(window: &WindowQuery, id: u32, title: &str) -> Result<bool> {
+    if window_has_focus(window, id, title)? {
+        return Ok(true);
+    }
+    focus_window_by_id(window, id)?;
+    sleep(Duration::from_millis(FOCUS_SETTLE_MS)).await;
+    window_has_focus(window, id, title)
+}
 
-    // Must have Cargo.toml and either src/bin/ or src/main.rs
-    if !cargo_toml.exists() || (!src_bin.exists() && !src_main.exists()) {
-        return false;
+/// Holds mGBA's fast-forward key for `duration_ms` through `session`, then waits for the
+/// emulator to settle. Skipped when mGBA can't be focused, so the key never reaches
+/// another application.
+async fn fast_forward(key: Key, duration_ms: u64, session: &InputSession) -> Result<()> {
+    if session.needs_focus() {
+        match focus_mgba(&session.window()).await {
+            Ok(true) => {}
+            Ok(false) => {
+                println!("mGBA is not focused, skipping fast-forward");
+                return Ok(());
+            }
+            Err(e) => {
+                println!("Skipping fast-forward, could not focus mGBA: {}", e);
+                return Ok(());
+            }
+        }
     }
 
-    // Look for GBA-specific configuration
-    if let Ok(config_content) = std::fs::read_to_string(&cargo_config) {
-        if config_content.contains("thumbv4t-none-eabi") || config_content.contains("mgba") {
-            return true;
+    println!("Fast-forwarding for {}ms...", duration_ms);
+    session.lock().press(key)?;
+
+    let deadline = Instant::now() + Duration::from_millis(duration_ms);
+    while !session.interrupted() {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
         }
+        sleep(remaining.min(Duration::from_millis(100))).await;
     }
 
-    false
+    // Always release the key, also when interrupted
+    session.lock().release(key)?;
+    sleep(Duration::from_millis(FAST_FORWARD_SETTLE_MS)).await;
+    Ok(())
 }
 
-/// Ensures nightly toolchain is installed (required for GBA build-std)
-async fn setup_gba_target() -> Result<()> {
-    println!("Checking nightly toolchain for GBA development...");
+/// Works around mGBA's OpenGL display driver, whose window captures as solid black on
+/// some Linux setups: relaunches mGBA with the software renderer where config overrides
+/// are supported, and otherwise names the setting to change. Returns the emulator to
+/// capture from, or `None` if shutdown was requested during the relaunch.
+async fn check_renderer(
+    binary_name: &str,
+    source: &dyn FrameSource,
+    mut child: Child,
+    window: &WindowQuery,
+    context: &LaunchContext<'_>,
+) -> Result<Option<Child>> {
+    if context.software_renderer.load(Ordering::Relaxed) {
+        return Ok(Some(child));
+    }
 
-    let output = Command::new("rustup")
-        .args(&["toolchain", "list"])
-        .output()?;
+    match window_is_black(source).await {
+        Ok(false) => return Ok(Some(child)),
+        Ok(true) => {}
+        Err(e) => {
+            println!("Warning: could not check the mGBA window contents: {}", e);
+            return Ok(Some(child));
+        }
+    }
 
-    let toolchains = String::from_utf8_lossy(&output.stdout);
+    if !mgba_config::overrides_supported() && !context.direct_launches.contains_key(binary_name) {
+        println!(
+            "Warning: the mGBA window captures as solid black. Unless the ROM shows a black screen, mGBA's OpenGL display driver is hiding the picture; select the software renderer in {}",
+            DISPLAY_DRIVER_SETTING
+        );
+        return Ok(Some(child));
+    }
 
-    if !toolchains.contains("nightly") {
-        println!("Installing nightly toolchain (required for build-std)...");
-        let output = Command::new("rustup")
-            .args(&["toolchain", "install", "nightly"])
-            .output()?;
+    println!(
+        "The mGBA window captures as solid black, as happens with mGBA's OpenGL display driver; relaunching with the software renderer ({})...",
+        DISPLAY_DRIVER_SETTING
+    );
+    let _ = child.kill();
+    context.software_renderer.store(true, Ordering::Relaxed);
+    start_emulator(binary_name, None, window, context).await
+}
 
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(anyhow::anyhow!(
-                "Failed to install nightly toolchain: {}",
-                stderr
-            ));
+/// Returns true if every frame polled over about a second is solid black
+async fn window_is_black(source: &dyn FrameSource) -> Result<bool> {
+    for attempt in 0..4 {
+        if attempt > 0 {
+            sleep(Duration::from_millis(300)).await;
+        }
+        let frame = source.capture()?;
+        let has_picture = frame
+            .pixels()
+            .any(|pixel| pixel[0] != 0 || pixel[1] != 0 || pixel[2] != 0);
+        if frame.width() == 0 || frame.height() == 0 || has_picture {
+            return Ok(false);
         }
-        println!("Nightly toolchain installed successfully!");
-    } else {
-        println!("Nightly toolchain is available.");
     }
-
-    Ok(())
+    Ok(true)
 }
 
-/// Pre-builds all binaries to eliminate compilation delays during capture
-async fn prebuild_binaries(binaries: &[String], project_dir: &Path) -> Result<()> {
-    let has_src_bin = project_dir.join("src/bin").exists();
-
-    for binary in binaries {
-        println!("Building {}...", binary);
-        let mut args = vec!["+nightly", "build", "--release"];
+/// Detects an mGBA that started paused and sends the pause toggle to resume it.
+/// A paused indicator in the title is trusted outright; a frozen screen is only treated
+/// as paused if toggling actually gets the picture moving, otherwise the toggle is undone.
+async fn auto_unpause(
+    source: &dyn FrameSource,
+    pause_actions: &[InputAction],
+    window: &WindowQuery,
+    require_focus: bool,
+) -> Result<()> {
+    let title = find_mgba_window(window)?.title().to_lowercase();
+    if title.contains("paused") {
+        println!("mGBA window title shows it is paused, sending pause toggle...");
+        send_to_mgba(pause_actions, window, require_focus).await?;
+        println!("Auto-unpause performed.");
+        return Ok(());
+    }
 
-        // Only use --bin flag for src/bin projects
-        if has_src_bin {
-            args.extend(["--bin", binary]);
-        }
+    if !screen_is_static(source).await? {
+        return Ok(());
+    }
 
-        let output = Command::new("cargo")
-            .current_dir(project_dir)
-            .args(&args)
-            .output()?;
+    println!("Screen is not changing, mGBA may be paused; sending pause toggle...");
+    send_to_mgba(pause_actions, window, require_focus).await?;
 
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(anyhow::anyhow!("Failed to build {}: {}", binary, stderr));
-        }
+    if screen_is_static(source).await? {
+        // Static either way, so this is most likely just a still scene
+        send_to_mgba(pause_actions, window, require_focus).await?;
+        println!("Screen is still static; assuming a still scene and leaving mGBA as it was.");
+    } else {
+        println!("Auto-unpause performed.");
     }
     Ok(())
 }
 
-/// Captures frames from an mGBA window and creates a GIF with configurable settings
-async fn capture_binary_gif(
-    binary_name: &String,
-    project_dir: &Path,
-    frame_count: u32,
-    frame_delay_ms: u64,
-    before_capture_actions: &[InputAction],
-    during_capture_actions: &[InputAction],
-    shutdown: &Arc<AtomicBool>,
+/// Loads a savestate by copying it into state slot 1 of the ROM, which mGBA keeps next to
+/// the ROM as `<name>.ss1`, and sending the load-state shortcut. Fails if the screen
+/// doesn't change afterwards, as nothing else tells whether the state was loaded.
+async fn load_savestate(
+    savestate: &Path,
+    rom_path: &Path,
+    load_state_actions: &[InputAction],
+    source: &dyn FrameSource,
+    window: &WindowQuery,
+    require_focus: bool,
 ) -> Result<()> {
-    let has_src_bin = project_dir.join("src/bin").exists();
-    let mut args = vec!["+nightly", "run", "--release"];
+    let rom_name = rom_path
+        .file_stem()
+        .ok_or_else(|| anyhow::anyhow!("ROM path {} has no file name", rom_path.display()))?;
+    let slot_path = rom_path.with_file_name(format!("{}.ss1", rom_name.to_string_lossy()));
+    std::fs::copy(savestate, &slot_path)
+        .map_err(|e| anyhow::anyhow!("could not copy it to {}: {}", slot_path.display(), e))?;
 
-    // Only use --bin flag for src/bin projects
-    if has_src_bin {
-        args.extend(["--bin", binary_name]);
+    println!("Loading savestate {}...", savestate.display());
+    let before = source.capture()?;
+    send_to_mgba(load_state_actions, window, require_focus).await?;
+    for _ in 0..SAVESTATE_CHECK_ATTEMPTS {
+        sleep(Duration::from_millis(200)).await;
+        if source.capture()?.as_raw() != before.as_raw() {
+            println!("Savestate loaded.");
+            return Ok(());
+        }
     }
+    Err(anyhow::anyhow!(
+        "the screen did not change after sending the load-state shortcut (settings.emulator_keys.load_state); check that mGBA keeps savestates next to the ROM"
+    ))
+}
 
-    let mut child = Command::new("cargo")
-        .current_dir(project_dir)
-        .args(&args)
-        .spawn()?;
-
-    println!("Waiting for mGBA to start...");
-    sleep(Duration::from_secs(2)).await;
-
-    // Check for shutdown during initial wait
-    if shutdown.load(Ordering::Relaxed) {
-        println!("Shutdown requested, terminating mGBA process...");
-        let _ = child.kill();
-        return Ok(());
+/// Returns true if the screen stays pixel-identical over a few polled frames
+async fn screen_is_static(source: &dyn FrameSource) -> Result<bool> {
+    let first = source.capture()?;
+    for _ in 0..3 {
+        sleep(Duration::from_millis(200)).await;
+        if source.capture()?.as_raw() != first.as_raw() {
+            return Ok(false);
+        }
     }
+    Ok(true)
+}
 
-    // Retry finding mGBA window up to 10 times
-    let mut attempts = 0;
-    let max_attempts = 10;
+/// Loads a ROM into the running mGBA through its Load ROM dialog, then waits for the
+/// window title to change and name the new ROM. A title that only changed could be the
+/// dialog or an error, and one that already named a ROM of a similar name (`demo2` when
+/// loading `demo`) is still the previous ROM, so neither counts.
+async fn load_rom_in_place(
+    rom_path: &Path,
+    load_rom_actions: &[InputAction],
+    settings: &ReuseEmulatorSettings,
+    window: &WindowQuery,
+    require_focus: bool,
+    shutdown: &Arc<AtomicBool>,
+) -> Result<()> {
+    let rom_name = rom_path
+        .file_stem()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    let title_before = find_mgba_window(window)
+        .map(|found| found.title().to_string())
+        .ok();
 
-    loop {
-        // Check for shutdown during window search
+    let mut actions = load_rom_actions.to_vec();
+    actions.push(InputAction::Wait {
+        duration: Duration::from_millis(settings.dialog_wait_ms),
+    });
+    actions.push(InputAction::Text {
+        text: rom_path.display().to_string(),
+    });
+    actions.push(InputAction::Press {
+        key: Key::Return.into(),
+        duration: None,
+    });
+    send_to_mgba(&actions, window, require_focus).await?;
+
+    let deadline = Instant::now() + Duration::from_millis(settings.load_timeout_ms);
+    while Instant::now() < deadline {
         if shutdown.load(Ordering::Relaxed) {
-            println!("Shutdown requested, terminating mGBA process...");
-            let _ = child.kill();
-            return Ok(());
+            return Err(anyhow::anyhow!("shutdown requested"));
         }
+        sleep(Duration::from_millis(200)).await;
 
-        attempts += 1;
-        match find_mgba_window() {
-            Ok(_) => {
-                println!("mGBA window found!");
-                break;
+        if let Ok(found) = find_mgba_window(window) {
+            let title = found.title();
+            if title_before.as_deref() != Some(title) && title_names_rom(title, rom_name) {
+                return Ok(());
             }
-            Err(_) if attempts < max_attempts => {
-                println!(
-                    "mGBA window not found yet, waiting... (attempt {}/{})",
-                    attempts, max_attempts
-                );
-                sleep(Duration::from_secs(1)).await;
-                continue;
+        }
+    }
+
+    Err(anyhow::anyhow!(
+        "the window title did not show {} within {}ms",
+        rom_name,
+        settings.load_timeout_ms
+    ))
+}
+
+/// Optional ways of starting, ending and timing a capture
+struct CaptureControl<'a> {
+    /// Pace GIF frames by the emulation speed shown in the mGBA window title
+    sync_to_emulator: bool,
+    /// Emulator output searched for markers, from line `log_cursor` on
+    log: &'a EmulatorLog,
+    log_cursor: usize,
+    /// Log text that starts the capture
+    start_marker: Option<&'a str>,
+    /// Log text that ends the capture early
+    stop_marker: Option<&'a str>,
+    /// How long to wait for the start marker before capturing anyway
+    marker_timeout: Duration,
+    /// Write the GIF even when the ROM panicked
+    keep_failed_output: bool,
+    /// Actions typed live during the capture, parsed with `key_mappings` and `macros`
+    live_input: Option<&'a Arc<LiveInput>>,
+    /// Start and end of a capture played by hand (`--interactive`)
+    interactive: Option<&'a Interactive>,
+    key_mappings: &'a GbaKeyMappings,
+    macros: &'a BTreeMap<String, String>,
+    parse_options: ParseOptions,
+    /// Command every frame is piped through before encoding
+    frame_filter: Option<&'a FrameFilter>,
+    /// How many times the written file plays
+    loop_count: Loop,
+    /// Built-in processing of the frames, after the frame filter
+    post: Option<&'a PostChain>,
+    /// Directory the capture and its panic screenshot are written to
+    output_root: &'a OutputRoot,
+    shutdown: &'a Arc<AtomicBool>,
+}
+
+/// A capture played by hand: it starts after a countdown, optionally waiting for Enter
+/// first, and Enter typed while recording ends it early
+struct Interactive {
+    terminal: Arc<LiveInput>,
+    countdown: u32,
+    start_on_enter: bool,
+}
+
+impl Interactive {
+    /// Waits for the player to be ready. Ctrl+C skips the wait.
+    async fn wait_to_start(&self, output_name: &str, shutdown: &AtomicBool) -> Result<()> {
+        // Enter pressed during an earlier capture must not start or end this one
+        self.terminal.discard_pending();
+
+        if self.start_on_enter {
+            println!("Press Enter to start recording {}...", output_name);
+            loop {
+                tokio::select! {
+                    line = self.terminal.next_line() => {
+                        line?;
+                        break;
+                    }
+                    _ = sleep(Duration::from_millis(100)) => {
+                        if shutdown.load(Ordering::Relaxed) {
+                            return Ok(());
+                        }
+                    }
+                }
             }
-            Err(e) => {
-                let _ = child.kill();
-                return Err(anyhow::anyhow!(
-                    "Failed to find mGBA window after {} attempts: {}",
-                    max_attempts,
-                    e
-                ));
+        }
+        for remaining in (1..=self.countdown).rev() {
+            if shutdown.load(Ordering::Relaxed) {
+                return Ok(());
             }
+            println!("Recording {} in {}...", output_name, remaining);
+            sleep(Duration::from_secs(1)).await;
         }
+        println!("Recording! Play in mGBA, or press Enter here to stop early.");
+        Ok(())
     }
+}
+
+/// How a capture that ran to completion turned out
+enum CaptureOutcome {
+    /// The GIF (or APNG) of `frames` frames was written to `output_path`
+    Written { output_path: String, frames: usize },
+    /// The ROM panicked; the panic screen was saved to `screenshot` and the GIF is only
+    /// written with `--keep-failed-output`
+    Panicked {
+        message: String,
+        screenshot: String,
+        output_path: Option<String>,
+    },
+    /// The during-capture script failed with `message`, so no GIF was written
+    ScriptFailed { message: String },
+}
+
+/// Captures frames from a frame source and creates a GIF with configurable settings.
+/// `emulator_to_close` is killed as soon as the last frame is captured.
+/// Returns the path of the written GIF.
+async fn capture_binary_gif(
+    job: &CaptureJob,
+    source: Arc<dyn FrameSource>,
+    emulator_to_close: Option<&mut Child>,
+    timing: FrameTiming,
+    phases: &InputPhases,
+    input_session: &InputSession,
+    control: &CaptureControl<'_>,
+) -> Result<CaptureOutcome> {
+    let output_name = job.output_name.as_str();
+    let format = job.format;
+    let FrameTiming {
+        frame_count,
+        frame_delay_ms,
+    } = timing;
+
+    // Keys held with down: carry over from one phase into the next
 
     // Execute before-capture input sequence
-    if !before_capture_actions.is_empty() {
+    if !phases.before.is_empty() {
         println!("Executing before-capture input sequence...");
-        execute_input_sequence(before_capture_actions).await?;
+        send_to_mgba_in(input_session, "before_capture", &phases.before).await?;
         println!("Before-capture input sequence completed.");
     }
 
-    let gif_path = format!("out/{}.gif", binary_name);
-    let mut gif_file = File::create(&gif_path)?;
+    if let Some(interactive) = control.interactive {
+        interactive
+            .wait_to_start(output_name, control.shutdown)
+            .await?;
+    }
 
-    // Capture first frame to determine GIF dimensions
-    let first_frame = find_mgba_window()?.capture_image()?;
-    let first_frame: RgbaImage = ImageBuffer::from_raw(
-        first_frame.width(),
-        first_frame.height(),
-        first_frame.into_raw(),
-    )
-    .ok_or_else(|| anyhow::anyhow!("Failed to convert first frame to RgbaImage"))?;
+    let mut log_position = control.log_cursor;
+    if let Some(marker) = control.start_marker {
+        println!("Waiting for start marker '{}'...", marker);
+        match control
+            .log
+            .wait_for(
+                marker,
+                log_position,
+                control.marker_timeout,
+                control.shutdown,
+            )
+            .await
+        {
+            Some(position) => {
+                println!("Start marker seen, capturing.");
+                log_position = position;
+            }
+            None => println!(
+                "Warning: start marker '{}' not seen within {:.1}s, capturing anyway",
+                marker,
+                control.marker_timeout.as_secs_f32()
+            ),
+        }
+    }
+
+    // Capture first frame to determine GIF dimensions; the other frames are scheduled
+    // from it
+    let capture_start = input_session.start_frame_schedule(Duration::from_millis(frame_delay_ms));
+    let first_frame = source.capture()?;
     let width = first_frame.width() as u16;
     let height = first_frame.height() as u16;
 
-    let mut encoder = Encoder::new(&mut gif_file, width, height, &[])?;
-    encoder.set_repeat(Repeat::Infinite)?;
+    // A mismatch usually means display scaling between the window and its backing store
+    if let Ok((source_width, source_height)) = source.dimensions() {
+        if (source_width, source_height) != first_frame.dimensions() {
+            let scaling = display_scale((source_width, source_height), first_frame.dimensions())
+                .map(|scale| format!(" (display scaling of {:.0}%)", scale * 100.0))
+                .unwrap_or_default();
+            println!(
+                "Note: source reports {}x{} but captured frames are {}x{}{}",
+                source_width, source_height, width, height, scaling
+            );
+        }
+    }
 
-    println!("Creating GIF {}x{} for {}", width, height, binary_name);
+    println!(
+        "Creating {} {}x{} for {}",
+        format.name().to_uppercase(),
+        width,
+        height,
+        output_name
+    );
 
-    add_frame_to_gif(&mut encoder, first_frame, frame_delay_ms)?;
+    // Sample the emulation speed from the window title while frames are captured
+    let stop_sampling = Arc::new(AtomicBool::new(false));
+    let sampler_task = control.sync_to_emulator.then(|| {
+        let stop = stop_sampling.clone();
+        let window = input_session.window();
+        tokio::spawn(async move {
+            let start = Instant::now();
+            let mut samples = Vec::new();
+            while !stop.load(Ordering::Relaxed) {
+                if let Ok(window) = find_mgba_window(&window) {
+                    let elapsed_ms = start.elapsed().as_millis() as u64;
+                    samples.push((elapsed_ms, parse_title_stats(window.title())));
+                }
+                sleep(Duration::from_millis(TITLE_SAMPLE_INTERVAL_MS)).await;
+            }
+            samples
+        })
+    });
 
     // Capture remaining frames in parallel with time offsets
     let remaining_frames = frame_count - 1;
@@ -818,27 +11316,98 @@ async fn capture_binary_gif(
     );
 
     // Start during-capture input sequence in parallel if provided
-    let input_task = if !during_capture_actions.is_empty() {
+    let input_task = if !phases.during.is_empty() {
         println!("Starting during-capture input sequence...");
         Some(tokio::spawn({
-            let actions = during_capture_actions.to_vec();
-            async move { execute_input_sequence(&actions).await }
+            let tracks = phases.during.clone();
+            let session = input_session.clone();
+            let shutdown = control.shutdown.clone();
+            async move { send_tracks_to_mgba(&session, &tracks, &shutdown).await }
         }))
     } else {
         None
     };
 
+    // A script runs on a thread of its own, where its calls can block
+    let script_done = Arc::new(AtomicBool::new(false));
+    let script_task = phases.script.clone().map(|script| {
+        println!(
+            "Starting during-capture script {}...",
+            script.path().display()
+        );
+        let session = input_session.clone();
+        let host = CaptureScriptHost::new(input_session, control.key_mappings, &script_done);
+        tokio::task::spawn_blocking(move || {
+            if session.needs_focus() {
+                host.runtime.block_on(prepare_mgba_for_input(
+                    &session.window(),
+                    session.pacing.require_focus,
+                ))?;
+            }
+            script.run(Arc::new(host))
+        })
+    });
+
+    let live_task =
+        control.live_input.map(|live_input| {
+            tokio::spawn({
+                let live_input = live_input.clone();
+                let session = input_session.clone();
+                let key_mappings = control.key_mappings.clone();
+                let macros = control.macros.clone();
+                let options = control.parse_options;
+                async move {
+                    run_live_input(&live_input, &session, &key_mappings, &macros, options).await
+                }
+            })
+        });
+
+    // Frames scheduled after the stop marker appears are skipped
+    let stop_capture = Arc::new(AtomicBool::new(false));
+    let stop_watcher = control.stop_marker.map(|marker| {
+        let log = control.log.clone();
+        let marker = marker.to_string();
+        let stop = stop_capture.clone();
+        let capture_time = Duration::from_millis(frame_count as u64 * frame_delay_ms);
+        tokio::spawn(async move {
+            if log
+                .wait_for(&marker, log_position, capture_time, &stop)
+                .await
+                .is_some()
+            {
+                println!("Stop marker '{}' seen, ending capture.", marker);
+                stop.store(true, Ordering::Relaxed);
+            }
+        })
+    });
+
+    // Enter typed in the terminal ends an interactive recording like a stop marker
+    let enter_watcher = control.interactive.map(|interactive| {
+        let terminal = interactive.terminal.clone();
+        let stop = stop_capture.clone();
+        tokio::spawn(async move {
+            if let Ok(Some(_)) = terminal.next_line().await {
+                println!("Enter pressed, ending capture.");
+                stop.store(true, Ordering::Relaxed);
+            }
+        })
+    });
+
     let mut tasks = Vec::new();
 
     for i in 1..frame_count {
         let delay_ms = (i as u64) * frame_delay_ms;
+        let source = source.clone();
+        let stop = stop_capture.clone();
         let task = tokio::spawn(async move {
-            sleep(Duration::from_millis(delay_ms)).await;
-            let image = find_mgba_window()?.capture_image()?;
-            let rgba_image: RgbaImage =
-                ImageBuffer::from_raw(image.width(), image.height(), image.into_raw())
-                    .ok_or_else(|| anyhow::anyhow!("Failed to convert frame {} to RgbaImage", i))?;
-            Ok::<(u32, RgbaImage), anyhow::Error>((i, rgba_image))
+            sleep_until(capture_start + Duration::from_millis(delay_ms)).await;
+            if stop.load(Ordering::Relaxed) {
+                return Ok(None);
+            }
+            let rgba_image = source
+                .capture()
+                .map_err(|e| anyhow::anyhow!("Failed to capture frame {}: {}", i, e))?;
+            Ok::<Option<(u32, RgbaImage)>, anyhow::Error>(Some((i, rgba_image)))
         });
         tasks.push(task);
     }
@@ -847,10 +11416,49 @@ async fn capture_binary_gif(
     let mut frames = Vec::with_capacity(remaining_frames as usize);
 
     for task in tasks {
-        let result = task.await??;
-        frames.push(result);
+        if stop_capture.load(Ordering::Relaxed) {
+            task.abort();
+            continue;
+        }
+        if let Some(result) = task.await?? {
+            frames.push(result);
+        }
+    }
+
+    // The script ends with the capture; errors fail it once the emulator is closed
+    script_done.store(true, Ordering::Relaxed);
+    let script_error = match script_task {
+        Some(task) => match task.await {
+            Ok(Ok(())) => None,
+            Ok(Err(e)) => Some(e.to_string()),
+            Err(e) => Some(format!("script panicked: {}", e)),
+        },
+        None => None,
+    };
+    match &script_error {
+        Some(message) => println!("During-capture script failed: {}", message),
+        None if phases.script.is_some() => println!("During-capture script completed."),
+        None => {}
+    }
+
+    // Recording ends on schedule; actions typed after this wait for the next capture
+    for task in [live_task, enter_watcher].into_iter().flatten() {
+        task.abort();
+        let _ = task.await;
+    }
+
+    // Also ends the stop watcher if the marker never appeared
+    stop_capture.store(true, Ordering::Relaxed);
+    if let Some(task) = stop_watcher {
+        let _ = task.await;
     }
 
+    stop_sampling.store(true, Ordering::Relaxed);
+    let frame_delays = match sampler_task {
+        Some(task) => emulated_timing(&task.await?, frame_count, frame_delay_ms),
+        None => vec![frame_delay_ms; frame_count as usize],
+    };
+
     // Handle during-capture input task completion
     if let Some(task) = input_task {
         match task.await {
@@ -860,64 +11468,171 @@ async fn capture_binary_gif(
         }
     }
 
-    // Close mGBA window immediately after capture is complete
-    let _ = child.kill();
-    println!("Frame capture complete! mGBA window closed.");
+    // Execute after-capture input sequence while the emulator is still running
+    if !phases.after.is_empty() && !control.shutdown.load(Ordering::Relaxed) {
+        println!("Executing after-capture input sequence...");
+        match send_to_mgba_in(input_session, "after_capture", &phases.after).await {
+            Ok(()) => println!("After-capture input sequence completed."),
+            Err(e) => println!("After-capture input sequence failed: {}", e),
+        }
+    }
+    input_session.finish();
+
+    // Close mGBA window immediately after capture is complete (unless it is being reused)
+    if let Some(child) = emulator_to_close {
+        let _ = child.kill();
+        println!("Frame capture complete! mGBA window closed.");
+    } else {
+        println!("Frame capture complete!");
+    }
+
+    if let Some(message) = script_error {
+        return Ok(CaptureOutcome::ScriptFailed { message });
+    }
 
     // Ensure frames are in correct chronological order
     frames.sort_by_key(|(index, _)| *index);
 
-    println!("Building GIF from {} captured frames...", frame_count);
+    // A panicked ROM leaves agb's panic screen up, which would otherwise pass for a capture
+    let last_frame = frames.last().map_or(&first_frame, |(_, frame)| frame);
+    let previous_frame = match frames.len() {
+        0 => None,
+        1 => Some(&first_frame),
+        len => Some(&frames[len - 2].1),
+    };
+    let panic_message = control.log.panic_message(control.log_cursor).or_else(|| {
+        previous_frame
+            .filter(|previous| looks_like_panic_screen(previous, last_frame))
+            .map(|_| "the agb panic screen is showing (no panic message was logged)".to_string())
+    });
+    let panic = match panic_message {
+        Some(message) => {
+            let screenshot = control
+                .output_root
+                .file(&format!("{}_panic.png", output_name));
+            last_frame
+                .save(&screenshot)
+                .map_err(|e| anyhow::anyhow!("Failed to save {}: {}", screenshot, e))?;
+            println!("{} panicked: {}", output_name, message);
+            println!("Panic screen saved to {}", screenshot);
+            if !control.keep_failed_output {
+                println!("Skipping GIF (use --keep-failed-output to write it anyway)");
+                return Ok(CaptureOutcome::Panicked {
+                    message,
+                    screenshot,
+                    output_path: None,
+                });
+            }
+            Some((message, screenshot))
+        }
+        None => None,
+    };
+
+    // Filtered frames replace the captured ones; a failure fails the binary
+    let (first_frame, frames) = match control.frame_filter {
+        Some(filter) => {
+            let (indexes, mut images): (Vec<u32>, Vec<RgbaImage>) = frames.into_iter().unzip();
+            images.insert(0, first_frame);
+            let filter = filter.clone();
+            let mut filtered = tokio::task::spawn_blocking(move || filter.apply(images)).await??;
+            let first_frame = filtered.remove(0);
+            (first_frame, indexes.into_iter().zip(filtered).collect())
+        }
+        None => (first_frame, frames),
+    };
+
+    // Cropping, scaling and the rest, measured on the first frame, come last
+    let (first_frame, frames, (width, height)) = match control.post {
+        Some(chain) => {
+            let prepared = chain.prepare(&first_frame)?;
+            let (width, height) = prepared.dimensions();
+            println!("Post-processed frames are {}x{}", width, height);
+            let frames = frames
+                .into_iter()
+                .map(|(index, frame)| (index, prepared.apply(&frame)))
+                .collect();
+            (prepared.apply(&first_frame), frames, prepared.dimensions())
+        }
+        None => (first_frame, frames, (width as u32, height as u32)),
+    };
+
+    let output_path = control
+        .output_root
+        .file(&format!("{}.{}", output_name, format.extension()));
+    // A comment in the file, so the input of a capture can be replayed from it alone
+    let comment = phases
+        .fuzz_seed()
+        .map(|seed| format!("agbrs-capture fuzz seed {}", seed));
+    let mut encoder = AnimationEncoder::create(
+        format,
+        &output_path,
+        (width, height),
+        frames.len() as u32 + 1,
+        control.loop_count,
+        comment.as_deref(),
+    )?;
+
+    let kind = format.name().to_uppercase();
+    println!(
+        "Building {} from {} captured frames...",
+        kind,
+        frames.len() + 1
+    );
+    let frame_total = frames.len() + 1;
+    encoder.add_frame(first_frame, frame_delays[0])?;
     for (index, frame) in frames {
-        add_frame_to_gif(&mut encoder, frame, frame_delay_ms)?;
+        encoder.add_frame(frame, frame_delays[index as usize])?;
         if index % 10 == 0 {
             println!(
-                "Added frame {}/{} to GIF for {}",
+                "Added frame {}/{} to {} for {}",
                 index + 1,
                 frame_count,
-                binary_name
+                kind,
+                output_name
             );
         }
     }
+    encoder.finish()?;
 
-    println!("Created GIF: {}", gif_path);
-    Ok(())
+    println!("Created {}: {}", kind, output_path);
+    Ok(match panic {
+        Some((message, screenshot)) => CaptureOutcome::Panicked {
+            message,
+            screenshot,
+            output_path: Some(output_path),
+        },
+        None => CaptureOutcome::Written {
+            output_path,
+            frames: frame_total,
+        },
+    })
 }
 
-/// Converts RGBA image to GIF frame and adds to encoder with configurable timing
-fn add_frame_to_gif(
-    encoder: &mut Encoder<&mut File>,
-    rgba_image: RgbaImage,
+/// Turns title samples into per-frame GIF delays in emulated time, warning when the
+/// emulator ran well below full speed. Falls back to wall-clock delays when the title
+/// shows neither an fps display nor a frame counter.
+fn emulated_timing(
+    samples: &[(u64, TitleStats)],
+    frame_count: u32,
     frame_delay_ms: u64,
-) -> Result<()> {
-    // Convert RGBA to RGB (GIF doesn't support alpha channel)
-    let rgb_image: RgbImage =
-        ImageBuffer::from_fn(rgba_image.width(), rgba_image.height(), |x, y| {
-            let rgba_pixel = rgba_image.get_pixel(x, y);
-            image::Rgb([rgba_pixel[0], rgba_pixel[1], rgba_pixel[2]])
-        });
-
-    let mut frame = Frame::from_rgb(
-        rgb_image.width() as u16,
-        rgb_image.height() as u16,
-        rgb_image.as_raw(),
-    );
-    frame.delay = (frame_delay_ms / 10) as u16; // Convert ms to centiseconds
-
-    encoder.write_frame(&frame)?;
-    Ok(())
-}
-
-/// Finds the first window with "mgba" in the title (case-insensitive)
-fn find_mgba_window() -> Result<Window> {
-    let windows = Window::all()?;
+) -> Vec<u64> {
+    let speeds = emulation_speeds(samples);
+    if speeds.is_empty() {
+        println!("Note: mGBA's window title shows no fps or frame counter, using wall-clock frame timing");
+        return vec![frame_delay_ms; frame_count as usize];
+    }
 
-    for window in windows {
-        let title = window.title();
-        if title.to_lowercase().contains("mgba") {
-            return Ok(window);
-        }
+    let slowest = speeds
+        .iter()
+        .map(|(_, speed)| *speed)
+        .fold(f32::INFINITY, f32::min);
+    if slowest < SLOW_EMULATION_RATIO {
+        println!(
+            "Warning: emulation dropped to {:.1} fps (full speed is {:.1}); the host is struggling to keep up",
+            slowest * GBA_FPS,
+            GBA_FPS
+        );
     }
 
-    Err(anyhow::anyhow!("mGBA window not found"))
+    emulated_frame_delays(&speeds, frame_count, frame_delay_ms)
 }
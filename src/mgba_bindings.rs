@@ -0,0 +1,246 @@
+//! Keyboard bindings from mGBA's own configuration
+//!
+//! mGBA keeps its keyboard bindings in `config.ini`, in a section per frontend:
+//! `[gba.input.QT_K]` for mGBA-Qt, holding Qt key codes, and `[gba.input.SDLK]` for
+//! mGBA-SDL, holding SDL keycodes. Each GBA button is an entry like `keyA=88`, with -1
+//! for a button without a key. The codes are translated into the key names used in
+//! sequences, so the tool sends whatever mGBA is actually bound to.
+
+use crate::window::Emulator;
+use anyhow::Result;
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+/// mGBA's name of each GBA button in config.ini, with the button's field in
+/// `key_mappings`
+pub const BUTTONS: [(&str, &str); 10] = [
+    ("keyA", "a"),
+    ("keyB", "b"),
+    ("keySelect", "select"),
+    ("keyStart", "start"),
+    ("keyRight", "right"),
+    ("keyLeft", "left"),
+    ("keyUp", "up"),
+    ("keyDown", "down"),
+    ("keyR", "r_shoulder"),
+    ("keyL", "l_shoulder"),
+];
+
+/// Keyboard binding sections of config.ini and whether their codes are SDL keycodes
+/// rather than Qt key codes
+const QT_SECTION: (&str, bool) = ("gba.input.QT_K", false);
+const SDL_SECTION: (&str, bool) = ("gba.input.SDLK", true);
+
+/// SDL keycodes of keys without a character have this bit set over their scancode
+const SDL_SCANCODE_MASK: i64 = 0x4000_0000;
+
+/// A button by its `key_mappings` field, with the key name bound to it or why there is none
+pub type BoundKey = (&'static str, std::result::Result<String, String>);
+
+/// mGBA's keyboard bindings as read from its config file
+#[derive(Debug)]
+pub struct KeyBindings {
+    /// The config file read
+    pub path: PathBuf,
+    /// The section the bindings came from
+    pub section: &'static str,
+    /// The key bound to each button
+    pub keys: Vec<BoundKey>,
+}
+
+/// Reads mGBA's keyboard bindings from the user's config file. The section of the
+/// frontend in use is preferred, mGBA-Qt's when that isn't known.
+pub fn load(emulator: Emulator) -> Result<KeyBindings> {
+    let path =
+        config_path(std::env::consts::OS, |name| std::env::var_os(name)).ok_or_else(|| {
+            anyhow::anyhow!("Could not tell where mGBA keeps its configuration on this system")
+        })?;
+    read(path, emulator)
+}
+
+/// Reads mGBA's keyboard bindings from the config file at `path`
+pub fn read(path: PathBuf, emulator: Emulator) -> Result<KeyBindings> {
+    let contents = std::fs::read_to_string(&path).map_err(|e| {
+        anyhow::anyhow!(
+            "Could not read mGBA's configuration {} for its key bindings: {}",
+            path.display(),
+            e
+        )
+    })?;
+    let (section, keys) = parse(&contents, emulator).ok_or_else(|| {
+        anyhow::anyhow!(
+            "mGBA's configuration {} has no keyboard bindings ([{}] or [{}]); change a binding in mGBA once to have them saved",
+            path.display(),
+            QT_SECTION.0,
+            SDL_SECTION.0
+        )
+    })?;
+    Ok(KeyBindings {
+        path,
+        section,
+        keys,
+    })
+}
+
+/// Translates the keyboard bindings in the contents of a config.ini, returning the
+/// section they came from
+fn parse(contents: &str, emulator: Emulator) -> Option<(&'static str, Vec<BoundKey>)> {
+    let sections = match emulator {
+        Emulator::MgbaSdl => [SDL_SECTION, QT_SECTION],
+        Emulator::Auto | Emulator::MgbaQt => [QT_SECTION, SDL_SECTION],
+    };
+    let (section, sdl, entries) = sections
+        .into_iter()
+        .find_map(|(section, sdl)| Some((section, sdl, ini_section(contents, section)?)))?;
+
+    let keys = BUTTONS
+        .iter()
+        .map(|&(name, field)| {
+            let value = entries
+                .iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| *value);
+            let name_of = if sdl { sdl_key_name } else { qt_key_name };
+            let key = match value.map(|value| (value, value.parse::<i64>())) {
+                None => Err(format!("{} is not set", name)),
+                Some((_, Ok(-1))) => Err(format!("{} is not bound to a key", name)),
+                Some((_, Ok(code))) => name_of(code)
+                    .ok_or_else(|| format!("{}={} is a key this tool can't send", name, code)),
+                Some((value, Err(_))) => Err(format!("{}={} is not a key code", name, value)),
+            };
+            (field, key)
+        })
+        .collect();
+    Some((section, keys))
+}
+
+/// Where mGBA keeps config.ini on the operating system `os` (as in
+/// `std::env::consts::OS`), given a lookup of environment variables
+pub fn config_path(os: &str, var: impl Fn(&str) -> Option<OsString>) -> Option<PathBuf> {
+    let dir = match os {
+        "windows" => PathBuf::from(var("APPDATA")?).join("mGBA"),
+        "macos" => PathBuf::from(var("HOME")?).join("Library/Application Support/mGBA"),
+        _ => var("XDG_CONFIG_HOME")
+            .filter(|config_home| !config_home.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| var("HOME").map(|home| PathBuf::from(home).join(".config")))?
+            .join("mgba"),
+    };
+    Some(dir.join("config.ini"))
+}
+
+/// The `key=value` entries of an ini section, if the section exists
+fn ini_section<'a>(contents: &'a str, section: &str) -> Option<Vec<(&'a str, &'a str)>> {
+    let header = format!("[{}]", section);
+    let mut lines = contents.lines().skip_while(|line| line.trim() != header);
+    lines.next()?;
+    Some(
+        lines
+            .take_while(|line| !line.trim_start().starts_with('['))
+            .filter_map(|line| line.split_once('='))
+            .map(|(key, value)| (key.trim(), value.trim()))
+            .collect(),
+    )
+}
+
+/// Name of a key with a character, which Qt and SDL both code as that character
+fn character_key_name(c: char) -> Option<String> {
+    let name = match c {
+        'a'..='z' | '0'..='9' => return Some(c.to_string()),
+        ' ' => "space",
+        ',' => "comma",
+        '.' => "period",
+        '/' => "slash",
+        '\\' => "backslash",
+        ';' => "semicolon",
+        '\'' => "quote",
+        '[' => "left_bracket",
+        ']' => "right_bracket",
+        '-' => "minus",
+        '=' => "equals",
+        '`' => "grave",
+        _ => return None,
+    };
+    Some(name.to_string())
+}
+
+/// Name of a Qt key code; Qt codes letters as their uppercase character
+fn qt_key_name(code: i64) -> Option<String> {
+    if let Some(c) = u32::try_from(code).ok().and_then(char::from_u32) {
+        if c.is_ascii() {
+            return character_key_name(c.to_ascii_lowercase());
+        }
+    }
+    // Qt calls the Command key Control on macOS, and the Control key Meta
+    let (control, meta) = if cfg!(target_os = "macos") {
+        ("meta", "ctrl")
+    } else {
+        ("ctrl", "meta")
+    };
+    let name = match code {
+        0x0100_0000 => "escape",
+        0x0100_0001 => "tab",
+        0x0100_0003 => "backspace",
+        0x0100_0004 | 0x0100_0005 => "enter",
+        0x0100_0006 => "insert",
+        0x0100_0007 => "delete",
+        0x0100_0010 => "home",
+        0x0100_0011 => "end",
+        0x0100_0012 => "left",
+        0x0100_0013 => "up",
+        0x0100_0014 => "right",
+        0x0100_0015 => "down",
+        0x0100_0016 => "pageup",
+        0x0100_0017 => "pagedown",
+        0x0100_0020 => "shift",
+        0x0100_0021 => control,
+        0x0100_0022 => meta,
+        0x0100_0023 => "alt",
+        0x0100_0030..=0x0100_003b => return Some(format!("f{}", code - 0x0100_0030 + 1)),
+        _ => return None,
+    };
+    Some(name.to_string())
+}
+
+/// Name of an SDL keycode
+fn sdl_key_name(code: i64) -> Option<String> {
+    let name = match code {
+        8 => "backspace",
+        9 => "tab",
+        13 => "enter",
+        27 => "escape",
+        127 => "delete",
+        0..=126 => return character_key_name(char::from_u32(code as u32)?),
+        _ if code & SDL_SCANCODE_MASK == 0 => return None,
+        _ => match code & !SDL_SCANCODE_MASK {
+            58..=69 => return Some(format!("f{}", (code & !SDL_SCANCODE_MASK) - 57)),
+            73 => "insert",
+            74 => "home",
+            75 => "pageup",
+            77 => "end",
+            78 => "pagedown",
+            79 => "right",
+            80 => "left",
+            81 => "down",
+            82 => "up",
+            84 => "kp_divide",
+            85 => "kp_multiply",
+            86 => "kp_subtract",
+            87 => "kp_add",
+            88 => "enter",
+            89..=97 => return Some(format!("kp_{}", (code & !SDL_SCANCODE_MASK) - 88)),
+            98 => "kp_0",
+            99 => "kp_decimal",
+            224 => "lctrl",
+            225 => "lshift",
+            226 => "lalt",
+            227 => "lmeta",
+            228 => "rctrl",
+            229 => "rshift",
+            230 => "ralt",
+            231 => "rmeta",
+            _ => return None,
+        },
+    };
+    Some(name.to_string())
+}
@@ -0,0 +1,209 @@
+//! Temporary mGBA configuration overrides
+//!
+//! mGBA reads `$XDG_CONFIG_HOME/mgba/config.ini` on Linux. Overrides are applied to a
+//! copy of the user's configuration in a temporary config home, so key bindings and
+//! other settings carry over while the user's own files are left untouched. Each run
+//! has its own config home, removed when the run ends. An emulator started directly
+//! takes them as `-C key=value` arguments instead, on any platform.
+
+use anyhow::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tempfile::TempDir;
+
+/// `displayDriver` value selecting mGBA-Qt's software (QPainter) renderer
+const SOFTWARE_DISPLAY_DRIVER: &str = "0";
+
+/// Where users change the setting by hand, for messages
+pub const DISPLAY_DRIVER_SETTING: &str =
+    "Settings > Display > Display driver in mGBA, or displayDriver under [ports.qt] in its config.ini";
+
+/// Frontend sections of config.ini; settings are written to both so either frontend
+/// picks them up
+const PORT_SECTIONS: [&str; 2] = ["ports.qt", "ports.sdl"];
+
+/// Settings applied to mGBA for every launch
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConfigOverrides {
+    /// Use the software display driver (`--force-software-renderer`, or black frames)
+    pub software_renderer: bool,
+    /// Settings for clean captures (`--managed-emulator-config`)
+    pub managed: Option<ManagedConfig>,
+}
+
+/// Settings that keep mGBA out of the way of captures
+#[derive(Debug, Clone, Copy)]
+pub struct ManagedConfig {
+    /// Window scale factor
+    pub scale: u32,
+    /// Keep the fps display in the window title, which `--sync-to-emulator` reads
+    pub title_stats: bool,
+}
+
+impl ConfigOverrides {
+    /// Whether anything is overridden
+    pub fn is_empty(&self) -> bool {
+        !self.software_renderer && self.managed.is_none()
+    }
+
+    /// Config keys and values to set
+    fn values(&self) -> Vec<(&'static str, String)> {
+        let mut values = Vec::new();
+        if self.software_renderer {
+            values.push(("displayDriver", SOFTWARE_DISPLAY_DRIVER.to_string()));
+        }
+        if let Some(managed) = self.managed {
+            values.push(("scaleMultiplier", managed.scale.to_string()));
+            values.push(("mute", "1".to_string()));
+            values.push(("pauseOnFocusLost", "0".to_string()));
+            values.push(("pauseOnMinimize", "0".to_string()));
+            if !managed.title_stats {
+                values.push(("showFps", "0".to_string()));
+            }
+        }
+        values
+    }
+
+    /// The overrides as `-C key=value` arguments for an emulator started directly
+    pub fn arguments(&self) -> Vec<String> {
+        self.values()
+            .into_iter()
+            .flat_map(|(key, value)| ["-C".to_string(), format!("{}={}", key, value)])
+            .collect()
+    }
+}
+
+/// Whether config overrides can be applied to an emulator started through the cargo
+/// runner on this platform
+pub fn overrides_supported() -> bool {
+    cfg!(target_os = "linux")
+}
+
+/// The config home of one run's emulators, a temporary directory created on first use
+/// and removed when this is dropped at the end of the run
+pub struct ConfigHome {
+    dir: Mutex<Option<TempDir>>,
+    /// Leave the directory behind for an emulator that outlives the run
+    keep: bool,
+}
+
+impl ConfigHome {
+    /// With `keep`, the directory stays after the run for an emulator left running
+    /// (`--keep-alive`) that may still read it
+    pub fn new(keep: bool) -> Self {
+        Self {
+            dir: Mutex::new(None),
+            keep,
+        }
+    }
+
+    /// Writes an mGBA configuration that applies `overrides` and returns the config
+    /// home, to be passed to mGBA as `XDG_CONFIG_HOME`
+    pub fn write(&self, overrides: &ConfigOverrides) -> Result<PathBuf> {
+        let mut dir = self.dir.lock().unwrap();
+        if dir.is_none() {
+            *dir = Some(
+                tempfile::Builder::new()
+                    .prefix("agbrs-capture-mgba-")
+                    .tempdir()
+                    .map_err(|e| {
+                        anyhow::anyhow!("Failed to create a temporary mGBA config home: {}", e)
+                    })?,
+            );
+        }
+        let config_home = dir.as_ref().unwrap().path().to_path_buf();
+        write_config(&config_home, overrides)?;
+        Ok(config_home)
+    }
+}
+
+impl Drop for ConfigHome {
+    fn drop(&mut self) {
+        if let Some(dir) = self.dir.get_mut().unwrap().take() {
+            if self.keep {
+                let _ = dir.keep();
+            }
+        }
+    }
+}
+
+/// Writes the user's mGBA configuration with `overrides` applied to `config_home`
+fn write_config(config_home: &Path, overrides: &ConfigOverrides) -> Result<()> {
+    let mgba_dir = config_home.join("mgba");
+    fs::create_dir_all(&mgba_dir)?;
+
+    // Start from the user's configuration so bindings and preferences still apply
+    if let Some(user_dir) = user_config_dir() {
+        if let Ok(entries) = fs::read_dir(&user_dir) {
+            for entry in entries.flatten() {
+                if entry.file_type().is_ok_and(|file_type| file_type.is_file()) {
+                    fs::copy(entry.path(), mgba_dir.join(entry.file_name()))?;
+                }
+            }
+        }
+    }
+
+    let config_path = mgba_dir.join("config.ini");
+    let contents = fs::read_to_string(&config_path).unwrap_or_default();
+    fs::write(&config_path, apply(&contents, overrides)).map_err(|e| {
+        anyhow::anyhow!(
+            "Failed to write mGBA config override {}: {}",
+            config_path.display(),
+            e
+        )
+    })?;
+    Ok(())
+}
+
+/// The contents of a config.ini with `overrides` set in each frontend's section, and
+/// everything else left as it was
+pub fn apply(contents: &str, overrides: &ConfigOverrides) -> String {
+    let mut contents = contents.to_string();
+    for section in PORT_SECTIONS {
+        for (key, value) in overrides.values() {
+            contents = set_ini_value(&contents, section, key, &value);
+        }
+    }
+    contents
+}
+
+/// The user's mGBA configuration directory, if it exists
+fn user_config_dir() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    let dir = config_home.join("mgba");
+    dir.is_dir().then_some(dir)
+}
+
+/// Sets `key=value` in an ini section, replacing an existing value and creating the
+/// section if needed
+fn set_ini_value(contents: &str, section: &str, key: &str, value: &str) -> String {
+    let header = format!("[{}]", section);
+    let mut lines: Vec<String> = contents.lines().map(str::to_string).collect();
+
+    let Some(start) = lines.iter().position(|line| line.trim() == header) else {
+        if lines.last().is_some_and(|line| !line.trim().is_empty()) {
+            lines.push(String::new());
+        }
+        lines.push(header);
+        lines.push(format!("{}={}", key, value));
+        return lines.join("\n") + "\n";
+    };
+
+    let end = lines[start + 1..]
+        .iter()
+        .position(|line| line.trim_start().starts_with('['))
+        .map_or(lines.len(), |offset| start + 1 + offset);
+    let existing = lines[start + 1..end].iter().position(|line| {
+        line.split_once('=')
+            .is_some_and(|(name, _)| name.trim() == key)
+    });
+
+    match existing {
+        Some(offset) => lines[start + 1 + offset] = format!("{}={}", key, value),
+        None => lines.insert(start + 1, format!("{}={}", key, value)),
+    }
+    lines.join("\n") + "\n"
+}
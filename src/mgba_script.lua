@@ -0,0 +1,64 @@
+-- Input shim loaded into mGBA by agbrs-capture for --input-backend mgba-script.
+-- Connects back to the tool and holds the GBA buttons it sends. Each line from the
+-- tool is "keys <mask>", the buttons to hold as a KEYINPUT bitmask (A = 1, B = 2, ...).
+-- The buttons are applied when the game reads them, so a change lands on the next
+-- frame that polls input.
+
+local PORT = {{PORT}}
+
+local client = nil
+local buffer = ""
+-- Buttons the tool wants held, and the ones added to the game's input so far
+local held = 0
+local applied = 0
+
+local function disconnect()
+	if client then
+		client:close()
+		client = nil
+	end
+	held = 0
+end
+
+local function received()
+	while client do
+		local data, err = client:receive(1024)
+		if not data then
+			if err ~= socket.ERRORS.AGAIN then
+				disconnect()
+			end
+			return
+		end
+		buffer = buffer .. data
+		for line in buffer:gmatch("([^\n]*)\n") do
+			local mask = line:match("^keys (%d+)$")
+			if mask then
+				held = tonumber(mask) & 0x3FF
+			end
+		end
+		buffer = buffer:match("[^\n]*$")
+	end
+end
+
+-- Sockets are polled every frame, so buttons arrive however the frontend runs
+callbacks:add("frame", function()
+	if client then
+		client:poll()
+	end
+end)
+
+callbacks:add("keysRead", function()
+	emu:clearKeys(applied & ~held)
+	emu:addKeys(held)
+	applied = held
+end)
+
+local sock, err = socket.connect("127.0.0.1", PORT)
+if sock then
+	client = sock
+	client:add("received", received)
+	client:add("error", disconnect)
+	client:send("ready\n")
+else
+	console:error("agbrs-capture: could not connect to the tool on port " .. PORT .. ": " .. tostring(err))
+end
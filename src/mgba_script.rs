@@ -0,0 +1,185 @@
+//! Input through mGBA's scripting for `--input-backend mgba-script`
+//!
+//! mGBA 0.10 and newer run Lua scripts that can set the GBA's key state directly. The
+//! emulator is started with `--script` and a small bundled shim, which connects back to
+//! the tool over a local TCP socket and holds whatever buttons it is sent. Input then
+//! reaches the game regardless of window focus, keyboard layout or compositor, and a
+//! change lands on the next frame the game polls its buttons. Only emulators started
+//! directly take the extra argument, so binaries launched through the cargo runner keep
+//! using the keyboard.
+
+use anyhow::Result;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The shim mGBA loads, with `{{PORT}}` standing for the port to connect back to
+const SHIM: &str = include_str!("mgba_script.lua");
+
+/// Bit of each GBA button in the KEYINPUT mask, by its `key_mappings` field
+const BUTTON_BITS: [(&str, u16); 10] = [
+    ("a", 1 << 0),
+    ("b", 1 << 1),
+    ("select", 1 << 2),
+    ("start", 1 << 3),
+    ("right", 1 << 4),
+    ("left", 1 << 5),
+    ("up", 1 << 6),
+    ("down", 1 << 7),
+    ("r_shoulder", 1 << 8),
+    ("l_shoulder", 1 << 9),
+];
+
+/// Time between looks for the shim's connection
+const ACCEPT_POLL: Duration = Duration::from_millis(50);
+
+/// Whether an mGBA executable can load scripts from its command line, judged by its
+/// `--help` listing `--script`
+pub fn supports_scripts(program: &Path) -> bool {
+    Command::new(program)
+        .arg("--help")
+        .output()
+        .is_ok_and(|output| {
+            String::from_utf8_lossy(&output.stdout).contains("--script")
+                || String::from_utf8_lossy(&output.stderr).contains("--script")
+        })
+}
+
+/// Listens for the shims of the emulators started this run. The shim file is removed
+/// when the server is dropped.
+pub struct ScriptServer {
+    listener: TcpListener,
+    shim: PathBuf,
+    /// The connection of the emulator running now
+    current: Mutex<Option<Arc<ScriptConnection>>>,
+}
+
+impl ScriptServer {
+    /// Listens on a free local port and writes the shim that connects to it
+    pub fn start() -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .map_err(|e| anyhow::anyhow!("Failed to listen for mGBA's script: {}", e))?;
+        listener.set_nonblocking(true)?;
+        let port = listener.local_addr()?.port();
+        let shim = std::env::temp_dir().join(format!("agbrs-capture-{}.lua", std::process::id()));
+        std::fs::write(&shim, SHIM.replace("{{PORT}}", &port.to_string())).map_err(|e| {
+            anyhow::anyhow!("Failed to write the mGBA script {}: {}", shim.display(), e)
+        })?;
+        Ok(Self {
+            listener,
+            shim,
+            current: Mutex::new(None),
+        })
+    }
+
+    /// Arguments that make mGBA load the shim
+    pub fn arguments(&self) -> Vec<String> {
+        vec!["--script".to_string(), self.shim.display().to_string()]
+    }
+
+    /// The connection of the emulator that started last, waiting up to `timeout` for
+    /// its shim to connect. A reused emulator keeps its connection.
+    pub fn connection(&self, timeout: Duration) -> Result<Arc<ScriptConnection>> {
+        let deadline = Instant::now() + timeout;
+        let mut current = self.current.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    let connection = Arc::new(ScriptConnection::handshake(stream)?);
+                    *current = Some(connection.clone());
+                    return Ok(connection);
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => return Err(anyhow::anyhow!("Failed to accept mGBA's script: {}", e)),
+            }
+            if let Some(connection) = current.as_ref().filter(|connection| connection.is_open()) {
+                return Ok(connection.clone());
+            }
+            if Instant::now() >= deadline {
+                return Err(anyhow::anyhow!(
+                    "mGBA's script did not connect within {}s",
+                    timeout.as_secs()
+                ));
+            }
+            std::thread::sleep(ACCEPT_POLL);
+        }
+    }
+}
+
+impl Drop for ScriptServer {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.shim);
+    }
+}
+
+/// The shim of one running emulator; every button is released when dropped
+pub struct ScriptConnection {
+    stream: Mutex<TcpStream>,
+    /// Buttons held, as a KEYINPUT mask
+    keys: Mutex<u16>,
+}
+
+impl ScriptConnection {
+    /// Waits for the shim to report that it is ready
+    fn handshake(stream: TcpStream) -> Result<Self> {
+        stream.set_nonblocking(false)?;
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+        let mut line = String::new();
+        BufReader::new(&stream)
+            .read_line(&mut line)
+            .map_err(|e| anyhow::anyhow!("mGBA's script did not finish connecting: {}", e))?;
+        if line.trim() != "ready" {
+            return Err(anyhow::anyhow!(
+                "Unexpected greeting from mGBA's script: {:?}",
+                line.trim()
+            ));
+        }
+        Ok(Self {
+            stream: Mutex::new(stream),
+            keys: Mutex::new(0),
+        })
+    }
+
+    /// Whether the emulator is still connected
+    fn is_open(&self) -> bool {
+        let stream = self.stream.lock().unwrap_or_else(|e| e.into_inner());
+        let mut byte = [0u8];
+        let _ = stream.set_nonblocking(true);
+        // The shim never writes after its greeting, so a read only ends on a close
+        let open = matches!(stream.peek(&mut byte), Err(e) if e.kind() == ErrorKind::WouldBlock);
+        let _ = stream.set_nonblocking(false);
+        open
+    }
+
+    /// Presses or releases the button with the `key_mappings` field `button`
+    pub fn set(&self, button: &str, pressed: bool) -> Result<()> {
+        let bit = BUTTON_BITS
+            .iter()
+            .find(|(field, _)| *field == button)
+            .map(|&(_, bit)| bit)
+            .ok_or_else(|| anyhow::anyhow!("mGBA's script has no button {}", button))?;
+        let mut keys = self.keys.lock().unwrap_or_else(|e| e.into_inner());
+        let held = if pressed { *keys | bit } else { *keys & !bit };
+        self.send(held)?;
+        *keys = held;
+        Ok(())
+    }
+
+    fn send(&self, keys: u16) -> Result<()> {
+        let mut stream = self.stream.lock().unwrap_or_else(|e| e.into_inner());
+        writeln!(stream, "keys {}", keys)
+            .map_err(|e| anyhow::anyhow!("Failed to send buttons to mGBA's script: {}", e))
+    }
+}
+
+impl Drop for ScriptConnection {
+    fn drop(&mut self) {
+        let _ = self.send(0);
+        let stream = self.stream.lock().unwrap_or_else(|e| e.into_inner());
+        let _ = stream.shutdown(Shutdown::Both);
+    }
+}
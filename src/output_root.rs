@@ -0,0 +1,99 @@
+//! The directory a run writes to: `out/` unless `--output-dir` or `settings.output.dir`
+//! names another
+//!
+//! Everything a run writes goes under it: the captures with their screenshots and input
+//! logs, the run logs and the run manifest. `--output-dir` is taken as given, relative to
+//! the current directory like other paths on the command line. `settings.output.dir` is
+//! relative to the project directory, and may only lead outside of it with
+//! `--allow-external-output`, so a shared config can't make a run write anywhere.
+
+use anyhow::Result;
+use std::path::{Component, Path, PathBuf};
+
+/// The directory written to unless one is configured, in the current directory
+pub const DEFAULT_DIR: &str = "out";
+
+/// Where a run writes, and which setting chose it
+#[derive(Debug, Clone)]
+pub struct OutputRoot {
+    pub dir: PathBuf,
+    /// `cli`, `settings` or `builtin`
+    pub source: &'static str,
+}
+
+impl OutputRoot {
+    /// Picks the directory: `--output-dir`, else `settings.output.dir` resolved against
+    /// `project_dir`, else `out`. A configured directory outside the project fails
+    /// unless `allow_external`.
+    pub fn resolve(
+        project_dir: &Path,
+        cli: Option<&Path>,
+        configured: Option<&str>,
+        allow_external: bool,
+    ) -> Result<Self> {
+        if let Some(dir) = cli {
+            return Ok(Self {
+                dir: dir.to_path_buf(),
+                source: "cli",
+            });
+        }
+        let Some(configured) = configured else {
+            return Ok(Self {
+                dir: PathBuf::from(DEFAULT_DIR),
+                source: "builtin",
+            });
+        };
+        let project = normalize(&std::path::absolute(project_dir)?);
+        let dir = normalize(&project.join(configured));
+        if !dir.starts_with(&project) && !allow_external {
+            return Err(anyhow::anyhow!(
+                "settings.output.dir '{}' leads to {}, outside the project {}; pass --allow-external-output to write there",
+                configured,
+                dir.display(),
+                project.display()
+            ));
+        }
+        // Shown relative to where the tool runs when it is below it, as `out` is
+        let dir = std::env::current_dir()
+            .ok()
+            .and_then(|current| Some(dir.strip_prefix(current).ok()?.to_path_buf()))
+            .filter(|relative| !relative.as_os_str().is_empty())
+            .unwrap_or(dir);
+        Ok(Self {
+            dir,
+            source: "settings",
+        })
+    }
+
+    /// Path of `name` inside the directory, as it is printed
+    pub fn file(&self, name: &str) -> String {
+        self.dir.join(name).display().to_string()
+    }
+
+    /// Creates the directory with its parents, ready for the run to write to
+    pub fn create(&self) -> Result<()> {
+        std::fs::create_dir_all(&self.dir).map_err(|e| {
+            anyhow::anyhow!(
+                "Failed to create the output directory {}: {}",
+                self.dir.display(),
+                e
+            )
+        })
+    }
+}
+
+/// `path` with its `.` and `..` components resolved without touching the filesystem, so
+/// a directory that doesn't exist yet can be checked
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
@@ -0,0 +1,72 @@
+//! Pre-flight check for the permissions capture and input need
+//!
+//! macOS withholds screen contents and drops synthetic key presses until the app running
+//! the tool (usually the terminal) is granted Screen Recording and Accessibility
+//! permission. Nothing fails loudly without them: windows capture as black and inputs
+//! vanish. Both are checked before anything is built, and macOS is asked to show its
+//! permission prompts for whichever is missing.
+
+use anyhow::Result;
+
+/// Checks Screen Recording and Accessibility permission, failing with instructions when
+/// either is missing
+#[cfg(target_os = "macos")]
+pub fn check_permissions() -> Result<()> {
+    use core_graphics::access::ScreenCaptureAccess;
+
+    let screen_recording = ScreenCaptureAccess.preflight();
+    // SAFETY: takes no arguments and only reads the process's trust status
+    let accessibility = unsafe { AXIsProcessTrusted() };
+    if screen_recording && accessibility {
+        return Ok(());
+    }
+
+    // Adds the app to the lists in System Settings, with a prompt the first time
+    if !screen_recording {
+        ScreenCaptureAccess.request();
+    }
+    if !accessibility {
+        request_accessibility();
+    }
+
+    let status = |granted: bool| if granted { "granted" } else { "MISSING" };
+    Err(anyhow::anyhow!(
+        "Missing macOS permissions:\n  Screen Recording: {} (needed to capture the mGBA window)\n  Accessibility: {} (needed to send key presses to mGBA)\nGrant them to your terminal app in System Settings > Privacy & Security, then restart the terminal and run again.",
+        status(screen_recording),
+        status(accessibility)
+    ))
+}
+
+/// Nothing to check outside macOS
+#[cfg(not(target_os = "macos"))]
+pub fn check_permissions() -> Result<()> {
+    Ok(())
+}
+
+/// Asks macOS to show the Accessibility permission prompt
+#[cfg(target_os = "macos")]
+fn request_accessibility() {
+    use core_foundation::base::TCFType;
+    use core_foundation::boolean::CFBoolean;
+    use core_foundation::dictionary::CFDictionary;
+    use core_foundation::string::{CFString, CFStringRef};
+
+    extern "C" {
+        static kAXTrustedCheckOptionPrompt: CFStringRef;
+    }
+
+    // SAFETY: the option key is a static CFString and the dictionary outlives the call
+    unsafe {
+        let prompt = CFString::wrap_under_get_rule(kAXTrustedCheckOptionPrompt);
+        let options = CFDictionary::from_CFType_pairs(&[(prompt, CFBoolean::true_value())]);
+        AXIsProcessTrustedWithOptions(options.as_concrete_TypeRef());
+    }
+}
+
+#[cfg(target_os = "macos")]
+#[link(name = "ApplicationServices", kind = "framework")]
+extern "C" {
+    fn AXIsProcessTrusted() -> bool;
+    fn AXIsProcessTrustedWithOptions(options: core_foundation::dictionary::CFDictionaryRef)
+        -> bool;
+}
@@ -0,0 +1,484 @@
+//! Image processing applied to every frame of a capture before it is encoded
+//!
+//! A binary's `post` block, `post` in `settings.default` and the matching flags choose
+//! the steps, which always run in this order, however they are written:
+//!
+//! 1. `region`: crop to a rectangle of the captured frame
+//! 2. `crop = "auto"`: trim borders of one solid color, measured on the first frame
+//! 3. `scale`: enlarge by a whole factor, keeping pixels sharp
+//! 4. `filters`, in the order listed: `lcd` darkens the gaps between the GBA's pixels
+//!    (which needs a scale of 2 or more), `grayscale` removes the color
+//! 5. `caption`: a line of text in a band added below the frame
+//!
+//! Every frame of a capture is cut and scaled the same way, so the file has one size.
+
+use anyhow::Result;
+use clap::ValueEnum;
+use image::{Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Largest `scale`, which keeps a scaled GBA screen well within what GIFs can hold
+pub const MAX_SCALE: u32 = 8;
+
+/// How much of its brightness a pixel of the `lcd` grid keeps
+const LCD_GRID_BRIGHTNESS: f32 = 0.6;
+
+/// Caption text is drawn this many pixels (before scaling) away from the band's edges
+const CAPTION_PADDING: u32 = 2;
+
+/// Whether borders of one solid color are cropped away
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Crop {
+    /// Trim borders of one solid color, as found on the first frame
+    Auto,
+    /// Keep the frame as captured, overriding an inherited `auto`
+    None,
+}
+
+/// A filter applied to the scaled frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Filter {
+    /// Darkened lines between the GBA's pixels, like the grid of its screen
+    Lcd,
+    /// Shades of gray
+    Grayscale,
+}
+
+impl Filter {
+    /// Name as written in the config
+    pub fn name(self) -> &'static str {
+        match self {
+            Filter::Lcd => "lcd",
+            Filter::Grayscale => "grayscale",
+        }
+    }
+}
+
+/// A rectangle of the frame, written `x,y,width,height`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    /// Parses `x,y,width,height`
+    pub fn parse(text: &str) -> Result<Self> {
+        let invalid = || {
+            anyhow::anyhow!(
+                "Invalid region '{}' (expected x,y,width,height in pixels, like 0,0,240,160)",
+                text
+            )
+        };
+        let numbers = text
+            .split(',')
+            .map(|number| number.trim().parse::<u32>().map_err(|_| invalid()))
+            .collect::<Result<Vec<_>>>()?;
+        let [x, y, width, height] = numbers[..] else {
+            return Err(invalid());
+        };
+        if width == 0 || height == 0 {
+            return Err(anyhow::anyhow!(
+                "Invalid region '{}': its width and height must be 1 or more",
+                text
+            ));
+        }
+        Ok(Region {
+            x,
+            y,
+            width,
+            height,
+        })
+    }
+
+    /// Whether the region lies within a frame of `width` by `height`
+    fn fits(&self, (width, height): (u32, u32)) -> bool {
+        self.x
+            .checked_add(self.width)
+            .is_some_and(|right| right <= width)
+            && self
+                .y
+                .checked_add(self.height)
+                .is_some_and(|bottom| bottom <= height)
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{},{},{}", self.x, self.y, self.width, self.height)
+    }
+}
+
+impl TryFrom<String> for Region {
+    type Error = String;
+
+    fn try_from(text: String) -> std::result::Result<Self, Self::Error> {
+        Region::parse(&text).map_err(|e| e.to_string())
+    }
+}
+
+impl From<Region> for String {
+    fn from(region: Region) -> Self {
+        region.to_string()
+    }
+}
+
+/// The `post` block of a binary's entry or of `settings.default`, field by field over
+/// the flags
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PostConfig {
+    /// `"auto"` to trim solid borders, or `"none"`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crop: Option<Crop>,
+    /// Rectangle to crop the captured frame to, as `"x,y,width,height"`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<Region>,
+    /// Whole factor to enlarge frames by
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scale: Option<u32>,
+    /// Filters applied after scaling, in order
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filters: Option<Vec<Filter>>,
+    /// Line of text shown below the frame
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub caption: Option<String>,
+}
+
+impl PostConfig {
+    /// Everything wrong with the block that shows without any frames, as
+    /// `field: problem`
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if let Some(problem) = self.scale.and_then(scale_problem) {
+            problems.push(format!("scale: {}", problem));
+        }
+        if let Some(problem) = self.caption.as_deref().and_then(caption_problem) {
+            problems.push(format!("caption: {}", problem));
+        }
+        problems
+    }
+}
+
+/// Why `scale` can't be used, if it can't
+pub fn scale_problem(scale: u32) -> Option<String> {
+    (!(1..=MAX_SCALE).contains(&scale))
+        .then(|| format!("{} is out of range (1 to {})", scale, MAX_SCALE))
+}
+
+/// Why `caption` can't be drawn, if it can't
+pub fn caption_problem(caption: &str) -> Option<String> {
+    if caption.trim().is_empty() {
+        return Some("the caption is empty".to_string());
+    }
+    caption.chars().find(|c| glyph(*c).is_none()).map(|c| {
+        format!(
+            "'{}' can't be drawn; captions are limited to printable ASCII",
+            c
+        )
+    })
+}
+
+/// The post-processing steps of a binary, resolved from its config and the flags
+#[derive(Debug, Clone, Default)]
+pub struct PostChain {
+    pub region: Option<Region>,
+    pub auto_crop: bool,
+    pub scale: u32,
+    pub filters: Vec<Filter>,
+    pub caption: Option<String>,
+}
+
+impl PostChain {
+    /// Whether frames are left as captured
+    pub fn is_empty(&self) -> bool {
+        self.region.is_none()
+            && !self.auto_crop
+            && self.scale <= 1
+            && self.filters.is_empty()
+            && self.caption.is_none()
+    }
+
+    /// Checks the steps against each other
+    pub fn check(&self) -> Result<()> {
+        if let Some(problem) = scale_problem(self.scale) {
+            return Err(anyhow::anyhow!("Invalid scale: {}", problem));
+        }
+        if let Some(problem) = self.caption.as_deref().and_then(caption_problem) {
+            return Err(anyhow::anyhow!("Invalid caption: {}", problem));
+        }
+        if self.filters.contains(&Filter::Lcd) && self.scale < 2 {
+            return Err(anyhow::anyhow!(
+                "The lcd filter needs a scale of 2 or more to leave room for its grid"
+            ));
+        }
+        Ok(())
+    }
+
+    /// The steps in the order they run, as printed
+    pub fn describe(&self) -> String {
+        let mut steps = Vec::new();
+        if let Some(region) = self.region {
+            steps.push(format!("region {}", region));
+        }
+        if self.auto_crop {
+            steps.push("auto-crop".to_string());
+        }
+        if self.scale > 1 {
+            steps.push(format!("scale {}x", self.scale));
+        }
+        steps.extend(self.filters.iter().map(|filter| filter.name().to_string()));
+        if let Some(caption) = &self.caption {
+            steps.push(format!("caption \"{}\"", caption));
+        }
+        steps.join(", then ")
+    }
+
+    /// Measures the steps on the first frame of a capture: the region must lie within
+    /// it, and the caption must fit across the result
+    pub fn prepare(&self, first_frame: &RgbaImage) -> Result<PreparedChain> {
+        let mut crop = Region {
+            x: 0,
+            y: 0,
+            width: first_frame.width(),
+            height: first_frame.height(),
+        };
+        if let Some(region) = self.region {
+            if !region.fits(first_frame.dimensions()) {
+                return Err(anyhow::anyhow!(
+                    "Region {} doesn't fit in the {}x{} captured frame",
+                    region,
+                    first_frame.width(),
+                    first_frame.height()
+                ));
+            }
+            crop = region;
+        }
+        if self.auto_crop {
+            match solid_border_crop(first_frame, crop) {
+                Some(trimmed) => crop = trimmed,
+                None => println!("Note: auto-crop skipped, the first frame is a single color"),
+            }
+        }
+
+        let caption_band = match &self.caption {
+            Some(caption) => {
+                let text_width = caption_width(caption) * self.scale;
+                let frame_width = crop.width * self.scale;
+                if text_width + 2 * CAPTION_PADDING * self.scale > frame_width {
+                    return Err(anyhow::anyhow!(
+                        "Caption \"{}\" is {} pixels wide, too wide for the {}-pixel frame; shorten it",
+                        caption,
+                        text_width,
+                        frame_width
+                    ));
+                }
+                (GLYPH_HEIGHT + 2 * CAPTION_PADDING) * self.scale
+            }
+            None => 0,
+        };
+        Ok(PreparedChain {
+            chain: self.clone(),
+            crop,
+            caption_band,
+        })
+    }
+}
+
+/// A [`PostChain`] measured on the first frame of a capture
+#[derive(Debug, Clone)]
+pub struct PreparedChain {
+    chain: PostChain,
+    /// The part of each frame that is kept
+    crop: Region,
+    /// Height of the caption band, after scaling
+    caption_band: u32,
+}
+
+impl PreparedChain {
+    /// Size of the processed frames
+    pub fn dimensions(&self) -> (u32, u32) {
+        let scale = self.chain.scale;
+        (
+            self.crop.width * scale,
+            self.crop.height * scale + self.caption_band,
+        )
+    }
+
+    /// Runs every step on `frame`
+    pub fn apply(&self, frame: &RgbaImage) -> RgbaImage {
+        let scale = self.chain.scale;
+        let (width, height) = self.dimensions();
+        let frame_height = self.crop.height * scale;
+        let mut output = RgbaImage::from_pixel(width, height, Rgba([0, 0, 0, 255]));
+        for y in 0..frame_height {
+            for x in 0..width {
+                // A frame smaller than the first stays black where it is missing
+                if let Some(pixel) =
+                    frame.get_pixel_checked(self.crop.x + x / scale, self.crop.y + y / scale)
+                {
+                    output.put_pixel(x, y, *pixel);
+                }
+            }
+        }
+
+        for filter in &self.chain.filters {
+            match filter {
+                Filter::Lcd => {
+                    for y in 0..frame_height {
+                        for x in 0..width {
+                            if x % scale == scale - 1 || y % scale == scale - 1 {
+                                let pixel = output.get_pixel_mut(x, y);
+                                for channel in &mut pixel.0[..3] {
+                                    *channel = (*channel as f32 * LCD_GRID_BRIGHTNESS) as u8;
+                                }
+                            }
+                        }
+                    }
+                }
+                Filter::Grayscale => {
+                    for pixel in output.pixels_mut().take((width * frame_height) as usize) {
+                        let [r, g, b, _] = pixel.0;
+                        let luma =
+                            (0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32).round() as u8;
+                        pixel.0[..3].copy_from_slice(&[luma; 3]);
+                    }
+                }
+            }
+        }
+
+        if let Some(caption) = &self.chain.caption {
+            let left = (width - caption_width(caption) * scale) / 2;
+            let top = frame_height + CAPTION_PADDING * scale;
+            draw_text(&mut output, caption, (left, top), scale);
+        }
+        output
+    }
+}
+
+/// `within` of `frame` with the borders that are all the color of its top-left corner
+/// trimmed, or `None` if nothing else is left
+fn solid_border_crop(frame: &RgbaImage, within: Region) -> Option<Region> {
+    let background = *frame.get_pixel(within.x, within.y);
+    let right = within.x + within.width;
+    let bottom = within.y + within.height;
+    let row_is_border = |y: u32| (within.x..right).all(|x| *frame.get_pixel(x, y) == background);
+    let column_is_border =
+        |x: u32, top: u32, bottom: u32| (top..bottom).all(|y| *frame.get_pixel(x, y) == background);
+
+    let top = (within.y..bottom).find(|&y| !row_is_border(y))?;
+    let bottom = (top..bottom).rev().find(|&y| !row_is_border(y))? + 1;
+    let left = (within.x..right).find(|&x| !column_is_border(x, top, bottom))?;
+    let right = (left..right)
+        .rev()
+        .find(|&x| !column_is_border(x, top, bottom))?
+        + 1;
+    Some(Region {
+        x: left,
+        y: top,
+        width: right - left,
+        height: bottom - top,
+    })
+}
+
+/// Height of a glyph of the caption font
+const GLYPH_HEIGHT: u32 = 7;
+
+/// Horizontal space a glyph takes, with the gap after it
+const GLYPH_ADVANCE: u32 = 6;
+
+/// Width of `text` in the caption font, before scaling
+fn caption_width(text: &str) -> u32 {
+    (text.chars().count() as u32 * GLYPH_ADVANCE).saturating_sub(1)
+}
+
+/// Draws `text` in white with its top-left corner at `origin`, each font pixel `scale`
+/// pixels across
+fn draw_text(image: &mut RgbaImage, text: &str, (left, top): (u32, u32), scale: u32) {
+    for (index, c) in text.chars().enumerate() {
+        let Some(columns) = glyph(c) else {
+            continue;
+        };
+        let glyph_left = left + index as u32 * GLYPH_ADVANCE * scale;
+        for (column, bits) in columns.iter().enumerate() {
+            for row in 0..GLYPH_HEIGHT {
+                if bits & (1 << row) == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let x = glyph_left + column as u32 * scale + dx;
+                        let y = top + row * scale + dy;
+                        if x < image.width() && y < image.height() {
+                            image.put_pixel(x, y, Rgba([255, 255, 255, 255]));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The columns of `c` in the caption font, lowest bit at the top
+fn glyph(c: char) -> Option<&'static [u8; 5]> {
+    let index = (c as u32).checked_sub(' ' as u32)?;
+    FONT.get(usize::try_from(index).ok()?)
+}
+
+/// A 5x7 font for printable ASCII, from space to `~`
+#[rustfmt::skip]
+const FONT: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], [0x00, 0x00, 0x5F, 0x00, 0x00], // space !
+    [0x00, 0x07, 0x00, 0x07, 0x00], [0x14, 0x7F, 0x14, 0x7F, 0x14], // " #
+    [0x24, 0x2A, 0x7F, 0x2A, 0x12], [0x23, 0x13, 0x08, 0x64, 0x62], // $ %
+    [0x36, 0x49, 0x55, 0x22, 0x50], [0x00, 0x05, 0x03, 0x00, 0x00], // & '
+    [0x00, 0x1C, 0x22, 0x41, 0x00], [0x00, 0x41, 0x22, 0x1C, 0x00], // ( )
+    [0x08, 0x2A, 0x1C, 0x2A, 0x08], [0x08, 0x08, 0x3E, 0x08, 0x08], // * +
+    [0x00, 0x50, 0x30, 0x00, 0x00], [0x08, 0x08, 0x08, 0x08, 0x08], // , -
+    [0x00, 0x60, 0x60, 0x00, 0x00], [0x20, 0x10, 0x08, 0x04, 0x02], // . /
+    [0x3E, 0x51, 0x49, 0x45, 0x3E], [0x00, 0x42, 0x7F, 0x40, 0x00], // 0 1
+    [0x42, 0x61, 0x51, 0x49, 0x46], [0x21, 0x41, 0x45, 0x4B, 0x31], // 2 3
+    [0x18, 0x14, 0x12, 0x7F, 0x10], [0x27, 0x45, 0x45, 0x45, 0x39], // 4 5
+    [0x3C, 0x4A, 0x49, 0x49, 0x30], [0x01, 0x71, 0x09, 0x05, 0x03], // 6 7
+    [0x36, 0x49, 0x49, 0x49, 0x36], [0x06, 0x49, 0x49, 0x29, 0x1E], // 8 9
+    [0x00, 0x36, 0x36, 0x00, 0x00], [0x00, 0x56, 0x36, 0x00, 0x00], // : ;
+    [0x08, 0x14, 0x22, 0x41, 0x00], [0x14, 0x14, 0x14, 0x14, 0x14], // < =
+    [0x00, 0x41, 0x22, 0x14, 0x08], [0x02, 0x01, 0x51, 0x09, 0x06], // > ?
+    [0x32, 0x49, 0x79, 0x41, 0x3E], [0x7E, 0x11, 0x11, 0x11, 0x7E], // @ A
+    [0x7F, 0x49, 0x49, 0x49, 0x36], [0x3E, 0x41, 0x41, 0x41, 0x22], // B C
+    [0x7F, 0x41, 0x41, 0x22, 0x1C], [0x7F, 0x49, 0x49, 0x49, 0x41], // D E
+    [0x7F, 0x09, 0x09, 0x01, 0x01], [0x3E, 0x41, 0x41, 0x51, 0x32], // F G
+    [0x7F, 0x08, 0x08, 0x08, 0x7F], [0x00, 0x41, 0x7F, 0x41, 0x00], // H I
+    [0x20, 0x40, 0x41, 0x3F, 0x01], [0x7F, 0x08, 0x14, 0x22, 0x41], // J K
+    [0x7F, 0x40, 0x40, 0x40, 0x40], [0x7F, 0x02, 0x04, 0x02, 0x7F], // L M
+    [0x7F, 0x04, 0x08, 0x10, 0x7F], [0x3E, 0x41, 0x41, 0x41, 0x3E], // N O
+    [0x7F, 0x09, 0x09, 0x09, 0x06], [0x3E, 0x41, 0x51, 0x21, 0x5E], // P Q
+    [0x7F, 0x09, 0x19, 0x29, 0x46], [0x46, 0x49, 0x49, 0x49, 0x31], // R S
+    [0x01, 0x01, 0x7F, 0x01, 0x01], [0x3F, 0x40, 0x40, 0x40, 0x3F], // T U
+    [0x1F, 0x20, 0x40, 0x20, 0x1F], [0x7F, 0x20, 0x18, 0x20, 0x7F], // V W
+    [0x63, 0x14, 0x08, 0x14, 0x63], [0x03, 0x04, 0x78, 0x04, 0x03], // X Y
+    [0x61, 0x51, 0x49, 0x45, 0x43], [0x00, 0x7F, 0x41, 0x41, 0x00], // Z [
+    [0x02, 0x04, 0x08, 0x10, 0x20], [0x00, 0x41, 0x41, 0x7F, 0x00], // \ ]
+    [0x04, 0x02, 0x01, 0x02, 0x04], [0x40, 0x40, 0x40, 0x40, 0x40], // ^ _
+    [0x00, 0x01, 0x02, 0x04, 0x00], [0x20, 0x54, 0x54, 0x54, 0x78], // ` a
+    [0x7F, 0x48, 0x44, 0x44, 0x38], [0x38, 0x44, 0x44, 0x44, 0x20], // b c
+    [0x38, 0x44, 0x44, 0x48, 0x7F], [0x38, 0x54, 0x54, 0x54, 0x18], // d e
+    [0x08, 0x7E, 0x09, 0x01, 0x02], [0x0C, 0x52, 0x52, 0x52, 0x3E], // f g
+    [0x7F, 0x08, 0x04, 0x04, 0x78], [0x00, 0x44, 0x7D, 0x40, 0x00], // h i
+    [0x20, 0x40, 0x44, 0x3D, 0x00], [0x7F, 0x10, 0x28, 0x44, 0x00], // j k
+    [0x00, 0x41, 0x7F, 0x40, 0x00], [0x7C, 0x04, 0x18, 0x04, 0x78], // l m
+    [0x7C, 0x08, 0x04, 0x04, 0x78], [0x38, 0x44, 0x44, 0x44, 0x38], // n o
+    [0x7C, 0x14, 0x14, 0x14, 0x08], [0x08, 0x14, 0x14, 0x18, 0x7C], // p q
+    [0x7C, 0x08, 0x04, 0x04, 0x08], [0x48, 0x54, 0x54, 0x54, 0x20], // r s
+    [0x04, 0x3F, 0x44, 0x40, 0x20], [0x3C, 0x40, 0x40, 0x20, 0x7C], // t u
+    [0x1C, 0x20, 0x40, 0x20, 0x1C], [0x3C, 0x40, 0x30, 0x40, 0x3C], // v w
+    [0x44, 0x28, 0x10, 0x28, 0x44], [0x0C, 0x50, 0x50, 0x50, 0x3C], // x y
+    [0x44, 0x64, 0x54, 0x4C, 0x44], [0x00, 0x08, 0x36, 0x41, 0x00], // z {
+    [0x00, 0x00, 0x7F, 0x00, 0x00], [0x00, 0x41, 0x36, 0x08, 0x00], // | }
+    [0x08, 0x04, 0x08, 0x10, 0x08],                                 // ~
+];
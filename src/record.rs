@@ -0,0 +1,528 @@
+//! Recording keys pressed while playing as an input sequence
+//!
+//! `agbrs-capture record` watches the keyboard while a binary runs in mGBA and turns the
+//! GBA buttons pressed into the input sequence format. Rather than hooking keyboard
+//! events, which needs a listener per platform and often extra permissions, the state of
+//! every key is polled every few milliseconds: `QueryKeymap` on X11, `GetAsyncKeyState`
+//! on Windows and `CGEventSourceKeyState` on macOS. Keys are seen wherever focus is, so
+//! only keys pressed while mGBA has focus should be played.
+
+use anyhow::Result;
+use enigo::Key;
+use std::collections::BTreeSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Time between two looks at the keyboard, well under a GBA frame
+const POLL_MS: u64 = 4;
+
+/// Presses shorter than this are written as quick presses (`A`) rather than holds
+/// (`A:40`), as mGBA sees a quick press for a frame either way
+const TAP_MS: u64 = 100;
+
+/// A GBA button held from `start` to `end`, both measured from the start of recording
+#[derive(Debug, Clone, Copy)]
+pub struct RecordedPress {
+    pub button: &'static str,
+    pub start: Duration,
+    pub end: Duration,
+}
+
+/// Records presses of `buttons`, given by sequence name and the key each is mapped to,
+/// until `running` returns false or Ctrl+C sets `shutdown`. Other keys are ignored with a
+/// note the first time each is pressed.
+pub async fn record(
+    buttons: &[(&'static str, Key)],
+    shutdown: &AtomicBool,
+    mut running: impl FnMut() -> bool,
+) -> Result<Vec<RecordedPress>> {
+    let keyboard = KeyboardState::new()?;
+    let button_codes: Vec<(&'static str, Vec<u32>)> = buttons
+        .iter()
+        .map(|&(button, key)| (button, keyboard.codes(key)))
+        .collect();
+    for (button, codes) in &button_codes {
+        if codes.is_empty() {
+            println!(
+                "Note: the key mapped to GBA button {} can't be watched and won't be recorded",
+                button
+            );
+        }
+    }
+
+    let start = Instant::now();
+    let mut down: Vec<(&'static str, Duration)> = Vec::new();
+    let mut presses = Vec::new();
+    let mut ignored = BTreeSet::new();
+    // Keys already held when recording starts, such as Enter from starting the tool,
+    // don't count until they are pressed again
+    let mut previous = keyboard.pressed()?;
+
+    while running() && !shutdown.load(Ordering::Relaxed) {
+        tokio::time::sleep(Duration::from_millis(POLL_MS)).await;
+        let pressed = keyboard.pressed()?;
+        let now = start.elapsed();
+
+        for (button, codes) in &button_codes {
+            let is_down = codes.iter().any(|code| pressed.contains(code));
+            let was_down = codes.iter().any(|code| previous.contains(code));
+            match down.iter().position(|(held, _)| held == button) {
+                Some(index) if !is_down => {
+                    let (button, start) = down.remove(index);
+                    presses.push(RecordedPress {
+                        button,
+                        start,
+                        end: now,
+                    });
+                }
+                None if is_down && !was_down => down.push((button, now)),
+                _ => {}
+            }
+        }
+
+        for code in pressed.difference(&previous) {
+            let mapped = button_codes.iter().any(|(_, codes)| codes.contains(code));
+            if !mapped && ignored.insert(*code) {
+                println!(
+                    "Note: ignoring key code {} (not mapped to a GBA button)",
+                    code
+                );
+            }
+        }
+        previous = pressed;
+    }
+
+    // Buttons still held when recording stops are let go at the end
+    let end = start.elapsed();
+    presses.extend(
+        down.into_iter()
+            .map(|(button, start)| RecordedPress { button, start, end }),
+    );
+    presses.sort_by_key(|press| press.start);
+    Ok(presses)
+}
+
+/// Writes recorded presses as an input sequence, with every time rounded to a multiple of
+/// `granularity`. A press that overlaps no other is written as `key` or `key:hold`;
+/// overlapping presses, such as A pressed while running right, become `down:`/`up:`
+/// pairs so they stay overlapped on replay.
+pub fn to_sequence(presses: &[RecordedPress], granularity: Duration) -> String {
+    let step = granularity.as_millis().max(1) as u64;
+    let round = |time: Duration| (time.as_millis() as u64 + step / 2) / step * step;
+
+    // (time, how long the step takes, text)
+    let mut steps: Vec<(u64, u64, String)> = Vec::new();
+    for (index, press) in presses.iter().enumerate() {
+        let start = round(press.start);
+        let end = round(press.end).max(start);
+        let overlaps = presses
+            .iter()
+            .enumerate()
+            .any(|(other, o)| other != index && o.start < press.end && press.start < o.end);
+        if overlaps {
+            steps.push((start, 0, format!("down:{}", press.button)));
+            steps.push((end, 0, format!("up:{}", press.button)));
+        } else if end - start < TAP_MS {
+            steps.push((start, 0, press.button.to_string()));
+        } else {
+            steps.push((
+                start,
+                end - start,
+                format!("{}:{}", press.button, end - start),
+            ));
+        }
+    }
+    steps.sort_by_key(|&(time, _, _)| time);
+
+    let mut parts = Vec::new();
+    let mut cursor = 0;
+    for (time, length, text) in steps {
+        if time > cursor {
+            parts.push(format!("wait:{}", time - cursor));
+        }
+        parts.push(text);
+        cursor = cursor.max(time + length);
+    }
+    parts.join(",")
+}
+
+/// Which keys are held down, read from the X server
+#[cfg(target_os = "linux")]
+struct KeyboardState {
+    conn: x11rb::rust_connection::RustConnection,
+    /// Keysyms of each keycode, starting at `min_keycode`
+    keysyms: Vec<Vec<u32>>,
+    min_keycode: u8,
+}
+
+#[cfg(target_os = "linux")]
+impl KeyboardState {
+    fn new() -> Result<Self> {
+        use x11rb::connection::Connection as _;
+        use x11rb::protocol::xproto::ConnectionExt as _;
+
+        let (conn, _) = x11rb::connect(None)
+            .map_err(|e| anyhow::anyhow!("Could not connect to the X server: {}", e))?;
+        let setup = conn.setup();
+        let (min_keycode, max_keycode) = (setup.min_keycode, setup.max_keycode);
+        let mapping = conn
+            .get_keyboard_mapping(min_keycode, max_keycode - min_keycode + 1)?
+            .reply()?;
+        let per_keycode = usize::from(mapping.keysyms_per_keycode).max(1);
+        let keysyms = mapping
+            .keysyms
+            .chunks(per_keycode)
+            .map(<[u32]>::to_vec)
+            .collect();
+        Ok(Self {
+            conn,
+            keysyms,
+            min_keycode,
+        })
+    }
+
+    /// Keycodes that produce `key`
+    fn codes(&self, key: Key) -> Vec<u32> {
+        let Some(keysym) = keysym(key) else {
+            return Vec::new();
+        };
+        self.keysyms
+            .iter()
+            .enumerate()
+            .filter(|(_, keysyms)| keysyms.contains(&keysym))
+            .map(|(index, _)| u32::from(self.min_keycode) + index as u32)
+            .collect()
+    }
+
+    /// Keycodes currently held down
+    fn pressed(&self) -> Result<BTreeSet<u32>> {
+        use x11rb::protocol::xproto::ConnectionExt as _;
+
+        let keymap = self.conn.query_keymap()?.reply()?;
+        Ok((0..256u32)
+            .filter(|&code| keymap.keys[code as usize / 8] & (1 << (code % 8)) != 0)
+            .collect())
+    }
+}
+
+/// X11 keysym of a key that can be mapped to a GBA button
+#[cfg(target_os = "linux")]
+fn keysym(key: Key) -> Option<u32> {
+    Some(match key {
+        // Latin-1 keysyms are the characters themselves, lowercase for letters
+        Key::Unicode(c) if c.is_ascii_graphic() || c == ' ' => c.to_ascii_lowercase() as u32,
+        Key::UpArrow => 0xff52,
+        Key::DownArrow => 0xff54,
+        Key::LeftArrow => 0xff51,
+        Key::RightArrow => 0xff53,
+        Key::Return => 0xff0d,
+        Key::Tab => 0xff09,
+        Key::Escape => 0xff1b,
+        Key::Backspace => 0xff08,
+        Key::Shift | Key::LShift => 0xffe1,
+        Key::RShift => 0xffe2,
+        Key::Control | Key::LControl => 0xffe3,
+        Key::RControl => 0xffe4,
+        Key::Alt => 0xffe9,
+        Key::Meta => 0xffeb,
+        Key::Home => 0xff50,
+        Key::PageUp => 0xff55,
+        Key::PageDown => 0xff56,
+        Key::End => 0xff57,
+        Key::Insert => 0xff63,
+        Key::Delete => 0xffff,
+        Key::Multiply => 0xffaa,
+        Key::Add => 0xffab,
+        Key::Subtract => 0xffad,
+        Key::Decimal => 0xffae,
+        Key::Divide => 0xffaf,
+        Key::Numpad0 => 0xffb0,
+        Key::Numpad1 => 0xffb1,
+        Key::Numpad2 => 0xffb2,
+        Key::Numpad3 => 0xffb3,
+        Key::Numpad4 => 0xffb4,
+        Key::Numpad5 => 0xffb5,
+        Key::Numpad6 => 0xffb6,
+        Key::Numpad7 => 0xffb7,
+        Key::Numpad8 => 0xffb8,
+        Key::Numpad9 => 0xffb9,
+        // Keys without a variant of their own are given by keysym
+        Key::Other(keysym) => keysym,
+        Key::F1 => 0xffbe,
+        Key::F2 => 0xffbf,
+        Key::F3 => 0xffc0,
+        Key::F4 => 0xffc1,
+        Key::F5 => 0xffc2,
+        Key::F6 => 0xffc3,
+        Key::F7 => 0xffc4,
+        Key::F8 => 0xffc5,
+        Key::F9 => 0xffc6,
+        Key::F10 => 0xffc7,
+        Key::F11 => 0xffc8,
+        Key::F12 => 0xffc9,
+        _ => return None,
+    })
+}
+
+/// Which virtual keys are held down
+#[cfg(target_os = "windows")]
+struct KeyboardState;
+
+#[cfg(target_os = "windows")]
+impl KeyboardState {
+    fn new() -> Result<Self> {
+        Ok(Self)
+    }
+
+    /// Virtual-key code of `key`
+    fn codes(&self, key: Key) -> Vec<u32> {
+        use windows::Win32::UI::Input::KeyboardAndMouse::*;
+
+        let vk = match key {
+            Key::A => 0x41,
+            Key::B => 0x42,
+            Key::C => 0x43,
+            Key::D => 0x44,
+            Key::E => 0x45,
+            Key::F => 0x46,
+            Key::G => 0x47,
+            Key::H => 0x48,
+            Key::I => 0x49,
+            Key::J => 0x4a,
+            Key::K => 0x4b,
+            Key::L => 0x4c,
+            Key::M => 0x4d,
+            Key::N => 0x4e,
+            Key::O => 0x4f,
+            Key::P => 0x50,
+            Key::Q => 0x51,
+            Key::R => 0x52,
+            Key::S => 0x53,
+            Key::T => 0x54,
+            Key::U => 0x55,
+            Key::V => 0x56,
+            Key::W => 0x57,
+            Key::X => 0x58,
+            Key::Y => 0x59,
+            Key::Z => 0x5a,
+            Key::Num0 => 0x30,
+            Key::Num1 => 0x31,
+            Key::Num2 => 0x32,
+            Key::Num3 => 0x33,
+            Key::Num4 => 0x34,
+            Key::Num5 => 0x35,
+            Key::Num6 => 0x36,
+            Key::Num7 => 0x37,
+            Key::Num8 => 0x38,
+            Key::Num9 => 0x39,
+            Key::Space => VK_SPACE.0,
+            Key::UpArrow => VK_UP.0,
+            Key::DownArrow => VK_DOWN.0,
+            Key::LeftArrow => VK_LEFT.0,
+            Key::RightArrow => VK_RIGHT.0,
+            Key::Return => VK_RETURN.0,
+            Key::Tab => VK_TAB.0,
+            Key::Escape => VK_ESCAPE.0,
+            Key::Backspace => VK_BACK.0,
+            Key::Shift => VK_SHIFT.0,
+            Key::Control => VK_CONTROL.0,
+            Key::Alt => VK_MENU.0,
+            Key::Meta => VK_LWIN.0,
+            Key::RWin => VK_RWIN.0,
+            Key::OEMComma => VK_OEM_COMMA.0,
+            Key::OEMPeriod => VK_OEM_PERIOD.0,
+            Key::OEMMinus => VK_OEM_MINUS.0,
+            Key::OEMPlus => VK_OEM_PLUS.0,
+            Key::OEM1 => VK_OEM_1.0,
+            Key::OEM2 => VK_OEM_2.0,
+            Key::OEM3 => VK_OEM_3.0,
+            Key::OEM4 => VK_OEM_4.0,
+            Key::OEM5 => VK_OEM_5.0,
+            Key::OEM6 => VK_OEM_6.0,
+            Key::OEM7 => VK_OEM_7.0,
+            Key::PageUp => VK_PRIOR.0,
+            Key::PageDown => VK_NEXT.0,
+            Key::Home => VK_HOME.0,
+            Key::End => VK_END.0,
+            Key::Insert => VK_INSERT.0,
+            Key::Delete => VK_DELETE.0,
+            Key::Numpad0 => VK_NUMPAD0.0,
+            Key::Numpad1 => VK_NUMPAD1.0,
+            Key::Numpad2 => VK_NUMPAD2.0,
+            Key::Numpad3 => VK_NUMPAD3.0,
+            Key::Numpad4 => VK_NUMPAD4.0,
+            Key::Numpad5 => VK_NUMPAD5.0,
+            Key::Numpad6 => VK_NUMPAD6.0,
+            Key::Numpad7 => VK_NUMPAD7.0,
+            Key::Numpad8 => VK_NUMPAD8.0,
+            Key::Numpad9 => VK_NUMPAD9.0,
+            Key::Add => VK_ADD.0,
+            Key::Subtract => VK_SUBTRACT.0,
+            Key::Multiply => VK_MULTIPLY.0,
+            Key::Divide => VK_DIVIDE.0,
+            Key::Decimal => VK_DECIMAL.0,
+            Key::F1 => VK_F1.0,
+            Key::F2 => VK_F2.0,
+            Key::F3 => VK_F3.0,
+            Key::F4 => VK_F4.0,
+            Key::F5 => VK_F5.0,
+            Key::F6 => VK_F6.0,
+            Key::F7 => VK_F7.0,
+            Key::F8 => VK_F8.0,
+            Key::F9 => VK_F9.0,
+            Key::F10 => VK_F10.0,
+            Key::F11 => VK_F11.0,
+            Key::F12 => VK_F12.0,
+            _ => return Vec::new(),
+        };
+        vec![u32::from(vk)]
+    }
+
+    /// Virtual-key codes currently held down, leaving out mouse buttons and the left and
+    /// right variants of modifiers, which are also reported as the plain modifier
+    fn pressed(&self) -> Result<BTreeSet<u32>> {
+        use windows::Win32::UI::Input::KeyboardAndMouse::GetAsyncKeyState;
+
+        Ok((0x08..0xa0u32)
+            .chain(0xa6..0xff)
+            // SAFETY: only reads the key state; any virtual-key code is accepted
+            .filter(|&vk| unsafe { GetAsyncKeyState(vk as i32) } < 0)
+            .collect())
+    }
+}
+
+/// Which keys are held down, from the combined state of every keyboard
+#[cfg(target_os = "macos")]
+struct KeyboardState;
+
+#[cfg(target_os = "macos")]
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGEventSourceKeyState(state_id: i32, key: u16) -> bool;
+}
+
+#[cfg(target_os = "macos")]
+impl KeyboardState {
+    fn new() -> Result<Self> {
+        Ok(Self)
+    }
+
+    /// Virtual keycode of `key` on an ANSI keyboard
+    fn codes(&self, key: Key) -> Vec<u32> {
+        let code = match key {
+            Key::Unicode(c) => match c.to_ascii_lowercase() {
+                'a' => 0x00,
+                's' => 0x01,
+                'd' => 0x02,
+                'f' => 0x03,
+                'h' => 0x04,
+                'g' => 0x05,
+                'z' => 0x06,
+                'x' => 0x07,
+                'c' => 0x08,
+                'v' => 0x09,
+                'b' => 0x0b,
+                'q' => 0x0c,
+                'w' => 0x0d,
+                'e' => 0x0e,
+                'r' => 0x0f,
+                'y' => 0x10,
+                't' => 0x11,
+                '1' => 0x12,
+                '2' => 0x13,
+                '3' => 0x14,
+                '4' => 0x15,
+                '6' => 0x16,
+                '5' => 0x17,
+                '9' => 0x19,
+                '7' => 0x1a,
+                '8' => 0x1c,
+                '0' => 0x1d,
+                'o' => 0x1f,
+                'u' => 0x20,
+                'i' => 0x22,
+                'p' => 0x23,
+                'l' => 0x25,
+                'j' => 0x26,
+                'k' => 0x28,
+                'n' => 0x2d,
+                'm' => 0x2e,
+                ' ' => 0x31,
+                '=' => 0x18,
+                '-' => 0x1b,
+                ']' => 0x1e,
+                '[' => 0x21,
+                '\'' => 0x27,
+                ';' => 0x29,
+                '\\' => 0x2a,
+                ',' => 0x2b,
+                '/' => 0x2c,
+                '.' => 0x2f,
+                '`' => 0x32,
+                _ => return Vec::new(),
+            },
+            Key::Return => 0x24,
+            Key::Tab => 0x30,
+            Key::Backspace => 0x33,
+            Key::Escape => 0x35,
+            Key::RCommand => 0x36,
+            Key::Meta => 0x37,
+            Key::Shift | Key::LShift => 0x38,
+            Key::Alt => 0x3a,
+            Key::Control | Key::LControl => 0x3b,
+            Key::RShift => 0x3c,
+            Key::ROption => 0x3d,
+            Key::RControl => 0x3e,
+            Key::Decimal => 0x41,
+            Key::Multiply => 0x43,
+            Key::Add => 0x45,
+            Key::Divide => 0x4b,
+            Key::Subtract => 0x4e,
+            Key::Numpad0 => 0x52,
+            Key::Numpad1 => 0x53,
+            Key::Numpad2 => 0x54,
+            Key::Numpad3 => 0x55,
+            Key::Numpad4 => 0x56,
+            Key::Numpad5 => 0x57,
+            Key::Numpad6 => 0x58,
+            Key::Numpad7 => 0x59,
+            Key::Numpad8 => 0x5b,
+            Key::Numpad9 => 0x5c,
+            Key::Help => 0x72,
+            Key::Home => 0x73,
+            Key::PageUp => 0x74,
+            Key::Delete => 0x75,
+            Key::End => 0x77,
+            Key::PageDown => 0x79,
+            Key::F1 => 0x7a,
+            Key::F2 => 0x78,
+            Key::F3 => 0x63,
+            Key::F4 => 0x76,
+            Key::F5 => 0x60,
+            Key::F6 => 0x61,
+            Key::F7 => 0x62,
+            Key::F8 => 0x64,
+            Key::F9 => 0x65,
+            Key::F10 => 0x6d,
+            Key::F11 => 0x67,
+            Key::F12 => 0x6f,
+            Key::LeftArrow => 0x7b,
+            Key::RightArrow => 0x7c,
+            Key::DownArrow => 0x7d,
+            Key::UpArrow => 0x7e,
+            _ => return Vec::new(),
+        };
+        vec![code]
+    }
+
+    /// Keycodes currently held down
+    fn pressed(&self) -> Result<BTreeSet<u32>> {
+        // kCGEventSourceStateCombinedSessionState
+        const COMBINED_SESSION_STATE: i32 = 0;
+        Ok((0..0x80u16)
+            // SAFETY: only reads the key state; any keycode is accepted
+            .filter(|&code| unsafe { CGEventSourceKeyState(COMBINED_SESSION_STATE, code) })
+            .map(u32::from)
+            .collect())
+    }
+}
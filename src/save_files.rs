@@ -0,0 +1,131 @@
+//! Per-binary save file handling
+//!
+//! mGBA keeps a ROM's battery save next to the ROM as `<name>.sav`. Demos that behave
+//! differently with saved data can start from a provided save or from none at all; any
+//! save that was already there is moved aside first and put back when the run ends,
+//! including when it fails.
+
+use anyhow::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// What happens to a binary's save file before each launch
+#[derive(Debug, Clone)]
+pub enum SavePolicy {
+    /// Use whatever save exists
+    Keep,
+    /// Start without a save
+    Clean,
+    /// Start from a copy of this save
+    Provide(PathBuf),
+}
+
+impl SavePolicy {
+    /// Describes the policy for the run output
+    pub fn describe(&self) -> String {
+        match self {
+            SavePolicy::Keep => "existing save file, if any".to_string(),
+            SavePolicy::Clean => "no save file (clean_save)".to_string(),
+            SavePolicy::Provide(path) => format!("copy of {}", path.display()),
+        }
+    }
+}
+
+/// Where mGBA looks for the save of a ROM
+pub fn save_path(rom_path: &Path) -> PathBuf {
+    rom_path.with_extension("sav")
+}
+
+/// A save file put in place by this run, with the user's own save it replaced
+struct ManagedSave {
+    path: PathBuf,
+    backup: Option<PathBuf>,
+}
+
+/// Save files replaced during the run, restored when dropped
+#[derive(Default)]
+pub struct SaveFiles {
+    managed: Vec<ManagedSave>,
+}
+
+impl SaveFiles {
+    /// Puts the save at `path` into the state `policy` asks for, backing up the user's
+    /// save the first time a path is touched
+    pub fn prepare(&mut self, path: &Path, policy: &SavePolicy) -> Result<()> {
+        if matches!(policy, SavePolicy::Keep) {
+            return Ok(());
+        }
+
+        if !self.managed.iter().any(|save| save.path == path) {
+            let backup = if path.exists() {
+                let mut backup_name = path.as_os_str().to_owned();
+                backup_name.push(".agbrs-capture-backup");
+                let backup = PathBuf::from(backup_name);
+                fs::rename(path, &backup).map_err(|e| {
+                    anyhow::anyhow!("Failed to back up save file {}: {}", path.display(), e)
+                })?;
+                Some(backup)
+            } else {
+                None
+            };
+            self.managed.push(ManagedSave {
+                path: path.to_path_buf(),
+                backup,
+            });
+        }
+
+        match policy {
+            SavePolicy::Keep => {}
+            SavePolicy::Clean => {
+                if path.exists() {
+                    fs::remove_file(path).map_err(|e| {
+                        anyhow::anyhow!("Failed to remove save file {}: {}", path.display(), e)
+                    })?;
+                }
+            }
+            SavePolicy::Provide(source) => {
+                fs::copy(source, path).map_err(|e| {
+                    anyhow::anyhow!(
+                        "Failed to copy save file {} to {}: {}",
+                        source.display(),
+                        path.display(),
+                        e
+                    )
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Leaves the current saves in place for an emulator that keeps running, which would
+    /// overwrite a restored save when it exits, and says where the user's saves went
+    pub fn leave_in_place(&mut self) {
+        for save in self.managed.drain(..) {
+            if let Some(backup) = save.backup {
+                println!(
+                    "Your save file {} was moved to {}; move it back after closing mGBA",
+                    save.path.display(),
+                    backup.display()
+                );
+            }
+        }
+    }
+}
+
+impl Drop for SaveFiles {
+    fn drop(&mut self) {
+        for save in self.managed.drain(..).rev() {
+            let _ = fs::remove_file(&save.path);
+            if let Some(backup) = save.backup {
+                if let Err(e) = fs::rename(&backup, &save.path) {
+                    println!(
+                        "Warning: could not restore save file {} from {}: {}",
+                        save.path.display(),
+                        backup.display(),
+                        e
+                    );
+                }
+            }
+        }
+    }
+}
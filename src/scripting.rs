@@ -0,0 +1,142 @@
+//! Input logic written in Rhai, for the cases a sequence can't express
+//!
+//! A binary's `script` in capture.json names a [Rhai](https://rhai.rs) file that runs
+//! during the capture in place of its `during_capture` sequence, so input can react to
+//! the screen: keep pressing A until the screen stops changing, then go right. The
+//! script sees a handful of functions, which the capture provides through
+//! [`ScriptHost`]. Scripts are compiled before anything is built, so syntax errors show
+//! up right away with their line. Embedding the interpreter is optional: without the
+//! `scripting` feature a binary with a script is reported instead.
+
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// What a script can do, provided by the capture it runs in. Calls block the script's
+/// thread; long ones return early once the script is interrupted.
+#[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+pub trait ScriptHost: Send + Sync {
+    /// Presses a key or GBA button, holding it for `ms` or the shortest tap
+    fn press(&self, key: &str, ms: Option<u64>) -> Result<()>;
+
+    /// Waits `ms` milliseconds
+    fn wait(&self, ms: u64);
+
+    /// Waits until the screen has stayed the same for `ms`, returning whether it did
+    /// before the usual `wait_for:` timeout
+    fn wait_stable(&self, ms: u64) -> Result<bool>;
+
+    /// The frame of the capture being recorded now, counting the first as 0
+    fn frame_index(&self) -> Result<i64>;
+
+    /// Whether the screen differs from how it was when `frame_index` returned `frame`
+    fn screen_changed_since(&self, frame: i64) -> Result<bool>;
+
+    /// Whether the script should stop: the capture is over, or Ctrl+C was pressed
+    fn interrupted(&self) -> bool;
+}
+
+/// A compiled input script
+#[derive(Debug)]
+pub struct Script {
+    path: PathBuf,
+    #[cfg(feature = "scripting")]
+    ast: rhai::AST,
+}
+
+impl Script {
+    /// The file the script was loaded from
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(not(feature = "scripting"))]
+impl Script {
+    /// Explains how to get a build that runs scripts
+    pub fn load(path: &Path) -> Result<Self> {
+        Err(anyhow::anyhow!(
+            "Scripting is not compiled in, so {} can't run. Reinstall with: cargo install --git https://github.com/zpg6/agbrs-capture --features scripting",
+            path.display()
+        ))
+    }
+
+    pub fn run(&self, _host: Arc<dyn ScriptHost>) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "scripting")]
+impl Script {
+    /// Reads and compiles the script at `path`
+    pub fn load(path: &Path) -> Result<Self> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read script {}: {}", path.display(), e))?;
+        let ast = rhai::Engine::new()
+            .compile(&source)
+            .map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            ast,
+        })
+    }
+
+    /// Runs the script to its end, or until `host` interrupts it. Errors name the line
+    /// of the script they happened on.
+    pub fn run(&self, host: Arc<dyn ScriptHost>) -> Result<()> {
+        let engine = engine(host);
+        match engine.run_ast(&self.ast) {
+            Ok(()) => Ok(()),
+            Err(e) if matches!(*e, rhai::EvalAltResult::ErrorTerminated(..)) => Ok(()),
+            Err(e) => Err(anyhow::anyhow!("{}: {}", self.path.display(), e)),
+        }
+    }
+}
+
+/// An engine with the host's functions, which stops scripts once the host interrupts
+#[cfg(feature = "scripting")]
+fn engine(host: Arc<dyn ScriptHost>) -> rhai::Engine {
+    use rhai::{Dynamic, Engine, EvalAltResult};
+
+    type ScriptResult<T> = std::result::Result<T, Box<EvalAltResult>>;
+    fn runtime<T>(result: Result<T>) -> ScriptResult<T> {
+        result.map_err(|e| e.to_string().into())
+    }
+    fn millis(ms: i64) -> ScriptResult<u64> {
+        u64::try_from(ms).map_err(|_| format!("Negative duration: {}ms", ms).into())
+    }
+
+    let mut engine = Engine::new();
+    engine.on_print(|text| println!("[script] {}", text));
+    engine.on_progress({
+        let host = host.clone();
+        move |_| host.interrupted().then_some(Dynamic::UNIT)
+    });
+    engine.register_fn("press", {
+        let host = host.clone();
+        move |key: &str| runtime(host.press(key, None))
+    });
+    engine.register_fn("press", {
+        let host = host.clone();
+        move |key: &str, ms: i64| runtime(host.press(key, Some(millis(ms)?)))
+    });
+    engine.register_fn("wait", {
+        let host = host.clone();
+        move |ms: i64| -> ScriptResult<()> {
+            host.wait(millis(ms)?);
+            Ok(())
+        }
+    });
+    engine.register_fn("wait_stable", {
+        let host = host.clone();
+        move |ms: i64| runtime(host.wait_stable(millis(ms)?))
+    });
+    engine.register_fn("frame_index", {
+        let host = host.clone();
+        move || runtime(host.frame_index())
+    });
+    engine.register_fn("screen_changed_since", move |frame: i64| {
+        runtime(host.screen_changed_since(frame))
+    });
+    engine
+}
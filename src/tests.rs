@@ -0,0 +1,19 @@
+//! Tests of the tool's own logic: parsing input, running it against a recording backend,
+//! and resolving the config, without mGBA, a display or an input device
+
+use std::path::{Path, PathBuf};
+
+mod config;
+mod input;
+mod mgba;
+mod migrations;
+mod pipeline;
+mod sequences;
+mod window;
+
+/// A project or file in `tests/fixtures`
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
@@ -0,0 +1,935 @@
+//! Resolving a binary's settings from its entry, `settings.default`, the flags and the
+//! built-in values: which of them each field comes from
+
+use super::fixture;
+use crate::*;
+
+/// A capture.json with `json` as its contents
+pub(super) fn config(json: &str) -> Option<CaptureConfig> {
+    Some(serde_json::from_str(json).unwrap())
+}
+
+/// The flags of `agbrs-capture` followed by `flags`
+pub(super) fn args(flags: &[&str]) -> Args {
+    Args::parse_from(["agbrs-capture"].iter().chain(flags))
+}
+
+/// The single capture of `binary`
+fn capture(binary: &str, config: &Option<CaptureConfig>, args: &Args) -> CaptureJob {
+    let mut launches = get_capture_launches(binary, config, args).unwrap();
+    assert_eq!(launches.len(), 1);
+    let mut jobs = launches.remove(0);
+    assert_eq!(jobs.len(), 1);
+    jobs.remove(0)
+}
+
+#[test]
+fn binary_setting_prefers_the_binary_then_the_default() {
+    let config = config(
+        r#"{
+            "settings": { "default": { "fps": 20, "fast_forward_ms": 500 } },
+            "binaries": { "game": { "fps": 30 }, "other": {} }
+        }"#,
+    );
+
+    assert_eq!(
+        binary_setting("game", &config, |binary| binary.fps),
+        Some((30.0, "binary"))
+    );
+    assert_eq!(
+        binary_setting("other", &config, |binary| binary.fps),
+        Some((20.0, "settings.default"))
+    );
+    assert_eq!(
+        binary_setting("missing", &config, |binary| binary.fps),
+        Some((20.0, "settings.default"))
+    );
+    assert_eq!(
+        binary_setting("game", &config, |binary| binary.start_marker.clone()),
+        None
+    );
+    assert_eq!(binary_setting("game", &None, |binary| binary.fps), None);
+}
+
+#[test]
+fn fps_comes_from_binary_then_default_then_flag_then_builtin() {
+    let both = config(
+        r#"{ "settings": { "default": { "fps": 20 } }, "binaries": { "game": { "fps": 30 } } }"#,
+    );
+    let default_only = config(r#"{ "settings": { "default": { "fps": 20 } } }"#);
+    let mut flag = args(&["--fps", "15"]);
+    flag.fps_given = true;
+
+    let job = capture("game", &both, &args(&[]));
+    assert_eq!((job.fps, job.fps_source), (30.0, "binary"));
+    let job = capture("game", &default_only, &args(&[]));
+    assert_eq!((job.fps, job.fps_source), (20.0, "settings.default"));
+    let job = capture("game", &None, &flag);
+    assert_eq!((job.fps, job.fps_source), (15.0, "--fps"));
+    let job = capture("game", &None, &args(&[]));
+    assert_eq!((job.fps, job.fps_source), (10.0, "default"));
+}
+
+#[test]
+fn duration_comes_from_binary_then_default_then_flag_then_builtin() {
+    let both = config(
+        r#"{ "settings": { "default": { "duration": 4 } }, "binaries": { "game": { "duration": 6 } } }"#,
+    );
+    let default_only = config(r#"{ "settings": { "default": { "duration": 4 } } }"#);
+    let mut flag = args(&["--duration", "2"]);
+    flag.duration_given = true;
+
+    let job = capture("game", &both, &args(&[]));
+    assert_eq!((job.duration, job.duration_source), (6.0, "binary"));
+    let job = capture("game", &default_only, &args(&[]));
+    assert_eq!(
+        (job.duration, job.duration_source),
+        (4.0, "settings.default")
+    );
+    let job = capture("game", &None, &flag);
+    assert_eq!((job.duration, job.duration_source), (2.0, "--duration"));
+    let job = capture("game", &None, &args(&[]));
+    assert_eq!((job.duration, job.duration_source), (3.0, "default"));
+}
+
+#[test]
+fn output_format_and_name_resolve_field_by_field() {
+    let config = config(
+        r#"{
+            "settings": {
+                "output": { "format": "apng", "name_template": "{bin}-demo" },
+                "default": { "output": { "dir": "shared" } }
+            },
+            "binaries": { "game": { "output": { "format": "gif" } }, "other": {} }
+        }"#,
+    );
+    let mut flag = args(&["--format", "gif"]);
+    flag.format_given = true;
+
+    // The binary's format, the default's directory and the settings' name template
+    assert_eq!(
+        binary_output("game", &config, &args(&[])),
+        (OutputFormat::Gif, "shared/game-demo".to_string())
+    );
+    // The flag is beneath the binary's entries but above `settings.output`
+    assert_eq!(binary_output("other", &config, &flag).0, OutputFormat::Gif);
+    assert_eq!(
+        binary_output("other", &config, &args(&[])).0,
+        OutputFormat::Apng
+    );
+    assert_eq!(
+        binary_output("game", &None, &args(&[])),
+        (OutputFormat::Gif, "game".to_string())
+    );
+}
+
+#[test]
+fn loop_comes_from_binary_then_default_then_flag_then_builtin() {
+    let both = config(
+        r#"{ "settings": { "default": { "loop": 2 } }, "binaries": { "game": { "loop": 3 } } }"#,
+    );
+    let default_only = config(r#"{ "settings": { "default": { "loop": 2 } } }"#);
+    let flag = args(&["--loop", "4"]);
+
+    assert_eq!(
+        get_effective_loop("game", &both, &flag).unwrap(),
+        Loop::Plays(3)
+    );
+    assert_eq!(
+        get_effective_loop("game", &default_only, &flag).unwrap(),
+        Loop::Plays(2)
+    );
+    assert_eq!(
+        get_effective_loop("game", &None, &flag).unwrap(),
+        Loop::Plays(4)
+    );
+    assert_eq!(
+        get_effective_loop("game", &None, &args(&[])).unwrap(),
+        Loop::Infinite
+    );
+}
+
+#[test]
+fn post_processing_resolves_each_step_on_its_own() {
+    let config = config(
+        r#"{
+            "settings": { "default": { "post": { "filters": ["grayscale"], "scale": 3 } } },
+            "binaries": { "game": { "post": { "scale": 2 } } }
+        }"#,
+    );
+    let flags = args(&["--scale", "4", "--filter", "lcd", "--caption", "Demo"]);
+
+    let chain = get_effective_post("game", &config, &flags).unwrap();
+    assert_eq!(chain.scale, 2);
+    assert_eq!(chain.filters, [Filter::Grayscale]);
+    assert_eq!(chain.caption.as_deref(), Some("Demo"));
+
+    let chain = get_effective_post("game", &None, &args(&[])).unwrap();
+    assert_eq!(chain.scale, 1);
+    assert!(chain.filters.is_empty());
+    assert_eq!(chain.caption, None);
+}
+
+#[test]
+fn hook_timeout_comes_from_binary_then_default_then_builtin() {
+    let both = config(
+        r#"{ "settings": { "default": { "hook_timeout": "30s" } }, "binaries": { "game": { "hook_timeout": 5 } } }"#,
+    );
+    let default_only = config(r#"{ "settings": { "default": { "hook_timeout": "30s" } } }"#);
+
+    assert_eq!(
+        get_effective_hook_timeout("game", &both).unwrap(),
+        Duration::from_secs(5)
+    );
+    assert_eq!(
+        get_effective_hook_timeout("game", &default_only).unwrap(),
+        Duration::from_secs(30)
+    );
+    assert_eq!(
+        get_effective_hook_timeout("game", &None).unwrap(),
+        DEFAULT_HOOK_TIMEOUT
+    );
+}
+
+#[test]
+fn window_wait_comes_from_flag_then_binary_then_default() {
+    let config = config(
+        r#"{
+            "settings": { "default": { "startup_wait": 4, "window_timeout": "20s" } },
+            "binaries": { "game": { "startup_wait": 1 } }
+        }"#,
+    );
+
+    let wait = get_effective_window_wait("game", &config, &args(&[])).unwrap();
+    assert_eq!(
+        (wait.startup_wait, wait.startup_wait_source),
+        (Duration::from_secs(1), "binary")
+    );
+    assert_eq!(
+        (wait.window_timeout, wait.window_timeout_source),
+        (Duration::from_secs(20), "settings.default")
+    );
+
+    let flags = args(&["--startup-wait", "3s"]);
+    let wait = get_effective_window_wait("game", &config, &flags).unwrap();
+    assert_eq!(
+        (wait.startup_wait, wait.startup_wait_source),
+        (Duration::from_secs(3), "--startup-wait")
+    );
+
+    let wait = get_effective_window_wait("game", &None, &args(&[])).unwrap();
+    assert_eq!(
+        (wait.startup_wait, wait.startup_wait_source),
+        (DEFAULT_STARTUP_WAIT, "default")
+    );
+    assert_eq!(
+        (wait.window_timeout, wait.window_timeout_source),
+        (DEFAULT_WINDOW_TIMEOUT, "default")
+    );
+}
+
+#[test]
+fn fast_forward_and_markers_come_from_flag_then_binary_then_default() {
+    let config = config(
+        r#"{
+            "settings": { "default": { "fast_forward_ms": 500, "start_marker": "ready", "stop_marker": "done" } },
+            "binaries": { "game": { "start_marker": "go" } }
+        }"#,
+    );
+
+    assert_eq!(get_effective_fast_forward("game", &config, None), Some(500));
+    assert_eq!(
+        get_effective_fast_forward("game", &config, Some(100)),
+        Some(100)
+    );
+    assert_eq!(get_effective_fast_forward("game", &None, None), None);
+
+    assert_eq!(
+        get_effective_markers("game", &config, &args(&[])),
+        (Some("go".to_string()), Some("done".to_string()))
+    );
+    assert_eq!(
+        get_effective_markers("game", &config, &args(&["--stop-marker", "end"])),
+        (Some("go".to_string()), Some("end".to_string()))
+    );
+}
+
+#[test]
+fn features_and_env_layer_the_binary_over_the_default() {
+    let config = config(
+        r#"{
+            "settings": { "default": {
+                "features": ["debug"],
+                "no_default_features": true,
+                "env": { "LEVEL": "1", "SHARED": "yes" }
+            } },
+            "binaries": { "game": { "features": ["demo"], "env": { "LEVEL": "2" } } }
+        }"#,
+    );
+
+    let features = get_effective_features("game", &config, &HashMap::new());
+    assert_eq!(features.features, ["demo"]);
+    assert!(features.no_default_features);
+    assert!(!features.all_features);
+
+    let env = get_effective_env("game", &config).unwrap();
+    assert_eq!(env["LEVEL"], ("2".to_string(), "binary"));
+    assert_eq!(env["SHARED"], ("yes".to_string(), "settings.default"));
+}
+
+// `settings.merge`: a shallow entry replaces the default's sequences, key mappings and
+// input pacing, a deep one only the fields it sets
+
+#[test]
+fn shallow_entry_replaces_the_default_sequences() {
+    let config = config(
+        r#"{
+            "settings": { "default": { "before_capture": "START", "after_capture": "B" } },
+            "binaries": { "game": { "during_capture": "A" } }
+        }"#,
+    );
+
+    let (before, during, after) = configured_input_sequences("game", &config).unwrap();
+    assert_eq!(before, None);
+    assert_eq!(during.unwrap().tracks(), ["A"]);
+    assert_eq!(after, None);
+
+    let (before, _, after) = configured_input_sequences("other", &config).unwrap();
+    assert_eq!(before.as_deref(), Some("START"));
+    assert_eq!(after.as_deref(), Some("B"));
+}
+
+#[test]
+fn deep_entry_inherits_the_sequences_it_leaves_out() {
+    let config = config(
+        r#"{
+            "settings": {
+                "merge": "deep",
+                "default": { "before_capture": "START", "during_capture": "R", "after_capture": "B" }
+            },
+            "binaries": { "game": { "during_capture": "A" }, "scripted": { "script": "demo.rhai" } }
+        }"#,
+    );
+
+    let (before, during, after) = configured_input_sequences("game", &config).unwrap();
+    assert_eq!(before.as_deref(), Some("START"));
+    assert_eq!(during.unwrap().tracks(), ["A"]);
+    assert_eq!(after.as_deref(), Some("B"));
+
+    // A script of its own stands in for the default's during-capture input
+    let (before, during, _) = configured_input_sequences("scripted", &config).unwrap();
+    assert_eq!(before.as_deref(), Some("START"));
+    assert!(during.is_none());
+}
+
+#[test]
+fn input_pacing_resolves_each_field_on_its_own() {
+    let shallow = r#"{
+        "settings": {
+            "input_pacing": { "min_gap_ms": 20 },
+            "default": { "input_pacing": { "linux_delay_ms": 5 }, "key_input_mode": "scancode" },
+            "chord_hold_ms": 90
+        },
+        "binaries": { "game": { "input_pacing": { "min_press_ms": 70 } } }
+    }"#;
+    let deep = shallow.replacen("\"settings\": {", "\"settings\": { \"merge\": \"deep\",", 1);
+
+    let pacing = get_effective_input_pacing("game", &config(shallow));
+    assert_eq!(pacing.min_press, Duration::from_millis(70));
+    assert_eq!(pacing.min_gap, Duration::from_millis(20));
+    assert_eq!(pacing.linux_delay_ms, DEFAULT_LINUX_DELAY_MS);
+    assert_eq!(pacing.key_input_mode, KeyInputMode::Scancode);
+    assert_eq!(pacing.chord_hold, Duration::from_millis(90));
+
+    let pacing = get_effective_input_pacing("game", &config(&deep));
+    assert_eq!(pacing.min_press, Duration::from_millis(70));
+    assert_eq!(pacing.min_gap, Duration::from_millis(20));
+    assert_eq!(pacing.linux_delay_ms, 5);
+
+    let pacing = get_effective_input_pacing("game", &None);
+    assert_eq!(
+        pacing.min_press,
+        Duration::from_millis(DEFAULT_MIN_PRESS_MS)
+    );
+    assert_eq!(pacing.min_gap, Duration::ZERO);
+    assert_eq!(
+        pacing.chord_hold,
+        Duration::from_millis(DEFAULT_CHORD_HOLD_MS)
+    );
+}
+
+#[test]
+fn key_mappings_come_from_cli_then_binary_then_default_then_settings() {
+    let shallow = r#"{
+        "settings": {
+            "key_mappings": { "a": "k" },
+            "default": { "key_mappings": { "a": "j", "b": "h" } }
+        },
+        "binaries": { "game": { "key_mappings": { "b": "n" } }, "other": {} }
+    }"#;
+    let deep = shallow.replacen("\"settings\": {", "\"settings\": { \"merge\": \"deep\",", 1);
+    let none = ExternalKeyMappings::default();
+
+    // An entry's key mappings are a whole table, with the builtin keys where it leaves
+    // a button out
+    let key_mappings = get_effective_key_mappings("game", &config(shallow), &none);
+    assert_eq!(
+        (key_mappings.a.as_str(), key_mappings.b.as_str()),
+        ("x", "n")
+    );
+    // Shallow: the default's table only applies through `settings.key_mappings`
+    let key_mappings = get_effective_key_mappings("other", &config(shallow), &none);
+    assert_eq!(
+        (key_mappings.a.as_str(), key_mappings.b.as_str()),
+        ("k", "z")
+    );
+    // Deep: the default's table comes before `settings.key_mappings`
+    let key_mappings = get_effective_key_mappings("other", &config(&deep), &none);
+    assert_eq!(
+        (key_mappings.a.as_str(), key_mappings.b.as_str()),
+        ("j", "h")
+    );
+
+    let cli = ExternalKeyMappings {
+        cli: BTreeMap::from([("a", "m".to_string())]),
+        ..Default::default()
+    };
+    let key_mappings = get_effective_key_mappings("game", &config(shallow), &cli);
+    assert_eq!(
+        (key_mappings.a.as_str(), key_mappings.b.as_str()),
+        ("m", "n")
+    );
+
+    let key_mappings = get_effective_key_mappings("game", &None, &none);
+    assert_eq!(key_mappings.a, GbaKeyMappings::default().a);
+}
+
+/// Every combination of a phase given on the command line, in the binary's entry and in
+/// `settings.default`: the first of them wins, and the other phases keep their
+/// configured sequences
+#[test]
+fn each_sequence_flag_overrides_only_its_own_phase() {
+    let phases = ["before_capture", "during_capture", "after_capture"];
+    for merge in ["shallow", "deep"] {
+        for phase in phases {
+            for combination in 0..8 {
+                let (cli, binary, default) = (
+                    combination & 4 != 0,
+                    combination & 2 != 0,
+                    combination & 1 != 0,
+                );
+                let entry = |set: bool, value: &str| {
+                    let mut entry = serde_json::Map::new();
+                    for other in phases.iter().filter(|other| **other != phase) {
+                        entry.insert(other.to_string(), "SELECT".into());
+                    }
+                    if set {
+                        entry.insert(phase.to_string(), value.into());
+                    }
+                    serde_json::Value::Object(entry)
+                };
+                let mut json = serde_json::json!({
+                    "settings": { "merge": merge, "default": entry(default, "B") },
+                });
+                if binary {
+                    json["binaries"] = serde_json::json!({ "game": entry(true, "A") });
+                }
+                let config = Some(serde_json::from_value(json).unwrap());
+                let flag = |name: &str| (cli && name == phase).then(|| "START".to_string());
+
+                let (before, during, after) = get_binary_input_sequences(
+                    "game",
+                    &config,
+                    &flag("before_capture"),
+                    &flag("during_capture"),
+                    &flag("after_capture"),
+                )
+                .unwrap();
+                let resolved = [
+                    before,
+                    during.map(|during| during.tracks().join("|")),
+                    after,
+                ];
+
+                let expected = if cli {
+                    Some("START")
+                } else if binary {
+                    Some("A")
+                } else if default {
+                    Some("B")
+                } else {
+                    None
+                };
+                let case = format!(
+                    "{} merge, {} given on the command line: {}, in the binary: {}, in the default: {}",
+                    merge, phase, cli, binary, default
+                );
+                for (name, sequence) in phases.iter().zip(&resolved) {
+                    if *name == phase {
+                        assert_eq!(sequence.as_deref(), expected, "{}", case);
+                    } else {
+                        assert_eq!(sequence.as_deref(), Some("SELECT"), "{}", case);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn window_title_comes_from_binary_then_default_then_settings() {
+    let config = config(
+        r#"{
+            "settings": { "window_title_pattern": "global", "default": { "window_title": "shared" } },
+            "binaries": {
+                "game": { "window_title": "custom" },
+                "wrapped": { "emulator": { "window_title": "wrapper" } },
+                "both": { "window_title": "own", "emulator": { "window_title": "wrapper" } },
+                "other": {}
+            }
+        }"#,
+    );
+    let title = |binary: &str, cli: Option<&str>| {
+        get_effective_window_title(binary, &config, &cli.map(str::to_string), "global")
+    };
+
+    assert_eq!(title("game", None), "custom");
+    assert_eq!(title("wrapped", None), "wrapper");
+    // An entry's own window_title wins over the one in its emulator block
+    assert_eq!(title("both", None), "own");
+    assert_eq!(title("other", None), "shared");
+    assert_eq!(title("game", Some("flag")), "flag");
+    assert_eq!(
+        get_effective_window_title("game", &None, &None, DEFAULT_TITLE_PATTERN),
+        DEFAULT_TITLE_PATTERN
+    );
+}
+
+#[test]
+fn window_title_without_a_default_falls_back_to_the_settings_pattern() {
+    let config = config(
+        r#"{
+            "settings": { "window_title_pattern": "global", "default": { "fps": 20 } },
+            "binaries": { "game": { "window_title": "custom" }, "other": {} }
+        }"#,
+    );
+
+    assert_eq!(
+        get_effective_window_title("other", &config, &None, "global"),
+        "global"
+    );
+    let profile =
+        get_effective_emulator_profile("game", &config, &args(&[]), "global", None).unwrap();
+    assert!(profile.title_pattern.is_match("Custom window"));
+    assert!(!profile.title_pattern.is_match("global"));
+}
+
+/// The sources `--print-config` gives the settings of `binary`, by name
+fn print_config_sources(
+    binary: &str,
+    config: &Option<CaptureConfig>,
+    user_settings: &[&str],
+    external: &ExternalKeyMappings,
+) -> IndexMap<String, String> {
+    let user_settings: Vec<String> = user_settings.iter().map(|key| key.to_string()).collect();
+    let effective = effective_config(
+        binary,
+        config,
+        &user_settings,
+        external,
+        None,
+        &args(&[]),
+        Path::new("."),
+    )
+    .unwrap();
+    effective
+        .settings
+        .into_iter()
+        .map(|(name, setting)| (name, setting.source))
+        .collect()
+}
+
+#[test]
+fn print_config_names_the_table_that_set_a_key_even_with_its_default_value() {
+    // Both keys are the built-in ones, written out
+    let config = config(
+        r#"{
+            "settings": { "key_mappings": { "a": "x" } },
+            "binaries": { "game": { "key_mappings": { "b": "z" } }, "other": {} }
+        }"#,
+    );
+    let none = ExternalKeyMappings::default();
+
+    let sources = print_config_sources("other", &config, &[], &none);
+    assert_eq!(sources["key_mappings.a"], "settings");
+    assert_eq!(sources["key_mappings.b"], "builtin");
+    let sources = print_config_sources("other", &config, &["key_mappings"], &none);
+    assert_eq!(sources["key_mappings.a"], "user");
+
+    let sources = print_config_sources("game", &config, &[], &none);
+    assert_eq!(sources["key_mappings.a"], "builtin");
+    assert_eq!(sources["key_mappings.b"], "binary");
+
+    // mGBA's bindings count for the buttons it binds, whatever their keys
+    let mgba = ExternalKeyMappings {
+        mgba: Some(serde_json::from_str(r#"{ "start": "enter" }"#).unwrap()),
+        prefer_mgba: true,
+        ..Default::default()
+    };
+    let sources = print_config_sources("game", &config, &[], &mgba);
+    assert_eq!(sources["key_mappings.start"], "mgba");
+    assert_eq!(sources["key_mappings.b"], "builtin");
+}
+
+#[test]
+fn print_config_names_the_layer_that_set_a_value_equal_to_the_builtin() {
+    let config = config(
+        r#"{
+            "settings": {
+                "input_pacing": { "min_gap_ms": 0 },
+                "default": { "loop": "infinite", "startup_wait": 2 }
+            },
+            "binaries": { "game": { "clean_save": false } }
+        }"#,
+    );
+
+    let sources = print_config_sources("game", &config, &["input_pacing"], &Default::default());
+    assert_eq!(sources["input_pacing.min_gap_ms"], "user");
+    assert_eq!(sources["loop"], "default");
+    assert_eq!(sources["startup_wait"], "default");
+    assert_eq!(sources["clean_save"], "binary");
+    assert_eq!(sources["skip"], "builtin");
+}
+
+#[test]
+fn user_config_supplies_the_settings_the_project_leaves_out() {
+    let project =
+        config(r#"{ "settings": { "key_mappings": { "a": "k" }, "input_latency_ms": 0 } }"#);
+    // The user's key mappings and latency lose to the project's, even where they agree
+    let user = config(
+        r#"{ "settings": {
+            "key_mappings": { "a": "k" },
+            "input_latency_ms": 0,
+            "default": { "startup_wait": 2 },
+            "chord_hold_ms": 100
+        } }"#,
+    );
+    let settings = |config: &Option<CaptureConfig>| config.as_ref().unwrap().settings.clone();
+
+    let from_user =
+        settings_from_user(settings(&project).as_ref(), settings(&user).as_ref()).unwrap();
+    assert_eq!(from_user, ["default", "chord_hold_ms"]);
+
+    let layered = layer_user_config(project, user).unwrap();
+    let sources = print_config_sources(
+        "game",
+        &layered,
+        &from_user.iter().map(String::as_str).collect::<Vec<_>>(),
+        &Default::default(),
+    );
+    assert_eq!(sources["key_mappings.a"], "settings");
+    assert_eq!(sources["input_latency_ms"], "settings");
+    // Both equal the built-in values, but the user wrote them
+    assert_eq!(sources["startup_wait"], "user");
+    assert_eq!(
+        get_effective_input_pacing("game", &layered).chord_hold,
+        Duration::from_millis(100)
+    );
+
+    // Where both set a table, the project only replaces the fields and buttons it sets
+    let project = config(
+        r#"{ "settings": {
+            "default": { "fps": 20 },
+            "key_mappings": { "b": "j" },
+            "macros": { "skip": "A" }
+        } }"#,
+    );
+    let user = config(
+        r#"{ "settings": {
+            "default": { "fps": 30, "startup_wait": 2 },
+            "key_mappings": { "a": "k" },
+            "macros": { "skip": "B", "intro": "S" }
+        } }"#,
+    );
+
+    let from_user =
+        settings_from_user(settings(&project).as_ref(), settings(&user).as_ref()).unwrap();
+    assert_eq!(
+        from_user,
+        ["key_mappings.a", "default.startup_wait", "macros.intro"]
+    );
+    assert_eq!(
+        layered_macros(project.as_ref(), user.as_ref()),
+        BTreeMap::from([
+            ("intro".to_string(), "S".to_string()),
+            ("skip".to_string(), "A".to_string()),
+        ])
+    );
+
+    let layered = layer_user_config(project, user).unwrap();
+    let sources = print_config_sources(
+        "game",
+        &layered,
+        &from_user.iter().map(String::as_str).collect::<Vec<_>>(),
+        &Default::default(),
+    );
+    assert_eq!(sources["key_mappings.a"], "user");
+    assert_eq!(sources["key_mappings.b"], "settings");
+    assert_eq!(sources["startup_wait"], "user");
+    let default = layered
+        .as_ref()
+        .unwrap()
+        .settings
+        .as_ref()
+        .unwrap()
+        .default
+        .as_ref();
+    assert_eq!(default.unwrap().fps, Some(20.0));
+    let mappings = get_effective_key_mappings("game", &layered, &Default::default());
+    assert_eq!((mappings.a.as_str(), mappings.b.as_str()), ("k", "j"));
+}
+
+#[test]
+fn load_time_check_reports_values_durations_and_sequences_together() {
+    let config = config(
+        r#"{
+            "settings": { "default": { "fps": 0, "during_capture": "A:10o", "startup_wait": "2x" } },
+            "binaries": { "game": { "before_capture": "(A", "during_capture": ["A", "@intro"] } }
+        }"#,
+    );
+    let macros = BTreeMap::from([("intro".to_string(), "S,wait:100".to_string())]);
+
+    let problems = config_problems(
+        config.as_ref().unwrap(),
+        Vec::new(),
+        &macros,
+        ParseOptions::default(),
+    );
+    let fields: Vec<&str> = problems
+        .iter()
+        .map(|problem| problem.split([' ', ':']).next().unwrap())
+        .collect();
+    assert_eq!(
+        fields,
+        [
+            "settings.default.fps",
+            "settings.default.during_capture",
+            "settings.default.startup_wait",
+            "binaries.game.before_capture",
+        ],
+        "{:#?}",
+        problems
+    );
+
+    // Without the macro, the track that uses it is reported too
+    let problems = config_problems(
+        config.as_ref().unwrap(),
+        Vec::new(),
+        &BTreeMap::new(),
+        ParseOptions::default(),
+    );
+    assert!(problems
+        .iter()
+        .any(|problem| problem.starts_with("binaries.game.during_capture[1]")));
+}
+
+#[test]
+fn load_time_check_keeps_segment_and_variant_outputs_in_the_output_directory() {
+    let config = config(
+        r#"{
+            "binaries": {
+                "game": { "segments": [
+                    { "name": "intro", "suffix": "../../x" },
+                    { "name": "boss/fight", "fps": 0 },
+                    { "name": "credits", "suffix": "end" }
+                ] },
+                "other": { "variants": { "..": {}, "slow": { "fps": 0 } } }
+            }
+        }"#,
+    );
+
+    let problems = config_problems(
+        config.as_ref().unwrap(),
+        Vec::new(),
+        &BTreeMap::new(),
+        ParseOptions::default(),
+    );
+    assert_eq!(
+        problems,
+        [
+            "binaries.game.segments[0].suffix '../../x' must not contain a path; set output.dir for the directory",
+            "binaries.game.segments[1].name 'boss/fight' must not contain a path; set output.dir for the directory",
+            "binaries.game.segments[1].fps must be greater than 0, not 0",
+            "binaries.other.variants '..' is not a file name",
+            "binaries.other.variants.slow.fps must be greater than 0, not 0",
+        ]
+    );
+}
+
+#[test]
+fn check_config_reports_the_problems_of_every_stage_together() {
+    let problems = check_config(&fixture("check-config"), &args(&[])).unwrap();
+    let problems: Vec<(&str, &str)> = problems
+        .iter()
+        .map(|problem| (problem.location.as_str(), problem.message.as_str()))
+        .collect();
+
+    assert_eq!(
+        problems,
+        [
+            (
+                "capture.toml",
+                "settings.key_mappings.a = 'notakey': Unsupported key: notakey"
+            ),
+            (
+                "capture.toml",
+                "binaries.demo.before_capture: Unbalanced parentheses in input sequence: 1 '(' not closed"
+            ),
+            ("binaries.ghost", "matches no binary"),
+        ]
+    );
+}
+
+#[test]
+fn check_config_reports_a_sequence_flag_apart_from_the_config() {
+    let flags = args(&["--during-capture", "B:xx"]);
+    let problems = check_config(&fixture("check-config"), &flags).unwrap();
+
+    let from_flag: Vec<&ConfigProblem> = problems
+        .iter()
+        .filter(|problem| problem.location == "demo")
+        .collect();
+    assert_eq!(from_flag.len(), 1);
+    assert!(from_flag[0]
+        .message
+        .starts_with("during_capture at position 1 ('B:xx')"));
+    assert_eq!(problems.len(), 4);
+}
+
+#[test]
+fn planning_reports_every_binary_whatever_the_others_have() {
+    let config = config(
+        r#"{
+            "binaries": {
+                "alpha": {
+                    "segments": [{ "name": "one" }],
+                    "variants": { "two": {} }
+                },
+                "beta": { "during_capture": "A,rigth" },
+                "gamma": { "output": { "filename": "same" } },
+                "delta": { "output": { "filename": "same" } },
+                "epsilon": { "before_capture": "wait:10o" }
+            }
+        }"#,
+    );
+    let binaries = ["alpha", "beta", "gamma", "delta", "epsilon"].map(String::from);
+    let output_root = OutputRoot::resolve(Path::new("."), None, None, false).unwrap();
+
+    let error = plan_captures(
+        &binaries,
+        &config,
+        &args(&[]),
+        &mut ExternalKeyMappings::default(),
+        Path::new("."),
+        &output_root,
+    )
+    .unwrap_err()
+    .to_string();
+
+    assert!(
+        error.starts_with("Invalid input sequences or settings, nothing was captured:"),
+        "{}",
+        error
+    );
+    assert!(error.contains("alpha: Binary alpha defines both segments and variants"));
+    assert!(error.contains("beta during_capture at position 3 ('rigth')"));
+    assert!(error.contains("epsilon before_capture at position 1 ('wait:10o')"));
+    assert!(error.contains("gamma and delta would both be written to"));
+}
+
+#[test]
+fn binary_entries_rank_the_exact_name_over_patterns() {
+    let keys = ["demo_*", "demo_title", "/demo_.+/"];
+
+    assert_eq!(
+        binary_keys::matching_keys(keys.into_iter(), "demo_title"),
+        ["demo_title", "demo_*", "/demo_.+/"]
+    );
+}
+
+#[test]
+fn binary_entries_rank_longer_literal_prefixes_first() {
+    let keys = ["d*", "/demo_\\d+/", "demo_?"];
+
+    assert_eq!(
+        binary_keys::matching_keys(keys.into_iter(), "demo_1"),
+        ["/demo_\\d+/", "demo_?", "d*"]
+    );
+}
+
+#[test]
+fn binary_entries_with_the_same_prefix_keep_their_order_in_the_file() {
+    // The `s` that `?` makes optional isn't part of the regex's guaranteed prefix
+    let keys = ["/demos?_.*/", "demo*", "/demo.*/"];
+
+    assert_eq!(
+        binary_keys::matching_keys(keys.into_iter(), "demo_1"),
+        ["/demos?_.*/", "demo*", "/demo.*/"]
+    );
+    assert_eq!(
+        binary_keys::matching_keys(keys.into_iter().rev(), "demo_1"),
+        ["/demo.*/", "demo*", "/demos?_.*/"]
+    );
+}
+
+#[test]
+fn binary_entries_with_alternatives_have_no_literal_prefix() {
+    let keys = ["/demo_x|bench_y/", "bench*", "/(bench|demo)_y/"];
+
+    assert_eq!(
+        binary_keys::matching_keys(keys.into_iter(), "bench_y"),
+        ["bench*", "/demo_x|bench_y/", "/(bench|demo)_y/"]
+    );
+}
+
+#[test]
+fn wrong_value_in_an_extended_config_names_its_file_and_line() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let write = |name: &str, contents: &str| std::fs::write(dir.path().join(name), contents);
+    write(
+        "shared.toml",
+        "[settings.default]\nfps = 20\n\n[binaries.demo]\nduration = \"long\"\n",
+    )
+    .unwrap();
+    write(
+        "capture.json",
+        "{\n  \"extends\": \"shared.toml\",\n  \"binaries\": { \"demo\": { \"fps\": 30 } }\n}\n",
+    )
+    .unwrap();
+    let parse_error = || {
+        ConfigFile::find(dir.path(), None)
+            .unwrap()
+            .unwrap()
+            .parse_quietly::<CaptureConfig>()
+            .unwrap_err()
+            .to_string()
+    };
+
+    let error = parse_error();
+    assert!(error.contains("shared.toml: TOML parse error"), "{}", error);
+    assert!(error.contains("line 5"), "{}", error);
+
+    write("shared.toml", "[settings.default]\nfps = 20\n").unwrap();
+    write(
+        "capture.json",
+        "{\n  \"extends\": \"shared.toml\",\n  \"binaries\": { \"demo\": {\n    \"fps\": \"fast\" } }\n}\n",
+    )
+    .unwrap();
+    let error = parse_error();
+    assert!(
+        error.starts_with("Failed to parse capture.json:"),
+        "{}",
+        error
+    );
+    assert!(error.contains("line 4"), "{}", error);
+}
@@ -0,0 +1,441 @@
+//! The input executor, run against the mock backend: holds, chords, `down:`/`up:`
+//! pairing, cancellation, the keys released when a sequence fails, the fast-forward
+//! hold, timed-out waits, and pacing and jitter
+
+use crate::*;
+
+/// Slack for timers, which may fire a little late but never early
+const LATE: Duration = Duration::from_millis(100);
+
+fn parse(sequence: &str) -> Vec<InputAction> {
+    parse_input_sequence(
+        sequence,
+        &GbaKeyMappings::default(),
+        &BTreeMap::new(),
+        ParseOptions::default(),
+    )
+    .unwrap()
+    .actions
+}
+
+/// The key a GBA button is mapped to by default, like `A` to X
+fn key(button: &str) -> Key {
+    let key_mappings = GbaKeyMappings::default();
+    let mapped = match button {
+        "A" => &key_mappings.a,
+        "B" => &key_mappings.b,
+        "R" => &key_mappings.right,
+        _ => panic!("no default mapping for {}", button),
+    };
+    parse_raw_combo(mapped).unwrap().key
+}
+
+/// A session recording its events, paced by `pacing`
+fn mock_session(pacing: InputPacing) -> (InputSession, MockEvents) {
+    let session = InputSession {
+        pacing,
+        ..Default::default()
+    };
+    session.lock().pacing = pacing;
+    let events = session.use_mock_input(false);
+    (session, events)
+}
+
+fn untimed(events: &MockEvents) -> Vec<MockEvent> {
+    events
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(_, event)| event.clone())
+        .collect()
+}
+
+/// Time between the `first` and `second` recorded events
+fn between(events: &MockEvents, first: usize, second: usize) -> Duration {
+    let events = events.lock().unwrap();
+    events[second].0 - events[first].0
+}
+
+#[tokio::test]
+async fn hold_keeps_the_key_down_for_its_duration() {
+    let (session, events) = mock_session(InputPacing::default());
+    session.run(&parse("A:120")).await.unwrap();
+
+    let a = key("A");
+    assert_eq!(
+        untimed(&events),
+        [MockEvent::Press(a), MockEvent::Release(a)]
+    );
+    let held = between(&events, 0, 1);
+    assert!(held >= Duration::from_millis(120), "held {:?}", held);
+    assert!(held < Duration::from_millis(120) + LATE, "held {:?}", held);
+}
+
+#[tokio::test]
+async fn tap_is_a_click_without_a_minimum_press() {
+    let pacing = InputPacing {
+        min_press: Duration::ZERO,
+        ..Default::default()
+    };
+    let (session, events) = mock_session(pacing);
+    session.run(&parse("A")).await.unwrap();
+
+    assert_eq!(untimed(&events), [MockEvent::Click(key("A"))]);
+}
+
+#[tokio::test]
+async fn chord_presses_in_order_and_releases_in_reverse() {
+    let (session, events) = mock_session(InputPacing::default());
+    session.run(&parse("A+B:80")).await.unwrap();
+
+    let (a, b) = (key("A"), key("B"));
+    assert_eq!(
+        untimed(&events),
+        [
+            MockEvent::Press(a),
+            MockEvent::Press(b),
+            MockEvent::Release(b),
+            MockEvent::Release(a),
+        ]
+    );
+    assert!(between(&events, 1, 2) >= Duration::from_millis(80));
+}
+
+#[tokio::test]
+async fn chord_without_duration_is_held_for_the_chord_hold() {
+    let pacing = InputPacing {
+        chord_hold: Duration::from_millis(150),
+        ..Default::default()
+    };
+    let (session, events) = mock_session(pacing);
+    session.run(&parse("A+B")).await.unwrap();
+
+    let held = between(&events, 1, 2);
+    assert!(held >= Duration::from_millis(150), "held {:?}", held);
+    assert!(held < Duration::from_millis(150) + LATE, "held {:?}", held);
+}
+
+#[tokio::test]
+async fn down_and_up_pair_around_other_input() {
+    let (session, events) = mock_session(InputPacing {
+        min_press: Duration::ZERO,
+        ..Default::default()
+    });
+    session.run(&parse("down:R,A,up:R")).await.unwrap();
+
+    let (a, right) = (key("A"), key("R"));
+    assert_eq!(
+        untimed(&events),
+        [
+            MockEvent::Press(right),
+            MockEvent::Click(a),
+            MockEvent::Release(right),
+        ]
+    );
+    assert!(session.lock().held.is_empty());
+}
+
+#[tokio::test]
+async fn held_key_stays_down_between_sequences() {
+    let (session, events) = mock_session(InputPacing::default());
+    session.run(&parse("down:R")).await.unwrap();
+    assert_eq!(untimed(&events), [MockEvent::Press(key("R"))]);
+
+    session.run(&parse("wait:10,up:R")).await.unwrap();
+    assert_eq!(
+        untimed(&events),
+        [MockEvent::Press(key("R")), MockEvent::Release(key("R"))]
+    );
+}
+
+#[tokio::test]
+async fn second_down_of_a_held_key_is_ignored() {
+    let (session, events) = mock_session(InputPacing::default());
+    session.run(&parse("down:A,down:A,up:A")).await.unwrap();
+
+    let a = key("A");
+    assert_eq!(
+        untimed(&events),
+        [MockEvent::Press(a), MockEvent::Release(a)]
+    );
+}
+
+#[tokio::test]
+async fn tap_of_a_held_key_sends_nothing() {
+    let (session, events) = mock_session(InputPacing {
+        min_press: Duration::ZERO,
+        ..Default::default()
+    });
+    session.run(&parse("down:A,A,up:A")).await.unwrap();
+
+    let a = key("A");
+    assert_eq!(
+        untimed(&events),
+        [MockEvent::Press(a), MockEvent::Release(a)]
+    );
+}
+
+#[tokio::test]
+async fn finish_releases_keys_left_held() {
+    let (session, events) = mock_session(InputPacing::default());
+    session.run(&parse("down:A,down:R")).await.unwrap();
+    session.finish();
+
+    let (a, right) = (key("A"), key("R"));
+    assert_eq!(
+        untimed(&events),
+        [
+            MockEvent::Press(a),
+            MockEvent::Press(right),
+            MockEvent::Release(right),
+            MockEvent::Release(a),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn failing_sequence_releases_held_keys() {
+    let (session, events) = mock_session(InputPacing::default());
+    // Without a captured window, wait_for fails after A went down
+    let result = session.run(&parse("down:A,wait_for:stable")).await;
+
+    assert!(result.is_err());
+    let a = key("A");
+    assert_eq!(
+        untimed(&events),
+        [MockEvent::Press(a), MockEvent::Release(a)]
+    );
+    assert!(session.lock().held.is_empty());
+    assert!(session.lock().pressed.is_empty());
+}
+
+#[tokio::test]
+async fn shutdown_ends_a_mash_early_with_its_key_up() {
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let (mut session, events) = mock_session(InputPacing::default());
+    session.shutdown = Some(shutdown.clone());
+
+    let started = Instant::now();
+    let mash = tokio::spawn({
+        let session = session.clone();
+        async move { session.run(&parse("mash:A:5000")).await }
+    });
+    sleep(Duration::from_millis(250)).await;
+    shutdown.store(true, Ordering::Relaxed);
+    mash.await.unwrap().unwrap();
+
+    assert!(started.elapsed() < Duration::from_secs(1));
+    let events = untimed(&events);
+    let presses = events
+        .iter()
+        .filter(|event| matches!(event, MockEvent::Press(_)))
+        .count();
+    let releases = events
+        .iter()
+        .filter(|event| matches!(event, MockEvent::Release(_)))
+        .count();
+    assert!(presses >= 2, "{:?}", events);
+    assert_eq!(presses, releases);
+    assert_eq!(events.last(), Some(&MockEvent::Release(key("A"))));
+}
+
+#[tokio::test]
+async fn fast_forward_holds_its_key_until_shutdown() {
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let (mut session, events) = mock_session(InputPacing::default());
+    session.shutdown = Some(shutdown.clone());
+
+    let started = Instant::now();
+    let holding = tokio::spawn({
+        let session = session.clone();
+        async move { fast_forward(Key::Tab, 5000, &session).await }
+    });
+    sleep(Duration::from_millis(150)).await;
+    assert_eq!(untimed(&events), [MockEvent::Press(Key::Tab)]);
+    shutdown.store(true, Ordering::Relaxed);
+    holding.await.unwrap().unwrap();
+
+    assert!(started.elapsed() < Duration::from_secs(2));
+    assert_eq!(
+        untimed(&events),
+        [MockEvent::Press(Key::Tab), MockEvent::Release(Key::Tab)]
+    );
+    assert!(session.lock().pressed.is_empty());
+}
+
+#[tokio::test]
+async fn cancelled_sequence_releases_its_keys_with_the_session() {
+    let (session, events) = mock_session(InputPacing::default());
+    let running = tokio::spawn({
+        let session = session.clone();
+        async move { session.run(&parse("down:A,wait:5000,up:A")).await }
+    });
+    sleep(Duration::from_millis(50)).await;
+    running.abort();
+    assert!(running.await.unwrap_err().is_cancelled());
+    assert_eq!(untimed(&events), [MockEvent::Press(key("A"))]);
+
+    // Dropping the last handle releases what the cancelled sequence held
+    drop(session);
+    let a = key("A");
+    assert_eq!(
+        untimed(&events),
+        [MockEvent::Press(a), MockEvent::Release(a)]
+    );
+}
+
+// Pacing: the shortest taps and gaps are added where the sequence leaves timing open,
+// and nowhere else
+
+#[tokio::test]
+async fn tap_is_held_for_the_minimum_press() {
+    let (session, events) = mock_session(InputPacing {
+        min_press: Duration::from_millis(60),
+        ..Default::default()
+    });
+    session.run(&parse("A")).await.unwrap();
+
+    let a = key("A");
+    assert_eq!(
+        untimed(&events),
+        [MockEvent::Press(a), MockEvent::Release(a)]
+    );
+    let held = between(&events, 0, 1);
+    assert!(held >= Duration::from_millis(60), "held {:?}", held);
+    assert!(held < Duration::from_millis(60) + LATE, "held {:?}", held);
+}
+
+#[tokio::test]
+async fn explicit_hold_is_not_stretched_to_the_minimum_press() {
+    let (session, events) = mock_session(InputPacing {
+        min_press: Duration::from_millis(300),
+        ..Default::default()
+    });
+    session.run(&parse("A:30")).await.unwrap();
+
+    let held = between(&events, 0, 1);
+    assert!(held >= Duration::from_millis(30), "held {:?}", held);
+    assert!(held < Duration::from_millis(300), "held {:?}", held);
+}
+
+#[tokio::test]
+async fn minimum_gap_separates_back_to_back_input() {
+    let (session, events) = mock_session(InputPacing {
+        min_press: Duration::ZERO,
+        min_gap: Duration::from_millis(100),
+        ..Default::default()
+    });
+    session.run(&parse("A,B")).await.unwrap();
+
+    assert_eq!(
+        untimed(&events),
+        [MockEvent::Click(key("A")), MockEvent::Click(key("B"))]
+    );
+    let gap = between(&events, 0, 1);
+    assert!(gap >= Duration::from_millis(100), "gap {:?}", gap);
+    assert!(gap < Duration::from_millis(100) + LATE, "gap {:?}", gap);
+}
+
+#[tokio::test]
+async fn minimum_gap_is_not_added_to_an_explicit_wait() {
+    let (session, events) = mock_session(InputPacing {
+        min_press: Duration::ZERO,
+        min_gap: Duration::from_millis(150),
+        ..Default::default()
+    });
+    session.run(&parse("A,wait:200,B")).await.unwrap();
+
+    // The wait already covers the gap, so B follows after the wait alone
+    let gap = between(&events, 0, 1);
+    assert!(gap >= Duration::from_millis(200), "gap {:?}", gap);
+    assert!(gap < Duration::from_millis(300), "gap {:?}", gap);
+}
+
+#[tokio::test]
+async fn minimum_gap_leaves_explicit_holds_alone() {
+    let (session, events) = mock_session(InputPacing {
+        min_gap: Duration::from_millis(150),
+        ..Default::default()
+    });
+    session.run(&parse("A:50,B:50")).await.unwrap();
+
+    let (a, b) = (key("A"), key("B"));
+    assert_eq!(
+        untimed(&events),
+        [
+            MockEvent::Press(a),
+            MockEvent::Release(a),
+            MockEvent::Press(b),
+            MockEvent::Release(b),
+        ]
+    );
+    for (press, release) in [(0, 1), (2, 3)] {
+        let held = between(&events, press, release);
+        assert!(held >= Duration::from_millis(50), "held {:?}", held);
+        assert!(held < Duration::from_millis(150), "held {:?}", held);
+    }
+    assert!(between(&events, 1, 2) >= Duration::from_millis(150));
+}
+
+#[test]
+fn jitter_continues_across_sequences_and_repeats_with_its_seed() {
+    let pacing = InputPacing {
+        jitter: Duration::from_millis(100),
+        jitter_seed: 7,
+        ..Default::default()
+    };
+    let draws = |session: &InputSession| -> Vec<Duration> {
+        (0..4)
+            .map(|_| session.lock().jittered(Duration::from_millis(500)))
+            .collect()
+    };
+    let (session, _) = mock_session(pacing);
+    let first = draws(&session);
+    let later = draws(&session);
+
+    for held in first.iter().chain(&later) {
+        assert!(*held >= Duration::from_millis(400), "held {:?}", held);
+        assert!(*held <= Duration::from_millis(600), "held {:?}", held);
+    }
+    assert_ne!(first, later);
+    let (replayed, _) = mock_session(pacing);
+    assert_eq!(draws(&replayed), first);
+}
+
+#[tokio::test]
+async fn up_without_down_releases_the_key_and_holds_nothing() {
+    let (session, events) = mock_session(InputPacing::default());
+    session.run(&parse("up:A")).await.unwrap();
+
+    assert_eq!(untimed(&events), [MockEvent::Release(key("A"))]);
+    assert!(session.lock().held.is_empty());
+    assert!(session.lock().pressed.is_empty());
+}
+
+#[tokio::test]
+async fn double_down_is_released_once_by_the_cleanup() {
+    let (session, events) = mock_session(InputPacing::default());
+    session.run(&parse("down:A,down:A")).await.unwrap();
+    assert_eq!(session.lock().held.len(), 1);
+    session.finish();
+
+    let a = key("A");
+    assert_eq!(
+        untimed(&events),
+        [MockEvent::Press(a), MockEvent::Release(a)]
+    );
+}
+
+#[tokio::test]
+async fn timed_out_wait_fails_unless_the_pacing_is_lenient() {
+    for lenient_waits in [false, true] {
+        let (mut session, _) = mock_session(InputPacing {
+            lenient_waits,
+            ..Default::default()
+        });
+        // Every frame of the synthetic source differs, so the screen never settles
+        session.source = Some(Arc::new(SyntheticFrameSource::new(64, 64)));
+        let result = session.run(&parse("wait_for:stable:200:300")).await;
+        assert_eq!(result.is_ok(), lenient_waits, "{:?}", result);
+    }
+}
@@ -0,0 +1,233 @@
+//! mGBA's own configuration: where each platform keeps it, the keyboard bindings read
+//! from it, and the overrides written into a copy of it
+
+use super::fixture;
+use crate::mgba_bindings::{self, KeyBindings};
+use crate::mgba_config::{self, ConfigOverrides, ManagedConfig};
+use crate::window::Emulator;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// A lookup of the environment variables in `vars`
+fn env(vars: &'static [(&'static str, &'static str)]) -> impl Fn(&str) -> Option<OsString> {
+    move |name| {
+        vars.iter()
+            .find(|(var, _)| *var == name)
+            .map(|(_, value)| OsString::from(value))
+    }
+}
+
+fn bindings(file: &str, emulator: Emulator) -> KeyBindings {
+    mgba_bindings::read(fixture(&format!("mgba/{}", file)), emulator).unwrap()
+}
+
+/// The key bound to each button, or why there is none
+fn keys(bindings: &KeyBindings) -> Vec<(&'static str, String)> {
+    bindings
+        .keys
+        .iter()
+        .map(|(button, key)| (*button, key.clone().unwrap_or_else(|e| format!("({})", e))))
+        .collect()
+}
+
+#[test]
+fn config_path_on_linux_follows_xdg_config_home() {
+    let path = mgba_bindings::config_path(
+        "linux",
+        env(&[("XDG_CONFIG_HOME", "/xdg"), ("HOME", "/home/me")]),
+    );
+    assert_eq!(path, Some(PathBuf::from("/xdg/mgba/config.ini")));
+}
+
+#[test]
+fn config_path_on_linux_falls_back_to_home_config() {
+    let home = Some(PathBuf::from("/home/me/.config/mgba/config.ini"));
+    assert_eq!(
+        mgba_bindings::config_path("linux", env(&[("HOME", "/home/me")])),
+        home
+    );
+    // An empty XDG_CONFIG_HOME counts as unset
+    assert_eq!(
+        mgba_bindings::config_path(
+            "linux",
+            env(&[("XDG_CONFIG_HOME", ""), ("HOME", "/home/me")])
+        ),
+        home
+    );
+    assert_eq!(mgba_bindings::config_path("linux", env(&[])), None);
+}
+
+#[test]
+fn config_path_on_macos_is_in_application_support() {
+    let path = mgba_bindings::config_path(
+        "macos",
+        env(&[("XDG_CONFIG_HOME", "/xdg"), ("HOME", "/Users/me")]),
+    );
+    assert_eq!(
+        path,
+        Some(PathBuf::from(
+            "/Users/me/Library/Application Support/mGBA/config.ini"
+        ))
+    );
+}
+
+#[test]
+fn config_path_on_windows_is_in_appdata() {
+    const APPDATA: &str = r"C:\Users\me\AppData\Roaming";
+    let path = mgba_bindings::config_path(
+        "windows",
+        env(&[("APPDATA", APPDATA), ("HOME", "/home/me")]),
+    );
+    assert_eq!(
+        path,
+        Some(Path::new(APPDATA).join("mGBA").join("config.ini"))
+    );
+    assert_eq!(
+        mgba_bindings::config_path("windows", env(&[("HOME", "/home/me")])),
+        None
+    );
+}
+
+#[test]
+fn qt_bindings_are_read_as_key_names() {
+    let bindings = bindings("qt.ini", Emulator::MgbaQt);
+
+    assert_eq!(bindings.section, "gba.input.QT_K");
+    assert_eq!(
+        keys(&bindings),
+        [
+            ("a", "x".to_string()),
+            ("b", "z".to_string()),
+            ("select", "backspace".to_string()),
+            ("start", "enter".to_string()),
+            ("right", "right".to_string()),
+            ("left", "left".to_string()),
+            ("up", "up".to_string()),
+            ("down", "down".to_string()),
+            ("r_shoulder", "s".to_string()),
+            ("l_shoulder", "a".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn sdl_bindings_are_read_as_key_names() {
+    let bindings = bindings("sdl.ini", Emulator::MgbaSdl);
+
+    assert_eq!(bindings.section, "gba.input.SDLK");
+    assert_eq!(
+        keys(&bindings),
+        [
+            ("a", "x".to_string()),
+            ("b", "z".to_string()),
+            ("select", "backspace".to_string()),
+            ("start", "enter".to_string()),
+            ("right", "right".to_string()),
+            ("left", "left".to_string()),
+            ("up", "up".to_string()),
+            ("down", "down".to_string()),
+            ("r_shoulder", "s".to_string()),
+            ("l_shoulder", "a".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn section_of_the_frontend_in_use_is_preferred() {
+    let qt = bindings("both.ini", Emulator::MgbaQt);
+    assert_eq!(qt.section, "gba.input.QT_K");
+    assert_eq!(qt.keys[0], ("a", Ok("j".to_string())));
+
+    let sdl = bindings("both.ini", Emulator::MgbaSdl);
+    assert_eq!(sdl.section, "gba.input.SDLK");
+    assert_eq!(sdl.keys[0], ("a", Ok("k".to_string())));
+
+    // Without knowing the frontend, mGBA-Qt's bindings win
+    assert_eq!(
+        bindings("both.ini", Emulator::Auto).section,
+        "gba.input.QT_K"
+    );
+    // And the other frontend's are used when they are the only ones
+    assert_eq!(
+        bindings("sdl.ini", Emulator::MgbaQt).section,
+        "gba.input.SDLK"
+    );
+}
+
+#[test]
+fn unusable_bindings_say_why() {
+    let keys = keys(&bindings("unbound.ini", Emulator::MgbaQt));
+
+    assert_eq!(keys[0], ("a", "(keyA is not bound to a key)".to_string()));
+    assert_eq!(keys[1], ("b", "(keyB is not set)".to_string()));
+    assert_eq!(
+        keys[2],
+        (
+            "select",
+            "(keySelect=16777345 is a key this tool can't send)".to_string()
+        )
+    );
+    assert_eq!(
+        keys[3],
+        ("start", "(keyStart=enter is not a key code)".to_string())
+    );
+    assert_eq!(keys[4], ("right", "right".to_string()));
+}
+
+#[test]
+fn config_without_bindings_is_an_error() {
+    let path = fixture("mgba/no-bindings.ini");
+    let error = mgba_bindings::read(path, Emulator::MgbaQt)
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("has no keyboard bindings"), "{}", error);
+
+    let missing = mgba_bindings::read(fixture("mgba/missing.ini"), Emulator::MgbaQt);
+    assert!(missing.is_err());
+}
+
+#[test]
+fn overrides_replace_values_and_keep_the_rest() {
+    let contents = std::fs::read_to_string(fixture("mgba/qt.ini")).unwrap();
+    let overrides = ConfigOverrides {
+        software_renderer: true,
+        managed: Some(ManagedConfig {
+            scale: 2,
+            title_stats: false,
+        }),
+    };
+
+    let applied = mgba_config::apply(&contents, &overrides);
+
+    // Values already in [ports.qt] are replaced where they are, others are added to it
+    let qt_section: Vec<&str> = applied
+        .lines()
+        .skip_while(|line| *line != "[ports.qt]")
+        .take_while(|line| !line.is_empty())
+        .collect();
+    assert!(qt_section.contains(&"displayDriver=0"), "{}", applied);
+    assert!(qt_section.contains(&"scaleMultiplier=2"), "{}", applied);
+    assert!(qt_section.contains(&"showFps=0"), "{}", applied);
+    assert!(qt_section.contains(&"mute=1"), "{}", applied);
+    assert!(qt_section.contains(&"audioSync=0"), "{}", applied);
+    assert_eq!(applied.matches("displayDriver=").count(), 2, "{}", applied);
+    // The SDL frontend gets its own section
+    assert!(applied.contains("[ports.sdl]\n"), "{}", applied);
+    // The bindings are left alone
+    assert!(
+        applied.contains("[gba.input.QT_K]\nkeyA=88\nkeyB=90\n"),
+        "{}",
+        applied
+    );
+    // Applying the same overrides again changes nothing
+    assert_eq!(mgba_config::apply(&applied, &overrides), applied);
+}
+
+#[test]
+fn no_overrides_leave_the_config_as_it_was() {
+    let contents = std::fs::read_to_string(fixture("mgba/sdl.ini")).unwrap();
+    assert_eq!(
+        mgba_config::apply(&contents, &ConfigOverrides::default()),
+        contents
+    );
+}
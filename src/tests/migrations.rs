@@ -0,0 +1,170 @@
+//! Versions of the config format: files of each version read and migrated from fixtures,
+//! and migrating a file a second time leaving it as it is
+
+use super::fixture;
+use crate::config_file::{ConfigFile, Migration, Schema, CONFIG_VERSION};
+use crate::*;
+use tempfile::TempDir;
+
+/// A made-up version 2 of the format, in which a capture's `duration` in seconds became
+/// `duration_ms`, to run a migration that changes something
+const VERSION_2: Schema = Schema {
+    version: 2,
+    migrations: &[Migration {
+        version: 2,
+        description: "duration is given in milliseconds as duration_ms",
+        migrate: duration_to_milliseconds,
+    }],
+};
+
+fn duration_to_milliseconds(config: &mut serde_json::Value) {
+    let binaries = config
+        .get_mut("binaries")
+        .and_then(serde_json::Value::as_object_mut);
+    for binary in binaries
+        .into_iter()
+        .flat_map(|binaries| binaries.values_mut())
+    {
+        let Some(binary) = binary.as_object_mut() else {
+            continue;
+        };
+        if let Some(seconds) = binary.remove("duration").and_then(|value| value.as_f64()) {
+            binary.insert(
+                "duration_ms".to_string(),
+                ((seconds * 1000.0) as u64).into(),
+            );
+        }
+    }
+}
+
+/// The fixture config file `name`
+fn config_file(name: &str) -> ConfigFile {
+    ConfigFile::find(Path::new("."), Some(&fixture(&format!("migrate/{}", name))))
+        .unwrap()
+        .unwrap()
+}
+
+/// A copy of the fixture config file `name` in a directory of its own, for tests that
+/// write to it
+fn copy(name: &str) -> (TempDir, ConfigFile) {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join(name);
+    std::fs::copy(fixture(&format!("migrate/{}", name)), &path).unwrap();
+    let file = ConfigFile::find(dir.path(), Some(&path)).unwrap().unwrap();
+    (dir, file)
+}
+
+fn contents(dir: &TempDir, name: &str) -> String {
+    std::fs::read_to_string(dir.path().join(name)).unwrap()
+}
+
+#[test]
+fn file_without_version_reads_as_version_1() {
+    for name in ["v1.toml", "v1.json", "v1-versioned.toml"] {
+        let config: CaptureConfig = config_file(name).parse_quietly().unwrap();
+        let demo = &config.binaries.as_ref().unwrap()["demo"];
+        assert!(
+            matches!(&demo.during_capture, Some(InputTracks::Single(during)) if during == "R:500,A"),
+            "{}",
+            name
+        );
+    }
+    let config: CaptureConfig = config_file("v1.toml").parse_quietly().unwrap();
+    assert_eq!(config.version, None);
+    let default = config.settings.unwrap().default.unwrap();
+    assert_eq!(default.fps, Some(20.0));
+}
+
+#[test]
+fn newer_version_asks_for_an_upgrade() {
+    let error = config_file("future.toml")
+        .parse_quietly::<CaptureConfig>()
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("version 99"), "{}", error);
+    assert!(error.contains("please upgrade agbrs-capture"), "{}", error);
+}
+
+#[test]
+fn version_below_1_is_invalid() {
+    let error = config_file("invalid-version.toml")
+        .parse_quietly::<CaptureConfig>()
+        .unwrap_err()
+        .to_string();
+    assert!(error.starts_with("Invalid version 0"), "{}", error);
+}
+
+#[test]
+fn migrating_a_version_1_file_adds_the_version_and_keeps_comments() {
+    let (dir, file) = copy("v1.toml");
+
+    assert_eq!(file.migrate().unwrap(), 1);
+    let migrated = contents(&dir, "v1.toml");
+    assert_eq!(file.get(&["version"]).unwrap(), Some(CONFIG_VERSION.into()));
+    assert!(migrated.contains("# Walk right, then jump"), "{}", migrated);
+    assert!(
+        migrated.contains("during_capture = \"R:500,A\""),
+        "{}",
+        migrated
+    );
+
+    // A second run finds nothing to do and leaves the file alone
+    assert_eq!(file.migrate().unwrap(), CONFIG_VERSION);
+    assert_eq!(contents(&dir, "v1.toml"), migrated);
+}
+
+#[test]
+fn migrating_a_json_file_adds_the_version() {
+    let (dir, file) = copy("v1.json");
+
+    assert_eq!(file.migrate().unwrap(), 1);
+    let migrated = contents(&dir, "v1.json");
+    let value: serde_json::Value = serde_json::from_str(&migrated).unwrap();
+    assert_eq!(value["version"], CONFIG_VERSION);
+    assert_eq!(value["binaries"]["demo"]["duration"], 4.0);
+
+    assert_eq!(file.migrate().unwrap(), CONFIG_VERSION);
+    assert_eq!(contents(&dir, "v1.json"), migrated);
+}
+
+#[test]
+fn migrations_turn_an_old_file_into_the_new_format() {
+    for name in ["v1.toml", "v1.json"] {
+        let (_dir, file) = copy(name);
+
+        assert_eq!(file.migrate_to(&VERSION_2).unwrap(), 1);
+        assert_eq!(file.get(&["version"]).unwrap(), Some(2.into()));
+        let demo = file.get(&["binaries", "demo"]).unwrap().unwrap();
+        assert_eq!(demo["duration_ms"], 4000, "{}", name);
+        assert_eq!(demo.get("duration"), None, "{}", name);
+        // Settings the migration doesn't touch come through unchanged
+        assert_eq!(demo["during_capture"], "R:500,A", "{}", name);
+        assert_eq!(
+            file.get(&["settings", "default", "fps"]).unwrap(),
+            Some(20.into()),
+            "{}",
+            name
+        );
+    }
+}
+
+#[test]
+fn migrating_twice_changes_nothing_the_second_time() {
+    for name in ["v1.toml", "v1.json"] {
+        let (dir, file) = copy(name);
+
+        file.migrate_to(&VERSION_2).unwrap();
+        let migrated = contents(&dir, name);
+        assert_eq!(file.migrate_to(&VERSION_2).unwrap(), 2, "{}", name);
+        assert_eq!(contents(&dir, name), migrated, "{}", name);
+    }
+}
+
+#[test]
+fn file_at_the_current_version_is_left_alone() {
+    let (dir, file) = copy("v1-versioned.toml");
+    let before = contents(&dir, "v1-versioned.toml");
+
+    assert_eq!(file.migrate().unwrap(), CONFIG_VERSION);
+    assert_eq!(contents(&dir, "v1-versioned.toml"), before);
+}
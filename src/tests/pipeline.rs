@@ -0,0 +1,144 @@
+//! The capture pipeline end to end, from synthetic frames through input and encoding to
+//! the written file, without mGBA or a display
+
+use super::config::{args, config};
+use crate::*;
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn synthetic_capture_writes_every_frame_with_its_input() {
+    let out = TempDir::new().unwrap();
+    let output_root = OutputRoot::resolve(Path::new("."), Some(out.path()), None, false).unwrap();
+
+    let config = config(
+        r#"{ "binaries": { "demo": {
+            "fps": 10,
+            "duration": 0.6,
+            "during_capture": "A,wait:100,screenshot:middle,B"
+        } } }"#,
+    );
+    let binaries = ["demo".to_string()];
+    let flags = args(&[]);
+    let mut plans = plan_captures(
+        &binaries,
+        &config,
+        &flags,
+        &mut ExternalKeyMappings::default(),
+        Path::new("."),
+        &output_root,
+    )
+    .unwrap();
+    let (job, phases) = plans.remove("demo").unwrap().remove(0).remove(0);
+
+    let (width, height) = GBA_SCREEN_SIZE;
+    let source: Arc<dyn FrameSource> = Arc::new(SyntheticFrameSource::new(width, height));
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let session = InputSession::new(
+        &shutdown,
+        source.clone(),
+        &WindowQuery::default(),
+        &output_root,
+        &job.output_name,
+        Default::default(),
+    );
+    let events = session.use_mock_input(false);
+    let log = EmulatorLog::new(false);
+    let outcome = capture_binary_gif(
+        &job,
+        source,
+        None,
+        frame_timing(job.fps, job.duration),
+        &phases,
+        &session,
+        &CaptureControl {
+            sync_to_emulator: false,
+            log: &log,
+            log_cursor: 0,
+            start_marker: None,
+            stop_marker: None,
+            marker_timeout: Duration::ZERO,
+            keep_failed_output: false,
+            live_input: None,
+            interactive: None,
+            key_mappings: &GbaKeyMappings::default(),
+            macros: &BTreeMap::new(),
+            parse_options: ParseOptions::default(),
+            frame_filter: None,
+            post: None,
+            output_root: &output_root,
+            loop_count: Loop::Infinite,
+            shutdown: &shutdown,
+        },
+    )
+    .await
+    .unwrap();
+
+    let CaptureOutcome::Written {
+        output_path,
+        frames,
+    } = outcome
+    else {
+        panic!("the capture wasn't written");
+    };
+    assert_eq!(frames, 6);
+    assert_eq!(Path::new(&output_path), out.path().join("demo.gif"));
+
+    // The GIF holds every frame at the screen's size. The source numbers its frames by
+    // where it draws the square, so they can be checked to be in the order captured.
+    let mut decoder = gif::DecodeOptions::new();
+    decoder.set_color_output(gif::ColorOutput::RGBA);
+    let mut decoder = decoder
+        .read_info(std::fs::File::open(&output_path).unwrap())
+        .unwrap();
+    assert_eq!(
+        (u32::from(decoder.width()), u32::from(decoder.height())),
+        (width, height)
+    );
+    let mut indexes = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+        assert_eq!(frame.delay, 10, "frame {} lasts 1/10 s", indexes.len());
+        indexes.push(synthetic_frame_index(&frame.buffer, width, height));
+    }
+    assert_eq!(indexes.len(), 6);
+    assert!(
+        indexes.windows(2).all(|pair| pair[0] < pair[1]),
+        "frames are written in the order they were captured, once each: {:?}",
+        indexes
+    );
+
+    // The during-capture input ran against the mock backend while frames were captured
+    let keys = GbaKeyMappings::default();
+    let key = |mapped: &str| parse_raw_combo(mapped).unwrap().key;
+    let sent: Vec<Key> = events
+        .lock()
+        .unwrap()
+        .iter()
+        .filter_map(|(_, event)| match event {
+            MockEvent::Press(key) | MockEvent::Click(key) => Some(*key),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(sent, [key(&keys.a), key(&keys.b)]);
+    assert_eq!(session.screenshots().len(), 1);
+    let screenshot = image::open(&session.screenshots()[0]).unwrap().to_rgba8();
+
+    // The screenshot took one frame from the source between two of the GIF's, so between
+    // them they hold every frame the source drew
+    indexes.push(synthetic_frame_index(screenshot.as_raw(), width, height));
+    indexes.sort_unstable();
+    assert_eq!(indexes, (0..7).collect::<Vec<_>>());
+}
+
+/// How many frames `SyntheticFrameSource` drew before an RGBA frame of `width` x `height`,
+/// from the left edge of its white square on the middle row, which moves 4 pixels a frame
+fn synthetic_frame_index(rgba: &[u8], width: u32, height: u32) -> u32 {
+    let row = (height / 2 * width) as usize * 4;
+    let x = (0..width)
+        .find(|&x| {
+            let pixel = &rgba[row + x as usize * 4..][..3];
+            pixel.iter().all(|&channel| channel >= 240)
+        })
+        .expect("every frame shows the square");
+    assert_eq!(x % 4, 0, "the square is drawn at a multiple of its step");
+    x / 4
+}
@@ -0,0 +1,203 @@
+//! Parsing input sequences: repeat groups and their limits, `down:`/`up:`, `fuzz:`, and
+//! the longest step
+
+use crate::*;
+
+fn parse(sequence: &str) -> Result<InputSequence> {
+    parse_input_sequence(
+        sequence,
+        &GbaKeyMappings::default(),
+        &BTreeMap::new(),
+        ParseOptions::default(),
+    )
+}
+
+fn parse_with_macros(sequence: &str, macros: &[(&str, &str)]) -> Result<InputSequence> {
+    let macros = macros
+        .iter()
+        .map(|&(name, body)| (name.to_string(), body.to_string()))
+        .collect();
+    parse_input_sequence(
+        sequence,
+        &GbaKeyMappings::default(),
+        &macros,
+        ParseOptions::default(),
+    )
+}
+
+#[test]
+fn group_repeats_its_steps() {
+    let sequence = parse("(A,wait:100)x3").unwrap();
+    assert_eq!(sequence.expanded(), "A,wait:100,A,wait:100,A,wait:100");
+}
+
+#[test]
+fn nested_groups_multiply() {
+    let sequence = parse("((A,wait:200)x3,B)x2").unwrap();
+    assert_eq!(sequence.actions.len(), 14);
+    assert_eq!(
+        sequence.expanded(),
+        "A,wait:200,A,wait:200,A,wait:200,B,A,wait:200,A,wait:200,A,wait:200,B"
+    );
+}
+
+#[test]
+fn group_once_is_its_steps() {
+    let sequence = parse("S,(A,B)x1,R").unwrap();
+    assert_eq!(sequence.expanded(), "S,A,B,R");
+}
+
+#[test]
+fn group_zero_times_is_skipped() {
+    let sequence = parse("S,(A,B)x0,R").unwrap();
+    assert_eq!(sequence.expanded(), "S,R");
+    assert!(parse("(A)x0").unwrap().is_empty());
+}
+
+#[test]
+fn group_without_count_is_an_error() {
+    let error = parse("(A,B)").unwrap_err().to_string();
+    assert!(error.contains("needs a repeat count"), "{}", error);
+    assert!(parse("(A)x-1").is_err());
+}
+
+#[test]
+fn group_up_to_the_action_limit_parses() {
+    let count = MAX_SEQUENCE_ACTIONS / 2;
+    let sequence = parse(&format!("(A,B)x{}", count)).unwrap();
+    assert_eq!(sequence.actions.len(), MAX_SEQUENCE_ACTIONS);
+}
+
+#[test]
+fn group_past_the_action_limit_is_an_error() {
+    let error = parse(&format!("(A)x{}", MAX_SEQUENCE_ACTIONS + 1))
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("expands to more than"), "{}", error);
+}
+
+#[test]
+fn nested_groups_past_the_action_limit_fail_without_expanding() {
+    let error = parse("((A)x100000)x100000").unwrap_err().to_string();
+    assert!(error.contains("expands to more than"), "{}", error);
+    // A count that overflows when multiplied is caught the same way
+    let error = parse(&format!("((A,B)x2)x{}", usize::MAX))
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("expands to more than"), "{}", error);
+}
+
+#[test]
+fn macros_doubling_past_the_action_limit_are_an_error() {
+    // Each macro runs the one before it twice: 2^20 taps in all
+    let mut macros = vec![("m0".to_string(), "A".to_string())];
+    for level in 1..=20 {
+        macros.push((
+            format!("m{}", level),
+            format!("@m{},@m{}", level - 1, level - 1),
+        ));
+    }
+    let macros: Vec<(&str, &str)> = macros
+        .iter()
+        .map(|(name, body)| (name.as_str(), body.as_str()))
+        .collect();
+    let error = parse_with_macros("@m20", &macros).unwrap_err().to_string();
+    assert!(error.contains("expands to more than"), "{}", error);
+}
+
+/// The key `down:`/`up:` actions of `sequence` act on, as `down A` or `up A`
+fn held_actions(sequence: &InputSequence) -> Vec<String> {
+    sequence
+        .actions
+        .iter()
+        .map(|action| match action {
+            InputAction::KeyDown { key } => format!("down {}", key),
+            InputAction::KeyUp { key } => format!("up {}", key),
+            other => format!("{:?}", other),
+        })
+        .collect()
+}
+
+#[test]
+fn down_and_up_parse_into_a_pair() {
+    let sequence = parse("down:right,A,up:right").unwrap();
+    assert!(matches!(sequence.actions[0], InputAction::KeyDown { .. }));
+    assert!(matches!(sequence.actions[1], InputAction::Press { .. }));
+    assert!(matches!(sequence.actions[2], InputAction::KeyUp { .. }));
+}
+
+#[test]
+fn up_without_down_parses_and_is_left_to_the_executor() {
+    // The key may have gone down in an earlier phase, so the parser can't reject it
+    let sequence = parse("up:right").unwrap();
+    let key = parse_raw_combo(&GbaKeyMappings::default().right).unwrap();
+    assert_eq!(held_actions(&sequence), [format!("up {}", key)]);
+}
+
+#[test]
+fn double_down_parses_as_two_downs() {
+    let sequence = parse("down:A,down:A").unwrap();
+    let key = parse_raw_combo(&GbaKeyMappings::default().a).unwrap();
+    assert_eq!(
+        held_actions(&sequence),
+        [format!("down {}", key), format!("down {}", key)]
+    );
+}
+
+#[test]
+fn down_with_a_duration_is_a_hold_of_the_down_arrow() {
+    let sequence = parse("down:300").unwrap();
+    assert!(matches!(
+        sequence.actions[0],
+        InputAction::Press {
+            duration: Some(_),
+            ..
+        }
+    ));
+}
+
+#[test]
+fn down_and_up_need_a_key() {
+    assert!(parse("down:").is_err());
+    assert!(parse("up:").is_err());
+}
+
+#[test]
+fn fuzz_presses_come_from_the_seed_of_the_parse_options() {
+    let fuzz = |seed: u64| {
+        let options = ParseOptions {
+            fuzz_seed: seed,
+            ..Default::default()
+        };
+        let sequence = parse_input_sequence(
+            "fuzz:2000",
+            &GbaKeyMappings::default(),
+            &BTreeMap::new(),
+            options,
+        )
+        .unwrap();
+        match &sequence.actions[0] {
+            InputAction::Fuzz { presses, seed } => (presses.clone(), *seed),
+            other => panic!("expected fuzz, got {:?}", other),
+        }
+    };
+    let (presses, seed) = fuzz(42);
+    assert_eq!(seed, 42);
+    assert_eq!(fuzz(42).0, presses);
+    assert_ne!(fuzz(43).0, presses);
+}
+
+#[test]
+fn long_waits_parse_only_when_the_options_allow_them() {
+    let long = format!("A,wait:{}", MAX_STEP_DURATION_MS + 1);
+    assert!(parse(&long).is_err());
+    assert!(parse(&format!("wait_for:stable:100:{}", MAX_STEP_DURATION_MS + 1)).is_err());
+
+    let allowed = ParseOptions {
+        allow_long_waits: true,
+        ..Default::default()
+    };
+    let sequence =
+        parse_input_sequence(&long, &GbaKeyMappings::default(), &BTreeMap::new(), allowed).unwrap();
+    assert_eq!(sequence.actions.len(), 2);
+}
@@ -0,0 +1,326 @@
+//! Picking the mGBA window out of fabricated window metadata, the way each detection
+//! strategy and the ranking among its matches do, the strategies tried on each platform,
+//! the launched process tree, display scaling, and telling a title that names a ROM
+
+use crate::window::{
+    add_descendants, best_window, candidate_windows, compile_title_pattern, display_scale,
+    strategies_for, title_names_rom, Emulator, Strategy, WindowInfo, WindowMatch, WindowQuery,
+    DEFAULT_TITLE_PATTERN,
+};
+use regex::Regex;
+
+fn window(id: u32, title: &str, width: u32, height: u32) -> WindowInfo {
+    WindowInfo {
+        id,
+        title: title.to_string(),
+        width,
+        height,
+        ..Default::default()
+    }
+}
+
+fn mgba_title() -> Regex {
+    compile_title_pattern(DEFAULT_TITLE_PATTERN).unwrap()
+}
+
+fn hints(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| name.to_lowercase()).collect()
+}
+
+/// Id of the window `strategy` picks from `windows`
+fn pick(
+    windows: &[WindowInfo],
+    strategy: Strategy,
+    tracked: &[u32],
+    hints: &[String],
+) -> Option<u32> {
+    let title_pattern = mgba_title();
+    let candidates = candidate_windows(windows, strategy, tracked, hints, &title_pattern);
+    best_window(&candidates, strategy, hints, &title_pattern).map(|window| window.id)
+}
+
+#[test]
+fn launched_process_takes_only_windows_of_the_tracked_processes() {
+    let windows = [
+        WindowInfo {
+            pid: Some(100),
+            ..window(1, "mGBA - my own session", 720, 480)
+        },
+        WindowInfo {
+            pid: Some(200),
+            ..window(2, "mGBA - demo", 480, 320)
+        },
+        // An unrelated window of a launched process, like the terminal cargo runs in
+        WindowInfo {
+            pid: Some(201),
+            ..window(3, "cargo run", 200, 100)
+        },
+    ];
+
+    assert_eq!(
+        pick(&windows, Strategy::LaunchedProcess, &[200, 201], &[]),
+        Some(2)
+    );
+    assert_eq!(pick(&windows, Strategy::LaunchedProcess, &[], &[]), None);
+}
+
+#[test]
+fn launched_process_prefers_the_largest_window_and_skips_minimized_ones() {
+    let windows = [
+        WindowInfo {
+            pid: Some(200),
+            ..window(5, "mGBA - Settings", 300, 200)
+        },
+        WindowInfo {
+            pid: Some(200),
+            ..window(4, "mGBA - demo", 720, 480)
+        },
+        WindowInfo {
+            pid: Some(200),
+            minimized: true,
+            ..window(6, "mGBA - demo", 1440, 960)
+        },
+    ];
+
+    assert_eq!(
+        pick(&windows, Strategy::LaunchedProcess, &[200], &[]),
+        Some(4)
+    );
+}
+
+#[test]
+fn title_pattern_prefers_the_window_of_the_newest_process() {
+    // Window ids don't follow creation order everywhere, so a newer process wins over a
+    // higher id
+    let windows = [
+        WindowInfo {
+            started: Some(1_000),
+            ..window(30, "mGBA - old session", 720, 480)
+        },
+        WindowInfo {
+            started: Some(2_000),
+            ..window(10, "mGBA - demo", 240, 160)
+        },
+        WindowInfo {
+            started: Some(3_000),
+            ..window(20, "Terminal", 800, 600)
+        },
+    ];
+    assert_eq!(pick(&windows, Strategy::TitlePattern, &[], &[]), Some(10));
+}
+
+#[cfg(not(target_os = "windows"))]
+#[test]
+fn windows_of_processes_started_together_go_by_the_newest_id() {
+    let windows = [
+        WindowInfo {
+            started: Some(1_000),
+            ..window(10, "mGBA - old session", 720, 480)
+        },
+        WindowInfo {
+            started: Some(1_000),
+            ..window(30, "mGBA - demo", 240, 160)
+        },
+    ];
+    assert_eq!(pick(&windows, Strategy::TitlePattern, &[], &[]), Some(30));
+}
+
+#[test]
+fn window_naming_the_rom_wins_over_a_newer_one() {
+    let windows = [
+        WindowInfo {
+            started: Some(1_000),
+            ..window(10, "mGBA - demo.gba", 720, 480)
+        },
+        WindowInfo {
+            started: Some(2_000),
+            ..window(30, "mGBA - other.gba", 720, 480)
+        },
+    ];
+    let demo = hints(&["demo"]);
+    assert_eq!(pick(&windows, Strategy::TitlePattern, &[], &demo), Some(10));
+}
+
+#[test]
+fn title_match_tells_the_main_window_from_dialogs_of_the_same_class() {
+    let class = Some("mgba io.mgba.mGBA".to_string());
+    let windows = [
+        WindowInfo {
+            class: class.clone(),
+            ..window(1, "mGBA - demo", 720, 480)
+        },
+        // mGBA's dialogs share the class, and may be newer
+        WindowInfo {
+            class: class.clone(),
+            ..window(2, "Controls", 400, 300)
+        },
+        window(3, "mGBA notes.txt - editor", 800, 600),
+    ];
+
+    assert_eq!(pick(&windows, Strategy::Class, &[], &[]), Some(1));
+}
+
+#[test]
+fn class_and_process_name_are_matched_case_insensitively() {
+    let windows = [
+        WindowInfo {
+            class: Some("io.mgba.mGBA".to_string()),
+            ..window(1, "", 720, 480)
+        },
+        WindowInfo {
+            process_name: Some("mGBA.exe".to_string()),
+            ..window(2, "", 720, 480)
+        },
+        WindowInfo {
+            class: Some("konsole".to_string()),
+            process_name: Some("konsole".to_string()),
+            ..window(3, "mGBA", 720, 480)
+        },
+    ];
+
+    assert_eq!(pick(&windows, Strategy::Class, &[], &[]), Some(1));
+    assert_eq!(pick(&windows, Strategy::ProcessName, &[], &[]), Some(2));
+}
+
+#[test]
+fn rom_name_finds_an_sdl_window_titled_with_just_the_rom() {
+    let windows = [window(1, "demo", 480, 320), window(2, "Terminal", 800, 600)];
+
+    assert_eq!(pick(&windows, Strategy::TitlePattern, &[], &[]), None);
+    assert_eq!(
+        pick(&windows, Strategy::RomName, &[], &hints(&["Demo"])),
+        Some(1)
+    );
+}
+
+#[test]
+fn custom_title_pattern_is_a_regex() {
+    let windows = [
+        window(1, "Mesen-GBA — demo.gba", 480, 320),
+        window(2, "mGBA - demo", 480, 320),
+    ];
+    let title_pattern = compile_title_pattern("^mesen").unwrap();
+
+    let candidates = candidate_windows(&windows, Strategy::TitlePattern, &[], &[], &title_pattern);
+    assert_eq!(
+        candidates
+            .iter()
+            .map(|window| window.id)
+            .collect::<Vec<_>>(),
+        [1]
+    );
+}
+
+#[test]
+fn auto_tries_the_executable_name_before_the_class_on_windows() {
+    assert_eq!(
+        strategies_for(WindowMatch::Auto, Emulator::MgbaQt, "windows"),
+        [
+            Strategy::LaunchedProcess,
+            Strategy::ProcessName,
+            Strategy::Class,
+            Strategy::TitlePattern,
+        ]
+    );
+    for os in ["linux", "macos"] {
+        assert_eq!(
+            strategies_for(WindowMatch::Auto, Emulator::MgbaQt, os),
+            [
+                Strategy::LaunchedProcess,
+                Strategy::Class,
+                Strategy::ProcessName,
+                Strategy::TitlePattern,
+            ]
+        );
+    }
+}
+
+#[test]
+fn match_mode_limits_the_strategies() {
+    let os = "windows";
+    assert_eq!(
+        strategies_for(WindowMatch::Pid, Emulator::MgbaQt, os),
+        [Strategy::LaunchedProcess, Strategy::TitlePattern]
+    );
+    assert_eq!(
+        strategies_for(WindowMatch::Process, Emulator::Auto, os),
+        [Strategy::ProcessName]
+    );
+    assert_eq!(
+        strategies_for(WindowMatch::Class, Emulator::Auto, os),
+        [Strategy::Class]
+    );
+    // mGBA-SDL titles its window with the ROM name alone
+    assert_eq!(
+        strategies_for(WindowMatch::Title, Emulator::MgbaSdl, os),
+        [Strategy::RomName, Strategy::TitlePattern]
+    );
+    assert_eq!(
+        strategies_for(WindowMatch::Title, Emulator::Auto, os),
+        [Strategy::TitlePattern, Strategy::RomName]
+    );
+}
+
+#[test]
+fn process_tree_takes_in_descendants_listed_before_their_parents() {
+    // cargo (1) runs a shim (2) that starts mGBA (3); 9 is unrelated
+    let processes = [(3, Some(2)), (9, Some(0)), (2, Some(1)), (1, Some(0))];
+    let mut tree = vec![1];
+    add_descendants(&processes, &mut tree);
+    tree.sort();
+    assert_eq!(tree, [1, 2, 3]);
+}
+
+#[test]
+fn process_tree_keeps_the_emulator_of_a_shim_that_exited() {
+    // The .bat shim (2) found earlier is gone, and mGBA (3) still names it as its parent
+    let processes = [(1, Some(0)), (3, Some(2))];
+    let mut tree = vec![1, 2];
+    add_descendants(&processes, &mut tree);
+    assert_eq!(tree, [1, 2, 3]);
+}
+
+#[test]
+fn display_scale_of_a_dpi_virtualized_window() {
+    assert_eq!(display_scale((480, 320), (720, 480)), Some(1.5));
+    assert_eq!(display_scale((480, 320), (960, 640)), Some(2.0));
+    // Scaled sizes are rounded to whole pixels
+    assert_eq!(display_scale((481, 321), (601, 401)), Some(601.0 / 481.0));
+}
+
+#[test]
+fn display_scale_needs_one_factor_for_both_sides() {
+    assert_eq!(display_scale((480, 320), (480, 320)), None);
+    assert_eq!(display_scale((480, 320), (720, 320)), None);
+    assert_eq!(display_scale((0, 0), (720, 480)), None);
+}
+
+#[test]
+fn queries_of_two_binaries_each_pick_their_own_window() {
+    let windows = [
+        window(1, "mGBA - demo", 480, 320),
+        window(2, "mGBA - title_screen", 480, 320),
+    ];
+    let demo = WindowQuery::default().with_rom_hints(&["demo", ""]);
+    let title_screen = WindowQuery::default().with_rom_hints(&["Title_Screen"]);
+    assert_eq!(demo.rom_hints, ["demo"]);
+
+    for (query, expected) in [(demo, 1), (title_screen, 2)] {
+        let strategy = Strategy::TitlePattern;
+        let (hints, title_pattern) = (&query.rom_hints, &query.title_pattern);
+        let candidates = candidate_windows(&windows, strategy, &[], hints, title_pattern);
+        let best = best_window(&candidates, strategy, hints, title_pattern);
+        assert_eq!(best.map(|window| window.id), Some(expected));
+    }
+}
+
+#[test]
+fn title_names_the_rom_only_as_a_whole_name() {
+    assert!(title_names_rom("mGBA - demo.gba - 0.10.2", "demo"));
+    assert!(title_names_rom("demo - mGBA", "Demo"));
+    assert!(title_names_rom("demo", "demo"));
+    assert!(!title_names_rom("mGBA - demo2.gba - 0.10.2", "demo"));
+    assert!(!title_names_rom("mGBA - mydemo.gba", "demo"));
+    assert!(title_names_rom("mGBA - demo2 (60 fps) - demo.gba", "demo"));
+    assert!(!title_names_rom("mGBA - Load ROM", ""));
+}
@@ -0,0 +1,109 @@
+//! Waits and timeouts written for people, like `15s`, `500ms`, `2m` or `1m30s`
+//!
+//! A value is one or more numbers, each followed by a unit: `h`, `m`, `s` or `ms`.
+//! Numbers can have a fraction (`1.5s`), and a bare number is seconds, so `15` and
+//! `15s` are the same wait.
+
+use std::fmt;
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// A wait in the config file: seconds as a number, or text like `"15s"`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum WaitTime {
+    Seconds(f64),
+    Text(String),
+}
+
+impl WaitTime {
+    /// The length of the wait, or why it can't be read
+    pub fn duration(&self) -> Result<Duration> {
+        match self {
+            WaitTime::Seconds(seconds) => seconds_duration(*seconds, &seconds.to_string()),
+            WaitTime::Text(text) => parse(text),
+        }
+    }
+}
+
+impl fmt::Display for WaitTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WaitTime::Seconds(seconds) => write!(f, "{}", seconds),
+            WaitTime::Text(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+/// Parses a wait like `15s`, `500ms` or `1m30s`; a bare number is seconds
+pub fn parse(text: &str) -> Result<Duration> {
+    let invalid = || {
+        anyhow::anyhow!(
+            "Invalid wait '{}' (expected a number of seconds, or numbers with units like 15s, 500ms or 1m30s)",
+            text
+        )
+    };
+    let trimmed = text.trim();
+    if let Ok(seconds) = trimmed.parse::<f64>() {
+        return seconds_duration(seconds, text);
+    }
+
+    let mut total = 0.0;
+    let mut rest = trimmed;
+    while !rest.is_empty() {
+        let number_len = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .ok_or_else(invalid)?;
+        let number: f64 = rest[..number_len].parse().map_err(|_| invalid())?;
+        rest = &rest[number_len..];
+        let unit_len = rest
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rest.len());
+        let seconds_per_unit = match rest[..unit_len].to_lowercase().as_str() {
+            "h" => 3600.0,
+            "m" => 60.0,
+            "s" => 1.0,
+            "ms" => 0.001,
+            _ => return Err(invalid()),
+        };
+        total += number * seconds_per_unit;
+        rest = rest[unit_len..].trim_start();
+    }
+    seconds_duration(total, text)
+}
+
+/// A duration as it would be written, like `1m30s`, `500ms` or `2.5s`
+pub fn format(duration: Duration) -> String {
+    let millis = duration.as_millis();
+    if !millis.is_multiple_of(1000) {
+        return if millis < 1000 {
+            format!("{}ms", millis)
+        } else {
+            format!("{}s", duration.as_secs_f64())
+        };
+    }
+    let seconds = duration.as_secs();
+    let parts = [
+        (seconds / 3600, "h"),
+        (seconds % 3600 / 60, "m"),
+        (seconds % 60, "s"),
+    ];
+    let text: String = parts
+        .iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, unit)| format!("{}{}", count, unit))
+        .collect();
+    if text.is_empty() {
+        "0s".to_string()
+    } else {
+        text
+    }
+}
+
+/// `seconds` as a duration, rejecting negative and non-finite values
+fn seconds_duration(seconds: f64, text: &str) -> Result<Duration> {
+    Duration::try_from_secs_f64(seconds)
+        .map_err(|_| anyhow::anyhow!("Invalid wait '{}' (it must be 0 or more)", text.trim()))
+}
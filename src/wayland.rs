@@ -0,0 +1,45 @@
+//! Wayland sessions
+//!
+//! Window lookup (xcap, x11rb) and key injection (enigo) speak X11, which under Wayland
+//! only reaches XWayland clients. mGBA is therefore asked to open its window through
+//! XWayland, and a session without XWayland is reported up front instead of leaving the
+//! tool to wait for a window it can never see.
+
+use crate::capture::CaptureBackend;
+use anyhow::Result;
+
+/// Whether the desktop session is Wayland
+pub fn is_wayland_session() -> bool {
+    std::env::var_os("WAYLAND_DISPLAY").is_some_and(|display| !display.is_empty())
+        || std::env::var("XDG_SESSION_TYPE")
+            .is_ok_and(|session_type| session_type.eq_ignore_ascii_case("wayland"))
+}
+
+/// Environment that makes mGBA-Qt and mGBA-SDL open an X11 window through XWayland.
+/// Variables the user has set already are left alone.
+pub fn xwayland_env() -> Vec<(&'static str, &'static str)> {
+    [("QT_QPA_PLATFORM", "xcb"), ("SDL_VIDEODRIVER", "x11")]
+        .into_iter()
+        .filter(|(name, _)| std::env::var_os(name).is_none())
+        .collect()
+}
+
+/// Explains how a Wayland session is handled, failing when there is no XWayland to run
+/// mGBA under
+pub fn check_session(backend: CaptureBackend) -> Result<()> {
+    if !is_wayland_session() {
+        return Ok(());
+    }
+
+    if std::env::var_os("DISPLAY").is_none() {
+        return Err(anyhow::anyhow!(
+            "Wayland session without XWayland (DISPLAY is not set): the mGBA window can't be found or sent keys. Enable XWayland in your compositor or log in to an X11 session. Sending keys to native Wayland windows needs a uinput-based tool such as ydotool."
+        ));
+    }
+
+    println!("Wayland session detected: starting mGBA under XWayland so its window can be found, captured and sent keys");
+    if backend != CaptureBackend::Portal {
+        println!("If frames come out black, capture through the desktop portal with --capture-backend portal (install with --features portal)");
+    }
+    Ok(())
+}
@@ -0,0 +1,1105 @@
+//! Finding the mGBA window
+//!
+//! Windows are matched by process: once an emulator is launched, its process tree is
+//! tracked and only windows owned by one of those processes count as the mGBA window,
+//! so an unrelated mGBA session left open on the desktop is never captured or sent
+//! input. When no window can be tied to the launched processes, windows are recognised
+//! as mGBA's by their window class (`WM_CLASS` on X11, the application name elsewhere)
+//! or the name of the owning executable, and finally by title. `--match-by`, or
+//! `match_by` in a binary's `emulator` block, picks a single strategy instead. Titles
+//! are matched against a case-insensitive regex, `mgba` unless `--window-title-pattern`
+//! says otherwise; mGBA-SDL, which titles its window with just the ROM name, is matched
+//! by the name of the ROM being captured instead.
+
+use anyhow::Result;
+use clap::ValueEnum;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::process::Command;
+use std::sync::{Arc, Mutex, OnceLock};
+use sysinfo::{Pid, Process, System};
+use xcap::Window;
+
+/// How the mGBA window is told apart from other windows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WindowMatch {
+    /// The launched emulator process, then window class or process name, then title
+    Auto,
+    /// Windows owned by the launched emulator process, falling back to the title
+    Pid,
+    /// Windows whose class (`WM_CLASS` on X11, application name elsewhere) names mGBA
+    Class,
+    /// Windows owned by a process whose executable name contains "mgba"
+    Process,
+    /// The window of the newest process whose title matches the title pattern
+    Title,
+}
+
+impl WindowMatch {
+    /// Whether windows owned by the launched process tree are preferred
+    pub fn tracks_process(self) -> bool {
+        matches!(self, Self::Auto | Self::Pid)
+    }
+}
+
+/// Emulator frontend whose window is looked for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Emulator {
+    /// Either frontend: the title pattern first, then the ROM name
+    Auto,
+    /// mGBA-Qt, whose window title contains "mGBA"
+    MgbaQt,
+    /// mGBA-SDL, whose window title is just the ROM name
+    MgbaSdl,
+}
+
+/// What tells the mGBA window of one capture apart: the emulator frontend, how windows
+/// are matched, the title pattern and the names of the ROM, along with the processes
+/// launched for the emulator. Every function that looks for the window takes one, so
+/// binaries matched differently can be captured side by side.
+///
+/// Clones share the launched processes, the window chosen last and the process
+/// cache, so the launch, input and frame capture of one binary agree on its window.
+#[derive(Debug, Clone)]
+pub struct WindowQuery {
+    pub emulator: Emulator,
+    pub match_mode: WindowMatch,
+    pub title_pattern: Regex,
+    /// Lowercased names of the ROM being captured, expected to show up in its window title
+    pub rom_hints: Vec<String>,
+    /// Whether the window is on the Xvfb display of `--headless`, which has no window
+    /// manager to focus windows or report the active one
+    pub virtual_display: bool,
+    /// Processes started for the emulator launch (cargo and its descendants)
+    tracked: Arc<Mutex<Vec<u32>>>,
+    /// Id of the window most recently chosen, so a change of choice is logged once
+    chosen: Arc<Mutex<Option<u32>>>,
+    /// Owners of windows by process id, refreshed when a window's process isn't listed
+    processes: Arc<Mutex<BTreeMap<u32, OwnerProcess>>>,
+}
+
+/// What window matching knows about the process that owns a window
+#[derive(Debug, Clone, Default)]
+struct OwnerProcess {
+    /// Executable name
+    name: String,
+    /// Start time in seconds since the Unix epoch, `None` for a process that is gone
+    started: Option<u64>,
+}
+
+impl Default for WindowQuery {
+    fn default() -> Self {
+        Self::new(
+            Emulator::Auto,
+            WindowMatch::Auto,
+            compile_title_pattern(DEFAULT_TITLE_PATTERN).unwrap(),
+        )
+    }
+}
+
+impl WindowQuery {
+    pub fn new(emulator: Emulator, match_mode: WindowMatch, title_pattern: Regex) -> Self {
+        Self {
+            emulator,
+            match_mode,
+            title_pattern,
+            rom_hints: Vec::new(),
+            virtual_display: false,
+            tracked: Arc::default(),
+            chosen: Arc::default(),
+            processes: Arc::default(),
+        }
+    }
+
+    /// The query with the names (binary name, ROM file name) that identify the ROM's
+    /// window when several windows match
+    pub fn with_rom_hints(mut self, hints: &[&str]) -> Self {
+        self.rom_hints = hints
+            .iter()
+            .filter(|hint| !hint.is_empty())
+            .map(|hint| hint.to_lowercase())
+            .collect();
+        self
+    }
+
+    /// The query for a window on the virtual display of `--headless` when
+    /// `virtual_display` is set
+    pub fn on_virtual_display(mut self, virtual_display: bool) -> Self {
+        self.virtual_display = virtual_display;
+        self
+    }
+
+    /// The query sharing the processes `other` tracks, for a ROM loaded into the
+    /// emulator that was launched for `other`
+    pub fn sharing_processes_of(mut self, other: &WindowQuery) -> Self {
+        self.tracked = other.tracked.clone();
+        self
+    }
+
+    /// Strategies to try, most reliable first
+    fn strategies(&self) -> Vec<Strategy> {
+        strategies_for(self.match_mode, self.emulator, std::env::consts::OS)
+    }
+
+    /// The order in which the mGBA window is looked for, for verbose output
+    pub fn detection_order(&self) -> String {
+        self.strategies()
+            .iter()
+            .map(|strategy| strategy.describe())
+            .collect::<Vec<_>>()
+            .join(", then ")
+    }
+
+    /// Id of the window last chosen as the mGBA window, which capture backends reuse
+    /// until it goes away
+    #[cfg_attr(
+        not(any(
+            all(feature = "x11-shm", target_os = "linux"),
+            all(feature = "coregraphics", target_os = "macos")
+        )),
+        allow(dead_code)
+    )]
+    pub fn chosen_window(&self) -> Option<u32> {
+        *self.chosen.lock().unwrap()
+    }
+
+    fn tracked_processes(&self) -> Vec<u32> {
+        self.tracked.lock().unwrap().clone()
+    }
+}
+
+/// A way of recognising the mGBA window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// Owned by the launched emulator process tree
+    LaunchedProcess,
+    /// Window class names mGBA
+    Class,
+    /// Owning executable's name contains "mgba"
+    ProcessName,
+    /// Title matches the title pattern
+    TitlePattern,
+    /// Title contains the name of the ROM being captured
+    RomName,
+}
+
+impl Strategy {
+    fn describe(self) -> &'static str {
+        match self {
+            Self::LaunchedProcess => "launched process",
+            Self::Class => "window class",
+            Self::ProcessName => "process name",
+            Self::TitlePattern => "title pattern",
+            Self::RomName => "ROM name in title",
+        }
+    }
+}
+
+/// Strategies to try for a match mode and emulator on the operating system `os` (as in
+/// `std::env::consts::OS`), most reliable first
+pub fn strategies_for(mode: WindowMatch, emulator: Emulator, os: &str) -> Vec<Strategy> {
+    let by_title: &[Strategy] = match emulator {
+        Emulator::MgbaQt => &[Strategy::TitlePattern],
+        Emulator::MgbaSdl => &[Strategy::RomName, Strategy::TitlePattern],
+        Emulator::Auto => &[Strategy::TitlePattern, Strategy::RomName],
+    };
+    // Executable names (mGBA.exe) are more telling than application names on Windows
+    let by_identity: &[Strategy] = match mode {
+        WindowMatch::Auto if os == "windows" => &[
+            Strategy::LaunchedProcess,
+            Strategy::ProcessName,
+            Strategy::Class,
+        ],
+        WindowMatch::Auto => &[
+            Strategy::LaunchedProcess,
+            Strategy::Class,
+            Strategy::ProcessName,
+        ],
+        WindowMatch::Pid => &[Strategy::LaunchedProcess],
+        WindowMatch::Class => return vec![Strategy::Class],
+        WindowMatch::Process => return vec![Strategy::ProcessName],
+        WindowMatch::Title => &[],
+    };
+    by_identity.iter().chain(by_title).copied().collect()
+}
+
+/// Identifies mGBA in window classes and executable names, compared case-insensitively
+const MGBA_IDENTITY: &str = "mgba";
+
+/// Title pattern used unless one is configured
+pub const DEFAULT_TITLE_PATTERN: &str = "mgba";
+
+/// Compiles a window title pattern (case-insensitive unless the pattern turns that off
+/// with `(?-i)`)
+pub fn compile_title_pattern(pattern: &str) -> Result<Regex> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(true)
+        .build()
+        .map_err(|e| anyhow::anyhow!("Invalid window title pattern '{}': {}", pattern, e))
+}
+
+/// Whether a window title mentions the current ROM
+fn matches_rom_hint(title: &str, hints: &[String]) -> bool {
+    let title = title.to_lowercase();
+    hints.iter().any(|hint| title.contains(hint.as_str()))
+}
+
+/// Whether a window title shows the ROM `rom_stem` as a whole name: bounded by the
+/// title's start or end or by characters other than letters and digits (its `.gba`
+/// extension, mGBA's ` - ` separators), so `demo` is not found in `demo2 - mGBA`
+pub fn title_names_rom(title: &str, rom_stem: &str) -> bool {
+    if rom_stem.is_empty() {
+        return false;
+    }
+    let title = title.to_lowercase();
+    let rom_stem = rom_stem.to_lowercase();
+    title.match_indices(&rom_stem).any(|(start, _)| {
+        let before = title[..start].chars().next_back();
+        let after = title[start + rom_stem.len()..].chars().next();
+        !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+    })
+}
+
+/// Tracks the process tree rooted at `root_pid` (the spawned `cargo run`) for the
+/// window matching of `query`. Call again while waiting for the window, as the emulator
+/// is started by cargo after a delay. Returns the number of processes in the tree.
+///
+/// Processes found by earlier calls for the same root stay tracked after they exit, so an
+/// emulator started through a shim (a `.bat` runner on Windows, a shell script
+/// elsewhere) that has since exited is still recognised by its parent id.
+pub fn track_process_tree(query: &WindowQuery, root_pid: u32) -> usize {
+    let mut system = System::new();
+    system.refresh_processes();
+
+    let mut tree = query.tracked_processes();
+    if tree.first() != Some(&root_pid) {
+        tree = vec![root_pid];
+    }
+    add_descendants(&process_parents(&system), &mut tree);
+
+    let count = tree.len();
+    *query.tracked.lock().unwrap() = tree;
+    count
+}
+
+/// The running processes' ids, each with its parent's
+fn process_parents(system: &System) -> Vec<(u32, Option<u32>)> {
+    system
+        .processes()
+        .iter()
+        .map(|(pid, process)| (pid.as_u32(), process.parent().map(Pid::as_u32)))
+        .collect()
+}
+
+/// Adds the descendants of the processes in `tree` to it, from the running processes'
+/// ids and their parents'
+pub fn add_descendants(processes: &[(u32, Option<u32>)], tree: &mut Vec<u32>) {
+    // Processes may be listed before their parents, so repeat until nothing is added
+    loop {
+        let before = tree.len();
+        for &(pid, parent) in processes {
+            let in_tree = parent.is_some_and(|parent| tree.contains(&parent));
+            if in_tree && !tree.contains(&pid) {
+                tree.push(pid);
+            }
+        }
+        if tree.len() == before {
+            break;
+        }
+    }
+}
+
+/// Whether `cargo_pid` is still compiling: a `rustc` or build script is running
+/// somewhere below it. Once cargo hands over to the runner, only the emulator is left.
+pub fn cargo_is_building(cargo_pid: u32) -> bool {
+    let mut system = System::new();
+    system.refresh_processes();
+
+    let mut tree = vec![cargo_pid];
+    add_descendants(&process_parents(&system), &mut tree);
+
+    tree.iter()
+        .filter_map(|pid| system.process(Pid::from_u32(*pid)))
+        .any(|process| {
+            let name = process.name().to_lowercase();
+            name.starts_with("rustc") || name.starts_with("build-script")
+        })
+}
+
+/// mGBA sessions that were running before this run started
+pub struct StaleEmulators {
+    /// Ids and executable names of mGBA processes outside the tracked process tree
+    pub processes: Vec<(u32, String)>,
+    /// Titles of windows that look like mGBA's
+    pub windows: Vec<String>,
+}
+
+/// Whether a process's executable is recognisably mGBA
+fn is_mgba_process(process: &Process) -> bool {
+    let exe_name = process
+        .exe()
+        .and_then(|exe| exe.file_name())
+        .map(|name| name.to_string_lossy().to_lowercase());
+    process.name().to_lowercase().contains(MGBA_IDENTITY)
+        || exe_name.is_some_and(|name| name.contains(MGBA_IDENTITY))
+}
+
+/// Finds running mGBA processes and windows that weren't started for `query`, which
+/// window matching could mistake for the emulator it launches
+pub fn find_stale_emulators(query: &WindowQuery) -> Result<StaleEmulators> {
+    let tracked = query.tracked_processes();
+    let mut system = System::new();
+    system.refresh_processes();
+    let processes = system
+        .processes()
+        .iter()
+        .filter(|(pid, process)| !tracked.contains(&pid.as_u32()) && is_mgba_process(process))
+        .map(|(pid, process)| (pid.as_u32(), process.name().to_string()))
+        .collect();
+
+    let windows = Window::all()?;
+    let mut infos = window_infos(&windows);
+    let mut stale_windows = Vec::new();
+    for strategy in [
+        Strategy::Class,
+        Strategy::ProcessName,
+        Strategy::TitlePattern,
+    ] {
+        look_up_identities(&mut infos, &windows, strategy, query);
+        let candidates = candidate_windows(&infos, strategy, &[], &[], &query.title_pattern);
+        if !candidates.is_empty() {
+            stale_windows = candidates
+                .into_iter()
+                .map(|window| window.title.clone())
+                .collect();
+            break;
+        }
+    }
+    let windows = stale_windows;
+
+    Ok(StaleEmulators { processes, windows })
+}
+
+/// Terminates a stale mGBA process, checking again that it is mGBA so a reused process
+/// id is never killed. Returns whether the process was signalled.
+pub fn kill_stale_emulator(pid: u32) -> bool {
+    let pid = Pid::from_u32(pid);
+    let mut system = System::new();
+    system.refresh_process(pid);
+    system
+        .process(pid)
+        .is_some_and(|process| is_mgba_process(process) && process.kill())
+}
+
+/// Id of the emulator process in the process tree tracked for `query`, if it has started
+pub fn tracked_emulator_pid(query: &WindowQuery) -> Option<u32> {
+    let tracked = query.tracked_processes();
+    let mut system = System::new();
+    system.refresh_processes();
+    tracked.into_iter().find(|pid| {
+        system
+            .process(Pid::from_u32(*pid))
+            .is_some_and(is_mgba_process)
+    })
+}
+
+/// What window matching goes by, gathered once per lookup. The owning process, class
+/// and executable name are looked up only for the strategies that need them.
+#[derive(Debug, Clone, Default)]
+pub struct WindowInfo {
+    pub id: u32,
+    pub title: String,
+    pub width: u32,
+    pub height: u32,
+    pub minimized: bool,
+    /// Id of the process that owns the window, where the platform can tell
+    pub pid: Option<u32>,
+    /// `WM_CLASS` on X11, the application name elsewhere
+    pub class: Option<String>,
+    /// Executable name of the process that owns the window
+    pub process_name: Option<String>,
+    /// When the process that owns the window started, in seconds since the Unix epoch
+    pub started: Option<u64>,
+}
+
+/// The windows' titles, sizes and ids, without anything that takes a lookup
+fn window_infos(windows: &[Window]) -> Vec<WindowInfo> {
+    windows
+        .iter()
+        .map(|window| WindowInfo {
+            id: window.id(),
+            title: window.title().to_string(),
+            width: window.width(),
+            height: window.height(),
+            minimized: window.is_minimized(),
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// Looks up what `strategy` tells windows apart by
+fn look_up_identities(
+    infos: &mut [WindowInfo],
+    windows: &[Window],
+    strategy: Strategy,
+    query: &WindowQuery,
+) {
+    match strategy {
+        Strategy::LaunchedProcess => {
+            let pids = window_pids(windows);
+            for info in infos.iter_mut() {
+                info.pid = pids.get(&info.id).copied();
+            }
+        }
+        Strategy::Class => {
+            let classes = window_classes(windows);
+            for info in infos.iter_mut() {
+                info.class = classes.get(&info.id).cloned();
+            }
+        }
+        Strategy::ProcessName => {
+            let owners = window_owners(windows, query);
+            for info in infos.iter_mut() {
+                info.process_name = owners.get(&info.id).map(|owner| owner.name.clone());
+            }
+        }
+        Strategy::TitlePattern | Strategy::RomName => {}
+    }
+}
+
+/// Finds the mGBA window `query` describes, trying each detection strategy in turn.
+/// Among the windows a strategy finds, ones naming the ROM are preferred, then ones
+/// whose title matches the title pattern.
+pub fn find_mgba_window(query: &WindowQuery) -> Result<Window> {
+    let windows = Window::all()?;
+    let mut infos = window_infos(&windows);
+    let hints = &query.rom_hints;
+    let tracked = query.tracked_processes();
+    let title_pattern = &query.title_pattern;
+
+    for strategy in query.strategies() {
+        look_up_identities(&mut infos, &windows, strategy, query);
+        // Several matches are told apart by how recently their process started
+        if strategy != Strategy::LaunchedProcess
+            && candidate_windows(&infos, strategy, &tracked, hints, title_pattern).len() > 1
+        {
+            look_up_start_times(&mut infos, &windows, query);
+        }
+        let candidates = candidate_windows(&infos, strategy, &tracked, hints, title_pattern);
+        let Some(chosen_window) = best_window(&candidates, strategy, hints, title_pattern) else {
+            continue;
+        };
+        let Some(window) = windows
+            .iter()
+            .find(|window| window.id() == chosen_window.id)
+        else {
+            continue;
+        };
+
+        let mut chosen = query.chosen.lock().unwrap();
+        if *chosen != Some(window.id()) {
+            if candidates.len() > 1 {
+                debug!(
+                    "{} windows match by {}: {}",
+                    candidates.len(),
+                    strategy.describe(),
+                    candidates
+                        .iter()
+                        .map(|candidate| format!("'{}'", candidate.title))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            }
+            println!(
+                "Using window '{}' (matched by {})",
+                window.title(),
+                strategy.describe()
+            );
+            *chosen = Some(window.id());
+        }
+        return Ok(window.clone());
+    }
+
+    Err(anyhow::anyhow!("mGBA window not found"))
+}
+
+/// How likely a window that `strategy` found is to be the main window of the ROM being
+/// captured, compared in order: it names the ROM, its title matches the title pattern,
+/// then its size for the launched process's windows, where the largest is the main one,
+/// and otherwise how recently its process started. A title match tells the main window
+/// apart from mGBA's own dialogs, which share its class and process.
+///
+/// Ties go to the highest window id on X11 and macOS, which hand ids out in increasing
+/// order, so the most recently created window wins. Window handles on Windows say
+/// nothing about creation time, so they don't break ties there.
+fn window_rank(
+    window: &WindowInfo,
+    strategy: Strategy,
+    hints: &[String],
+    title_pattern: &Regex,
+) -> (bool, bool, u64, u32) {
+    (
+        matches_rom_hint(&window.title, hints),
+        title_pattern.is_match(&window.title),
+        if strategy == Strategy::LaunchedProcess {
+            u64::from(window.width) * u64::from(window.height)
+        } else {
+            window.started.unwrap_or(0)
+        },
+        if cfg!(target_os = "windows") {
+            0
+        } else {
+            window.id
+        },
+    )
+}
+
+/// The candidate with the highest [`window_rank`]
+pub fn best_window<'a>(
+    candidates: &[&'a WindowInfo],
+    strategy: Strategy,
+    hints: &[String],
+    title_pattern: &Regex,
+) -> Option<&'a WindowInfo> {
+    candidates
+        .iter()
+        .copied()
+        .max_by_key(|window| window_rank(window, strategy, hints, title_pattern))
+}
+
+/// Windows that `strategy` recognises as mGBA's, given the processes launched for the
+/// emulator and the names of the ROM being captured
+pub fn candidate_windows<'a>(
+    windows: &'a [WindowInfo],
+    strategy: Strategy,
+    tracked: &[u32],
+    hints: &[String],
+    title_pattern: &Regex,
+) -> Vec<&'a WindowInfo> {
+    let names_mgba = |identity: &Option<String>| {
+        identity
+            .as_ref()
+            .is_some_and(|identity| identity.to_lowercase().contains(MGBA_IDENTITY))
+    };
+    windows
+        .iter()
+        .filter(|window| match strategy {
+            Strategy::LaunchedProcess => {
+                window.pid.is_some_and(|pid| tracked.contains(&pid)) && !window.minimized
+            }
+            Strategy::Class => names_mgba(&window.class),
+            Strategy::ProcessName => names_mgba(&window.process_name),
+            Strategy::TitlePattern => title_pattern.is_match(&window.title),
+            Strategy::RomName => matches_rom_hint(&window.title, hints),
+        })
+        .collect()
+}
+
+/// Looks up when the processes that own the windows started
+fn look_up_start_times(infos: &mut [WindowInfo], windows: &[Window], query: &WindowQuery) {
+    let owners = window_owners(windows, query);
+    for info in infos.iter_mut() {
+        info.started = owners.get(&info.id).and_then(|owner| owner.started);
+    }
+}
+
+/// Maps window ids to the processes that own them
+fn window_owners(windows: &[Window], query: &WindowQuery) -> HashMap<u32, OwnerProcess> {
+    let pids = window_pids(windows);
+    let mut processes = query.processes.lock().unwrap();
+    if pids.values().any(|pid| !processes.contains_key(pid)) {
+        let mut system = System::new();
+        system.refresh_processes();
+        *processes = system
+            .processes()
+            .iter()
+            .map(|(pid, process)| {
+                let owner = OwnerProcess {
+                    name: process.name().to_string(),
+                    started: Some(process.start_time()),
+                };
+                (pid.as_u32(), owner)
+            })
+            .collect();
+        // Remember processes that are gone too, so they don't trigger a refresh each time
+        for pid in pids.values() {
+            processes.entry(*pid).or_default();
+        }
+    }
+
+    pids.into_iter()
+        .filter_map(|(id, pid)| Some((id, processes.get(&pid)?.clone())))
+        .collect()
+}
+
+/// Maps window ids to their window class: `WM_CLASS` (instance and class name) on X11,
+/// the application name reported by xcap elsewhere
+#[cfg(target_os = "linux")]
+fn window_classes(windows: &[Window]) -> HashMap<u32, String> {
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xproto::{AtomEnum, ConnectionExt as _};
+
+    let Some((conn, _)) = x11_connection() else {
+        return HashMap::new();
+    };
+
+    let mut classes = HashMap::new();
+    for window in windows {
+        let reply = conn
+            .get_property(
+                false,
+                window.id(),
+                AtomEnum::WM_CLASS,
+                AtomEnum::STRING,
+                0,
+                64,
+            )
+            .ok()
+            .and_then(|cookie| cookie.reply().ok());
+        if let Some(reply) = reply.filter(|reply| !reply.value.is_empty()) {
+            // Instance and class names are stored as consecutive NUL-terminated strings
+            let class = String::from_utf8_lossy(&reply.value)
+                .split('\0')
+                .filter(|part| !part.is_empty())
+                .collect::<Vec<_>>()
+                .join(" ");
+            classes.insert(window.id(), class);
+        }
+    }
+    let _ = conn.flush();
+    classes
+}
+
+/// Maps window ids to their window class: `WM_CLASS` (instance and class name) on X11,
+/// the application name reported by xcap elsewhere
+#[cfg(not(target_os = "linux"))]
+fn window_classes(windows: &[Window]) -> HashMap<u32, String> {
+    windows
+        .iter()
+        .map(|window| (window.id(), window.app_name().to_string()))
+        .collect()
+}
+
+/// Whether the mGBA window can currently be tied to the emulator processes tracked for
+/// `query`
+pub fn tracked_window_found(query: &WindowQuery) -> bool {
+    let tracked = query.tracked_processes();
+    Window::all().is_ok_and(|windows| {
+        let strategy = Strategy::LaunchedProcess;
+        let mut infos = window_infos(&windows);
+        look_up_identities(&mut infos, &windows, strategy, query);
+        !candidate_windows(&infos, strategy, &tracked, &[], &query.title_pattern).is_empty()
+    })
+}
+
+/// Makes window positions and sizes reported to this process physical pixels, matching
+/// the captured images. Without this, Windows scales them down for DPI-unaware processes
+/// on high-DPI displays, so captures are larger than the window's reported size and
+/// crops land in the wrong place.
+#[cfg(target_os = "windows")]
+pub fn enable_dpi_awareness() {
+    use windows::Win32::UI::HiDpi::{
+        SetProcessDpiAwarenessContext, DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2,
+    };
+
+    // SAFETY: called once at startup, before any window is queried
+    if let Err(e) =
+        unsafe { SetProcessDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2) }
+    {
+        debug!("Could not enable per-monitor DPI awareness: {}", e);
+    }
+}
+
+/// Window coordinates are already physical pixels outside Windows
+#[cfg(not(target_os = "windows"))]
+pub fn enable_dpi_awareness() {}
+
+/// The display scaling between the size a window reports and the frames captured from
+/// it, like 1.5 when Windows reports a DPI-virtualized window at two thirds of its
+/// pixels. `None` when the sizes are the same, or don't differ by a single factor.
+pub fn display_scale(reported: (u32, u32), captured: (u32, u32)) -> Option<f64> {
+    if reported == captured || reported.0 == 0 || reported.1 == 0 {
+        return None;
+    }
+    let scale = f64::from(captured.0) / f64::from(reported.0);
+    // Scaled sizes are rounded to whole pixels
+    let height = f64::from(reported.1) * scale;
+    ((height - f64::from(captured.1)).abs() <= 1.0).then_some(scale)
+}
+
+/// Maps window ids to the ids of the processes that own them, where the platform can
+/// tell
+#[cfg(target_os = "windows")]
+fn window_pids(windows: &[Window]) -> HashMap<u32, u32> {
+    windows
+        .iter()
+        .map(|window| (window.id(), window.process_id()))
+        .collect()
+}
+
+/// Maps window ids to the ids of the processes that own them, where the platform can
+/// tell
+#[cfg(target_os = "linux")]
+fn window_pids(windows: &[Window]) -> HashMap<u32, u32> {
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xproto::{AtomEnum, ConnectionExt as _};
+
+    let Some((conn, pid_atom)) = x11_connection() else {
+        return HashMap::new();
+    };
+
+    let mut pids = HashMap::new();
+    for window in windows {
+        let reply = conn
+            .get_property(false, window.id(), *pid_atom, AtomEnum::CARDINAL, 0, 1)
+            .ok()
+            .and_then(|cookie| cookie.reply().ok());
+        if let Some(pid) = reply.and_then(|reply| reply.value32()?.next()) {
+            pids.insert(window.id(), pid);
+        }
+    }
+    let _ = conn.flush();
+    pids
+}
+
+/// Brings the mGBA window to the front and gives it keyboard focus, since synthetic key
+/// presses go to whichever window is focused
+pub fn focus_mgba_window(query: &WindowQuery) -> Result<()> {
+    focus_window(&find_mgba_window(query)?, query.virtual_display)
+}
+
+/// Brings the window `id` (a popup of the emulator `query` finds, from
+/// [`find_popup_windows`]) to the front and gives it keyboard focus
+pub fn focus_window_by_id(query: &WindowQuery, id: u32) -> Result<()> {
+    let window = Window::all()?
+        .into_iter()
+        .find(|window| window.id() == id)
+        .ok_or_else(|| anyhow::anyhow!("the window is gone"))?;
+    focus_window(&window, query.virtual_display)
+}
+
+/// Brings a window to the front and gives it keyboard focus
+#[cfg(target_os = "linux")]
+fn focus_window(window: &Window, virtual_display: bool) -> Result<()> {
+    use x11rb::connection::Connection as _;
+    use x11rb::protocol::xproto::{ClientMessageEvent, ConnectionExt as _, EventMask, InputFocus};
+
+    let (conn, _) =
+        x11_connection().ok_or_else(|| anyhow::anyhow!("Failed to connect to the X server"))?;
+
+    if virtual_display {
+        // A virtual display has no window manager, so focus is set directly
+        conn.set_input_focus(InputFocus::PARENT, window.id(), x11rb::CURRENT_TIME)?;
+    } else {
+        // Ask the window manager to activate the window. Source 2 marks the request as
+        // coming from a pager, which focus-stealing prevention lets through.
+        let active_window = conn
+            .intern_atom(false, b"_NET_ACTIVE_WINDOW")?
+            .reply()?
+            .atom;
+        let root = conn.query_tree(window.id())?.reply()?.root;
+        let event = ClientMessageEvent::new(
+            32,
+            window.id(),
+            active_window,
+            [2, x11rb::CURRENT_TIME, 0, 0, 0],
+        );
+        conn.send_event(
+            false,
+            root,
+            EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY,
+            event,
+        )?;
+    }
+    conn.flush()?;
+    Ok(())
+}
+
+/// Brings a window to the front and gives it keyboard focus
+#[cfg(target_os = "windows")]
+fn focus_window(window: &Window, _virtual_display: bool) -> Result<()> {
+    use windows::Win32::Foundation::HWND;
+    use windows::Win32::UI::Input::KeyboardAndMouse::{
+        SendInput, INPUT, INPUT_0, INPUT_KEYBOARD, KEYBDINPUT, KEYBD_EVENT_FLAGS, KEYEVENTF_KEYUP,
+        VK_MENU,
+    };
+    use windows::Win32::UI::WindowsAndMessaging::{
+        IsIconic, SetForegroundWindow, ShowWindow, SW_RESTORE,
+    };
+
+    let hwnd = HWND(window.id() as isize);
+    let alt = |flags: KEYBD_EVENT_FLAGS| INPUT {
+        r#type: INPUT_KEYBOARD,
+        Anonymous: INPUT_0 {
+            ki: KEYBDINPUT {
+                wVk: VK_MENU,
+                dwFlags: flags,
+                ..Default::default()
+            },
+        },
+    };
+
+    // SAFETY: the handle comes from a window that exists; the calls only change which
+    // window is in the foreground
+    unsafe {
+        if IsIconic(hwnd).as_bool() {
+            let _ = ShowWindow(hwnd, SW_RESTORE);
+        }
+        if SetForegroundWindow(hwnd).as_bool() {
+            return Ok(());
+        }
+        // Only the process that received the last input may change the foreground
+        // window, and a tapped Alt key counts as that input
+        SendInput(
+            &[alt(KEYBD_EVENT_FLAGS(0)), alt(KEYEVENTF_KEYUP)],
+            std::mem::size_of::<INPUT>() as i32,
+        );
+        if SetForegroundWindow(hwnd).as_bool() {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!(
+                "Windows refused to bring '{}' to the foreground",
+                window.title()
+            ))
+        }
+    }
+}
+
+/// Brings a window's application to the front, which shows its frontmost window
+#[cfg(target_os = "macos")]
+fn focus_window(window: &Window, _virtual_display: bool) -> Result<()> {
+    let pid = window_pids(std::slice::from_ref(window))
+        .get(&window.id())
+        .copied()
+        .ok_or_else(|| anyhow::anyhow!("Could not tell which process owns '{}'", window.title()))?;
+
+    let script = format!(
+        "tell application \"System Events\" to set frontmost of (first process whose unix id is {}) to true",
+        pid
+    );
+    let output = Command::new("osascript")
+        .args(["-e", &script])
+        .output()
+        .map_err(|e| anyhow::anyhow!("Failed to run osascript: {}", e))?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "Failed to bring '{}' to the front: {}",
+            window.title(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+/// The window holding keyboard focus, asked of the X server itself, which also works
+/// without a window manager
+#[cfg(target_os = "linux")]
+fn input_focus() -> Result<u32> {
+    use x11rb::protocol::xproto::ConnectionExt as _;
+
+    let (conn, _) =
+        x11_connection().ok_or_else(|| anyhow::anyhow!("Failed to connect to the X server"))?;
+    Ok(conn.get_input_focus()?.reply()?.focus)
+}
+
+/// The X connection used for window properties and the `_NET_WM_PID` atom. One
+/// connection serves the whole run, as window lookups happen on every captured frame.
+#[cfg(target_os = "linux")]
+fn x11_connection() -> Option<&'static (x11rb::rust_connection::RustConnection, u32)> {
+    use x11rb::protocol::xproto::ConnectionExt as _;
+    use x11rb::rust_connection::RustConnection;
+
+    static CONNECTION: OnceLock<Option<(RustConnection, u32)>> = OnceLock::new();
+    CONNECTION
+        .get_or_init(|| {
+            let (conn, _) = x11rb::connect(None).ok()?;
+            let atom = conn
+                .intern_atom(true, b"_NET_WM_PID")
+                .ok()?
+                .reply()
+                .ok()?
+                .atom;
+            Some((conn, atom))
+        })
+        .as_ref()
+}
+
+/// Maps window ids to the ids of the processes that own them, where the platform can
+/// tell
+#[cfg(target_os = "macos")]
+fn window_pids(_windows: &[Window]) -> HashMap<u32, u32> {
+    use core_foundation::base::{CFType, TCFType};
+    use core_foundation::dictionary::{CFDictionary, CFDictionaryRef};
+    use core_foundation::number::CFNumber;
+    use core_foundation::string::CFString;
+    use core_graphics::window::{
+        copy_window_info, kCGNullWindowID, kCGWindowListOptionAll, kCGWindowNumber,
+        kCGWindowOwnerPID,
+    };
+
+    let Some(info) = copy_window_info(kCGWindowListOptionAll, kCGNullWindowID) else {
+        return HashMap::new();
+    };
+
+    // SAFETY: the keys are static CFStrings and every array item is a CFDictionary
+    let (number_key, pid_key) = unsafe {
+        (
+            CFString::wrap_under_get_rule(kCGWindowNumber),
+            CFString::wrap_under_get_rule(kCGWindowOwnerPID),
+        )
+    };
+    let read_number = |dict: &CFDictionary<CFString, CFType>, key: &CFString| {
+        dict.find(key)
+            .and_then(|value| value.downcast::<CFNumber>())
+            .and_then(|number| number.to_i64())
+    };
+
+    info.iter()
+        .filter_map(|item| {
+            let dict: CFDictionary<CFString, CFType> =
+                unsafe { CFDictionary::wrap_under_get_rule(*item as CFDictionaryRef) };
+            let id = read_number(&dict, &number_key)?;
+            let pid = read_number(&dict, &pid_key)?;
+            Some((id as u32, pid as u32))
+        })
+        .collect()
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
+fn window_pids(_windows: &[Window]) -> HashMap<u32, u32> {
+    HashMap::new()
+}
+
+/// Finds popups that would cover or steal focus from the mGBA window: secondary
+/// windows of the emulator that are smaller than its main window, and any of its windows
+/// whose title contains one of `title_patterns` (case-insensitive). Returns their ids and
+/// titles.
+///
+/// Keys are sent to dismiss these, so only windows owned by the emulator count: a
+/// process tracked for `query`, or one whose executable is recognisably mGBA. Windows
+/// whose owner can't be told are left alone.
+pub fn find_popup_windows(
+    query: &WindowQuery,
+    title_patterns: &[String],
+) -> Result<Vec<(u32, String)>> {
+    let windows: Vec<Window> = Window::all()?
+        .into_iter()
+        .filter(|window| !window.is_minimized() && window.width() > 1 && window.height() > 1)
+        .collect();
+
+    let pids = window_pids(&windows);
+    let tracked = query.tracked_processes();
+    let mut system = System::new();
+    system.refresh_processes();
+    let emulator_windows: Vec<&Window> = windows
+        .iter()
+        .filter(|window| {
+            pids.get(&window.id()).is_some_and(|pid| {
+                tracked.contains(pid)
+                    || system
+                        .process(Pid::from_u32(*pid))
+                        .is_some_and(is_mgba_process)
+            })
+        })
+        .collect();
+
+    let main_window = emulator_windows
+        .iter()
+        .max_by_key(|window| window.width() * window.height());
+    let (main_id, main_area) = main_window
+        .map(|window| (Some(window.id()), window.width() * window.height()))
+        .unwrap_or((None, 0));
+
+    let patterns: Vec<String> = title_patterns
+        .iter()
+        .map(|pattern| pattern.to_lowercase())
+        .collect();
+
+    Ok(emulator_windows
+        .iter()
+        .filter(|window| Some(window.id()) != main_id)
+        .filter(|window| {
+            let title = window.title().to_lowercase();
+            window.width() * window.height() < main_area
+                || patterns.iter().any(|pattern| title.contains(pattern))
+        })
+        .map(|window| (window.id(), window.title().to_string()))
+        .collect())
+}
+
+/// Reports whether the mGBA window `query` describes is focused, so keys aren't sent to
+/// other applications
+pub fn mgba_has_focus(query: &WindowQuery) -> Result<bool> {
+    // xdotool relies on the window manager's _NET_ACTIVE_WINDOW, which a virtual display
+    // doesn't have
+    #[cfg(target_os = "linux")]
+    if query.virtual_display {
+        return Ok(input_focus()? == find_mgba_window(query)?.id());
+    }
+
+    // macOS reports the application name rather than the window title
+    let focused = focused_window_name()?;
+    let sdl_title =
+        query.emulator != Emulator::MgbaQt && matches_rom_hint(focused.trim(), &query.rom_hints);
+    Ok(query.title_pattern.is_match(focused.trim())
+        || focused.to_lowercase().contains(MGBA_IDENTITY)
+        || sdl_title)
+}
+
+/// Reports whether the window `id`, titled `title`, a popup of the emulator `query`
+/// finds, is focused, so keys meant for it don't reach the window behind it. On macOS,
+/// where only the focused application is known, the popup counts as focused while mGBA
+/// is.
+pub fn window_has_focus(query: &WindowQuery, id: u32, title: &str) -> Result<bool> {
+    #[cfg(target_os = "linux")]
+    if query.virtual_display {
+        return Ok(input_focus()? == id);
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = (query, id);
+
+    let focused = focused_window_name()?;
+    if cfg!(target_os = "macos") {
+        Ok(focused.to_lowercase().contains(MGBA_IDENTITY))
+    } else {
+        Ok(focused.trim() == title)
+    }
+}
+
+/// Title of the focused window, or the name of the focused application on macOS, from
+/// the platform's own tools: `xdotool` on Linux, System Events on macOS and PowerShell
+/// on Windows
+fn focused_window_name() -> Result<String> {
+    let output = if cfg!(target_os = "macos") {
+        Command::new("osascript")
+            .args([
+                "-e",
+                "tell application \"System Events\" to get name of first application process whose frontmost is true",
+            ])
+            .output()
+    } else if cfg!(target_os = "windows") {
+        Command::new("powershell")
+            .args([
+                "-NoProfile",
+                "-Command",
+                "Add-Type -Name W -Namespace U -MemberDefinition '[DllImport(\"user32.dll\")] public static extern IntPtr GetForegroundWindow(); [DllImport(\"user32.dll\", CharSet = CharSet.Unicode)] public static extern int GetWindowText(IntPtr h, System.Text.StringBuilder s, int n);'; $s = New-Object System.Text.StringBuilder 512; [void][U.W]::GetWindowText([U.W]::GetForegroundWindow(), $s, 512); $s.ToString()",
+            ])
+            .output()
+    } else {
+        Command::new("xdotool")
+            .args(["getactivewindow", "getwindowname"])
+            .output()
+    }
+    .map_err(|e| anyhow::anyhow!("Failed to query the focused window: {}", e))?;
+
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "Failed to query the focused window: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}